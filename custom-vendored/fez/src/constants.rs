@@ -409,6 +409,12 @@ pub enum IndexSignatureTag {
     // If this tag is present, then the SIGTAG_FILESIGNATURE shall also be present.
     RPMSIGTAG_FILESIGNATURE_LENGTH = 275,
 
+    // fsverity signatures of files, base64 encoded, in the same order as files.
+    RPMSIGTAG_VERITYSIGNATURES = 276,
+
+    // Public key algorithm of fsverity signatures.
+    RPMSIGTAG_VERITYSIGNATUREALGO = 277,
+
     // This  tag  specifies  the  RSA  signature  of  the  combined  Header  and  Payload  sections.
     // The data is formatted as a Version 3 Signature Packet as specified in RFC 2440: OpenPGP Message Format.
     RPMSIGTAG_PGP = 1002,
@@ -381,6 +381,20 @@ pub(crate) fn write_signature<W: std::io::Write>(&self, out: &mut W) -> Result<(
         Ok(())
     }
 
+    /// Raw OpenPGP signatures. RSA and DSA tags sign header only, PGP and GPG tags sign header and
+    /// payload
+    pub fn get_pgp_signatures(&self) -> Vec<(IndexSignatureTag, &[u8])> {
+        [
+            IndexSignatureTag::RPMSIGTAG_RSA,
+            IndexSignatureTag::RPMSIGTAG_DSA,
+            IndexSignatureTag::RPMSIGTAG_PGP,
+            IndexSignatureTag::RPMSIGTAG_GPG,
+        ]
+        .iter()
+        .filter_map(|tag| self.get_entry_binary_data(*tag).ok().map(|v| (*tag, v)))
+        .collect()
+    }
+
     #[inline]
     pub fn get_file_ima_signatures(&self) -> Result<&[String], RPMError> {
         self.get_entry_string_array_data(IndexSignatureTag::RPMSIGTAG_FILESIGNATURES)
@@ -390,6 +404,22 @@ pub fn get_file_ima_signatures(&self) -> Result<&[String], RPMError> {
     pub fn get_file_ima_signature_length(&self) -> Result<i32, RPMError> {
         self.get_entry_i32_data(IndexSignatureTag::RPMSIGTAG_FILESIGNATURE_LENGTH)
     }
+
+    /// fsverity signatures of files, empty for files without signature
+    #[inline]
+    pub fn get_file_verity_signatures(&self) -> Result<&[String], RPMError> {
+        self.get_entry_string_array_data(IndexSignatureTag::RPMSIGTAG_VERITYSIGNATURES)
+    }
+
+    /// Uncompressed payload size. Modern rpm stores it in signature header only
+    pub fn get_payload_size(&self) -> Result<u64, RPMError> {
+        self.get_entry_i64_data(IndexSignatureTag::RPMSIGTAG_LONGARCHIVESIZE)
+            .map(|v| v as u64)
+            .or_else(|_| {
+                self.get_entry_i32_data(IndexSignatureTag::RPMSIGTAG_PAYLOADSIZE)
+                    .map(|v| v as u32 as u64)
+            })
+    }
 }
 
 impl Header<IndexTag> {
@@ -403,6 +433,19 @@ pub fn get_payload_compressor(&self) -> Result<&str, RPMError> {
         self.get_entry_string_data(IndexTag::RPMTAG_PAYLOADCOMPRESSOR)
     }
 
+    /// IMA signatures of files as written by modern rpm, empty for files without signature. Older
+    /// rpm stores them in signature header
+    #[inline]
+    pub fn get_file_signatures(&self) -> Result<&[String], RPMError> {
+        self.get_entry_string_array_data(IndexTag::RPMTAG_FILESIGNATURES)
+    }
+
+    /// Compressor options, e.g. compression level
+    #[inline]
+    pub fn get_payload_flags(&self) -> Result<&str, RPMError> {
+        self.get_entry_string_data(IndexTag::RPMTAG_PAYLOADFLAGS)
+    }
+
     #[inline]
     pub fn get_file_checksums(&self) -> Result<&[String], RPMError> {
         self.get_entry_string_array_data(IndexTag::RPMTAG_FILEDIGESTS)
@@ -448,7 +491,7 @@ pub fn get_archive_size(&self) -> Result<u64, RPMError> {
             .map(|v| v as u64)
             .or_else(|_| {
                 self.get_entry_i32_data(IndexTag::RPMTAG_ARCHIVESIZE)
-                    .map(|v| v as u64)
+                    .map(|v| v as u32 as u64)
             })
     }
 
@@ -457,7 +500,7 @@ pub fn get_installed_size(&self) -> Result<u64, RPMError> {
             .map(|v| v as u64)
             .or_else(|_| {
                 self.get_entry_i32_data(IndexTag::RPMTAG_SIZE)
-                    .map(|v| v as u64)
+                    .map(|v| v as u32 as u64)
             })
     }
 
@@ -504,6 +547,12 @@ pub fn get_group(&self) -> Result<&[String], RPMError> {
         self.get_entry_string_array_data(IndexTag::RPMTAG_GROUP)
     }
 
+    /// Locales of translated strings (summary, description, group), in the same order
+    #[inline]
+    pub fn get_i18n_table(&self) -> Result<&[String], RPMError> {
+        self.get_entry_string_array_data(IndexTag::RPMTAG_HEADERI18NTABLE)
+    }
+
     #[inline]
     pub fn get_buildhost(&self) -> Result<&str, RPMError> {
         self.get_entry_string_data(IndexTag::RPMTAG_BUILDHOST)
@@ -560,6 +609,43 @@ pub fn get_file_digest_algorithm(&self) -> Result<FileDigestAlgorithm, RPMError>
             })
     }
 
+    /// Raw file modes, in the same order as `get_file_paths`
+    #[inline]
+    pub fn get_file_modes(&self) -> Result<Vec<i16>, RPMError> {
+        self.get_entry_i16_array_data(IndexTag::RPMTAG_FILEMODES)
+    }
+
+    #[inline]
+    pub fn get_file_user_names(&self) -> Result<&[String], RPMError> {
+        self.get_entry_string_array_data(IndexTag::RPMTAG_FILEUSERNAME)
+    }
+
+    #[inline]
+    pub fn get_file_group_names(&self) -> Result<&[String], RPMError> {
+        self.get_entry_string_array_data(IndexTag::RPMTAG_FILEGROUPNAME)
+    }
+
+    /// Raw RPMFILE_* flags of files
+    #[inline]
+    pub fn get_file_flags(&self) -> Result<Vec<i32>, RPMError> {
+        self.get_entry_i32_array_data(IndexTag::RPMTAG_FILEFLAGS)
+    }
+
+    pub fn get_file_sizes(&self) -> Result<Vec<u64>, RPMError> {
+        self.get_entry_i64_array_data(IndexTag::RPMTAG_LONGFILESIZES)
+            .map(|file_sizes| file_sizes.into_iter().map(|v| v as u64).collect())
+            .or_else(|_e| {
+                self.get_entry_i32_array_data(IndexTag::RPMTAG_FILESIZES)
+                    .map(|file_sizes| {
+                        file_sizes
+                            .into_iter()
+                            // 32-bit sizes are unsigned
+                            .map(|file_size| file_size as u32 as u64)
+                            .collect()
+                    })
+            })
+    }
+
     /// Extract a the set of contained file names including the additional metadata.
     pub fn get_file_entries(&self) -> Result<Vec<FileEntry>, RPMError> {
         // rpm does not encode it, if it is the default md5
@@ -577,7 +663,8 @@ pub fn get_file_entries(&self) -> Result<Vec<FileEntry>, RPMError> {
                     .map(|file_sizes| {
                         file_sizes
                             .into_iter()
-                            .map(|file_size| file_size as _)
+                            // 32-bit sizes are unsigned
+                            .map(|file_size| file_size as u32 as i64)
                             .collect::<Vec<i64>>()
                     })
             })?;
@@ -897,6 +984,47 @@ fn signature_header_build() {
 
         assert_eq!(built, truth);
     }
+
+    #[test]
+    fn large_sizes() {
+        let entries = vec![
+            IndexEntry::new(
+                IndexTag::RPMTAG_SIZE,
+                0,
+                IndexData::Int32(vec![3_000_000_000u32 as i32]),
+            ),
+            IndexEntry::new(
+                IndexTag::RPMTAG_ARCHIVESIZE,
+                0,
+                IndexData::Int32(vec![4_000_000_000u32 as i32]),
+            ),
+        ];
+        let header = Header::<IndexTag>::from_entries(entries, IndexTag::RPMTAG_HEADERIMMUTABLE);
+        assert_eq!(header.get_installed_size().unwrap(), 3_000_000_000);
+        assert_eq!(header.get_archive_size().unwrap(), 4_000_000_000);
+
+        let entries = vec![
+            IndexEntry::new(IndexTag::RPMTAG_SIZE, 0, IndexData::Int32(vec![1])),
+            IndexEntry::new(
+                IndexTag::RPMTAG_LONGSIZE,
+                0,
+                IndexData::Int64(vec![6 << 30]),
+            ),
+        ];
+        let header = Header::<IndexTag>::from_entries(entries, IndexTag::RPMTAG_HEADERIMMUTABLE);
+        assert_eq!(header.get_installed_size().unwrap(), 6 << 30);
+
+        let entries = vec![IndexEntry::new(
+            IndexSignatureTag::RPMSIGTAG_LONGARCHIVESIZE,
+            0,
+            IndexData::Int64(vec![5 << 30]),
+        )];
+        let header = Header::<IndexSignatureTag>::from_entries(
+            entries,
+            IndexSignatureTag::HEADER_SIGNATURES,
+        );
+        assert_eq!(header.get_payload_size().unwrap(), 5 << 30);
+    }
 }
 
 /// A header keeping track of all other headerr records.
@@ -50,6 +50,12 @@ rm -rf %{buildroot}
 %{__install} -pD -m 755 target/release/rpm-tool %{buildroot}%{_bindir}/rpm-tool
 %{__install} -pD -m 755 etc/rpm-tool.example.yaml %{buildroot}%{_sysconfdir}/rpm-tool.example.yaml
 %{__install} -pD -m 755 README.org %{buildroot}%{_docdir}/rpm-tool/README.org
+%{_mandir}/man1/rpm-tool*.1*
+%{_datadir}/bash-completion/completions/rpm-tool
+
+%{__mkdir} -p %{buildroot}%{_mandir}/man1 %{buildroot}%{_datadir}/bash-completion/completions
+target/release/rpm-tool manpages %{buildroot}%{_mandir}/man1
+target/release/rpm-tool completions bash > %{buildroot}%{_datadir}/bash-completion/completions/rpm-tool
 
 %clean
 rm -rf %{buildroot}
@@ -1,5 +1,23 @@
-use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use slog_scope::{error, info, warn};
+
+/// Comments of example config are used as descriptions of settings
+const EXAMPLE_CONFIG: &str = include_str!("../etc/rpm-tool.example.yaml");
+
+/// Settings applied by daemon modes on reload, changes of other settings require restart. Package
+/// policy (license_denylist, sanitize, permissions, gpg) and hooks are used by generation through
+/// the config borrowed at start, so they are not live
+const LIVE_SETTINGS: [&str; 6] = [
+    "log_level",
+    "logging",
+    "repodata.watch.debounce",
+    "repodata.watch.quiet_period",
+    "repodata.watch.max_batch",
+    "repodata.watch.schedule",
+];
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum LogLevel {
@@ -26,8 +44,37 @@ fn from(level: LogLevel) -> Self {
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
+    /// Syslog log level, used when `logging` is empty
     pub log_level: LogLevel,
+    #[serde(default)]
+    pub logging: Vec<crate::logging::LogBackendConfig>,
     pub repodata: crate::repodata::RepodataConfig,
+    /// Repositories generated by "repository generate --all"
+    #[serde(default)]
+    pub repositories: Vec<crate::repodata::profiles::RepositoryProfile>,
+    /// Settings given by --set KEY=VALUE, they are applied again on reload
+    #[serde(skip)]
+    pub overrides: Vec<String>,
+}
+
+/// Set value at dotted path, missing objects are created
+fn set_value(value: &mut serde_json::Value, key: &str, new: serde_json::Value) -> Result<()> {
+    let mut value = value;
+    for part in key.split('.') {
+        value = match value {
+            serde_json::Value::Object(map) => map
+                .entry(part)
+                .or_insert_with(|| serde_json::Value::Object(Default::default())),
+            serde_json::Value::Array(list) => part
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| list.get_mut(n))
+                .ok_or_else(|| anyhow!("No list item {:?} in {:?}", part, key))?,
+            _ => bail!("Setting {:?} is not a section", key),
+        }
+    }
+    *value = new;
+    Ok(())
 }
 
 impl Config {
@@ -35,6 +82,35 @@ fn validate(&self) -> Result<()> {
         Ok(())
     }
 
+    /// Apply KEY=VALUE overrides of command line, VALUE is parsed as YAML
+    pub fn with_overrides(self, overrides: &[String]) -> Result<Self> {
+        if overrides.is_empty() {
+            return Ok(self);
+        }
+        let mut value = serde_json::to_value(&self)?;
+        for v in overrides {
+            let (key, new) = v
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Override {:?} is not in KEY=VALUE form", v))?;
+            let new: serde_json::Value = serde_yaml::from_str(new)
+                .map_err(|err| anyhow!("Invalid value of {:?}: {}", key, err))?;
+            set_value(&mut value, key, new)?;
+        }
+        let mut config: Self = serde_json::from_value(value)
+            .map_err(|err| anyhow!("Invalid settings on command line: {}", err))?;
+        config.overrides = overrides.to_vec();
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Whether setting is given by --set, directly or as part of section
+    fn is_overridden(&self, key: &str) -> bool {
+        self.overrides.iter().any(|v| {
+            let name = v.split_once('=').map_or(v.as_str(), |v| v.0);
+            key == name || key.starts_with(&format!("{}.", name))
+        })
+    }
+
     pub fn read(file: &str) -> Result<Self> {
         let config = std::fs::read_to_string(file)
             .with_context(|| format!("Failed to load config file {:?}", file))?;
@@ -45,3 +121,259 @@ pub fn read(file: &str) -> Result<Self> {
         Ok(config)
     }
 }
+
+/// Effective setting with origin of its value
+#[derive(Serialize)]
+pub struct Setting {
+    pub key: String,
+    pub value: serde_json::Value,
+    /// "file", "default", "env" or "cli"
+    pub source: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Key defined by line of YAML, possibly commented out, e.g. "  # retry:"
+fn yaml_key(line: &str) -> Option<(usize, &str)> {
+    let text = line.trim_start();
+    let (indent, text) = match text.strip_prefix('#') {
+        // "#" takes place of first space of indentation
+        Some(v) => (
+            (line.len() - text.len() + v.len() - v.trim_start().len()).saturating_sub(1),
+            v.trim_start(),
+        ),
+        None => (line.len() - text.len(), text),
+    };
+    let (indent, text) = match text.strip_prefix("- ") {
+        Some(v) => (indent + 2, v),
+        None => (indent, text),
+    };
+    let (key, rest) = text.split_once(':')?;
+    let is_key = key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && (rest.is_empty() || rest.starts_with(' '));
+    if is_key {
+        Some((indent, key))
+    } else {
+        None
+    }
+}
+
+/// Comment blocks preceding keys by dotted path of key, list indexes are omitted
+fn key_descriptions(example: &str) -> HashMap<String, String> {
+    let mut r = HashMap::new();
+    let mut comment = Vec::new();
+    let mut path: Vec<(usize, &str)> = Vec::new();
+    for line in example.lines() {
+        if line.trim().is_empty() {
+            comment.clear();
+            continue;
+        }
+        if let Some((indent, key)) = yaml_key(line) {
+            while path.last().map_or(false, |(v, _)| *v >= indent) {
+                let _ = path.pop();
+            }
+            path.push((indent, key));
+            if !comment.is_empty() {
+                let key = path.iter().map(|(_, v)| *v).collect::<Vec<_>>().join(".");
+                let _ = r.insert(key, comment.join(" "));
+                comment.clear();
+            }
+        } else if let Some(text) = line.trim_start().strip_prefix('#') {
+            comment.push(text.trim_start_matches(|c| c == ' ' || c == '#').to_owned());
+        }
+    }
+    r
+}
+
+/// Scalars and empty collections by dotted path
+fn flatten(prefix: &str, value: &serde_json::Value, r: &mut Vec<(String, serde_json::Value)>) {
+    let join = |key: &str| {
+        if prefix.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten(&join(key), value, r)
+            }
+        }
+        serde_json::Value::Array(list) if !list.is_empty() => {
+            for (n, value) in list.iter().enumerate() {
+                flatten(&join(&n.to_string()), value, r)
+            }
+        }
+        v => r.push((prefix.to_owned(), v.clone())),
+    }
+}
+
+fn lookup<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    key.split('.').try_fold(value, |value, part| match value {
+        serde_json::Value::Object(map) => map.get(part),
+        serde_json::Value::Array(list) => list.get(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+impl Config {
+    /// Every effective setting with source of its value and description taken from example config
+    pub fn settings(&self, file: &str) -> Result<Vec<Setting>> {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to load config file {:?}", file))?;
+        let from_file: serde_json::Value = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse config file {:?}", file))?;
+        let descriptions = key_descriptions(EXAMPLE_CONFIG);
+        let from_env = std::env::var("RUST_LOG").is_ok();
+
+        let mut leaves = Vec::new();
+        flatten("", &serde_json::to_value(self)?, &mut leaves);
+        Ok(leaves
+            .into_iter()
+            .map(|(key, value)| {
+                let source = if self.is_overridden(&key) {
+                    "cli"
+                } else if from_env && (key == "log_level" || key.starts_with("logging")) {
+                    "env"
+                } else if lookup(&from_file, &key).is_some() {
+                    "file"
+                } else {
+                    "default"
+                };
+                let description_key = key
+                    .split('.')
+                    .filter(|v| v.parse::<usize>().is_err())
+                    .collect::<Vec<_>>()
+                    .join(".");
+                Setting {
+                    description: descriptions.get(&description_key).cloned(),
+                    key,
+                    value,
+                    source,
+                }
+            })
+            .collect())
+    }
+}
+
+impl Config {
+    /// Keys of settings which differ in `other`, split into applied live and requiring restart
+    pub fn changed_keys(&self, other: &Self) -> Result<(Vec<String>, Vec<String>)> {
+        let leaves = |config: &Self| -> Result<HashMap<String, serde_json::Value>> {
+            let mut r = Vec::new();
+            flatten("", &serde_json::to_value(config)?, &mut r);
+            Ok(r.into_iter().collect())
+        };
+        let (current, new) = (leaves(self)?, leaves(other)?);
+        let mut changed: Vec<_> = current
+            .keys()
+            .chain(new.keys().filter(|v| !current.contains_key(*v)))
+            .filter(|v| current.get(*v) != new.get(*v))
+            .cloned()
+            .collect();
+        changed.sort();
+        Ok(changed.into_iter().partition(|key| {
+            LIVE_SETTINGS
+                .iter()
+                .any(|v| key == v || key.starts_with(&format!("{}.", v)))
+        }))
+    }
+}
+
+/// Re-reads configuration file of daemon mode on SIGHUP or when file is modified
+pub struct Reloader {
+    path: String,
+    modified: Option<std::time::SystemTime>,
+    current: Config,
+}
+
+impl Reloader {
+    fn modified(path: &str) -> Option<std::time::SystemTime> {
+        std::fs::metadata(path).and_then(|v| v.modified()).ok()
+    }
+
+    pub fn new(path: &str, overrides: &[String]) -> Result<Self> {
+        crate::platform::install_reload_handler()?;
+        Ok(Self {
+            path: path.to_owned(),
+            modified: Self::modified(path),
+            current: Config::read(path)?.with_overrides(overrides)?,
+        })
+    }
+
+    /// Reloaded configuration if reload is requested and live settings are changed. Changes of
+    /// other settings are reported and ignored
+    pub fn poll(&mut self) -> Option<&Config> {
+        let modified = Self::modified(&self.path);
+        if !crate::platform::take_reload_request() && modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        info!("Reloading config file {:?}", self.path);
+        let config = match Config::read(&self.path)
+            .and_then(|v| v.with_overrides(&self.current.overrides))
+        {
+            Ok(v) => v,
+            Err(err) => {
+                error!("Config is not reloaded: {:#}", err);
+                return None;
+            }
+        };
+        let (live, restart) = match self.current.changed_keys(&config) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("Config is not reloaded: {:#}", err);
+                return None;
+            }
+        };
+        if !restart.is_empty() {
+            warn!(
+                "Changes of {} require restart, they are not applied",
+                restart.join(", ")
+            );
+        }
+        self.current = config;
+        if live.is_empty() {
+            return None;
+        }
+        info!("Applying changes of {}", live.join(", "));
+        Some(&self.current)
+    }
+}
+
+#[test]
+fn test_key_descriptions() {
+    let descriptions = key_descriptions(
+        "# Level\nlog_level: Info\n\nrepodata:\n  # Number of\n  # workers\n  concurrency: 8\n  # Retries\n  # retry:\n  #   attempts: 3\n",
+    );
+    assert_eq!(descriptions["log_level"], "Level");
+    assert_eq!(descriptions["repodata.concurrency"], "Number of workers");
+    assert_eq!(descriptions["repodata.retry"], "Retries");
+    assert!(!descriptions.contains_key("repodata"));
+    assert!(!descriptions.contains_key("repodata.retry.attempts"));
+}
+
+#[test]
+fn test_with_overrides() {
+    let config: Config = serde_yaml::from_str(
+        "log_level: Info\nrepodata:\n  concurrency: 4\n  useful_files: ^/etc\n",
+    )
+    .unwrap();
+    let config = config
+        .with_overrides(&[
+            "repodata.concurrency=8".to_owned(),
+            "repodata.lock={timeout: 5}".to_owned(),
+        ])
+        .unwrap();
+    assert_eq!(config.repodata.concurrency, 8);
+    assert_eq!(config.repodata.lock.timeout, Some(5));
+    assert!(config.is_overridden("repodata.concurrency"));
+    assert!(config.is_overridden("repodata.lock.timeout"));
+    assert!(!config.is_overridden("repodata.concurrency_limit"));
+    assert!(!config.is_overridden("log_level"));
+}
@@ -0,0 +1,182 @@
+//! Reading of cpio archives in "newc" format, which is used for RPM payloads
+
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Result};
+
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+const MODE_TYPE_MASK: u32 = 0o170000;
+const MODE_REGULAR: u32 = 0o100000;
+
+pub struct Entry {
+    /// Path as stored in archive, e.g. "./usr/bin/bash"
+    pub path: String,
+    pub mode: u32,
+    pub size: u64,
+    pub ino: u64,
+    pub nlink: u64,
+    pub dev: (u64, u64),
+}
+
+impl Entry {
+    pub fn is_file(&self) -> bool {
+        self.mode & MODE_TYPE_MASK == MODE_REGULAR
+    }
+
+    /// Identity of hardlinked file. Data of hardlinked file is stored only in one of its links,
+    /// usually the last one, other links have zero size
+    pub fn link_key(&self) -> Option<(u64, u64, u64)> {
+        (self.is_file() && self.nlink > 1).then_some((self.dev.0, self.dev.1, self.ino))
+    }
+}
+
+struct CountingReader<R> {
+    input: R,
+    offset: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.input.read(buf)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> CountingReader<R> {
+    /// Skip padding to 4-byte boundary
+    fn align(&mut self) -> Result<()> {
+        let padding = (4 - self.offset % 4) % 4;
+        let _ = self.skip(padding)?;
+        Ok(())
+    }
+
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        let skipped = std::io::copy(&mut self.by_ref().take(n), &mut std::io::sink())?;
+        if skipped < n {
+            bail!("Unexpected end of cpio archive")
+        }
+        Ok(skipped)
+    }
+}
+
+fn hex_field(header: &[u8], n: usize) -> Result<u64> {
+    let field = &header[6 + n * 8..6 + (n + 1) * 8];
+    let field = std::str::from_utf8(field).map_err(|_| anyhow!("Invalid cpio header field"))?;
+    u64::from_str_radix(field, 16).map_err(|err| anyhow!("Invalid cpio header field: {}", err))
+}
+
+/// Call `f` for every entry with reader of its data. Data not read by `f` is skipped
+pub fn for_each_entry<R, F>(input: R, mut f: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(&Entry, &mut dyn Read) -> Result<()>,
+{
+    let mut input = CountingReader { input, offset: 0 };
+    loop {
+        let mut header = [0; HEADER_SIZE];
+        input
+            .read_exact(&mut header)
+            .map_err(|err| anyhow!("Cannot read cpio header: {}", err))?;
+        match &header[..6] {
+            b"070701" | b"070702" => (),
+            b"07070X" => {
+                bail!("Stripped cpio archives of packages with large files are not supported")
+            }
+            magic => bail!("Invalid cpio magic {:?}", String::from_utf8_lossy(magic)),
+        }
+
+        let mut name = vec![0; hex_field(&header, 11)? as usize];
+        input
+            .read_exact(&mut name)
+            .map_err(|err| anyhow!("Cannot read cpio entry name: {}", err))?;
+        input.align()?;
+        let entry = Entry {
+            path: String::from_utf8_lossy(name.split(|v| *v == 0).next().unwrap_or_default())
+                .to_string(),
+            mode: hex_field(&header, 1)? as u32,
+            size: hex_field(&header, 6)?,
+            ino: hex_field(&header, 0)?,
+            nlink: hex_field(&header, 4)?,
+            dev: (hex_field(&header, 7)?, hex_field(&header, 8)?),
+        };
+        if entry.path == TRAILER {
+            return Ok(());
+        }
+
+        let mut data = input.by_ref().take(entry.size);
+        f(&entry, &mut data)?;
+        let rest = data.limit();
+        let _ = input.skip(rest)?;
+        input.align()?;
+    }
+}
+
+#[cfg(test)]
+pub fn test_entry(r: &mut Vec<u8>, path: &str, mode: u32, data: &[u8]) {
+    test_link(r, path, mode, 0, 1, data)
+}
+
+/// Entry with given inode number and number of links
+#[cfg(test)]
+pub fn test_link(r: &mut Vec<u8>, path: &str, mode: u32, ino: u32, nlink: u32, data: &[u8]) {
+    let fields = [
+        ino,
+        mode,
+        0,
+        0,
+        nlink,
+        0,
+        data.len() as u32,
+        0,
+        0,
+        0,
+        0,
+        path.len() as u32 + 1,
+        0,
+    ];
+    r.extend(b"070701");
+    for field in fields {
+        r.extend(format!("{:08x}", field).as_bytes());
+    }
+    r.extend(path.as_bytes());
+    r.push(0);
+    while r.len() % 4 != 0 {
+        r.push(0)
+    }
+    r.extend(data);
+    while r.len() % 4 != 0 {
+        r.push(0)
+    }
+}
+
+#[test]
+fn test_for_each_entry() {
+    let mut archive = Vec::new();
+    test_entry(&mut archive, "./usr", 0o040755, b"");
+    test_entry(&mut archive, "./usr/foo", 0o100644, b"hello");
+    test_entry(&mut archive, "./usr/bar", 0o100644, b"world!");
+    test_entry(&mut archive, TRAILER, 0, b"");
+
+    let mut r = Vec::new();
+    for_each_entry(&archive[..], |entry, data| {
+        let mut content = String::new();
+        // Data of the second file is left unread
+        if entry.path != "./usr/bar" {
+            let _ = data.read_to_string(&mut content)?;
+        }
+        r.push((entry.path.clone(), entry.is_file(), content));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(
+        r,
+        vec![
+            ("./usr".to_owned(), false, "".to_owned()),
+            ("./usr/foo".to_owned(), true, "hello".to_owned()),
+            ("./usr/bar".to_owned(), true, "".to_owned()),
+        ]
+    );
+}
@@ -0,0 +1,61 @@
+//! Transparent decompression of streams, compression format is detected by magic bytes
+
+use std::io::{BufRead, Read};
+
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else if header.starts_with(b"BZh") {
+            Self::Bzip2
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// Wrap reader into decoder matching its content. Data without known magic is passed as is
+pub fn decompress<'a, R>(mut input: R) -> Result<Box<dyn Read + 'a>>
+where
+    R: BufRead + 'a,
+{
+    let header = input
+        .fill_buf()
+        .map_err(|err| anyhow!("Cannot read compression header: {}", err))?;
+
+    let r: Box<dyn Read + 'a> = match Compression::detect(header) {
+        Compression::None => Box::new(input),
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(input)),
+        Compression::Xz => Box::new(xz2::bufread::XzDecoder::new(input)),
+        Compression::Zstd => Box::new(
+            zstd::stream::read::Decoder::with_buffer(input)
+                .map_err(|err| anyhow!("Cannot initialize zstd decoder: {}", err))?,
+        ),
+        Compression::Bzip2 => Box::new(bzip2::bufread::BzDecoder::new(input)),
+    };
+    Ok(r)
+}
+
+#[test]
+fn test_detect() {
+    assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+    assert_eq!(Compression::detect(b"\xfd7zXZ\x00\x00"), Compression::Xz);
+    assert_eq!(Compression::detect(b"BZh9"), Compression::Bzip2);
+    assert_eq!(Compression::detect(b"DLT3"), Compression::None);
+    assert_eq!(Compression::detect(b""), Compression::None);
+}
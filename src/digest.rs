@@ -1,25 +1,252 @@
-use std::io::{Read, Seek, SeekFrom};
+//! SHA digests. Hashing is the dominant cost on large repositories, so implementation is chosen at
+//! runtime: RustCrypto uses SHA CPU extensions when they are present, otherwise OpenSSL assembly is
+//! faster if binary is built with `openssl` feature
 
-use anyhow::Result;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
 
-pub fn file_sha128(file: &mut std::fs::File) -> Result<String> {
-    use crypto::digest::Digest;
-    use crypto::sha1::Sha1;
+use anyhow::{bail, Result};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum DigestBackend {
+    /// Choose by CPU features
+    Auto,
+    /// sha1 and sha2 crates
+    RustCrypto,
+    /// Requires `openssl` feature
+    Openssl,
+}
+
+/// Resolved backend, 0 if not chosen yet
+static BACKEND: AtomicU8 = AtomicU8::new(0);
+
+const RUST_CRYPTO: u8 = 1;
+const OPENSSL: u8 = 2;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn has_sha_extensions() -> bool {
+    std::is_x86_feature_detected!("sha")
+}
+
+#[cfg(target_arch = "aarch64")]
+fn has_sha_extensions() -> bool {
+    std::arch::is_aarch64_feature_detected!("sha2")
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn has_sha_extensions() -> bool {
+    false
+}
+
+impl DigestBackend {
+    fn resolve(self) -> Result<u8> {
+        match self {
+            Self::Auto if cfg!(feature = "openssl") && !has_sha_extensions() => Ok(OPENSSL),
+            Self::Auto | Self::RustCrypto => Ok(RUST_CRYPTO),
+            Self::Openssl if cfg!(feature = "openssl") => Ok(OPENSSL),
+            Self::Openssl => {
+                bail!("OpenSSL digest backend is not available, binary is built without openssl feature")
+            }
+        }
+    }
+}
+
+/// Override automatic choice of backend
+pub fn set_backend(backend: DigestBackend) -> Result<()> {
+    BACKEND.store(backend.resolve()?, Ordering::SeqCst);
+    Ok(())
+}
+
+fn backend() -> u8 {
+    match BACKEND.load(Ordering::Relaxed) {
+        0 => {
+            let r = DigestBackend::Auto.resolve().unwrap_or(RUST_CRYPTO);
+            BACKEND.store(r, Ordering::Relaxed);
+            r
+        }
+        v => v,
+    }
+}
+
+/// Incremental digest calculation, implemented by every backend
+trait Hasher: Send {
+    fn update(&mut self, data: &[u8]);
+    /// Hex digest of data hashed so far
+    fn hex_digest(&self) -> String;
+}
 
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|v| format!("{:02x}", v)).collect()
+}
+
+struct RustCrypto<D>(D);
+
+impl<D: digest::Digest + Clone + Send> Hasher for RustCrypto<D> {
+    fn update(&mut self, data: &[u8]) {
+        digest::Digest::update(&mut self.0, data)
+    }
+
+    fn hex_digest(&self) -> String {
+        to_hex(&self.0.clone().finalize())
+    }
+}
+
+#[cfg(feature = "openssl")]
+macro_rules! openssl_hasher {
+    ($name:ident) => {
+        impl Hasher for openssl::sha::$name {
+            fn update(&mut self, data: &[u8]) {
+                openssl::sha::$name::update(self, data)
+            }
+
+            fn hex_digest(&self) -> String {
+                to_hex(&self.clone().finish())
+            }
+        }
+    };
+}
+
+#[cfg(feature = "openssl")]
+openssl_hasher!(Sha1);
+#[cfg(feature = "openssl")]
+openssl_hasher!(Sha256);
+#[cfg(feature = "openssl")]
+openssl_hasher!(Sha512);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DigestType {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl DigestType {
+    /// Name used in repository metadata
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    /// Parse checksum type of repository metadata, "sha" means SHA1
+    pub fn of_name(name: &str) -> Option<Self> {
+        match name {
+            "sha" | "sha1" => Some(Self::Sha1),
+            "sha256" => Some(Self::Sha256),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Length of digest in hex form
+    pub fn hex_len(self) -> usize {
+        match self {
+            Self::Sha1 => 40,
+            Self::Sha256 => 64,
+            Self::Sha512 => 128,
+        }
+    }
+
+    fn hasher(self) -> Box<dyn Hasher> {
+        match (backend(), self) {
+            #[cfg(feature = "openssl")]
+            (OPENSSL, Self::Sha1) => Box::new(openssl::sha::Sha1::new()),
+            #[cfg(feature = "openssl")]
+            (OPENSSL, Self::Sha256) => Box::new(openssl::sha::Sha256::new()),
+            #[cfg(feature = "openssl")]
+            (OPENSSL, Self::Sha512) => Box::new(openssl::sha::Sha512::new()),
+            (_, Self::Sha1) => Box::new(RustCrypto(sha1::Sha1::default())),
+            (_, Self::Sha256) => Box::new(RustCrypto(sha2::Sha256::default())),
+            (_, Self::Sha512) => Box::new(RustCrypto(sha2::Sha512::default())),
+        }
+    }
+}
+
+/// Digests of several types calculated over single read of file, in order of `types`
+pub fn file_digests(file: &mut std::fs::File, types: &[DigestType]) -> Result<Vec<String>> {
     file.seek(SeekFrom::Start(0))?;
 
-    let mut hasher = Sha1::new();
-    let mut buffer = [0; 1024];
+    let mut hashers: Vec<_> = types.iter().map(|v| v.hasher()).collect();
+    let mut buffer = [0; 65536];
 
     loop {
         let count = file.read(&mut buffer)?;
         if count == 0 {
             break;
         }
-        hasher.input(&buffer[..count]);
+        for hasher in &mut hashers {
+            hasher.update(&buffer[..count]);
+        }
     }
 
-    Ok(hasher.result_str())
+    Ok(hashers.iter().map(|v| v.hex_digest()).collect())
+}
+
+/// Digest and size of data written through `DigestWriter`, available after writer is moved into
+/// compressor which doesn't give it back
+#[derive(Clone)]
+pub struct SharedDigest(Arc<Mutex<(Box<dyn Hasher>, u64)>>);
+
+impl SharedDigest {
+    /// Hex digest and size of data written so far
+    pub fn result(&self) -> (String, u64) {
+        let state = self.0.lock().unwrap();
+        (state.0.hex_digest(), state.1)
+    }
+}
+
+/// Writer calculating digest of data while passing it to inner writer, so that written file doesn't
+/// have to be read again
+pub struct DigestWriter<W> {
+    inner: W,
+    digest: SharedDigest,
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub fn new(inner: W, digest_type: DigestType) -> Self {
+        Self {
+            inner,
+            digest: SharedDigest(Arc::new(Mutex::new((digest_type.hasher(), 0)))),
+        }
+    }
+
+    pub fn digest(&self) -> SharedDigest {
+        self.digest.clone()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        let mut state = self.digest.0.lock().unwrap();
+        state.0.update(&buf[..count]);
+        state.1 += count as u64;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn path_digests(path: &std::path::Path, types: &[DigestType]) -> Result<Vec<String>> {
+    let mut file = std::fs::File::open(path)?;
+    file_digests(&mut file, types)
+}
+
+fn file_digest(file: &mut std::fs::File, digest_type: DigestType) -> Result<String> {
+    Ok(file_digests(file, &[digest_type])?.remove(0))
+}
+
+pub fn file_sha128(file: &mut std::fs::File) -> Result<String> {
+    file_digest(file, DigestType::Sha1)
 }
 
 pub fn path_sha128(path: &std::path::Path) -> Result<String> {
@@ -27,12 +254,54 @@ pub fn path_sha128(path: &std::path::Path) -> Result<String> {
     file_sha128(&mut file)
 }
 
+pub fn path_sha256(path: &std::path::Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    file_digest(&mut file, DigestType::Sha256)
+}
+
+/// Digest of all data read from reader
+pub fn reader_digest<R: Read>(mut reader: R, digest_type: DigestType) -> Result<String> {
+    let mut hasher = digest_type.hasher();
+    let mut buffer = [0; 65536];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(hasher.hex_digest())
+}
+
 pub fn str_sha128(str: &str) -> String {
-    use crypto::digest::Digest;
-    use crypto::sha1::Sha1;
+    let mut hasher = DigestType::Sha1.hasher();
+    hasher.update(str.as_bytes());
+    hasher.hex_digest()
+}
 
-    let mut hasher = Sha1::new();
-    hasher.input_str(str);
+#[test]
+fn test_file_digests() {
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(b"abc").unwrap();
+    let digests = file_digests(&mut file, &[DigestType::Sha1, DigestType::Sha256]).unwrap();
+    assert_eq!(
+        digests,
+        vec![
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        ]
+    );
+}
 
-    hasher.result_str()
+#[test]
+fn test_digest_writer() {
+    let mut writer = DigestWriter::new(Vec::new(), DigestType::Sha1);
+    let digest = writer.digest();
+    writer.write_all(b"ab").unwrap();
+    writer.write_all(b"c").unwrap();
+    assert_eq!(writer.inner, b"abc");
+    assert_eq!(
+        digest.result(),
+        ("a9993e364706816aba3e25717850c26c9cd0d89d".to_owned(), 3)
+    );
 }
@@ -0,0 +1,230 @@
+//! Comparison of package versions following rpm rules
+
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Result};
+
+/// Compare two version or release strings the same way rpmvercmp() does. Strings are split into
+/// numeric and alphabetic segments, numeric segment is newer than alphabetic one, "~" sorts before
+/// anything, "^" sorts after the base version but before any other addition
+pub fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+
+    let is_separator = |c: &u8| !c.is_ascii_alphanumeric() && *c != b'~' && *c != b'^';
+
+    let mut one = a.as_bytes();
+    let mut two = b.as_bytes();
+
+    loop {
+        while one.first().map(is_separator).unwrap_or(false) {
+            one = &one[1..]
+        }
+        while two.first().map(is_separator).unwrap_or(false) {
+            two = &two[1..]
+        }
+
+        if one.first() == Some(&b'~') || two.first() == Some(&b'~') {
+            if one.first() != Some(&b'~') {
+                return Ordering::Greater;
+            }
+            if two.first() != Some(&b'~') {
+                return Ordering::Less;
+            }
+            one = &one[1..];
+            two = &two[1..];
+            continue;
+        }
+
+        if one.first() == Some(&b'^') || two.first() == Some(&b'^') {
+            if one.is_empty() {
+                return Ordering::Less;
+            }
+            if two.is_empty() {
+                return Ordering::Greater;
+            }
+            if one.first() != Some(&b'^') {
+                return Ordering::Greater;
+            }
+            if two.first() != Some(&b'^') {
+                return Ordering::Less;
+            }
+            one = &one[1..];
+            two = &two[1..];
+            continue;
+        }
+
+        if one.is_empty() || two.is_empty() {
+            break;
+        }
+
+        let is_num = one[0].is_ascii_digit();
+        let segment_len = |s: &[u8]| {
+            s.iter()
+                .take_while(|c| {
+                    if is_num {
+                        c.is_ascii_digit()
+                    } else {
+                        c.is_ascii_alphabetic()
+                    }
+                })
+                .count()
+        };
+        let (seg_one, rest_one) = one.split_at(segment_len(one));
+        let (seg_two, rest_two) = two.split_at(segment_len(two));
+        one = rest_one;
+        two = rest_two;
+
+        // Segments of different types, numeric one is newer
+        if seg_two.is_empty() {
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let r = if is_num {
+            let seg_one = &seg_one[seg_one.iter().take_while(|c| **c == b'0').count()..];
+            let seg_two = &seg_two[seg_two.iter().take_while(|c| **c == b'0').count()..];
+            seg_one
+                .len()
+                .cmp(&seg_two.len())
+                .then_with(|| seg_one.cmp(seg_two))
+        } else {
+            seg_one.cmp(seg_two)
+        };
+        if r != Ordering::Equal {
+            return r;
+        }
+    }
+
+    match (one.is_empty(), two.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, _) => Ordering::Greater,
+    }
+}
+
+/// Epoch, version and optional release of package or dependency
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Evr {
+    pub epoch: u32,
+    pub version: String,
+    pub release: Option<String>,
+}
+
+impl Evr {
+    /// Release is compared only if both sides have it, so "1.0" matches any "1.0-N"
+    pub fn compare(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| rpmvercmp(&self.version, &other.version))
+            .then_with(|| match (&self.release, &other.release) {
+                (Some(a), Some(b)) => rpmvercmp(a, b),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl std::str::FromStr for Evr {
+    type Err = anyhow::Error;
+
+    /// Parse "[epoch:]version[-release]"
+    fn from_str(s: &str) -> Result<Self> {
+        let (epoch, rest) = match s.split_once(':') {
+            Some((epoch, rest)) => (
+                epoch
+                    .parse()
+                    .map_err(|err| anyhow!("Invalid epoch in {:?}: {}", s, err))?,
+                rest,
+            ),
+            None => (0, s),
+        };
+        let (version, release) = match rest.rsplit_once('-') {
+            Some((version, release)) => (version, Some(release.to_owned())),
+            None => (rest, None),
+        };
+        if version.is_empty() {
+            bail!("Empty version in {:?}", s)
+        }
+        Ok(Self {
+            epoch,
+            version: version.to_owned(),
+            release,
+        })
+    }
+}
+
+impl std::fmt::Display for Evr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.epoch > 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.version)?;
+        if let Some(release) = &self.release {
+            write!(f, "-{}", release)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&crate::repodata::primary::PackageVersion> for Evr {
+    fn from(v: &crate::repodata::primary::PackageVersion) -> Self {
+        Self {
+            epoch: v.epoch.unwrap_or_default(),
+            version: v.ver.clone(),
+            release: Some(v.rel.clone()),
+        }
+    }
+}
+
+#[test]
+fn test_rpmvercmp() {
+    for (a, b, expected) in [
+        ("1.0", "1.0", Ordering::Equal),
+        ("1.0", "2.0", Ordering::Less),
+        ("1.10", "1.9", Ordering::Greater),
+        ("1.001", "1.1", Ordering::Equal),
+        ("1.0a", "1.0", Ordering::Greater),
+        ("1.0", "1.0a", Ordering::Less),
+        ("2.0a", "2.0.1", Ordering::Less),
+        ("a", "1", Ordering::Less),
+        ("1.0~rc1", "1.0", Ordering::Less),
+        ("1.0~rc1", "1.0~rc2", Ordering::Less),
+        ("1.0^git1", "1.0", Ordering::Greater),
+        ("1.0^git1", "1.0.1", Ordering::Less),
+        ("1.0_1", "1.0.1", Ordering::Equal),
+    ] {
+        assert_eq!(rpmvercmp(a, b), expected, "{} vs {}", a, b);
+    }
+
+    let a: Evr = "1:1.2-3".parse().unwrap();
+    let b: Evr = "1.10-1".parse().unwrap();
+    assert_eq!(a.compare(&b), Ordering::Greater);
+    let b: Evr = "1:1.2".parse().unwrap();
+    assert_eq!(a.compare(&b), Ordering::Equal);
+}
+
+#[test]
+fn test_evr_from_str() {
+    let evr: Evr = "1:1.2-3.el9".parse().unwrap();
+    assert_eq!(
+        evr,
+        Evr {
+            epoch: 1,
+            version: "1.2".to_owned(),
+            release: Some("3.el9".to_owned()),
+        }
+    );
+    assert_eq!(evr.to_string(), "1:1.2-3.el9");
+
+    let evr: Evr = "0:1.2".parse().unwrap();
+    assert_eq!(evr.release, None);
+    assert_eq!(evr.to_string(), "1.2");
+
+    assert!("x:1.2".parse::<Evr>().is_err());
+    assert!("1:".parse::<Evr>().is_err());
+    assert!("-1".parse::<Evr>().is_err());
+}
@@ -0,0 +1,95 @@
+//! Exit codes of process by class of failure, so that wrapper scripts can branch on them. Class is
+//! attached to error as context, errors without class are classified by their causes
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Unclassified failure
+pub const FAILURE: i32 = 1;
+/// Invalid command line, returned by clap
+pub const USAGE: i32 = 2;
+pub const CONFIG: i32 = 3;
+pub const LOCK_TIMEOUT: i32 = 4;
+/// Metadata is published, but some packages were skipped
+pub const PARTIAL_FAILURE: i32 = 5;
+pub const MISMATCH: i32 = 6;
+pub const IO: i32 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Config,
+    LockTimeout,
+    /// Checked file or metadata doesn't match expectations
+    Mismatch,
+    /// Filesystem operation failed, e.g. publishing of metadata
+    Io,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let v = match self {
+            Self::Config => "Configuration error",
+            Self::LockTimeout => "Lock timeout",
+            Self::Mismatch => "Verification failed",
+            Self::Io => "I/O error",
+        };
+        write!(f, "{}", v)
+    }
+}
+
+impl Failure {
+    fn code(self) -> i32 {
+        match self {
+            Self::Config => CONFIG,
+            Self::LockTimeout => LOCK_TIMEOUT,
+            Self::Mismatch => MISMATCH,
+            Self::Io => IO,
+        }
+    }
+}
+
+static PARTIAL: AtomicBool = AtomicBool::new(false);
+
+/// Command succeeded, but skipped some of its inputs
+pub fn set_partial_failure() {
+    PARTIAL.store(true, Ordering::SeqCst)
+}
+
+pub fn of_error(err: &anyhow::Error) -> i32 {
+    if let Some(v) = err.downcast_ref::<Failure>() {
+        return v.code();
+    }
+    if err.chain().any(|v| v.is::<std::io::Error>()) {
+        return IO;
+    }
+    FAILURE
+}
+
+pub fn of_result(r: &anyhow::Result<()>) -> i32 {
+    match r {
+        Ok(()) if PARTIAL.load(Ordering::SeqCst) => PARTIAL_FAILURE,
+        Ok(()) => 0,
+        Err(err) => of_error(err),
+    }
+}
+
+#[test]
+fn test_of_error() {
+    use anyhow::Context;
+
+    let err = anyhow::Result::<()>::Err(anyhow::anyhow!("locked"))
+        .context(Failure::LockTimeout)
+        .context("Cannot generate")
+        .unwrap_err();
+    assert_eq!(of_error(&err), LOCK_TIMEOUT);
+
+    let err = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::NotFound))
+        .context("Cannot read");
+    assert_eq!(of_error(&err), IO);
+
+    let err = anyhow::Result::<()>::Err(anyhow::anyhow!("Cannot move"))
+        .context(Failure::Io)
+        .unwrap_err();
+    assert_eq!(of_error(&err), IO);
+
+    assert_eq!(of_error(&anyhow::anyhow!("other")), FAILURE);
+}
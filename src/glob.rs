@@ -0,0 +1,125 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Shell-like path pattern. `*` and `?` do not match `/`, `**` matches any number of directories.
+/// Pattern without `/` is matched against the last path component only.
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: String,
+    regex: regex::Regex,
+    basename_only: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let trimmed = pattern.trim_end_matches('/');
+        if trimmed.is_empty() {
+            bail!("Empty glob pattern {:?}", pattern)
+        }
+
+        let mut re = String::from("^");
+        let mut chars = trimmed.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' => {
+                    if chars.peek() == Some(&'*') {
+                        let _ = chars.next();
+                        if chars.peek() == Some(&'/') {
+                            let _ = chars.next();
+                            re.push_str("(?:.*/)?")
+                        } else {
+                            re.push_str(".*")
+                        }
+                    } else {
+                        re.push_str("[^/]*")
+                    }
+                }
+                '?' => re.push_str("[^/]"),
+                '[' => {
+                    re.push('[');
+                    if chars.peek() == Some(&'!') {
+                        let _ = chars.next();
+                        re.push('^');
+                    }
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(c @ ('\\' | '[' | '&' | '~')) => {
+                                re.push('\\');
+                                re.push(c)
+                            }
+                            Some(c) => re.push(c),
+                            None => bail!("Unclosed character class in glob {:?}", pattern),
+                        }
+                    }
+                    re.push(']');
+                }
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        re.push('$');
+
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            regex: regex::Regex::new(&re)?,
+            basename_only: !trimmed.contains('/'),
+        })
+    }
+
+    pub fn is_match(&self, path: &std::path::Path) -> bool {
+        if self.basename_only {
+            match path.file_name() {
+                Some(name) => self.regex.is_match(&name.to_string_lossy()),
+                None => false,
+            }
+        } else {
+            self.regex.is_match(&path.to_string_lossy())
+        }
+    }
+}
+
+impl std::str::FromStr for Glob {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl Serialize for Glob {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.pattern)
+    }
+}
+
+impl<'de> Deserialize<'de> for Glob {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+#[test]
+fn test_glob() {
+    let glob = Glob::new("incoming/").unwrap();
+    assert!(glob.is_match(std::path::Path::new("incoming")));
+    assert!(glob.is_match(std::path::Path::new("pool/incoming")));
+
+    let glob = Glob::new("pool/*.rpm").unwrap();
+    assert!(glob.is_match(std::path::Path::new("pool/foo-1.0-1.x86_64.rpm")));
+    assert!(!glob.is_match(std::path::Path::new("pool/a/foo-1.0-1.x86_64.rpm")));
+
+    let glob = Glob::new("pool/**/*.rpm").unwrap();
+    assert!(glob.is_match(std::path::Path::new("pool/foo.rpm")));
+    assert!(glob.is_match(std::path::Path::new("pool/a/b/foo.rpm")));
+
+    let glob = Glob::new("*-[0-9]*.src.rpm").unwrap();
+    assert!(glob.is_match(std::path::Path::new("a/foo-1.0-1.src.rpm")));
+    assert!(!glob.is_match(std::path::Path::new("a/foo-1.0-1.noarch.rpm")));
+}
@@ -0,0 +1,211 @@
+//! Logging backends configured in config file, each with its own level
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use slog::{o, Drain};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+fn default_level() -> crate::config::LogLevel {
+    crate::config::LogLevel::Info
+}
+
+fn default_facility() -> String {
+    "user".to_owned()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StderrFormat {
+    /// Timestamp, level and message on each line
+    #[default]
+    Full,
+    /// Messages grouped by their key-values
+    Compact,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LogBackend {
+    Syslog {
+        /// Facility name, e.g. user, daemon or local0
+        #[serde(default = "default_facility")]
+        facility: String,
+    },
+    Stderr {
+        #[serde(default)]
+        format: StderrFormat,
+    },
+    File {
+        path: std::path::PathBuf,
+    },
+    /// Native journald protocol, key-values of records are not sent
+    Journald,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LogBackendConfig {
+    #[serde(flatten)]
+    pub backend: LogBackend,
+    #[serde(default = "default_level")]
+    pub level: crate::config::LogLevel,
+    /// Use backend only if stderr is (true) or is not (false) a terminal, e.g. to log to stderr
+    /// when run by user and to syslog when run as service
+    #[serde(default)]
+    pub tty: Option<bool>,
+}
+
+type BoxedDrain = Box<dyn slog::SendSyncRefUnwindSafeDrain<Ok = (), Err = slog::Never>>;
+
+/// Record is passed to every backend, errors of backends are ignored
+struct Backends(Vec<BoxedDrain>);
+
+impl Drain for Backends {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        values: &slog::OwnedKVList,
+    ) -> std::result::Result<(), slog::Never> {
+        for drain in &self.0 {
+            let _ = drain.log(record, values);
+        }
+        Ok(())
+    }
+}
+
+struct Journald {
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+impl Drain for Journald {
+    type Ok = ();
+    type Err = std::io::Error;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        _values: &slog::OwnedKVList,
+    ) -> std::result::Result<(), std::io::Error> {
+        let priority = match record.level() {
+            slog::Level::Critical => 2,
+            slog::Level::Error => 3,
+            slog::Level::Warning => 4,
+            slog::Level::Info => 6,
+            slog::Level::Debug | slog::Level::Trace => 7,
+        };
+        let message = record.msg().to_string();
+        let mut data = format!("PRIORITY={}\nSYSLOG_IDENTIFIER=rpm-tool\n", priority).into_bytes();
+        // Binary safe form of field, message may contain newlines
+        data.extend(b"MESSAGE\n");
+        data.extend((message.len() as u64).to_le_bytes());
+        data.extend(message.as_bytes());
+        data.push(b'\n');
+        let _ = self.socket.send_to(&data, JOURNALD_SOCKET)?;
+        Ok(())
+    }
+}
+
+fn stderr_is_tty() -> bool {
+    unsafe { libc::isatty(libc::STDERR_FILENO) == 1 }
+}
+
+impl LogBackendConfig {
+    /// Backend without `tty` condition is always used
+    fn is_enabled(&self, tty: bool) -> bool {
+        self.tty.map(|v| v == tty).unwrap_or(true)
+    }
+
+    fn drain(&self) -> Result<BoxedDrain> {
+        let level: slog::Level = self.level.into();
+        let r: BoxedDrain = match &self.backend {
+            LogBackend::Syslog { facility } => {
+                let facility: slog_syslog::Facility = facility
+                    .parse()
+                    .map_err(|_| anyhow!("Unknown syslog facility {:?}", facility))?;
+                let drain = slog_syslog::SyslogBuilder::new()
+                    .facility(facility)
+                    .level(level)
+                    .unix(crate::platform::SYSLOG_SOCKET)
+                    .start()?;
+                Box::new(slog::LevelFilter::new(drain, level).ignore_res())
+            }
+            LogBackend::Stderr { format } => {
+                let decorator = slog_term::PlainSyncDecorator::new(std::io::stderr());
+                match format {
+                    StderrFormat::Full => Box::new(
+                        slog::LevelFilter::new(
+                            std::sync::Mutex::new(slog_term::FullFormat::new(decorator).build()),
+                            level,
+                        )
+                        .ignore_res(),
+                    ),
+                    StderrFormat::Compact => Box::new(
+                        slog::LevelFilter::new(
+                            std::sync::Mutex::new(slog_term::CompactFormat::new(decorator).build()),
+                            level,
+                        )
+                        .ignore_res(),
+                    ),
+                }
+            }
+            LogBackend::File { path } => {
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|err| anyhow!("Cannot open log file {:?}: {}", path, err))?;
+                let decorator = slog_term::PlainSyncDecorator::new(file);
+                Box::new(
+                    slog::LevelFilter::new(
+                        std::sync::Mutex::new(slog_term::FullFormat::new(decorator).build()),
+                        level,
+                    )
+                    .ignore_res(),
+                )
+            }
+            LogBackend::Journald => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()
+                    .map_err(|err| anyhow!("Cannot create socket for journald: {}", err))?;
+                Box::new(slog::LevelFilter::new(Journald { socket }, level).ignore_res())
+            }
+        };
+        Ok(r)
+    }
+}
+
+/// Set global logger writing to backends enabled for current terminal state
+pub fn init(backends: &[LogBackendConfig]) -> Result<slog_scope::GlobalLoggerGuard> {
+    let tty = stderr_is_tty();
+    let drains = backends
+        .iter()
+        .filter(|v| v.is_enabled(tty))
+        .map(|v| v.drain())
+        .collect::<Result<Vec<_>>>()?;
+    let logger = slog::Logger::root(Backends(drains), o!());
+    Ok(slog_scope::set_global_logger(logger))
+}
+
+#[test]
+fn test_is_enabled() {
+    let backends: Vec<LogBackendConfig> = serde_yaml::from_str(
+        "- type: stderr\n  tty: true\n- type: syslog\n  tty: false\n- type: journald\n",
+    )
+    .unwrap();
+    let enabled = |tty: bool| {
+        backends
+            .iter()
+            .filter(|v| v.is_enabled(tty))
+            .map(|v| match v.backend {
+                LogBackend::Syslog { .. } => "syslog",
+                LogBackend::Stderr { .. } => "stderr",
+                LogBackend::File { .. } => "file",
+                LogBackend::Journald => "journald",
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(enabled(true), ["stderr", "journald"]);
+    assert_eq!(enabled(false), ["syslog", "journald"]);
+}
@@ -1,14 +1,30 @@
 use std::fmt;
+use std::io::Write;
 
-use anyhow::{anyhow, Context, Result};
-use clap::{Args, Parser, Subcommand};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use slog::{o, Drain};
-use slog_scope::error;
+use slog_scope::{error, info};
 
 mod config;
+mod cpio;
+mod decompress;
 pub mod digest;
+pub mod evr;
+mod exit_code;
+pub mod glob;
 pub mod lazy_result;
+mod logging;
+#[cfg(feature = "otlp")]
+mod otlp;
+mod platform;
+mod progress;
 mod repodata;
+mod rpm_dump;
+mod secret;
+mod summary;
+mod table;
+mod trace;
 
 const CONFIG_DEFAULT_PATH: &str = "/etc/rpm-tool.yaml";
 
@@ -17,152 +33,1672 @@ enum DumpFormat {
     Yaml,
     Json,
     RepodataXml,
+    Table,
+    Msgpack,
+    Cbor,
 }
 
 impl DumpFormat {
-    pub fn dump<T>(&self, v: &T) -> Result<String>
+    pub fn dump<T>(&self, v: &T) -> Result<Vec<u8>>
     where
         T: serde::Serialize,
     {
         let r = match self {
-            DumpFormat::Yaml => serde_yaml::to_string(v)?,
-            DumpFormat::Json => serde_json::to_string(v)?,
-            DumpFormat::RepodataXml => quick_xml::se::to_string(v)?,
+            DumpFormat::Yaml => serde_yaml::to_string(v)?.into_bytes(),
+            DumpFormat::Json => serde_json::to_vec(v)?,
+            DumpFormat::RepodataXml => quick_xml::se::to_string(v)?.into_bytes(),
+            DumpFormat::Table => crate::table::render(v)?.into_bytes(),
+            DumpFormat::Msgpack => rmp_serde::to_vec_named(v)?,
+            DumpFormat::Cbor => {
+                let mut r = Vec::new();
+                ciborium::ser::into_writer(v, &mut r)
+                    .map_err(|err| anyhow!("Cannot encode CBOR: {:?}", err))?;
+                r
+            }
+        };
+        Ok(r)
+    }
+
+    /// Record of stream. YAML documents are separated by "---", JSON records are written one per
+    /// line, binary records are concatenated
+    pub fn dump_record<T>(&self, v: &T) -> Result<Vec<u8>>
+    where
+        T: serde::Serialize,
+    {
+        let mut r = match self {
+            DumpFormat::Yaml => b"---\n".to_vec(),
+            _ => Vec::new(),
         };
+        r.extend(self.dump(v)?);
         Ok(r)
     }
+
+    /// Binary output is written without trailing newline
+    pub fn is_binary(&self) -> bool {
+        matches!(self, DumpFormat::Msgpack | DumpFormat::Cbor)
+    }
+}
+
+impl fmt::Display for DumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Output format of informational commands
+#[derive(Args)]
+struct OutputArgs {
+    /// Output format, default depends on command
+    #[arg(
+        short = 'o',
+        long = "output",
+        visible_alias = "format",
+        short_alias = 'f',
+        value_enum
+    )]
+    output: Option<DumpFormat>,
+}
+
+impl OutputArgs {
+    pub fn print<T>(&self, v: &T, default: DumpFormat) -> Result<()>
+    where
+        T: serde::Serialize,
+    {
+        let format = self.output.as_ref().unwrap_or(&default);
+        Self::write(format, &format.dump(v)?)
+    }
+
+    /// Print one record of stream, records are written as soon as they are produced
+    pub fn print_record<T>(&self, v: &T, default: DumpFormat) -> Result<()>
+    where
+        T: serde::Serialize,
+    {
+        let format = self.output.as_ref().unwrap_or(&default);
+        Self::write(format, &format.dump_record(v)?)
+    }
+
+    fn write(format: &DumpFormat, data: &[u8]) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(data)?;
+        if !format.is_binary() {
+            stdout.write_all(b"\n")?;
+        }
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Dump metadata of RPM files. Metadata of several files or directories is written as stream of
+/// records: multi-document YAML, newline-delimited JSON or concatenated binary records
+#[derive(Args)]
+struct CmdRpmDump {
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Include digest, size, mode, owner and flags of each file
+    #[clap(long)]
+    file_details: bool,
+    /// Fail unless package signature is valid and made by the key. Public key file, ASCII armored
+    /// or binary, signatures are checked by rpmkeys
+    #[clap(long)]
+    require_key: Option<std::path::PathBuf>,
+    /// Fail unless every regular file has IMA or fsverity signature
+    #[clap(long, value_enum)]
+    require_file_signatures: Option<crate::rpm_dump::FileSignatureKind>,
+    /// RPM files or directories to search for RPM files recursively
+    #[clap(required = true)]
+    files: Vec<std::path::PathBuf>,
+}
+
+/// Files are taken as is, directories are searched for *.rpm recursively
+fn expand_rpm_files(paths: &[std::path::PathBuf]) -> Result<Vec<std::path::PathBuf>> {
+    let mut r = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            r.push(path.clone());
+            continue;
+        }
+        let mut found = Vec::new();
+        for entry in walkdir::WalkDir::new(path) {
+            let entry = entry?;
+            if entry.file_type().is_file()
+                && entry.path().extension() == Some(std::ffi::OsStr::new("rpm"))
+            {
+                found.push(entry.into_path())
+            }
+        }
+        found.sort();
+        r.extend(found);
+    }
+    Ok(r)
+}
+
+fn read_rpm_file(
+    file: &std::path::Path,
+) -> Result<(rpm::RPMPackage, crate::repodata::primary::Package)> {
+    let mut rpm_file = std::fs::File::open(file)?;
+    let mut buf_reader = std::io::BufReader::new(&rpm_file);
+    let pkg =
+        rpm::RPMPackage::parse(&mut buf_reader).map_err(|err| anyhow!("{}", err.to_string()))?;
+
+    let file_sha = crate::digest::file_sha128(&mut rpm_file)?;
+    let rpm = crate::repodata::primary::Package::of_rpm_package(
+        &pkg,
+        file.parent().unwrap(),
+        file,
+        &file_sha,
+        &regex::Regex::new(".*").unwrap(),
+        None,
+        // Header values are shown as they are, unreadable ones are errors
+        true,
+    )?;
+    Ok((pkg, rpm))
+}
+
+impl CmdRpmDump {
+    /// Returns dump and files without required signatures
+    fn dump(
+        &self,
+        file: &std::path::Path,
+    ) -> Result<(crate::rpm_dump::Dump, Vec<std::path::PathBuf>)> {
+        let (pkg, rpm) = read_rpm_file(file)?;
+        let file_details = if self.file_details || self.require_file_signatures.is_some() {
+            crate::rpm_dump::FileDetails::of_package(&pkg)?
+        } else {
+            Vec::new()
+        };
+        let unsigned = match &self.require_file_signatures {
+            Some(kind) => crate::rpm_dump::unsigned_files(&file_details, kind),
+            None => Vec::new(),
+        };
+        let dump = crate::rpm_dump::Dump {
+            package: rpm,
+            payload: crate::rpm_dump::PayloadInfo::of_package(&pkg),
+            signatures: crate::rpm_dump::SignatureInfo::of_package(&pkg),
+            file_signatures: crate::rpm_dump::FileSignatureSummary::of_package(&pkg),
+            file_details: if self.file_details {
+                Some(file_details)
+            } else {
+                None
+            },
+        };
+        Ok((dump, unsigned))
+    }
+
+    /// Record of primary.xml has no place for payload, signatures and file details, so only the
+    /// package is written as repodata XML
+    fn print(&self, dump: &crate::rpm_dump::Dump, record: bool) -> Result<()> {
+        match (&self.output.output, record) {
+            (Some(DumpFormat::RepodataXml), false) => {
+                self.output.print(&dump.package, DumpFormat::Yaml)
+            }
+            (Some(DumpFormat::RepodataXml), true) => {
+                self.output.print_record(&dump.package, DumpFormat::Yaml)
+            }
+            (_, false) => self.output.print(dump, DumpFormat::Yaml),
+            (_, true) => self.output.print_record(dump, DumpFormat::Yaml),
+        }
+    }
+
+    fn check(
+        &self,
+        file: &std::path::Path,
+        verifier: Option<&crate::repodata::signature::RpmVerifier>,
+        unsigned: &[std::path::PathBuf],
+    ) -> Result<()> {
+        if let Some(verifier) = verifier {
+            verifier.verify(file)?
+        }
+        if let (Some(kind), Some(first)) = (&self.require_file_signatures, unsigned.first()) {
+            return Err(anyhow!(
+                "{} files of package {:?} have no {:?} signature, e.g. {:?}",
+                unsigned.len(),
+                file,
+                kind,
+                first
+            )
+            .context(crate::exit_code::Failure::Mismatch));
+        }
+        Ok(())
+    }
+
+    fn run(&self) -> Result<()> {
+        let verifier = match &self.require_key {
+            Some(key) => Some(
+                crate::repodata::signature::GpgConfig {
+                    key_files: vec![key.clone()],
+                    ..Default::default()
+                }
+                .verifier()?
+                .rpm_verifier()?,
+            ),
+            None => None,
+        };
+
+        if let [file] = self.files.as_slice() {
+            if !file.is_dir() {
+                let (dump, unsigned) = self.dump(file)?;
+                self.print(&dump, false)?;
+                return self.check(file, verifier.as_ref(), &unsigned);
+            }
+        }
+
+        let mut failed = 0;
+        let mut mismatched = 0;
+        for file in expand_rpm_files(&self.files)? {
+            let r = self.dump(&file).and_then(|(dump, unsigned)| {
+                self.print(&dump, true)?;
+                self.check(&file, verifier.as_ref(), &unsigned)
+            });
+            if let Err(err) = r {
+                error!("{:?}: {:#}", file, err);
+                failed += 1;
+                if crate::exit_code::of_error(&err) == crate::exit_code::MISMATCH {
+                    mismatched += 1
+                }
+            }
+        }
+        if failed > 0 && failed == mismatched {
+            return Err(anyhow!("{} files failed verification", failed)
+                .context(crate::exit_code::Failure::Mismatch));
+        }
+        if failed > 0 {
+            bail!("{} files failed", failed)
+        }
+        Ok(())
+    }
+}
+
+/// Show information about RPM file as text, like "rpm -qi" does
+#[derive(Args)]
+struct CmdRpmInfo {
+    file: std::path::PathBuf,
+}
+
+impl CmdRpmInfo {
+    fn run(&self) -> Result<()> {
+        let (pkg, rpm) = read_rpm_file(&self.file)?;
+        let signatures = crate::rpm_dump::SignatureInfo::of_package(&pkg);
+        println!("{}", crate::rpm_dump::info(&rpm, &signatures));
+        Ok(())
+    }
+}
+
+/// Show SHA256 of every file of payload, e.g. to compare two builds of the same package
+#[derive(Args)]
+struct CmdRpmPayloadDigests {
+    #[clap(flatten)]
+    output: OutputArgs,
+    file: std::path::PathBuf,
+}
+
+impl CmdRpmPayloadDigests {
+    fn run(&self) -> Result<()> {
+        let mut buf_reader = std::io::BufReader::new(
+            std::fs::File::open(&self.file)
+                .map_err(|err| anyhow!("Cannot open {:?}: {}", self.file, err))?,
+        );
+        let pkg = rpm::RPMPackage::parse(&mut buf_reader)
+            .map_err(|err| anyhow!("{}", err.to_string()))?;
+        let digests = crate::rpm_dump::payload_digests(&pkg)?;
+        self.output.print(&digests, DumpFormat::Yaml)
+    }
+}
+
+/// Check RPM file against repository before publishing: whether it is newer than packages of
+/// repository, is already published and its requirements are satisfiable. Exits with error if
+/// package should not be published
+#[derive(Args)]
+struct CmdRpmCheckAgainstRepo {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Repository directory or URL of remote repository, remote metadata is fetched with curl
+    #[clap(long)]
+    repo: String,
+    file: std::path::PathBuf,
+}
+
+impl CmdRpmCheckAgainstRepo {
+    fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let (_, rpm) = read_rpm_file(&self.file)?;
+        let types = [
+            crate::digest::DigestType::Sha1,
+            crate::digest::DigestType::Sha256,
+        ];
+        let digests = crate::digest::path_digests(&self.file, &types)
+            .map_err(|err| anyhow!("Cannot read {:?}: {}", self.file, err))?;
+        let checksums: Vec<_> = types
+            .iter()
+            .map(|v| v.name())
+            .zip(digests.into_iter())
+            .collect();
+
+        let tempdir = if self.repo.starts_with("http://") || self.repo.starts_with("https://") {
+            Some(config.repodata.scratch_tempdir("rpm-tool-repo")?)
+        } else {
+            None
+        };
+        let path = match &tempdir {
+            Some(tempdir) => {
+                let token = match &config.repodata.http_token {
+                    Some(v) => Some(v.resolve()?),
+                    None => None,
+                };
+                crate::repodata::package_check::fetch_metadata(
+                    &self.repo,
+                    tempdir.path(),
+                    token.as_deref(),
+                )?;
+                tempdir.path().to_path_buf()
+            }
+            None => std::path::PathBuf::from(&self.repo),
+        };
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: crate::repodata::RepodataOptions {
+                no_lock: self.lock.no_lock,
+                path,
+                ..Default::default()
+            },
+        };
+
+        let report = repodata.check_package(&rpm, &checksums)?;
+        self.output.print(&report, DumpFormat::Yaml)?;
+        let problems = report.problems();
+        if !problems.is_empty() {
+            return Err(
+                anyhow!("Package should not be published: {}", problems.join("; "))
+                    .context(crate::exit_code::Failure::Mismatch),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Compare two versions in [epoch:]version[-release] form by rpm rules
+#[derive(Args)]
+struct CmdRpmVercmp {
+    a: crate::evr::Evr,
+    b: crate::evr::Evr,
+}
+
+impl CmdRpmVercmp {
+    fn run(&self) -> Result<()> {
+        let op = match self.a.compare(&self.b) {
+            std::cmp::Ordering::Less => "<",
+            std::cmp::Ordering::Equal => "==",
+            std::cmp::Ordering::Greater => ">",
+        };
+        println!("{} {} {}", self.a, op, self.b);
+        Ok(())
+    }
+}
+
+/// Operations on single RPM file
+#[derive(Subcommand)]
+enum CmdRpm {
+    Dump(CmdRpmDump),
+    Info(CmdRpmInfo),
+    PayloadDigests(CmdRpmPayloadDigests),
+    CheckAgainstRepo(CmdRpmCheckAgainstRepo),
+    Vercmp(CmdRpmVercmp),
+}
+
+impl CmdRpm {
+    fn run(&self, config: &crate::config::Config) -> Result<()> {
+        match self {
+            CmdRpm::Dump(v) => v.run(),
+            CmdRpm::Info(v) => v.run(),
+            CmdRpm::PayloadDigests(v) => v.run(),
+            CmdRpm::CheckAgainstRepo(v) => v.run(config),
+            CmdRpm::Vercmp(v) => v.run(),
+        }
+    }
+}
+
+/// Restrict files to be indexed
+#[derive(Args)]
+struct ScanFilterArgs {
+    /// Index only files matching glob pattern (relative to repository root). Repeatable
+    #[clap(long)]
+    include: Vec<crate::glob::Glob>,
+    /// Skip files and directories matching glob pattern (relative to repository root). Repeatable
+    #[clap(long)]
+    exclude: Vec<crate::glob::Glob>,
+    /// Maximum depth of files relative to repository root, 1 means top-level files only
+    #[clap(long)]
+    max_depth: Option<usize>,
+}
+
+impl From<&ScanFilterArgs> for crate::repodata::ScanFilter {
+    fn from(v: &ScanFilterArgs) -> Self {
+        Self {
+            include: v.include.clone(),
+            exclude: v.exclude.clone(),
+            max_depth: v.max_depth,
+        }
+    }
+}
+
+/// Locking of read-only commands
+#[derive(Args)]
+struct LockArgs {
+    /// Don't take shared lock on repository (see shared_for_readers of lock section of repodata config), e.g. when
+    /// reading snapshot copy of repository
+    #[clap(long)]
+    no_lock: bool,
+}
+
+/// Backpressure of indexing
+#[derive(Args)]
+struct MemoryArgs {
+    /// Resident memory limit, e.g. 4G. Above 80% of it fileslists records are spilled to disk and packages
+    /// are processed one at a time, if limit is still reached, generation stops without updating metadata
+    #[clap(long, value_parser = crate::repodata::memory::parse_size)]
+    max_memory: Option<u64>,
+}
+
+/// Handling of unreadable header values
+#[derive(Args)]
+struct StrictArgs {
+    /// Fail on packages with unreadable header values (build time, sizes, summary, description, file
+    /// entries) instead of indexing them with defaults. Unreadable dependencies always fail
+    #[clap(long)]
+    strict: bool,
+}
+
+/// Restrict packages by header fields
+#[derive(Args)]
+struct PackageFilterArgs {
+    /// Comma separated list of architectures. noarch packages are always included
+    #[clap(long, value_delimiter = ',')]
+    arch: Vec<String>,
+    /// Index only packages with name or NEVRA matching glob pattern. Repeatable
+    #[clap(long)]
+    include_pkg: Vec<crate::glob::Glob>,
+    /// Skip packages with name or NEVRA matching glob pattern. Repeatable
+    #[clap(long)]
+    exclude_pkg: Vec<crate::glob::Glob>,
+}
+
+impl From<&PackageFilterArgs> for crate::repodata::PackageFilter {
+    fn from(v: &PackageFilterArgs) -> Self {
+        Self {
+            arch: v.arch.clone(),
+            include: v.include_pkg.clone(),
+            exclude: v.exclude_pkg.clone(),
+        }
+    }
+}
+
+/// Generate RPM repository in given directory
+#[derive(Args)]
+struct CmdRepositoryGenerate {
+    #[clap(flatten)]
+    package_filter: PackageFilterArgs,
+    /// Index only N newest versions of each package (1 if N is omitted), older files stay on disk
+    #[clap(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+    latest_only: Option<usize>,
+    /// Move debuginfo and debugsource packages into debug/ subdirectory and publish them in separate repository there
+    #[clap(long)]
+    split_debug: bool,
+    /// Report records of current metadata about packages which no longer exist on disk, they are not published
+    #[clap(long)]
+    prune_missing: bool,
+    #[clap(long)]
+    fileslists: bool,
+    /// Follow symlinks to RPM files and directories
+    #[clap(long)]
+    follow_symlinks: bool,
+    #[clap(flatten)]
+    scan_filter: ScanFilterArgs,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    /// Validate generated metadata against RELAX NG schemas before publishing it
+    #[clap(long)]
+    self_check: bool,
+    #[clap(flatten)]
+    strict: StrictArgs,
+    /// What to do with packages which can't be indexed: skip them, fail on first one, or fail on N-th one
+    /// (max:N). Metadata is not updated when generation fails
+    #[clap(long, value_name = "skip|fail|max:N", default_value = "skip")]
+    on_error: crate::repodata::error_policy::ErrorPolicy,
+    #[clap(flatten)]
+    memory: MemoryArgs,
+    /// Reuse records of packages saved to checkpoint by interrupted run
+    #[clap(long)]
+    resume: bool,
+    /// Index only i-th of N deterministic subsets of packages into .shards/, see "merge-shards"
+    #[clap(long, value_name = "i/N", requires = "run_id")]
+    shard: Option<crate::repodata::shard::Shard>,
+    /// Identifier of sharded run, the same for all shards, e.g. CI pipeline ID
+    #[clap(long, requires = "shard")]
+    run_id: Option<String>,
+    /// Write repodata/ to this directory instead of package tree, e.g. when packages are on read-only storage.
+    /// Location hrefs stay relative to package tree
+    #[clap(long)]
+    output_path: Option<std::path::PathBuf>,
+    /// Generate all repositories listed in "repositories" of configuration file instead of one at given path.
+    /// Settings of each repository are added to other options
+    #[clap(long, conflicts_with_all = ["path", "output_path", "shard"])]
+    all: bool,
+    /// Number of repositories generated simultaneously with --all
+    #[clap(long, default_value_t = 1)]
+    jobs: usize,
+    #[clap(required_unless_present = "all")]
+    path: Option<std::path::PathBuf>,
+}
+
+impl From<&CmdRepositoryGenerate> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryGenerate) -> Self {
+        Self {
+            latest_only: v.latest_only,
+            split_debug: v.split_debug,
+            prune_missing: v.prune_missing,
+            package_filter: (&v.package_filter).into(),
+            generate_fileslists: v.fileslists,
+            follow_symlinks: v.follow_symlinks,
+            scan_filter: (&v.scan_filter).into(),
+            timestamp: v.timestamp,
+            self_check: v.self_check,
+            strict: v.strict.strict,
+            on_error: v.on_error,
+            max_memory: v.memory.max_memory,
+            resume: v.resume,
+            shard: v.shard,
+            shard_run_id: v.run_id.clone(),
+            no_lock: false,
+            output_path: v.output_path.clone(),
+            path: v.path.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl CmdRepositoryGenerate {
+    fn run_all(&self, config: &crate::config::Config) -> Result<()> {
+        if config.repositories.is_empty() {
+            bail!("No repositories are listed in configuration file")
+        }
+        let summaries = crate::repodata::profiles::generate_all(
+            &config.repodata,
+            &config.repositories,
+            self.jobs,
+            |profile| {
+                let mut options: crate::repodata::RepodataOptions = self.into();
+                profile.apply(&mut options);
+                options
+            },
+        )?;
+        let mut failed = 0;
+        for summary in &summaries {
+            match &summary.error {
+                None => info!(
+                    "Repository {:?} generated in {} secs",
+                    summary.name, summary.seconds
+                ),
+                Some(err) => {
+                    failed += 1;
+                    error!(
+                        "Repository {:?} failed in {} secs: {}",
+                        summary.name, summary.seconds, err
+                    )
+                }
+            }
+        }
+        if failed > 0 {
+            bail!("{} of {} repositories failed", failed, summaries.len())
+        }
+        Ok(())
+    }
+
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        if self.all {
+            return self.run_all(config);
+        }
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.generate()
+    }
+}
+
+/// Combine partial metadata of all shards generated with "generate --shard" into repository metadata
+#[derive(Args)]
+struct CmdRepositoryMergeShards {
+    #[clap(long)]
+    fileslists: bool,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    /// Validate generated metadata against RELAX NG schemas before publishing it
+    #[clap(long)]
+    self_check: bool,
+    /// Merge only shards of this run, by default all shards must be of the same run
+    #[clap(long)]
+    run_id: Option<String>,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryMergeShards> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryMergeShards) -> Self {
+        Self {
+            generate_fileslists: v.fileslists,
+            timestamp: v.timestamp,
+            self_check: v.self_check,
+            shard_run_id: v.run_id.clone(),
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryMergeShards {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.merge_shards()
+    }
+}
+
+/// Generate metadata, then keep rescanning repository tree and regenerate metadata on changes
+#[derive(Args)]
+struct CmdRepositoryWatch {
+    /// Period of scans, e.g. 30s, 15m or 1h. Default is repodata→watch→interval from config, or 1m
+    #[clap(long, value_parser = crate::repodata::watch::parse_interval)]
+    interval: Option<std::time::Duration>,
+    /// Serve /healthz and /readyz on this address, e.g. 0.0.0.0:9732
+    #[clap(long)]
+    health_listen: Option<String>,
+    #[clap(flatten)]
+    generate: CmdRepositoryGenerate,
+}
+
+impl CmdRepositoryWatch {
+    pub fn run(&self, config: &crate::config::Config, config_path: &str) -> Result<()> {
+        if self.generate.all {
+            bail!("--all is not supported by watch")
+        }
+        let interval = match (self.interval, config.repodata.watch.interval) {
+            (Some(v), _) => v,
+            (None, Some(v)) => std::time::Duration::from_secs(v.max(1)),
+            (None, None) => std::time::Duration::from_secs(60),
+        };
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: (&self.generate).into(),
+        };
+        let mut reloader = crate::config::Reloader::new(config_path, &config.overrides)?;
+        let health = crate::repodata::health::Health::default().with_lock_path(
+            repodata
+                .options
+                .metadata_root(&repodata.options.path)
+                .join("repodata")
+                .join("repomd.xml"),
+        );
+        let listener = match &self.health_listen {
+            Some(listen) => Some(crate::repodata::health::bind(listen)?),
+            None => None,
+        };
+        let stop = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            if let Some(listener) = &listener {
+                let _ = scope.spawn(|| {
+                    crate::repodata::health::serve(listener, &stop, &|path| health.respond(path))
+                });
+            }
+            let r = repodata.watch(
+                interval,
+                &mut || {
+                    let config = reloader.poll()?;
+                    match Application::init_logger(config) {
+                        // Logger stays installed after guard is dropped
+                        Ok(guard) => guard.cancel_reset(),
+                        Err(err) => error!("Cannot reinitialize logger: {}", err),
+                    }
+                    Some(config.repodata.watch.clone())
+                },
+                &health,
+            );
+            stop.store(true, std::sync::atomic::Ordering::SeqCst);
+            r
+        })
+    }
+}
+
+/// Generate repositories of all variants and architectures described in manifest
+#[derive(Args)]
+struct CmdRepositoryCompose {
+    /// YAML file with variants, their architectures and package filters
+    #[clap(long)]
+    manifest: std::path::PathBuf,
+    /// Index only N newest versions of each package (1 if N is omitted), older files stay on disk
+    #[clap(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+    latest_only: Option<usize>,
+    #[clap(long)]
+    fileslists: bool,
+    /// Follow symlinks to RPM files and directories
+    #[clap(long)]
+    follow_symlinks: bool,
+    #[clap(flatten)]
+    scan_filter: ScanFilterArgs,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    /// Validate generated metadata against RELAX NG schemas before publishing it
+    #[clap(long)]
+    self_check: bool,
+    #[clap(flatten)]
+    strict: StrictArgs,
+    #[clap(flatten)]
+    memory: MemoryArgs,
+    path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryCompose> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryCompose) -> Self {
+        Self {
+            latest_only: v.latest_only,
+            generate_fileslists: v.fileslists,
+            follow_symlinks: v.follow_symlinks,
+            scan_filter: (&v.scan_filter).into(),
+            timestamp: v.timestamp,
+            self_check: v.self_check,
+            strict: v.strict.strict,
+            max_memory: v.memory.max_memory,
+            path: v.path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryCompose {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let manifest = crate::repodata::compose::ComposeManifest::read(&self.manifest)?;
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.compose(&manifest)
+    }
+}
+
+/// Validate RPM files, copy them into repository according to repodata→upload→layout and add them to
+/// index
+#[derive(Args)]
+struct CmdRepositoryUpload {
+    /// Move files instead of copying
+    #[clap(long = "move")]
+    move_files: bool,
+    /// Refuse packages not signed by keys configured in repodata→gpg
+    #[clap(long)]
+    require_signature: bool,
+    /// Replace files already existing in repository
+    #[clap(long)]
+    force: bool,
+    #[clap(long)]
+    fileslists: bool,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    /// Validate generated metadata against RELAX NG schemas before publishing it
+    #[clap(long)]
+    self_check: bool,
+    #[clap(flatten)]
+    strict: StrictArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    #[clap(required = true)]
+    file_path: Vec<std::path::PathBuf>,
+}
+
+impl From<&CmdRepositoryUpload> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryUpload) -> Self {
+        Self {
+            generate_fileslists: v.fileslists,
+            timestamp: v.timestamp,
+            self_check: v.self_check,
+            strict: v.strict.strict,
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryUpload {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.upload(
+            &self.file_path,
+            self.move_files,
+            self.require_signature,
+            self.force,
+        )
+    }
+}
+
+/// Remove packages from metadata and repository. With repodata→trash configured files are moved to .trash/
+#[derive(Args)]
+struct CmdRepositoryRemove {
+    /// Delete files even if trash is configured
+    #[clap(long)]
+    no_trash: bool,
+    #[clap(long)]
+    fileslists: bool,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    /// Glob patterns matched against package name or NEVRA
+    #[clap(required = true)]
+    pattern: Vec<crate::glob::Glob>,
+}
+
+impl From<&CmdRepositoryRemove> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryRemove) -> Self {
+        Self {
+            generate_fileslists: v.fileslists,
+            timestamp: v.timestamp,
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryRemove {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.remove(&self.pattern, !self.no_trash)
+    }
+}
+
+/// Delete removed packages kept in trash longer than repodata→trash→retention
+#[derive(Args)]
+struct CmdRepositoryPurgeTrash {
+    /// Delete all packages in trash regardless of retention
+    #[clap(long)]
+    all: bool,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryPurgeTrash> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryPurgeTrash) -> Self {
+        Self {
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryPurgeTrash {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.purge_trash(self.all)
+    }
+}
+
+/// Move indexed packages into Packages/<first letter>/<name>-<version>-<release>.<arch>.rpm and
+/// regenerate metadata
+#[derive(Args)]
+struct CmdRepositoryOrganize {
+    /// Only print planned moves
+    #[clap(long)]
+    dry_run: bool,
+    #[clap(long)]
+    fileslists: bool,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryOrganize> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryOrganize) -> Self {
+        Self {
+            generate_fileslists: v.fileslists,
+            timestamp: v.timestamp,
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryOrganize {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.organize(self.dry_run)
+    }
+}
+
+/// Add given files to repository index
+#[derive(Args)]
+struct CmdRepositoryAddFiles {
+    #[clap(flatten)]
+    package_filter: PackageFilterArgs,
+    #[clap(long)]
+    fileslists: bool,
+    #[clap(flatten)]
+    scan_filter: ScanFilterArgs,
+    /// Remove records about packages which no longer exist on disk
+    #[clap(long)]
+    prune_missing: bool,
+    /// Add debug packages to debug repository, the same way "generate --split-debug" does
+    #[clap(long)]
+    split_debug: bool,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    /// Validate generated metadata against RELAX NG schemas before publishing it
+    #[clap(long)]
+    self_check: bool,
+    #[clap(flatten)]
+    strict: StrictArgs,
+    /// What to do with packages which can't be indexed: skip them, fail on first one, or fail on N-th one
+    /// (max:N). Metadata is not updated when indexing fails
+    #[clap(long, value_name = "skip|fail|max:N", default_value = "skip")]
+    on_error: crate::repodata::error_policy::ErrorPolicy,
+    #[clap(flatten)]
+    memory: MemoryArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    file_path: Vec<std::path::PathBuf>,
+}
+
+impl From<&CmdRepositoryAddFiles> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryAddFiles) -> Self {
+        Self {
+            package_filter: (&v.package_filter).into(),
+            generate_fileslists: v.fileslists,
+            scan_filter: (&v.scan_filter).into(),
+            prune_missing: v.prune_missing,
+            split_debug: v.split_debug,
+            timestamp: v.timestamp,
+            self_check: v.self_check,
+            strict: v.strict.strict,
+            on_error: v.on_error,
+            max_memory: v.memory.max_memory,
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryAddFiles {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.add_files(&self.file_path)
+    }
+}
+
+/// Fix inconsistent metadata without full rescan: recalculate records with mismatched checksums,
+/// generate missing fileslists and rewrite repomd.xml
+#[derive(Args)]
+struct CmdRepositoryRepair {
+    #[clap(long)]
+    fileslists: bool,
+    /// Fixed UNIX timestamp for reproducible metadata, overrides SOURCE_DATE_EPOCH
+    #[clap(long)]
+    timestamp: Option<u64>,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryRepair> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryRepair) -> Self {
+        Self {
+            generate_fileslists: v.fileslists,
+            timestamp: v.timestamp,
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryRepair {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.repair()
+    }
+}
+
+/// Validate repository index
+#[derive(Args)]
+struct CmdRepositoryValidate {
+    #[clap(flatten)]
+    output: OutputArgs,
+    #[clap(long)]
+    fileslists: bool,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryValidate> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryValidate) -> Self {
+        Self {
+            generate_fileslists: v.fileslists,
+            path: v.repository_path.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryValidate {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let validation = repodata.validate()?;
+        self.output.print(&validation, DumpFormat::Yaml)?;
+        if !validation.is_ok() {
+            return Err(anyhow!(
+                "{} packages listed in metadata are missing on disk",
+                validation.missing.len()
+            )
+            .context(crate::exit_code::Failure::Mismatch));
+        }
+        Ok(())
+    }
+}
+
+/// Check single file against its record in repository metadata: checksum, size and mtime of
+/// package or checksum, size and open-checksum of metadata file
+#[derive(Args)]
+struct CmdRepositoryVerifyFile {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    /// Path relative to repository root or starting with repository path
+    file: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryVerifyFile> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryVerifyFile) -> Self {
+        Self {
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryVerifyFile {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let verification = repodata.verify_file(&self.file)?;
+        self.output.print(&verification, DumpFormat::Yaml)?;
+        if !verification.is_ok() {
+            return Err(anyhow!(
+                "{} doesn't match metadata: {}",
+                verification.location,
+                verification
+                    .mismatches
+                    .iter()
+                    .map(|v| v.attribute.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .context(crate::exit_code::Failure::Mismatch));
+        }
+        Ok(())
+    }
+}
+
+/// Validate repository metadata against RELAX NG schemas
+#[derive(Args)]
+struct CmdRepositoryValidateSchema {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryValidateSchema> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryValidateSchema) -> Self {
+        Self {
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryValidateSchema {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        repodata.validate_schema()
+    }
+}
+
+/// Find packages providing capability or file, e.g. 'libfoo.so.1()(64bit)' or 'foo >= 1.2'
+#[derive(Args)]
+struct CmdRepositoryWhatProvides {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    package_filter: PackageFilterArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    capability: String,
+}
+
+impl From<&CmdRepositoryWhatProvides> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryWhatProvides) -> Self {
+        Self {
+            package_filter: (&v.package_filter).into(),
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryWhatProvides {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let found = repodata.whatprovides(&self.capability)?;
+        self.output.print(&found, DumpFormat::Table)
+    }
+}
+
+/// Find packages requiring package or capability
+#[derive(Args)]
+struct CmdRepositoryWhatRequires {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    package_filter: PackageFilterArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    /// Also find packages requiring found packages
+    #[clap(long)]
+    recursive: bool,
+    capability: String,
+}
+
+impl From<&CmdRepositoryWhatRequires> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryWhatRequires) -> Self {
+        Self {
+            package_filter: (&v.package_filter).into(),
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryWhatRequires {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let found = repodata.whatrequires(&self.capability, self.recursive)?;
+        self.output.print(&found, DumpFormat::Table)
+    }
+}
+
+/// Find packages owning files matched by glob, e.g. '/usr/lib64/libfoo.so*'
+#[derive(Args)]
+struct CmdRepositoryFindFile {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    package_filter: PackageFilterArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    glob: crate::glob::Glob,
+}
+
+impl From<&CmdRepositoryFindFile> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryFindFile) -> Self {
+        Self {
+            package_filter: (&v.package_filter).into(),
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
 }
 
-impl fmt::Display for DumpFormat {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+impl CmdRepositoryFindFile {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let found = repodata.find_files(&self.glob)?;
+        self.output.print(&found, DumpFormat::Table)
     }
 }
 
-/// Dump metadata of RPM file
+/// Check that requirements of all packages are satisfiable within repository
 #[derive(Args)]
-struct CmdRpmDump {
-    #[arg(short, long, default_value_t = DumpFormat::Yaml, value_enum)]
-    format: DumpFormat,
-    file: std::path::PathBuf,
+struct CmdRepositoryDepcheck {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    package_filter: PackageFilterArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+    /// Path to another repository which may provide dependencies, can be repeated
+    #[clap(long)]
+    base_repository: Vec<std::path::PathBuf>,
 }
 
-impl CmdRpmDump {
-    fn run(&self) -> Result<()> {
-        let mut rpm_file = std::fs::File::open(&self.file)?;
-        let mut buf_reader = std::io::BufReader::new(&rpm_file);
-        let pkg = rpm::RPMPackage::parse(&mut buf_reader)
-            .map_err(|err| anyhow!("{}", err.to_string()))?;
+impl From<&CmdRepositoryDepcheck> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryDepcheck) -> Self {
+        Self {
+            package_filter: (&v.package_filter).into(),
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
 
-        let file_sha = crate::digest::file_sha128(&mut rpm_file)?;
-        let rpm = crate::repodata::primary::Package::of_rpm_package(
-            &pkg,
-            self.file.parent().unwrap(),
-            &self.file,
-            &file_sha,
-            &regex::Regex::new(".*").unwrap(),
-        )?;
-        let s = self.format.dump(&rpm)?;
-        println!("{}", s);
+impl CmdRepositoryDepcheck {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let unresolved = repodata.depcheck(&self.base_repository)?;
+        if !unresolved.is_empty() {
+            self.output.print(&unresolved, DumpFormat::Table)?;
+            bail!("{} unresolvable dependencies found", unresolved.len())
+        }
+        info!("All dependencies are resolvable");
         Ok(())
     }
 }
 
-/// Operations on single RPM file
-#[derive(Subcommand)]
-enum CmdRpm {
-    Dump(CmdRpmDump),
+/// Show package-level changes published after given revision of repository
+#[derive(Args)]
+struct CmdRepositoryChanges {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Revision of repomd.xml, all recorded changes are shown by default
+    #[clap(long, default_value_t = 0)]
+    since: u64,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
 }
 
-impl CmdRpm {
-    fn run(&self, _config: &crate::config::Config) -> Result<()> {
-        match self {
-            CmdRpm::Dump(v) => v.run(),
+impl From<&CmdRepositoryChanges> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryChanges) -> Self {
+        Self {
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
         }
     }
 }
 
-/// Generate RPM repository in given directory
+impl CmdRepositoryChanges {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let changes = repodata.changes(self.since)?;
+        self.output.print(&changes, DumpFormat::Yaml)
+    }
+}
+
+/// Show statistics of metadata updates: growth of repository and duration of runs
 #[derive(Args)]
-struct CmdRepositoryGenerate {
+struct CmdRepositoryTrends {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Number of last runs to show
+    #[clap(long, default_value_t = 20)]
+    last: usize,
     #[clap(long)]
-    fileslists: bool,
-    path: std::path::PathBuf,
+    repository_path: std::path::PathBuf,
 }
 
-impl From<&CmdRepositoryGenerate> for crate::repodata::RepodataOptions {
-    fn from(v: &CmdRepositoryGenerate) -> Self {
+impl From<&CmdRepositoryTrends> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryTrends) -> Self {
         Self {
-            generate_fileslists: v.fileslists,
-            path: v.path.clone(),
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
         }
     }
 }
 
-impl CmdRepositoryGenerate {
+impl CmdRepositoryTrends {
     pub fn run(&self, config: &crate::config::Config) -> Result<()> {
         let repodata = crate::repodata::Repodata {
             config: &config.repodata,
             options: self.into(),
         };
-        repodata.generate()
+        let trends = repodata.trends(self.last)?;
+        self.output.print(&trends, DumpFormat::Table)
     }
 }
 
-/// Add given files to repository index
+/// Record current state of repository (repomd.xml checksum and list of packages) under given name
 #[derive(Args)]
-struct CmdRepositoryAddFiles {
+struct CmdRepositoryTag {
+    /// Replace existing tag
     #[clap(long)]
-    fileslists: bool,
+    force: bool,
     #[clap(long)]
     repository_path: std::path::PathBuf,
-    file_path: Vec<std::path::PathBuf>,
+    name: String,
 }
 
-impl From<&CmdRepositoryAddFiles> for crate::repodata::RepodataOptions {
-    fn from(v: &CmdRepositoryAddFiles) -> Self {
+impl From<&CmdRepositoryTag> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryTag) -> Self {
         Self {
-            generate_fileslists: v.fileslists,
             path: v.repository_path.clone(),
+            ..Default::default()
         }
     }
 }
 
-impl CmdRepositoryAddFiles {
+impl CmdRepositoryTag {
     pub fn run(&self, config: &crate::config::Config) -> Result<()> {
         let repodata = crate::repodata::Repodata {
             config: &config.repodata,
             options: self.into(),
         };
-        repodata.add_files(&self.file_path)
+        repodata.tag(&self.name, self.force)
     }
 }
 
-/// Validate repository index
+/// List tags of repository, or show one tag with list of packages
 #[derive(Args)]
-struct CmdRepositoryValidate {
+struct CmdRepositoryTags {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
     #[clap(long)]
-    fileslists: bool,
+    repository_path: std::path::PathBuf,
+    name: Option<String>,
+}
+
+impl From<&CmdRepositoryTags> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryTags) -> Self {
+        Self {
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryTags {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        match &self.name {
+            Some(name) => self
+                .output
+                .print(&repodata.read_tag(name)?, DumpFormat::Yaml),
+            None => self.output.print(&repodata.tags()?, DumpFormat::Table),
+        }
+    }
+}
+
+/// Report mutual conflicts, self-obsoletion and obsoletes matching no package. Fails on the first two
+#[derive(Args)]
+struct CmdRepositoryCheckRelations {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
     #[clap(long)]
     repository_path: std::path::PathBuf,
 }
 
-impl From<&CmdRepositoryValidate> for crate::repodata::RepodataOptions {
-    fn from(v: &CmdRepositoryValidate) -> Self {
+impl From<&CmdRepositoryCheckRelations> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryCheckRelations) -> Self {
         Self {
-            generate_fileslists: v.fileslists,
             path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
         }
     }
 }
 
-impl CmdRepositoryValidate {
+impl CmdRepositoryCheckRelations {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let report = repodata.relations_report()?;
+        self.output.print(&report, DumpFormat::Yaml)?;
+        let errors = report.errors();
+        if errors > 0 {
+            bail!("{} problems found", errors)
+        }
+        Ok(())
+    }
+}
+
+/// Report licenses of packages and packages matching repodata.license_denylist
+#[derive(Args)]
+struct CmdRepositoryLicenses {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryLicenses> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryLicenses) -> Self {
+        Self {
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryLicenses {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let report = repodata.license_report()?;
+        self.output.print(&report, DumpFormat::Yaml)?;
+        if !report.denied.is_empty() {
+            bail!("{} packages have denied license", report.denied.len())
+        }
+        Ok(())
+    }
+}
+
+/// Report packages affected by security advisories of updateinfo.xml, OVAL or CSAF feed
+#[derive(Args)]
+struct CmdRepositoryVulns {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Path to updateinfo.xml, OVAL definitions or CSAF advisory, may be compressed
+    #[clap(long, alias = "updateinfo")]
+    feed: std::path::PathBuf,
+    #[clap(long, value_enum, default_value = "auto")]
+    feed_format: crate::repodata::updateinfo::FeedFormat,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryVulns> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryVulns) -> Self {
+        Self {
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryVulns {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let report = repodata.vulnerability_report(&self.feed, self.feed_format)?;
+        self.output.print(&report, DumpFormat::Yaml)?;
+        Ok(())
+    }
+}
+
+/// Estimate space taken by old versions, duplicate and delisted packages, find byte-identical files shipped by several
+/// packages
+#[derive(Args)]
+struct CmdRepositoryWaste {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Number of newest versions of each package considered useful
+    #[clap(long, default_value_t = 3)]
+    keep_versions: usize,
+    /// Smaller byte-identical files are not reported. 0 disables reading of package headers
+    #[clap(long, default_value_t = 1024 * 1024)]
+    min_shared_size: u64,
+    #[clap(flatten)]
+    scan_filter: ScanFilterArgs,
+    /// Follow symlinks to RPM files and directories
+    #[clap(long)]
+    follow_symlinks: bool,
+    #[clap(long)]
+    repository_path: std::path::PathBuf,
+}
+
+impl From<&CmdRepositoryWaste> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryWaste) -> Self {
+        Self {
+            follow_symlinks: v.follow_symlinks,
+            scan_filter: (&v.scan_filter).into(),
+            path: v.repository_path.clone(),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryWaste {
     pub fn run(&self, config: &crate::config::Config) -> Result<()> {
         let repodata = crate::repodata::Repodata {
             config: &config.repodata,
             options: self.into(),
         };
-        repodata.validate()
+        let report = repodata.waste_report(self.keep_versions, self.min_shared_size)?;
+        self.output.print(&report, DumpFormat::Yaml)?;
+        Ok(())
+    }
+}
+
+/// Compare metadata of two repositories ignoring ordering and formatting, e.g. to check that metadata generated by
+/// rpm-tool is equivalent to createrepo_c one. Exits with error if metadata differs
+#[derive(Args)]
+struct CmdRepositoryMetadataDiff {
+    #[clap(flatten)]
+    lock: LockArgs,
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Repository directory or its repodata/ directory
+    left: std::path::PathBuf,
+    /// Repository directory or its repodata/ directory
+    right: std::path::PathBuf,
+}
+
+/// repodata/ directory is accepted in place of repository directory
+fn repository_root(path: &std::path::Path) -> std::path::PathBuf {
+    match path.parent() {
+        Some(parent) if path.join("repomd.xml").exists() => parent.to_path_buf(),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl From<&CmdRepositoryMetadataDiff> for crate::repodata::RepodataOptions {
+    fn from(v: &CmdRepositoryMetadataDiff) -> Self {
+        Self {
+            generate_fileslists: true,
+            path: repository_root(&v.left),
+            no_lock: v.lock.no_lock,
+            ..Default::default()
+        }
+    }
+}
+
+impl CmdRepositoryMetadataDiff {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let repodata = crate::repodata::Repodata {
+            config: &config.repodata,
+            options: self.into(),
+        };
+        let report = repodata.metadata_diff(&repository_root(&self.right))?;
+        self.output.print(&report, DumpFormat::Yaml)?;
+        if !report.is_empty() {
+            return Err(anyhow!("Metadata differs").context(crate::exit_code::Failure::Mismatch));
+        }
+        Ok(())
     }
 }
 
@@ -170,29 +1706,196 @@ pub fn run(&self, config: &crate::config::Config) -> Result<()> {
 #[derive(Subcommand)]
 enum CmdRepository {
     Generate(CmdRepositoryGenerate),
+    Watch(CmdRepositoryWatch),
+    MergeShards(CmdRepositoryMergeShards),
+    Compose(CmdRepositoryCompose),
     AddFiles(CmdRepositoryAddFiles),
+    Upload(CmdRepositoryUpload),
+    Remove(CmdRepositoryRemove),
+    PurgeTrash(CmdRepositoryPurgeTrash),
+    Organize(CmdRepositoryOrganize),
     Validate(CmdRepositoryValidate),
+    Repair(CmdRepositoryRepair),
+    VerifyFile(CmdRepositoryVerifyFile),
+    ValidateSchema(CmdRepositoryValidateSchema),
+    #[clap(alias = "whatprovides")]
+    WhatProvides(CmdRepositoryWhatProvides),
+    #[clap(alias = "whatrequires")]
+    WhatRequires(CmdRepositoryWhatRequires),
+    FindFile(CmdRepositoryFindFile),
+    Depcheck(CmdRepositoryDepcheck),
+    Changes(CmdRepositoryChanges),
+    Trends(CmdRepositoryTrends),
+    Tag(CmdRepositoryTag),
+    Tags(CmdRepositoryTags),
+    CheckRelations(CmdRepositoryCheckRelations),
+    Licenses(CmdRepositoryLicenses),
+    Vulns(CmdRepositoryVulns),
+    Waste(CmdRepositoryWaste),
+    MetadataDiff(CmdRepositoryMetadataDiff),
 }
 
 impl CmdRepository {
-    fn run(&self, config: &crate::config::Config) -> Result<()> {
+    /// Command changes repository and polls for interruption to clean up before exit
+    fn handles_interrupts(&self) -> bool {
+        matches!(
+            self,
+            Self::Generate(_)
+                | Self::Watch(_)
+                | Self::MergeShards(_)
+                | Self::Compose(_)
+                | Self::AddFiles(_)
+                | Self::Upload(_)
+                | Self::Remove(_)
+                | Self::Organize(_)
+                | Self::Repair(_)
+        )
+    }
+
+    fn run(&self, config: &crate::config::Config, config_path: &str) -> Result<()> {
         match self {
             Self::Generate(v) => v.run(config),
+            Self::Watch(v) => v.run(config, config_path),
+            Self::MergeShards(v) => v.run(config),
+            Self::Compose(v) => v.run(config),
             Self::AddFiles(v) => v.run(config),
+            Self::Upload(v) => v.run(config),
+            Self::Remove(v) => v.run(config),
+            Self::PurgeTrash(v) => v.run(config),
+            Self::Organize(v) => v.run(config),
             Self::Validate(v) => v.run(config),
+            Self::Repair(v) => v.run(config),
+            Self::VerifyFile(v) => v.run(config),
+            Self::ValidateSchema(v) => v.run(config),
+            Self::WhatProvides(v) => v.run(config),
+            Self::WhatRequires(v) => v.run(config),
+            Self::FindFile(v) => v.run(config),
+            Self::Depcheck(v) => v.run(config),
+            Self::Changes(v) => v.run(config),
+            Self::Trends(v) => v.run(config),
+            Self::Tag(v) => v.run(config),
+            Self::Tags(v) => v.run(config),
+            Self::CheckRelations(v) => v.run(config),
+            Self::Licenses(v) => v.run(config),
+            Self::Vulns(v) => v.run(config),
+            Self::Waste(v) => v.run(config),
+            Self::MetadataDiff(v) => v.run(config),
+        }
+    }
+}
+
+/// Dump parsed config file. Helps to find typos
+#[derive(Args)]
+struct CmdDumpConfig {
+    #[clap(flatten)]
+    output: OutputArgs,
+    /// Show every effective setting with source of its value (file, default, env or cli) and
+    /// description
+    #[clap(long)]
+    annotate: bool,
+}
+
+impl CmdDumpConfig {
+    fn run(&self, config: &crate::config::Config, config_path: &str) -> Result<()> {
+        if self.annotate {
+            return self
+                .output
+                .print(&config.settings(config_path)?, DumpFormat::Yaml)
+                .with_context(|| "Failed to dump config");
+        }
+        self.output
+            .print(config, DumpFormat::Yaml)
+            .with_context(|| "Failed to dump config")
+    }
+}
+
+/// Print shell completion script to stdout
+#[derive(Args)]
+struct CmdCompletions {
+    shell: clap_complete::Shell,
+}
+
+impl CmdCompletions {
+    fn run(&self) -> Result<()> {
+        let mut command = Application::command();
+        let name = command.get_name().to_owned();
+        clap_complete::generate(self.shell, &mut command, name, &mut std::io::stdout());
+        Ok(())
+    }
+}
+
+/// Periodically inspect repositories and serve their metrics in Prometheus format on /metrics
+#[derive(Args)]
+struct CmdExporter {
+    /// YAML file with list of repositories: name, path and verify flag
+    #[clap(long)]
+    repos: std::path::PathBuf,
+    /// Address to serve metrics on
+    #[clap(long, default_value = "127.0.0.1:9731")]
+    listen: String,
+    /// Period of inspections, e.g. 30s, 5m or 1h
+    #[clap(long, value_parser = crate::repodata::watch::parse_interval, default_value = "5m")]
+    interval: std::time::Duration,
+}
+
+impl CmdExporter {
+    pub fn run(&self, config: &crate::config::Config) -> Result<()> {
+        let exporter = crate::repodata::exporter::ExporterConfig::read(&self.repos)
+            .context(crate::exit_code::Failure::Config)?;
+        crate::repodata::exporter::run(&config.repodata, &exporter, &self.listen, self.interval)
+    }
+}
+
+/// Write man pages of rpm-tool and all its subcommands into directory
+#[derive(Args)]
+struct CmdManpages {
+    dir: std::path::PathBuf,
+}
+
+impl CmdManpages {
+    fn write(command: &clap::Command, name: &str, dir: &std::path::Path) -> Result<()> {
+        let path = dir.join(format!("{}.1", name));
+        let mut file = std::fs::File::create(&path)
+            .map_err(|err| anyhow!("Cannot create {:?}: {}", path, err))?;
+        clap_mangen::Man::new(command.clone())
+            .title(name)
+            .render(&mut file)
+            .map_err(|err| anyhow!("Cannot write {:?}: {}", path, err))?;
+
+        for subcommand in command.get_subcommands() {
+            if subcommand.is_hide_set() || subcommand.get_name() == "help" {
+                continue;
+            }
+            Self::write(
+                subcommand,
+                &format!("{}-{}", name, subcommand.get_name()),
+                dir,
+            )?;
         }
+        Ok(())
+    }
+
+    fn run(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|err| anyhow!("Cannot create {:?}: {}", self.dir, err))?;
+        let mut command = Application::command();
+        command.build();
+        let name = command.get_name().to_owned();
+        Self::write(&command, &name, &self.dir)
     }
 }
 
 #[derive(Subcommand)]
 enum CommandLine {
-    /// Dump parsed config file. Helps to find typos
-    DumpConfig,
+    DumpConfig(CmdDumpConfig),
     /// Operations on single RPM file
     #[clap(subcommand)]
     Rpm(CmdRpm),
     #[clap(subcommand)]
     Repository(CmdRepository),
+    Exporter(CmdExporter),
+    Completions(CmdCompletions),
+    Manpages(CmdManpages),
 }
 
 #[derive(Parser)]
@@ -201,6 +1904,32 @@ struct Application {
     /// Path to configuration file
     #[clap(short, default_value = CONFIG_DEFAULT_PATH)]
     config_path: String,
+    /// Override setting of configuration file, e.g. --set repodata.concurrency=8. Value is parsed as
+    /// YAML
+    #[clap(long = "set", global = true, value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+    /// Write durations of scan, hash, parse, serialize, compress and swap stages to file in Chrome
+    /// trace event format
+    #[clap(long, global = true)]
+    trace_out: Option<std::path::PathBuf>,
+    /// Implementation of SHA digests, for debugging and benchmarking
+    #[clap(long, global = true, value_enum, default_value = "auto")]
+    digest_backend: crate::digest::DigestBackend,
+    /// Print summary of run to stdout: package counters, durations of stages, published metadata
+    #[clap(long, global = true, value_enum)]
+    summary: Option<crate::summary::SummaryFormat>,
+    /// Write progress events as JSON lines to file descriptor (number) or file (path)
+    #[clap(long, global = true)]
+    progress_events: Option<String>,
+    /// Export spans of pipeline stages and package counters to OTLP/HTTP collector, e.g.
+    /// http://localhost:4318
+    #[cfg(feature = "otlp")]
+    #[clap(long, global = true)]
+    otlp_endpoint: Option<String>,
+    /// Name of invoked subcommand, set from parsed arguments
+    #[cfg(feature = "otlp")]
+    #[clap(skip)]
+    command_name: String,
     /// Subcommand
     #[clap(subcommand)]
     command: CommandLine,
@@ -211,48 +1940,174 @@ fn init_syslog_logger(log_level: slog::Level) -> Result<slog_scope::GlobalLogger
         let logger = slog_syslog::SyslogBuilder::new()
             .facility(slog_syslog::Facility::LOG_USER)
             .level(log_level)
-            .unix("/dev/log")
+            .unix(crate::platform::SYSLOG_SOCKET)
             .start()?;
 
         let logger = slog::Logger::root(logger.fuse(), o!());
         Ok(slog_scope::set_global_logger(logger))
     }
 
+    /// Name of invoked subcommand, e.g. "rpm-tool repository generate"
+    #[cfg(feature = "otlp")]
+    fn command_name(matches: &clap::ArgMatches) -> String {
+        let mut r = "rpm-tool".to_owned();
+        let mut matches = matches;
+        while let Some((name, subcommand)) = matches.subcommand() {
+            r.push(' ');
+            r.push_str(name);
+            matches = subcommand;
+        }
+        r
+    }
+
     fn init_env_logger() -> Result<slog_scope::GlobalLoggerGuard> {
         Ok(slog_envlogger::init()?)
     }
 
-    fn init_logger(&self, config: &config::Config) -> Result<slog_scope::GlobalLoggerGuard> {
+    fn init_logger(config: &config::Config) -> Result<slog_scope::GlobalLoggerGuard> {
         if std::env::var("RUST_LOG").is_ok() {
             Self::init_env_logger()
-        } else {
+        } else if config.logging.is_empty() {
             Self::init_syslog_logger(config.log_level.into())
+        } else {
+            crate::logging::init(&config.logging)
+        }
+    }
+
+    /// Other commands have nothing to clean up and are terminated by signal as usual, they don't
+    /// poll for interruption
+    fn handles_interrupts(&self) -> bool {
+        match &self.command {
+            CommandLine::Repository(v) => v.handles_interrupts(),
+            CommandLine::Exporter(_) => true,
+            _ => false,
         }
     }
 
     fn run_command(&self, config: config::Config) -> Result<()> {
         match &self.command {
-            CommandLine::DumpConfig => {
-                let config =
-                    serde_yaml::to_string(&config).with_context(|| "Failed to dump config")?;
-                println!("{}", config);
-                Ok(())
-            }
+            CommandLine::DumpConfig(v) => v.run(&config, &self.config_path),
             CommandLine::Rpm(v) => v.run(&config),
-            CommandLine::Repository(v) => v.run(&config),
+            CommandLine::Repository(v) => v.run(&config, &self.config_path),
+            CommandLine::Exporter(v) => v.run(&config),
+            CommandLine::Completions(v) => v.run(),
+            CommandLine::Manpages(v) => v.run(),
         }
     }
 
     pub fn run(&self) {
-        let config = config::Config::read(&self.config_path).expect("Config");
-        let _logger_guard = self.init_logger(&config).expect("Logger");
+        // Used while packaging, when configuration file is not installed yet
+        let r = match &self.command {
+            CommandLine::Completions(v) => Some(v.run()),
+            CommandLine::Manpages(v) => Some(v.run()),
+            _ => None,
+        };
+        if let Some(r) = r {
+            if let Err(err) = &r {
+                eprintln!("Failed with error: {:#}", err);
+            }
+            std::process::exit(crate::exit_code::of_result(&r))
+        }
+
+        let config = match config::Config::read(&self.config_path)
+            .and_then(|v| v.with_overrides(&self.overrides))
+        {
+            Ok(v) => v,
+            Err(err) => {
+                eprintln!("Cannot read configuration: {:#}", err);
+                std::process::exit(crate::exit_code::CONFIG)
+            }
+        };
+        let _logger_guard = Self::init_logger(&config).expect("Logger");
+        if self.handles_interrupts() {
+            crate::platform::install_interrupt_handlers().expect("Signal handlers");
+        }
+        crate::digest::set_backend(self.digest_backend).expect("Digest backend");
+        match crate::platform::raise_open_files_limit() {
+            Ok(v) => info!("Limit of open files is {}", v),
+            Err(err) => error!("{}", err),
+        }
 
-        if let Err(err) = self.run_command(config) {
+        if self.trace_out.is_some() {
+            crate::trace::enable()
+        }
+        #[cfg(feature = "otlp")]
+        if let Some(endpoint) = &self.otlp_endpoint {
+            crate::otlp::init(endpoint, &self.command_name)
+        }
+        if self.summary.is_some() {
+            crate::summary::enable()
+        }
+        if let Some(target) = &self.progress_events {
+            if let Err(err) = crate::progress::open(target) {
+                error!("{}", err);
+                drop(_logger_guard);
+                std::process::exit(crate::exit_code::FAILURE)
+            }
+        }
+
+        let r = self.run_command(config);
+        if let Err(err) = &r {
             error!("Failed with error: {:#}", err);
         }
+
+        if let Some(path) = &self.trace_out {
+            if let Err(err) = crate::trace::write(path) {
+                error!("{}", err);
+            }
+        }
+
+        let code = crate::exit_code::of_result(&r);
+        #[cfg(feature = "otlp")]
+        if let Err(err) = crate::otlp::export(crate::trace::start_time(), code) {
+            error!("Cannot export to OTLP collector: {}", err);
+        }
+        crate::progress::finish(code);
+        if let Some(format) = self.summary {
+            if let Err(err) = crate::summary::print(format, code) {
+                error!("Cannot print summary: {}", err);
+            }
+        }
+        if code != 0 {
+            drop(_logger_guard);
+            std::process::exit(code)
+        }
     }
 }
 
 fn main() {
-    Application::parse().run();
+    let matches = Application::command().get_matches();
+    let application = Application::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    #[cfg(feature = "otlp")]
+    let application = Application {
+        command_name: Application::command_name(&matches),
+        ..application
+    };
+    application.run();
+}
+
+#[test]
+fn test_handles_interrupts() {
+    let handles_interrupts = |args: &[&str]| {
+        Application::try_parse_from(args)
+            .unwrap()
+            .handles_interrupts()
+    };
+    assert!(handles_interrupts(&[
+        "rpm-tool",
+        "repository",
+        "generate",
+        "/srv/repo"
+    ]));
+    assert!(!handles_interrupts(&[
+        "rpm-tool",
+        "repository",
+        "find-file",
+        "--repository-path",
+        "/srv/repo",
+        "/bin/bash"
+    ]));
+    assert!(!handles_interrupts(&[
+        "rpm-tool", "rpm", "vercmp", "1.0", "1.1"
+    ]));
 }
@@ -0,0 +1,272 @@
+//! Export of recorded spans of pipeline stages and package counters to OpenTelemetry collector
+//! over OTLP/HTTP with JSON encoding. Spans of each stage are merged into one, so that request
+//! size doesn't grow with number of packages. Requests are sent with curl. If TRACEPARENT is set
+//! (e.g. by CI job), spans of run are attached to its trace
+
+use std::io::Write;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+
+struct Collector {
+    /// Base URL like http://localhost:4318
+    endpoint: String,
+    /// Name of root spans, e.g. "rpm-tool repository generate"
+    command: String,
+}
+
+lazy_static::lazy_static! {
+    static ref COLLECTOR: Mutex<Option<Collector>> = Mutex::new(None);
+}
+
+/// Start recording of stages for export to collector at `endpoint`
+pub fn init(endpoint: &str, command: &str) {
+    crate::trace::enable_stages();
+    *COLLECTOR.lock().unwrap() = Some(Collector {
+        endpoint: endpoint.trim_end_matches('/').to_owned(),
+        command: command.to_owned(),
+    })
+}
+
+/// Hex string of `bytes` pseudo-random bytes for trace and span ids
+fn random_id(bytes: usize) -> String {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut r = String::new();
+    while r.len() < bytes * 2 {
+        // Every RandomState is seeded differently
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u32(std::process::id());
+        hasher.write_u128(unix_nanos(std::time::SystemTime::now()));
+        r.push_str(&format!("{:016x}", hasher.finish()))
+    }
+    r.truncate(bytes * 2);
+    r
+}
+
+fn unix_nanos(time: std::time::SystemTime) -> u128 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map(|v| v.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Trace id and parent span id of W3C traceparent header value
+fn parse_traceparent(v: &str) -> Option<(String, String)> {
+    let parts: Vec<_> = v.trim().split('-').collect();
+    match parts.as_slice() {
+        [_version, trace_id, span_id, _flags]
+            if trace_id.len() == 32
+                && span_id.len() == 16
+                && trace_id
+                    .chars()
+                    .chain(span_id.chars())
+                    .all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Some((trace_id.to_lowercase(), span_id.to_lowercase()))
+        }
+        _ => None,
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({"key": key, "value": {"stringValue": value}})
+}
+
+fn int_attribute(key: &str, value: i64) -> Value {
+    json!({"key": key, "value": {"intValue": value.to_string()}})
+}
+
+fn resource() -> Value {
+    json!({
+        "attributes": [
+            string_attribute("service.name", "rpm-tool"),
+            string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+        ]
+    })
+}
+
+fn scope() -> Value {
+    json!({"name": "rpm-tool", "version": env!("CARGO_PKG_VERSION")})
+}
+
+/// Root span of run with merged spans of stages as children
+fn traces(
+    command: &str,
+    exit_code: i32,
+    start: u128,
+    end: u128,
+    stages: &[(&'static str, crate::trace::StageSpan)],
+) -> Value {
+    let traceparent = std::env::var("TRACEPARENT")
+        .ok()
+        .and_then(|v| parse_traceparent(&v));
+    let (trace_id, parent_span_id) = match traceparent {
+        Some((trace_id, span_id)) => (trace_id, span_id),
+        None => (random_id(16), String::new()),
+    };
+    let root_span_id = random_id(8);
+    // 1 is OK, 2 is ERROR
+    let status_code = if exit_code == 0 { 1 } else { 2 };
+
+    let mut spans = vec![json!({
+        "traceId": trace_id,
+        "spanId": root_span_id,
+        "parentSpanId": parent_span_id,
+        "name": command,
+        "kind": 1,
+        "startTimeUnixNano": start.to_string(),
+        "endTimeUnixNano": end.to_string(),
+        "attributes": [int_attribute("process.exit_code", exit_code.into())],
+        "status": {"code": status_code},
+    })];
+    for (name, stage) in stages {
+        spans.push(json!({
+            "traceId": trace_id,
+            "spanId": random_id(8),
+            "parentSpanId": root_span_id,
+            "name": name,
+            "kind": 1,
+            "startTimeUnixNano": stage.start.to_string(),
+            "endTimeUnixNano": stage.end.to_string(),
+            "attributes": [
+                int_attribute("rpm_tool.stage.spans", stage.count as i64),
+                json!({
+                    "key": "rpm_tool.stage.duration_sum",
+                    "value": {"doubleValue": stage.duration.as_secs_f64()},
+                }),
+            ],
+        }))
+    }
+    json!({
+        "resourceSpans": [{
+            "resource": resource(),
+            "scopeSpans": [{"scope": scope(), "spans": spans}],
+        }]
+    })
+}
+
+/// Package counters of run as cumulative sums
+fn metrics(start: u128, end: u128) -> Value {
+    let (indexed, reused, failed) = crate::summary::counters();
+    let sum = |name: &str, description: &str, value: usize| {
+        json!({
+            "name": name,
+            "description": description,
+            "unit": "{package}",
+            "sum": {
+                "dataPoints": [{
+                    "asInt": value.to_string(),
+                    "startTimeUnixNano": start.to_string(),
+                    "timeUnixNano": end.to_string(),
+                }],
+                // Cumulative since start of process
+                "aggregationTemporality": 2,
+                "isMonotonic": true,
+            }
+        })
+    };
+    json!({
+        "resourceMetrics": [{
+            "resource": resource(),
+            "scopeMetrics": [{
+                "scope": scope(),
+                "metrics": [
+                    sum("rpm_tool.packages.indexed", "Packages read from RPM files", indexed),
+                    sum("rpm_tool.packages.reused", "Packages taken from cached metadata", reused),
+                    sum("rpm_tool.packages.failed", "Packages which can't be indexed", failed),
+                ],
+            }]
+        }]
+    })
+}
+
+fn post(url: &str, body: &Value) -> Result<()> {
+    let mut child = std::process::Command::new("curl")
+        .arg("--fail")
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--max-time")
+        .arg("30")
+        .arg("--header")
+        .arg("Content-Type: application/json")
+        .arg("--data-binary")
+        .arg("@-")
+        .arg(url)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|err| anyhow!("Cannot run curl: {}", err))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(body.to_string().as_bytes())
+            .map_err(|err| anyhow!("Cannot pass request to curl: {}", err))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| anyhow!("Cannot run curl: {}", err))?;
+    if !output.status.success() {
+        bail!(
+            "Cannot send to {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+    Ok(())
+}
+
+/// Send stages recorded since previous export and counters to collector, e.g. after each
+/// generation of watch and at exit. Root span starts at `start` or at first stage. Nothing is
+/// sent if export is not initialized
+pub fn export(start: std::time::SystemTime, exit_code: i32) -> Result<()> {
+    let collector = COLLECTOR.lock().unwrap();
+    let collector = match &*collector {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let stages = crate::trace::take_stages();
+    let start = stages
+        .iter()
+        .map(|(_, stage)| stage.start)
+        .fold(unix_nanos(start), u128::min);
+    let end = unix_nanos(std::time::SystemTime::now());
+    post(
+        &format!("{}/v1/traces", collector.endpoint),
+        &traces(&collector.command, exit_code, start, end, &stages),
+    )?;
+    // Counters are cumulative since start of process
+    let process_start = unix_nanos(crate::trace::start_time());
+    post(
+        &format!("{}/v1/metrics", collector.endpoint),
+        &metrics(process_start, end),
+    )
+}
+
+#[test]
+fn test_parse_traceparent() {
+    assert_eq!(
+        parse_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"),
+        Some((
+            "4bf92f3577b34da6a3ce929d0e0e4736".to_owned(),
+            "00f067aa0ba902b7".to_owned()
+        ))
+    );
+    assert_eq!(parse_traceparent("00-xyz-00f067aa0ba902b7-01"), None);
+    assert_eq!(random_id(16).len(), 32);
+}
+
+#[test]
+fn test_traces() {
+    let stage = crate::trace::StageSpan {
+        start: 10,
+        end: 20,
+        count: 3,
+        duration: std::time::Duration::from_secs(1),
+    };
+    let r = traces("rpm-tool repository generate", 0, 5, 30, &[("hash", stage)]);
+    let spans = &r["resourceSpans"][0]["scopeSpans"][0]["spans"];
+    assert_eq!(spans.as_array().unwrap().len(), 2);
+    assert_eq!(spans[1]["name"], "hash");
+    assert_eq!(spans[1]["parentSpanId"], spans[0]["spanId"]);
+}
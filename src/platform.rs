@@ -0,0 +1,243 @@
+//! Platform specific access to file metadata, process information and signals, works on unix-like systems
+
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Result};
+
+#[cfg(target_os = "macos")]
+pub const SYSLOG_SOCKET: &str = "/var/run/syslog";
+#[cfg(not(target_os = "macos"))]
+pub const SYSLOG_SOCKET: &str = "/dev/log";
+
+pub fn file_size(metadata: &std::fs::Metadata) -> u64 {
+    metadata.size()
+}
+
+/// Modification time in seconds since UNIX epoch
+pub fn file_mtime(metadata: &std::fs::Metadata) -> i64 {
+    metadata.mtime()
+}
+
+/// Permission bits of file
+pub fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    metadata.mode() & 0o7777
+}
+
+/// ID of device containing the file
+pub fn file_device(metadata: &std::fs::Metadata) -> u64 {
+    metadata.dev()
+}
+
+/// Read exactly `buf.len()` bytes at `offset` without moving position of file
+pub fn read_exact_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    file.read_exact_at(buf, offset)
+}
+
+/// Take ownership of file descriptor inherited from parent process
+pub fn file_of_fd(fd: i32) -> std::io::Result<std::fs::File> {
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { std::os::unix::io::FromRawFd::from_raw_fd(fd) })
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn interrupt_handler(signal: libc::c_int) {
+    // Second signal terminates process immediately
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        unsafe {
+            let _ = libc::signal(signal, libc::SIG_DFL);
+            let _ = libc::raise(signal);
+        }
+    }
+}
+
+/// Catch SIGINT and SIGTERM. Long operations poll `check_interrupted` and unwind, so that temporary
+/// files are removed and locks are released
+pub fn install_interrupt_handlers() -> Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let handler = interrupt_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            bail!(
+                "Cannot install handler of signal {}: {}",
+                signal,
+                std::io::Error::last_os_error()
+            )
+        }
+    }
+    Ok(())
+}
+
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn reload_handler(_signal: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst)
+}
+
+/// Catch SIGHUP, daemon modes poll `take_reload_request` and re-read configuration
+pub fn install_reload_handler() -> Result<()> {
+    let handler = reload_handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGHUP, handler) } == libc::SIG_ERR {
+        bail!(
+            "Cannot install handler of signal {}: {}",
+            libc::SIGHUP,
+            std::io::Error::last_os_error()
+        )
+    }
+    Ok(())
+}
+
+/// Check and reset request to reload configuration
+pub fn take_reload_request() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::SeqCst)
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+pub fn check_interrupted() -> Result<()> {
+    if is_interrupted() {
+        bail!("Interrupted by signal")
+    }
+    Ok(())
+}
+
+fn get_open_files_limit() -> Result<libc::rlimit> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        bail!(
+            "Cannot get limit of open files: {}",
+            std::io::Error::last_os_error()
+        )
+    }
+    Ok(limit)
+}
+
+/// Soft limit of open files
+// rlim_t is not u64 on every platform
+#[allow(clippy::unnecessary_cast)]
+pub fn open_files_limit() -> Result<u64> {
+    Ok(get_open_files_limit()?.rlim_cur as u64)
+}
+
+/// Raise soft limit of open files to hard limit, called once on start. Returns resulting soft limit
+// rlim_t is not u64 on every platform
+#[allow(clippy::unnecessary_cast)]
+pub fn raise_open_files_limit() -> Result<u64> {
+    let mut limit = get_open_files_limit()?;
+    if limit.rlim_cur < limit.rlim_max {
+        let raised = libc::rlimit {
+            rlim_cur: limit.rlim_max,
+            rlim_max: limit.rlim_max,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) } == 0 {
+            limit = raised;
+        }
+    }
+    Ok(limit.rlim_cur as u64)
+}
+
+/// Resident memory of current process in bytes
+#[cfg(target_os = "linux")]
+pub fn process_rss() -> Option<u64> {
+    psutil::process::Process::current()
+        .ok()?
+        .memory_info()
+        .ok()
+        .map(|v| v.rss())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn process_rss() -> Option<u64> {
+    None
+}
+
+/// CPU and memory usage of current process, formatted for progress messages
+#[cfg(target_os = "linux")]
+pub fn process_usage() -> String {
+    let proc = match psutil::process::Process::current() {
+        Ok(v) => v,
+        Err(_) => return "".to_owned(),
+    };
+    let cpu_times = match proc.cpu_times() {
+        Ok(v) => format!(
+            ", CPU utime={} secs, stime={} secs",
+            v.user().as_secs(),
+            v.system().as_secs()
+        ),
+        Err(_) => "".to_owned(),
+    };
+    let rss = match proc.memory_info() {
+        Ok(v) => format!(", rss={} B", v.rss()),
+        Err(_) => "".to_owned(),
+    };
+    format!("{}{}", cpu_times, rss)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn process_usage() -> String {
+    "".to_owned()
+}
+
+/// Mount point containing `path`, found in /proc/self/mounts without touching the filesystem
+/// itself, so that it works for path on hung mount. Parent directory if mount table is unknown
+#[cfg(target_os = "linux")]
+pub fn mount_point(path: &std::path::Path) -> std::path::PathBuf {
+    let path = match std::env::current_dir() {
+        Ok(v) => v.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+    let mounts = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(|v| std::path::PathBuf::from(unescape_mount_field(v)))
+        .filter(|v| path.starts_with(v))
+        .max_by_key(|v| v.as_os_str().len())
+        .unwrap_or_else(|| path.parent().unwrap_or(&path).to_path_buf())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn mount_point(path: &std::path::Path) -> std::path::PathBuf {
+    path.parent().unwrap_or(path).to_path_buf()
+}
+
+/// Fields of mount table have space, tab, newline and backslash escaped as octal, e.g. "\040"
+#[cfg(target_os = "linux")]
+fn unescape_mount_field(v: &str) -> String {
+    let mut r = String::new();
+    let mut rest = v;
+    while let Some(pos) = rest.find('\\') {
+        r.push_str(&rest[..pos]);
+        let code = rest.get(pos + 1..pos + 4);
+        match code.and_then(|v| u8::from_str_radix(v, 8).ok()) {
+            Some(c) => {
+                r.push(c as char);
+                rest = &rest[pos + 4..]
+            }
+            None => {
+                r.push('\\');
+                rest = &rest[pos + 1..]
+            }
+        }
+    }
+    r.push_str(rest);
+    r
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_mount_point() {
+    assert_eq!(unescape_mount_field("/mnt/a\\040b"), "/mnt/a b");
+    assert_eq!(unescape_mount_field("/mnt/a\\b"), "/mnt/a\\b");
+    assert_eq!(
+        mount_point(std::path::Path::new("/proc/self/mounts")),
+        std::path::PathBuf::from("/proc")
+    );
+}
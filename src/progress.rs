@@ -0,0 +1,126 @@
+//! Progress events written as JSON lines to file or inherited file descriptor, for GUIs and
+//! orchestration tools which render live progress
+
+use std::io::Write;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use slog_scope::warn;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    /// Pipeline stage started
+    Stage {
+        stage: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<usize>,
+    },
+    /// Package processed, successfully or not
+    Package {
+        location: &'a str,
+        ok: bool,
+        done: usize,
+        total: usize,
+        percent: f64,
+    },
+    Finish {
+        exit_code: i32,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    /// Milliseconds since UNIX epoch
+    time: u128,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+lazy_static::lazy_static! {
+    static ref SINK: Mutex<Option<std::io::LineWriter<std::fs::File>>> = Mutex::new(None);
+}
+
+/// Target is number of file descriptor inherited from parent process or path of file (FIFO)
+pub fn open(target: &str) -> Result<()> {
+    let file = match target.parse::<i32>() {
+        Ok(fd) => crate::platform::file_of_fd(fd).map_err(|err| {
+            anyhow!(
+                "Cannot use file descriptor {} for progress events: {}",
+                fd,
+                err
+            )
+        })?,
+        Err(_) => std::fs::File::create(target)
+            .map_err(|err| anyhow!("Cannot open {:?} for progress events: {}", target, err))?,
+    };
+    *SINK.lock().unwrap() = Some(std::io::LineWriter::new(file));
+    Ok(())
+}
+
+fn emit(event: Event) {
+    let mut sink = SINK.lock().unwrap();
+    let writer = match sink.as_mut() {
+        Some(v) => v,
+        None => return,
+    };
+    let record = Record {
+        time: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|v| v.as_millis())
+            .unwrap_or_default(),
+        event,
+    };
+    let r = serde_json::to_writer(&mut *writer, &record)
+        .map_err(std::io::Error::from)
+        .and_then(|_| writer.write_all(b"\n"));
+    // Reader may go away, it must not break the run
+    if let Err(err) = r {
+        warn!("Progress events are disabled, cannot write: {}", err);
+        *sink = None
+    }
+}
+
+pub fn stage(stage: &str, total: Option<usize>) {
+    emit(Event::Stage { stage, total })
+}
+
+pub fn package(location: &std::path::Path, ok: bool, done: usize, total: usize) {
+    if SINK.lock().unwrap().is_none() {
+        return;
+    }
+    emit(Event::Package {
+        location: &location.to_string_lossy(),
+        ok,
+        done,
+        total,
+        percent: if total == 0 {
+            100.0
+        } else {
+            (done * 10000 / total) as f64 / 100.0
+        },
+    })
+}
+
+pub fn finish(exit_code: i32) {
+    emit(Event::Finish { exit_code })
+}
+
+#[test]
+fn test_record() {
+    let record = Record {
+        time: 1,
+        event: Event::Package {
+            location: "a.rpm",
+            ok: true,
+            done: 1,
+            total: 3,
+            percent: 33.33,
+        },
+    };
+    assert_eq!(
+        serde_json::to_string(&record).unwrap(),
+        r#"{"time":1,"event":"package","location":"a.rpm","ok":true,"done":1,"total":3,"percent":33.33}"#
+    );
+}
@@ -0,0 +1,103 @@
+use std::sync::{Condvar, Mutex};
+
+use slog_scope::debug;
+
+/// Throughput is measured over windows of at least this duration
+const WINDOW: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Throughput drop which is considered as degradation rather than noise
+const TOLERANCE: f64 = 0.95;
+
+struct LimiterState {
+    limit: usize,
+    active: usize,
+    window_start: std::time::Instant,
+    window_done: usize,
+    last_throughput: f64,
+    /// Direction of the next change of limit, +1 or -1
+    direction: isize,
+}
+
+/// Limits number of packages processed simultaneously. The limit is tuned by hill climbing: it is
+/// changed by one after each measurement window, and direction is reversed when throughput drops,
+/// e.g. because storage is saturated
+pub struct AdaptiveLimiter {
+    max: usize,
+    state: Mutex<LimiterState>,
+    released: Condvar,
+}
+
+pub struct Permit<'a> {
+    limiter: &'a AdaptiveLimiter,
+}
+
+impl AdaptiveLimiter {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            state: Mutex::new(LimiterState {
+                limit: max.max(1),
+                active: 0,
+                window_start: std::time::Instant::now(),
+                window_done: 0,
+                last_throughput: 0.0,
+                direction: -1,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn acquire(&self) -> Permit {
+        let mut state = self.state.lock().unwrap();
+        while state.active >= state.limit {
+            state = self.released.wait(state).unwrap();
+        }
+        state.active += 1;
+        Permit { limiter: self }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.active -= 1;
+        state.window_done += 1;
+
+        let elapsed = state.window_start.elapsed();
+        if elapsed >= WINDOW && state.window_done >= state.limit {
+            let throughput = state.window_done as f64 / elapsed.as_secs_f64();
+            if throughput < state.last_throughput * TOLERANCE {
+                state.direction = -state.direction;
+            }
+            let limit = (state.limit as isize + state.direction).clamp(1, self.max as isize);
+            debug!(
+                "Throughput {:.1} packages/s with {} workers, changing to {}",
+                throughput, state.limit, limit
+            );
+            state.limit = limit as usize;
+            state.last_throughput = throughput;
+            state.window_start = std::time::Instant::now();
+            state.window_done = 0;
+        }
+        self.released.notify_all();
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        self.limiter.release()
+    }
+}
+
+#[test]
+fn test_limiter() {
+    let limiter = AdaptiveLimiter::new(2);
+    let first = limiter.acquire();
+    let _second = limiter.acquire();
+    assert_eq!(limiter.state.lock().unwrap().active, 2);
+    drop(first);
+    assert_eq!(limiter.state.lock().unwrap().active, 1);
+    assert_eq!(limiter.limit(), 2);
+}
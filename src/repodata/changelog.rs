@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// History of package-level changes published in repodata/
+pub const CHANGELOG_FILE: &str = "changelog.json";
+
+/// Older revisions are dropped from changelog
+const MAX_REVISIONS: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Revision {
+    /// Revision of repomd.xml
+    pub revision: u64,
+    /// NEVRAs of packages
+    #[serde(default)]
+    pub added: Vec<String>,
+    #[serde(default)]
+    pub removed: Vec<String>,
+    /// Packages with the same NEVRA, but different checksum
+    #[serde(default)]
+    pub changed: Vec<String>,
+}
+
+impl Revision {
+    /// Difference between package sets, maps are NEVRA -> checksum
+    pub fn new(
+        revision: u64,
+        previous: &HashMap<String, String>,
+        current: &HashMap<String, String>,
+    ) -> Self {
+        let mut r = Self {
+            revision,
+            ..Default::default()
+        };
+        for (nevra, checksum) in current {
+            match previous.get(nevra) {
+                None => r.added.push(nevra.clone()),
+                Some(v) if v != checksum => r.changed.push(nevra.clone()),
+                Some(_) => (),
+            }
+        }
+        r.removed = previous
+            .keys()
+            .filter(|nevra| !current.contains_key(*nevra))
+            .cloned()
+            .collect();
+        r.added.sort();
+        r.removed.sort();
+        r.changed.sort();
+        r
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Add changes made after this revision under the same revision number, e.g. with fixed
+    /// --timestamp. Result is difference between package sets before this and after `later`
+    fn merge(&mut self, later: Revision) {
+        let mut added: HashSet<_> = self.added.drain(..).collect();
+        let mut removed: HashSet<_> = self.removed.drain(..).collect();
+        let mut changed: HashSet<_> = self.changed.drain(..).collect();
+
+        for nevra in later.added {
+            if removed.remove(&nevra) {
+                let _ = changed.insert(nevra);
+            } else {
+                let _ = added.insert(nevra);
+            }
+        }
+        for nevra in later.removed {
+            let _ = changed.remove(&nevra);
+            if !added.remove(&nevra) {
+                let _ = removed.insert(nevra);
+            }
+        }
+        for nevra in later.changed {
+            if !added.contains(&nevra) {
+                let _ = changed.insert(nevra);
+            }
+        }
+
+        self.added = added.into_iter().collect();
+        self.removed = removed.into_iter().collect();
+        self.changed = changed.into_iter().collect();
+        self.added.sort();
+        self.removed.sort();
+        self.changed.sort();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Changelog {
+    #[serde(default)]
+    pub revisions: Vec<Revision>,
+}
+
+impl Changelog {
+    pub fn read(path: &std::path::Path) -> Result<Self> {
+        let file =
+            std::fs::File::open(path).map_err(|err| anyhow!("Cannot open {:?}: {}", path, err))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|err| anyhow!("Cannot parse {:?}: {}", path, err))
+    }
+
+    /// Revision number repeated by the next run is merged into the last one
+    pub fn push(&mut self, revision: Revision) {
+        if let Some(last) = self.revisions.last_mut() {
+            if last.revision == revision.revision {
+                last.merge(revision);
+                return;
+            }
+        }
+        self.revisions.push(revision);
+        if self.revisions.len() > MAX_REVISIONS {
+            let _ = self.revisions.drain(..self.revisions.len() - MAX_REVISIONS);
+        }
+    }
+
+    /// Revisions newer than given one
+    pub fn since(self, revision: u64) -> Vec<Revision> {
+        self.revisions
+            .into_iter()
+            .filter(|v| v.revision > revision)
+            .collect()
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Package-level changes published after given revision of repomd.xml
+    pub fn changes(&self, since: u64) -> Result<Vec<Revision>> {
+        let path = self.options.path.join("repodata").join(CHANGELOG_FILE);
+        if !path.exists() {
+            bail!("No changelog in repository, it is written since first update of metadata")
+        }
+        let _lock = self.read_lock()?;
+        Ok(Changelog::read(&path)?.since(since))
+    }
+}
+
+#[test]
+fn test_revision_new() {
+    let previous: HashMap<_, _> = [("foo-1.0-1.x86_64", "a"), ("bar-1.0-1.noarch", "b")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let current: HashMap<_, _> = [("foo-1.1-1.x86_64", "c"), ("bar-1.0-1.noarch", "d")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let revision = Revision::new(2, &previous, &current);
+    assert_eq!(revision.added, vec!["foo-1.1-1.x86_64"]);
+    assert_eq!(revision.removed, vec!["foo-1.0-1.x86_64"]);
+    assert_eq!(revision.changed, vec!["bar-1.0-1.noarch"]);
+}
+
+#[test]
+fn test_push_same_revision() {
+    let revision = |added: &[&str], removed: &[&str], changed: &[&str]| Revision {
+        revision: 1,
+        added: added.iter().map(|v| v.to_string()).collect(),
+        removed: removed.iter().map(|v| v.to_string()).collect(),
+        changed: changed.iter().map(|v| v.to_string()).collect(),
+    };
+    let mut changelog = Changelog::default();
+    changelog.push(revision(&["a", "b"], &["c", "d"], &["e"]));
+    changelog.push(revision(&["c", "f"], &["a", "e"], &["b"]));
+    assert_eq!(changelog.revisions.len(), 1);
+    assert_eq!(
+        changelog.revisions[0],
+        revision(&["b", "f"], &["d", "e"], &["c"])
+    );
+}
@@ -0,0 +1,77 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use slog_scope::{info, warn};
+
+/// Directory in repository root with records of packages processed by interrupted run
+const CHECKPOINT_DIR: &str = ".checkpoint";
+
+const PRIMARY_FILE: &str = "primary.xml.gz";
+const FILELISTS_FILE: &str = "filelists.xml.gz";
+
+pub fn write_gz(path: &std::path::Path, content: &str) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let file = std::fs::File::create(&tmp_path)
+        .map_err(|err| anyhow!("Cannot create {:?}: {}", tmp_path, err))?;
+    let mut writer = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+    writer.write_all(content.as_bytes())?;
+    let _ = writer.finish()?;
+    std::fs::rename(&tmp_path, path)
+        .map_err(|err| anyhow!("Cannot rename {:?} to {:?}: {}", tmp_path, path, err))
+}
+
+/// Save serialized records of `packages` processed packages
+pub fn write(
+    root: &std::path::Path,
+    primary: &str,
+    packages: usize,
+    filelists: Option<&str>,
+) -> Result<()> {
+    let dir = root.join(CHECKPOINT_DIR);
+    std::fs::create_dir_all(&dir).map_err(|err| anyhow!("Cannot create {:?}: {}", dir, err))?;
+    write_gz(&dir.join(PRIMARY_FILE), primary)?;
+    match filelists {
+        Some(v) => write_gz(&dir.join(FILELISTS_FILE), v)?,
+        None => {
+            let _ = std::fs::remove_file(dir.join(FILELISTS_FILE));
+        }
+    }
+    info!("Saved checkpoint of {} packages to {:?}", packages, dir);
+    Ok(())
+}
+
+/// Records saved by previous run, empty if there is no checkpoint
+pub fn read(
+    root: &std::path::Path,
+) -> Result<(
+    Vec<crate::repodata::primary::Package>,
+    Vec<crate::repodata::filelists::Package>,
+)> {
+    let dir = root.join(CHECKPOINT_DIR);
+    let primary_path = dir.join(PRIMARY_FILE);
+    if !primary_path.exists() {
+        warn!("No checkpoint found in {:?}, starting from scratch", dir);
+        return Ok((Vec::new(), Vec::new()));
+    }
+    let primary = crate::repodata::primary::Primary::read(&primary_path)?;
+    let filelists_path = dir.join(FILELISTS_FILE);
+    let filelists = if filelists_path.exists() {
+        crate::repodata::filelists::Filelists::read(&filelists_path)?.package
+    } else {
+        Vec::new()
+    };
+    info!(
+        "Resuming from checkpoint of {} packages",
+        primary.package.len()
+    );
+    Ok((primary.package, filelists))
+}
+
+pub fn remove(root: &std::path::Path) {
+    let dir = root.join(CHECKPOINT_DIR);
+    if dir.exists() {
+        if let Err(err) = std::fs::remove_dir_all(&dir) {
+            warn!("Cannot remove checkpoint {:?}: {}", dir, err);
+        }
+    }
+}
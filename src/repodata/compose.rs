@@ -0,0 +1,116 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+/// Variant of distribution, e.g. BaseOS or AppStream. Repository is generated for every
+/// architecture of variant in <variant>/<arch>/os
+#[derive(Serialize, Deserialize)]
+pub struct ComposeVariant {
+    pub name: String,
+    pub arch: Vec<String>,
+    /// Patterns matched against package name or NEVRA
+    #[serde(default)]
+    pub include: Vec<crate::glob::Glob>,
+    #[serde(default)]
+    pub exclude: Vec<crate::glob::Glob>,
+}
+
+impl ComposeVariant {
+    /// Repository root relative to package tree
+    pub fn relative_root(name: &str, arch: &str) -> std::path::PathBuf {
+        std::path::Path::new(name).join(arch).join("os")
+    }
+
+    pub fn package_filter(&self, arch: &str) -> crate::repodata::PackageFilter {
+        crate::repodata::PackageFilter {
+            arch: vec![arch.to_owned()],
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ComposeManifest {
+    pub variants: Vec<ComposeVariant>,
+}
+
+impl ComposeManifest {
+    fn validate(&self) -> Result<()> {
+        let mut names = std::collections::HashSet::new();
+        for variant in &self.variants {
+            if variant.name.is_empty()
+                || variant.name.contains('/')
+                || variant.name.starts_with('.')
+            {
+                bail!("Invalid variant name {:?}", variant.name)
+            }
+            if !names.insert(variant.name.as_str()) {
+                bail!("Variant {:?} is defined twice", variant.name)
+            }
+            if variant.arch.is_empty() {
+                bail!("No architectures defined for variant {:?}", variant.name)
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(path: &std::path::Path) -> Result<Self> {
+        let manifest = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Cannot read compose manifest {:?}: {}", path, err))?;
+        let manifest: Self = serde_yaml::from_str(&manifest)
+            .map_err(|err| anyhow!("Cannot parse compose manifest {:?}: {}", path, err))?;
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Generate repositories of all variants and architectures of manifest in
+    /// <variant>/<arch>/os. Repository tree is scanned and packages are read once, hrefs point to
+    /// packages in place
+    pub fn compose(&self, manifest: &ComposeManifest) -> Result<()> {
+        let scanned = self.scan()?;
+
+        info!("Indexing {} packages", scanned.packages.len());
+        let (packages, fileslists) = {
+            let index = crate::repodata::State::new(self.config, &self.options)?;
+            self.index_files(&index, &scanned.packages)?;
+            index.report_cache_warnings();
+            index.take_records()?
+        };
+
+        for variant in &manifest.variants {
+            for arch in &variant.arch {
+                info!("Composing variant {} for {}", variant.name, arch);
+                let options = crate::repodata::RepodataOptions {
+                    generate_fileslists: self.options.generate_fileslists,
+                    follow_symlinks: self.options.follow_symlinks,
+                    package_filter: variant.package_filter(arch),
+                    latest_only: self.options.latest_only,
+                    timestamp: self.options.timestamp,
+                    self_check: self.options.self_check,
+                    strict: self.options.strict,
+                    on_error: self.options.on_error,
+                    max_memory: self.options.max_memory,
+                    output_path: self.options.output_path.clone(),
+                    path: self.options.path.clone(),
+                    ..Default::default()
+                };
+                let relative_root = ComposeVariant::relative_root(&variant.name, arch);
+                let mut state = crate::repodata::State::new_in(
+                    self.config,
+                    &options,
+                    self.options.path.join(&relative_root),
+                    crate::repodata::parent_prefix(&relative_root),
+                )?;
+                state.reuse_records(&packages, &fileslists);
+                state.delta_files = Some(Vec::new());
+                self.register_files_list(state, &scanned.packages)?;
+            }
+        }
+
+        Ok(())
+    }
+}
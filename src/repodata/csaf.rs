@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use crate::repodata::updateinfo::{Advisory, UpdatePackage};
+
+#[derive(Deserialize, Debug, Default)]
+struct Helper {
+    #[serde(default)]
+    purl: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Product {
+    #[serde(default)]
+    name: Option<String>,
+    product_id: String,
+    #[serde(default)]
+    product_identification_helper: Option<Helper>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Branch {
+    #[serde(default)]
+    branches: Vec<Branch>,
+    #[serde(default)]
+    product: Option<Product>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Relationship {
+    full_product_name: Product,
+    product_reference: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ProductTree {
+    #[serde(default)]
+    branches: Vec<Branch>,
+    #[serde(default)]
+    full_product_names: Vec<Product>,
+    #[serde(default)]
+    relationships: Vec<Relationship>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Severity {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Tracking {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Document {
+    tracking: Tracking,
+    #[serde(default)]
+    aggregate_severity: Severity,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ProductStatus {
+    #[serde(default)]
+    fixed: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Threat {
+    category: String,
+    #[serde(default)]
+    details: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Vulnerability {
+    #[serde(default)]
+    cve: Option<String>,
+    #[serde(default)]
+    product_status: ProductStatus,
+    #[serde(default)]
+    threats: Vec<Threat>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Csaf {
+    document: Document,
+    #[serde(default)]
+    product_tree: ProductTree,
+    #[serde(default)]
+    vulnerabilities: Vec<Vulnerability>,
+}
+
+/// Parse package URL of RPM, e.g. "pkg:rpm/redhat/bash@5.1.8-6.el9_1?arch=x86_64&epoch=1"
+fn parse_purl(purl: &str) -> Option<UpdatePackage> {
+    let rest = purl.strip_prefix("pkg:rpm/")?;
+    let (rest, qualifiers) = match rest.split_once('?') {
+        Some((rest, qualifiers)) => (rest, qualifiers.split_once('#').map_or(qualifiers, |v| v.0)),
+        None => (rest.split_once('#').map_or(rest, |v| v.0), ""),
+    };
+    let (path, version) = rest.split_once('@')?;
+    let name = crate::repodata::primary::percent_decode(path.rsplit('/').next()?);
+    let evr: crate::evr::Evr = crate::repodata::primary::percent_decode(version)
+        .parse()
+        .ok()?;
+
+    let mut epoch = (evr.epoch > 0).then_some(evr.epoch);
+    let mut arch = None;
+    for (key, value) in qualifiers.split('&').filter_map(|v| v.split_once('=')) {
+        match key {
+            "arch" => arch = Some(crate::repodata::primary::percent_decode(value)),
+            "epoch" => epoch = value.parse().ok(),
+            _ => (),
+        }
+    }
+    Some(UpdatePackage {
+        name,
+        epoch,
+        version: evr.version,
+        release: evr.release?,
+        arch,
+    })
+}
+
+/// Parse product name in form of "name-[epoch:]version-release.arch"
+fn parse_nevra(nevra: &str) -> Option<UpdatePackage> {
+    let (rest, arch) = nevra.rsplit_once('.')?;
+    let (rest, release) = rest.rsplit_once('-')?;
+    let (name, version) = rest.rsplit_once('-')?;
+    let (epoch, version) = match version.split_once(':') {
+        Some((epoch, version)) => (Some(epoch.parse().ok()?), version),
+        None => (None, version),
+    };
+    if name.is_empty()
+        || name.contains(char::is_whitespace)
+        || version.is_empty()
+        || !arch.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+    Some(UpdatePackage {
+        name: name.to_owned(),
+        epoch,
+        version: version.to_owned(),
+        release: release.to_owned(),
+        arch: Some(arch.to_owned()),
+    })
+}
+
+fn collect_products<'a>(branches: &'a [Branch], r: &mut HashMap<&'a str, &'a Product>) {
+    for branch in branches {
+        if let Some(product) = &branch.product {
+            let _ = r.insert(&product.product_id, product);
+        }
+        collect_products(&branch.branches, r)
+    }
+}
+
+/// Advisories of CSAF 2.0 document, one per vulnerability. Fixed products are resolved to RPM
+/// packages by their package URL or by NEVRA in product name, products referenced by
+/// relationships (e.g. "AppStream-9.2.0.Z.MAIN:bash-0:5.1.8-6.el9_1.x86_64") are resolved to
+/// the referenced package. Fixed products which are not RPM packages are ignored
+pub fn advisories(data: &[u8]) -> Result<Vec<Advisory>> {
+    let csaf: Csaf = serde_json::from_slice(data).map_err(|err| anyhow!("{}", err))?;
+
+    let mut products = HashMap::new();
+    collect_products(&csaf.product_tree.branches, &mut products);
+    for product in &csaf.product_tree.full_product_names {
+        let _ = products.insert(product.product_id.as_str(), product);
+    }
+    let references: HashMap<_, _> = csaf
+        .product_tree
+        .relationships
+        .iter()
+        .map(|v| {
+            (
+                v.full_product_name.product_id.as_str(),
+                v.product_reference.as_str(),
+            )
+        })
+        .collect();
+
+    let package = |product_id: &str| -> Option<UpdatePackage> {
+        let product_id = references.get(product_id).copied().unwrap_or(product_id);
+        let product = products.get(product_id);
+        let purl = product
+            .and_then(|v| v.product_identification_helper.as_ref())
+            .and_then(|v| v.purl.as_deref());
+        match purl {
+            Some(purl) => parse_purl(purl),
+            None => parse_nevra(
+                product
+                    .and_then(|v| v.name.as_deref())
+                    .unwrap_or(product_id),
+            ),
+        }
+    };
+
+    let r = csaf
+        .vulnerabilities
+        .iter()
+        .map(|vulnerability| {
+            let mut fixed = Vec::new();
+            for package in vulnerability
+                .product_status
+                .fixed
+                .iter()
+                .filter_map(|v| package(v))
+            {
+                if !fixed.contains(&package) {
+                    fixed.push(package)
+                }
+            }
+            let severity = vulnerability
+                .threats
+                .iter()
+                .find(|v| v.category == "impact")
+                .and_then(|v| v.details.clone())
+                .or_else(|| csaf.document.aggregate_severity.text.clone());
+            Advisory {
+                id: csaf.document.tracking.id.clone(),
+                severity,
+                cves: vulnerability.cve.iter().cloned().collect(),
+                fixed,
+            }
+        })
+        .filter(|v| !v.fixed.is_empty())
+        .collect();
+    Ok(r)
+}
+
+#[test]
+fn test_advisories() {
+    let json = r#"{
+  "document": {
+    "category": "csaf_security_advisory",
+    "aggregate_severity": {"text": "Moderate"},
+    "tracking": {"id": "RHSA-2023:0001"}
+  },
+  "product_tree": {
+    "branches": [{
+      "category": "vendor",
+      "name": "Red Hat",
+      "branches": [
+        {"category": "product_name", "name": "Red Hat Enterprise Linux AppStream (v. 9)",
+         "product": {"name": "Red Hat Enterprise Linux AppStream (v. 9)", "product_id": "AppStream-9.2.0.Z.MAIN"}},
+        {"category": "product_version", "name": "bash-0:5.1.8-6.el9_1.x86_64",
+         "product": {"name": "bash-0:5.1.8-6.el9_1.x86_64", "product_id": "bash-0:5.1.8-6.el9_1.x86_64",
+                     "product_identification_helper": {"purl": "pkg:rpm/redhat/bash@5.1.8-6.el9_1?arch=x86_64"}}},
+        {"category": "product_version", "name": "bash-doc-1:5.1.8-6.el9_1.noarch",
+         "product": {"name": "bash-doc-1:5.1.8-6.el9_1.noarch", "product_id": "bash-doc-1:5.1.8-6.el9_1.noarch"}}
+      ]
+    }],
+    "relationships": [
+      {"category": "default_component_of",
+       "full_product_name": {"name": "bash as a component", "product_id": "AppStream-9.2.0.Z.MAIN:bash-0:5.1.8-6.el9_1.x86_64"},
+       "product_reference": "bash-0:5.1.8-6.el9_1.x86_64",
+       "relates_to_product_reference": "AppStream-9.2.0.Z.MAIN"}
+    ]
+  },
+  "vulnerabilities": [
+    {"cve": "CVE-2023-0001",
+     "product_status": {"fixed": ["AppStream-9.2.0.Z.MAIN", "AppStream-9.2.0.Z.MAIN:bash-0:5.1.8-6.el9_1.x86_64", "bash-doc-1:5.1.8-6.el9_1.noarch"]},
+     "threats": [{"category": "impact", "details": "Important"}]},
+    {"cve": "CVE-2023-0002",
+     "product_status": {"known_affected": ["bash-0:5.1.8-6.el9_1.x86_64"]}}
+  ]
+}"#;
+    let advisories = advisories(json.as_bytes()).unwrap();
+    assert_eq!(advisories.len(), 1);
+    let advisory = &advisories[0];
+    assert_eq!(advisory.id, "RHSA-2023:0001");
+    assert_eq!(advisory.severity.as_deref(), Some("Important"));
+    assert_eq!(advisory.cves, ["CVE-2023-0001"]);
+    assert_eq!(
+        advisory.fixed,
+        [
+            UpdatePackage {
+                name: "bash".to_owned(),
+                epoch: None,
+                version: "5.1.8".to_owned(),
+                release: "6.el9_1".to_owned(),
+                arch: Some("x86_64".to_owned()),
+            },
+            UpdatePackage {
+                name: "bash-doc".to_owned(),
+                epoch: Some(1),
+                version: "5.1.8".to_owned(),
+                release: "6.el9_1".to_owned(),
+                arch: Some("noarch".to_owned()),
+            },
+        ]
+    );
+}
@@ -0,0 +1,63 @@
+use anyhow::{anyhow, bail, Result};
+
+/// What to do when package can't be indexed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Log error and index other packages
+    Skip,
+    /// Abort on first failed package
+    Fail,
+    /// Abort on N-th failed package
+    Max(usize),
+}
+
+impl Default for ErrorPolicy {
+    fn default() -> Self {
+        Self::Skip
+    }
+}
+
+impl ErrorPolicy {
+    /// Generation must be aborted after `failures` packages have failed
+    pub fn is_exceeded(&self, failures: usize) -> bool {
+        match self {
+            Self::Skip => false,
+            Self::Fail => failures > 0,
+            Self::Max(max) => failures >= *max,
+        }
+    }
+}
+
+impl std::str::FromStr for ErrorPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "fail" => Ok(Self::Fail),
+            _ => {
+                let max = s.strip_prefix("max:").ok_or_else(|| {
+                    anyhow!("Invalid error policy {:?}, expected skip, fail or max:N", s)
+                })?;
+                let max = max
+                    .parse()
+                    .map_err(|err| anyhow!("Invalid error policy {:?}: {}", s, err))?;
+                if max == 0 {
+                    bail!("Invalid error policy {:?}, N must be at least 1", s)
+                }
+                Ok(Self::Max(max))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_error_policy() {
+    let policy: ErrorPolicy = "max:3".parse().unwrap();
+    assert_eq!(policy, ErrorPolicy::Max(3));
+    assert!(!policy.is_exceeded(2));
+    assert!(policy.is_exceeded(3));
+    assert!("fail".parse::<ErrorPolicy>().unwrap().is_exceeded(1));
+    assert!(!"skip".parse::<ErrorPolicy>().unwrap().is_exceeded(100));
+    assert!("max:0".parse::<ErrorPolicy>().is_err());
+}
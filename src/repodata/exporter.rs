@@ -0,0 +1,306 @@
+//! Prometheus exporter: metadata of configured repositories is inspected periodically, gauges are
+//! served on /metrics
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::{error, info, warn};
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedRepository {
+    /// Value of "repository" label
+    pub name: String,
+    pub path: std::path::PathBuf,
+    /// Check checksums of metadata files and presence of packages on disk on every inspection
+    #[serde(default)]
+    pub verify: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExporterConfig {
+    pub repositories: Vec<ExportedRepository>,
+}
+
+impl ExporterConfig {
+    pub fn read(path: &std::path::Path) -> Result<Self> {
+        let file =
+            std::fs::File::open(path).map_err(|err| anyhow!("Cannot open {:?}: {}", path, err))?;
+        let r: Self = serde_yaml::from_reader(file)
+            .map_err(|err| anyhow!("Cannot parse {:?}: {}", path, err))?;
+        let mut names = std::collections::HashSet::new();
+        for repository in &r.repositories {
+            if !names.insert(&repository.name) {
+                return Err(anyhow!("Duplicate repository name {:?}", repository.name));
+            }
+        }
+        Ok(r)
+    }
+}
+
+/// Result of inspection of repository metadata
+#[derive(Default)]
+pub struct RepositoryStatus {
+    pub revision: u64,
+    /// Seconds since repomd.xml was written
+    pub metadata_age: i64,
+    pub packages: usize,
+    pub packages_size: u64,
+    /// Set if verification is enabled
+    pub verify: Option<Verification>,
+}
+
+pub struct Verification {
+    /// Metadata files which don't match repomd.xml
+    pub metadata_mismatches: usize,
+    /// Packages listed in metadata, but missing on disk
+    pub missing_packages: usize,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.metadata_mismatches == 0 && self.missing_packages == 0
+    }
+}
+
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render gauges in Prometheus text format. Failed inspection is exported as up=0 only
+pub fn render(statuses: &[(&str, Result<RepositoryStatus>)], timestamp: u64) -> String {
+    let mut r = String::new();
+    let mut gauge = |name: &str, help: &str, values: &mut dyn Iterator<Item = (&str, String)>| {
+        r.push_str(&format!("# HELP rpm_tool_repository_{} {}\n", name, help));
+        r.push_str(&format!("# TYPE rpm_tool_repository_{} gauge\n", name));
+        for (repository, value) in values {
+            r.push_str(&format!(
+                "rpm_tool_repository_{}{{repository=\"{}\"}} {}\n",
+                name,
+                escape_label(repository),
+                value
+            ))
+        }
+    };
+    let ok = || {
+        statuses
+            .iter()
+            .filter_map(|(name, status)| status.as_ref().ok().map(|v| (*name, v)))
+    };
+
+    gauge(
+        "up",
+        "Metadata of repository is readable",
+        &mut statuses
+            .iter()
+            .map(|(name, status)| (*name, u8::from(status.is_ok()).to_string())),
+    );
+    gauge(
+        "revision",
+        "Revision of repomd.xml",
+        &mut ok().map(|(name, v)| (name, v.revision.to_string())),
+    );
+    gauge(
+        "metadata_age_seconds",
+        "Seconds since repomd.xml was written",
+        &mut ok().map(|(name, v)| (name, v.metadata_age.to_string())),
+    );
+    gauge(
+        "packages",
+        "Number of packages in metadata",
+        &mut ok().map(|(name, v)| (name, v.packages.to_string())),
+    );
+    gauge(
+        "packages_size_bytes",
+        "Total size of packages in metadata",
+        &mut ok().map(|(name, v)| (name, v.packages_size.to_string())),
+    );
+    let verified = || ok().filter_map(|(name, v)| v.verify.as_ref().map(|v| (name, v)));
+    gauge(
+        "verify_ok",
+        "Metadata files match repomd.xml and all packages are present",
+        &mut verified().map(|(name, v)| (name, u8::from(v.is_ok()).to_string())),
+    );
+    gauge(
+        "metadata_mismatches",
+        "Metadata files which don't match repomd.xml",
+        &mut verified().map(|(name, v)| (name, v.metadata_mismatches.to_string())),
+    );
+    gauge(
+        "missing_packages",
+        "Packages listed in metadata, but missing on disk",
+        &mut verified().map(|(name, v)| (name, v.missing_packages.to_string())),
+    );
+    let name = "rpm_tool_exporter_last_inspection_timestamp_seconds";
+    r.push_str(&format!("# HELP {} UNIX time of last inspection\n", name));
+    r.push_str(&format!("# TYPE {} gauge\n", name));
+    r.push_str(&format!("{} {}\n", name, timestamp));
+    r
+}
+
+/// Metrics of repositories. Fails if none of them can be inspected
+fn inspect(
+    config: &crate::repodata::RepodataConfig,
+    exporter: &ExporterConfig,
+) -> (String, Result<()>) {
+    let statuses: Vec<_> = exporter
+        .repositories
+        .iter()
+        .map(|repository| {
+            let repodata = crate::repodata::Repodata {
+                config,
+                options: crate::repodata::RepodataOptions {
+                    path: repository.path.clone(),
+                    ..Default::default()
+                },
+            };
+            let status = repodata.status(repository.verify);
+            if let Err(err) = &status {
+                warn!("Cannot inspect repository {:?}: {}", repository.name, err);
+            }
+            (repository.name.as_str(), status)
+        })
+        .collect();
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or_default();
+    let r = if !statuses.is_empty() && statuses.iter().all(|(_, status)| status.is_err()) {
+        Err(anyhow!(
+            "None of {} repositories can be inspected",
+            statuses.len()
+        ))
+    } else {
+        Ok(())
+    };
+    (render(&statuses, timestamp), r)
+}
+
+/// Inspect repositories every `interval` and serve metrics on `listen` until SIGINT/SIGTERM.
+/// Readiness is reported while last inspection read at least one repository
+pub fn run(
+    config: &crate::repodata::RepodataConfig,
+    exporter: &ExporterConfig,
+    listen: &str,
+    interval: std::time::Duration,
+) -> Result<()> {
+    let listener = crate::repodata::health::bind(listen)?;
+    info!("Serving metrics on http://{}/metrics", listen);
+
+    let metrics = std::sync::Mutex::new(String::new());
+    let health = crate::repodata::health::Health::strict();
+    let stop = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        let _ = scope.spawn(|| {
+            let mut next = std::time::Instant::now();
+            while !crate::platform::is_interrupted() {
+                if std::time::Instant::now() >= next {
+                    let (r, status) = inspect(config, exporter);
+                    if let Err(err) = &status {
+                        error!("{}", err)
+                    }
+                    *metrics.lock().unwrap() = r;
+                    health.finish(&status);
+                    next = std::time::Instant::now() + interval;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(200))
+            }
+        });
+
+        crate::repodata::health::serve(&listener, &stop, &|path| match path {
+            "/metrics" => Some(crate::repodata::health::Response {
+                status: "200 OK",
+                content_type: "text/plain; version=0.0.4",
+                body: metrics.lock().unwrap().clone(),
+            }),
+            path => health.respond(path),
+        })
+    });
+    crate::platform::check_interrupted()
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Summary of current metadata for monitoring. With `verify` metadata files are checked against
+    /// repomd.xml and packages are looked up on disk
+    pub fn status(&self, verify: bool) -> Result<RepositoryStatus> {
+        let _lock = self.read_lock()?;
+        let repomd_path = self.options.path.join("repodata").join("repomd.xml");
+        let repomd = crate::repodata::repomd::Repomd::read(&repomd_path)?;
+        let mtime = crate::platform::file_mtime(&repomd_path.metadata()?);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        let primary = match repomd
+            .data
+            .iter()
+            .find(|elt| elt.type_ == crate::repodata::repomd::DataType::Primary)
+        {
+            Some(data) => {
+                let path = self.options.path.join(&data.location.href);
+                crate::repodata::primary::Primary::read(&path)
+                    .map_err(|err| anyhow!("Cannot read {:?}: {}", path, err))?
+            }
+            None => bail!("No 'primary' record in repomd.xml"),
+        };
+
+        let verify = if verify {
+            let mut metadata_mismatches = 0;
+            for data in &repomd.data {
+                let path = self.options.path.join(&data.location.href);
+                match crate::repodata::verify_file::FileVerification::data(&path, data) {
+                    Ok(v) if v.is_ok() => (),
+                    Ok(_) => metadata_mismatches += 1,
+                    Err(err) => {
+                        warn!("{}", err);
+                        metadata_mismatches += 1
+                    }
+                }
+            }
+            let missing_packages = primary
+                .package
+                .iter()
+                .filter(|package| !self.options.path.join(&package.location.href).exists())
+                .count();
+            Some(Verification {
+                metadata_mismatches,
+                missing_packages,
+            })
+        } else {
+            None
+        };
+
+        Ok(RepositoryStatus {
+            revision: repomd.revision,
+            metadata_age: now - mtime,
+            packages: primary.package.len(),
+            packages_size: primary.package.iter().map(|v| v.size.package).sum(),
+            verify,
+        })
+    }
+}
+
+#[test]
+fn test_render() {
+    let statuses = vec![
+        (
+            "base",
+            Ok(RepositoryStatus {
+                revision: 2,
+                packages: 3,
+                verify: Some(Verification {
+                    metadata_mismatches: 0,
+                    missing_packages: 1,
+                }),
+                ..Default::default()
+            }),
+        ),
+        ("broken", Err(anyhow!("No repomd.xml"))),
+    ];
+    let r = render(&statuses, 10);
+    assert!(r.contains("rpm_tool_repository_up{repository=\"base\"} 1\n"));
+    assert!(r.contains("rpm_tool_repository_up{repository=\"broken\"} 0\n"));
+    assert!(r.contains("rpm_tool_repository_packages{repository=\"base\"} 3\n"));
+    assert!(!r.contains("rpm_tool_repository_packages{repository=\"broken\"}"));
+    assert!(r.contains("rpm_tool_repository_verify_ok{repository=\"base\"} 0\n"));
+    assert!(r.contains("rpm_tool_exporter_last_inspection_timestamp_seconds 10\n"));
+}
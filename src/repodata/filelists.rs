@@ -33,8 +33,7 @@ pub fn of_rpm_package(pkg: &rpm::RPMPackage, file_sha: &str) -> Result<Self> {
                 .map_err(|err| anyhow!("Cannot extract package name: {}", err))?
                 .to_owned(),
             arch: header.get_arch().map(|v| v.to_owned()).ok(),
-            version: super::primary::PackageVersion::of_header(header)
-                .map_err(|err| anyhow!("{}", err.to_string()))?,
+            version: super::primary::PackageVersion::of_header(header)?,
             files,
             pkgid: file_sha.to_owned(),
         };
@@ -67,6 +66,10 @@ pub fn add_package(&mut self, package: Package) {
         self.package.push(package)
     }
 
+    pub fn sort(&mut self) {
+        self.package.sort_by(|a, b| a.pkgid.cmp(&b.pkgid))
+    }
+
     pub fn drain_filter<F>(&mut self, pred: F) -> Vec<Package>
     where
         F: Fn(&Package) -> bool,
@@ -87,10 +90,12 @@ pub fn drain_filter<F>(&mut self, pred: F) -> Vec<Package>
         drained
     }
 
+    /// Documents compressed by gzip, xz, zstd, bzip2 or uncompressed are accepted, format is
+    /// detected by magic bytes
     pub fn read(path: &std::path::Path) -> Result<Self> {
         info!("Reading fileslists from {:?}", path);
         let file = std::fs::File::open(path)?;
-        let reader = flate2::read::GzDecoder::new(file);
+        let reader = crate::decompress::decompress(std::io::BufReader::new(file))?;
         let buf_reader = std::io::BufReader::new(reader);
         let r = quick_xml::de::from_reader(buf_reader)?;
         Ok(r)
@@ -0,0 +1,206 @@
+//! Cached fileslists records fetched on demand. Fileslists of large repository take gigabytes in
+//! memory, so document is decompressed into temporary file and only offsets of records are kept
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use slog_scope::{info, warn};
+
+/// Copies everything read from `input` into `output`, so that positions of XML reader are offsets
+/// in `output`
+struct TeeReader<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.input.read(buf)?;
+        self.output.write_all(&buf[..count])?;
+        Ok(count)
+    }
+}
+
+/// Record taken out of index. Stored records are read and parsed by `read`, so index doesn't need
+/// to be locked meanwhile
+pub enum CachedRecord {
+    Parsed(crate::repodata::filelists::Package),
+    /// Uncompressed document, offset and length of record in it
+    Stored(Arc<std::fs::File>, u64, usize),
+}
+
+impl CachedRecord {
+    pub fn read(self) -> Result<crate::repodata::filelists::Package> {
+        match self {
+            Self::Parsed(v) => Ok(v),
+            Self::Stored(file, offset, len) => {
+                let mut buf = vec![0; len];
+                crate::platform::read_exact_at(&file, &mut buf, offset)
+                    .map_err(|err| anyhow!("{}", err))?;
+                Ok(quick_xml::de::from_str(&String::from_utf8_lossy(&buf))?)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FilelistsIndex {
+    /// Uncompressed document
+    file: Option<Arc<std::fs::File>>,
+    /// pkgid -> offset and length of record in `file`
+    offsets: HashMap<String, (u64, usize)>,
+    /// Records added in memory, e.g. restored from checkpoint
+    records: HashMap<String, crate::repodata::filelists::Package>,
+    /// Records which couldn't be read
+    warnings: Vec<crate::repodata::records::RecordWarning>,
+}
+
+impl FilelistsIndex {
+    /// Index fileslists document, decompressed copy is written to unnamed file in `temp_dir`
+    pub fn read(path: &std::path::Path, temp_dir: &std::path::Path) -> Result<Self> {
+        info!("Indexing fileslists from {:?}", path);
+        let input = std::fs::File::open(path)?;
+        let file = tempfile::tempfile_in(temp_dir)
+            .map_err(|err| anyhow!("Cannot create temporary file in {:?}: {}", temp_dir, err))?;
+        let mut output = std::io::BufWriter::new(file);
+
+        let mut offsets = HashMap::new();
+        {
+            let tee = TeeReader {
+                input: crate::decompress::decompress(std::io::BufReader::new(input))?,
+                output: &mut output,
+            };
+            let mut reader = quick_xml::Reader::from_reader(std::io::BufReader::new(tee));
+            let mut buf = Vec::new();
+            let mut skip_buf = Vec::new();
+            loop {
+                match reader.read_event_into(&mut buf)? {
+                    quick_xml::events::Event::Start(e) if e.local_name().as_ref() == b"package" => {
+                        let start =
+                            crate::repodata::records::start_offset(reader.buffer_position(), &e);
+                        let pkgid = match e.try_get_attribute("pkgid")? {
+                            Some(v) => v.unescape_value()?.to_string(),
+                            None => continue,
+                        };
+                        let name = e.name().as_ref().to_vec();
+                        let _ = reader
+                            .read_to_end_into(quick_xml::name::QName(&name), &mut skip_buf)?;
+                        let _ =
+                            offsets.insert(pkgid, (start as u64, reader.buffer_position() - start));
+                        skip_buf.clear();
+                    }
+                    quick_xml::events::Event::Eof => break,
+                    _ => (),
+                }
+                buf.clear();
+            }
+        }
+        let file = output
+            .into_inner()
+            .map_err(|err| anyhow!("Cannot write temporary file: {}", err))?;
+
+        info!("Got fileslists for {} packages", offsets.len());
+        Ok(Self {
+            file: Some(Arc::new(file)),
+            offsets,
+            ..Default::default()
+        })
+    }
+
+    pub fn insert(&mut self, package: crate::repodata::filelists::Package) {
+        let _ = self.offsets.remove(&package.pkgid);
+        let _ = self.records.insert(package.pkgid.clone(), package);
+    }
+
+    /// Record read failure, it's reported at the end of run
+    pub fn add_warning(&mut self, pkgid: &str, err: &anyhow::Error) {
+        warn!("Cannot read cached fileslist of {}: {}", pkgid, err);
+        self.warnings.push(crate::repodata::records::RecordWarning {
+            document: "filelists".to_owned(),
+            record: pkgid.to_owned(),
+            error: err.to_string(),
+        });
+    }
+
+    fn fetch(
+        &mut self,
+        pkgid: &str,
+        offset: u64,
+        len: usize,
+    ) -> Option<crate::repodata::filelists::Package> {
+        let file = self.file.clone()?;
+        match CachedRecord::Stored(file, offset, len).read() {
+            Ok(v) => Some(v),
+            Err(err) => {
+                self.add_warning(pkgid, &err);
+                None
+            }
+        }
+    }
+
+    /// Take cached record out of index without reading it
+    pub fn take(&mut self, pkgid: &str) -> Option<CachedRecord> {
+        if let Some(v) = self.records.remove(pkgid) {
+            return Some(CachedRecord::Parsed(v));
+        }
+        let (offset, len) = self.offsets.remove(pkgid)?;
+        Some(CachedRecord::Stored(self.file.clone()?, offset, len))
+    }
+
+    /// Take cached record out of index
+    pub fn remove(&mut self, pkgid: &str) -> Option<crate::repodata::filelists::Package> {
+        match self.take(pkgid)?.read() {
+            Ok(v) => Some(v),
+            Err(err) => {
+                self.add_warning(pkgid, &err);
+                None
+            }
+        }
+    }
+
+    /// Take all records out of index, in order of document
+    pub fn drain(&mut self) -> Vec<crate::repodata::filelists::Package> {
+        let mut offsets: Vec<_> = self.offsets.drain().collect();
+        offsets.sort_by_key(|(_, (offset, _))| *offset);
+        let mut r = Vec::new();
+        for (pkgid, (offset, len)) in offsets {
+            r.extend(self.fetch(&pkgid, offset, len));
+        }
+        r.extend(self.records.drain().map(|(_, v)| v));
+        r
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<crate::repodata::records::RecordWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn clear(&mut self) {
+        self.offsets.clear();
+        self.records.clear();
+    }
+}
+
+#[test]
+fn test_filelists_index() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(
+        br#"<?xml version="1.0" encoding="UTF-8"?>
+<filelists xmlns="http://linux.duke.edu/metadata/filelists" packages="2">
+<package pkgid="aaa" name="foo" arch="x86_64"><version epoch="0" ver="1.0" rel="1"/><file>/usr/bin/foo</file></package>
+<package pkgid="bbb" name="bar" arch="noarch"><version epoch="0" ver="2.0" rel="1"/></package>
+</filelists>"#,
+    )
+    .unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    let mut index = FilelistsIndex::read(file.path(), temp_dir.path()).unwrap();
+    let package = index.remove("aaa").unwrap();
+    assert_eq!(package.name, "foo");
+    assert_eq!(package.files.len(), 1);
+    assert!(index.remove("aaa").is_none());
+    let rest = index.drain();
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].pkgid, "bbb");
+}
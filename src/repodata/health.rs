@@ -0,0 +1,281 @@
+//! Liveness and readiness of daemon modes for orchestration, served on /healthz and /readyz by
+//! minimal HTTP server
+
+use std::io::{BufRead, Read, Write};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use slog_scope::{error, warn};
+
+/// Slow client is disconnected after this time of reading request or writing response
+const IO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Requests are answered in own threads, connections over this number are closed at once
+const MAX_CONNECTIONS: usize = 16;
+
+/// Request line and headers over this size are not read
+const MAX_REQUEST_SIZE: u64 = 8192;
+
+#[derive(Serialize, Default, Clone)]
+pub struct HealthState {
+    /// Repository lock is held: metadata is being generated by this process, or lock is taken by
+    /// another one
+    pub locked: bool,
+    /// UNIX timestamp of last successful generation
+    pub last_success: Option<u64>,
+    pub last_error: Option<String>,
+    /// Changed files waiting for regeneration
+    pub pending: usize,
+}
+
+#[derive(Default)]
+pub struct Health {
+    state: Mutex<HealthState>,
+    /// Not ready while last run failed, instead of being ready since first success
+    strict: bool,
+    /// repomd.xml locked by generation, checked for locks of other processes on requests received
+    /// while this process doesn't generate
+    lock_path: Option<std::path::PathBuf>,
+}
+
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: &'static str, state: &HealthState) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(state).unwrap_or_default(),
+        }
+    }
+}
+
+impl Health {
+    pub fn strict() -> Self {
+        Self {
+            strict: true,
+            ..Default::default()
+        }
+    }
+
+    /// Report lock on `path` held by other processes, e.g. generation started by cron
+    pub fn with_lock_path(self, path: std::path::PathBuf) -> Self {
+        Self {
+            lock_path: Some(path),
+            ..self
+        }
+    }
+
+    pub fn start(&self) {
+        self.state.lock().unwrap().locked = true
+    }
+
+    pub fn finish(&self, r: &Result<()>) {
+        let mut state = self.state.lock().unwrap();
+        state.locked = false;
+        match r {
+            Ok(()) => {
+                state.last_success = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .ok()
+                    .map(|v| v.as_secs());
+                state.last_error = None
+            }
+            Err(err) => state.last_error = Some(format!("{:#}", err)),
+        }
+    }
+
+    pub fn set_pending(&self, pending: usize) {
+        self.state.lock().unwrap().pending = pending
+    }
+
+    /// Process is alive while it responds. Ready after first successful generation, so that
+    /// published metadata exists. Strict health is not ready while last run failed
+    pub fn respond(&self, path: &str) -> Option<Response> {
+        let state = {
+            let state = self.state.lock().unwrap();
+            let mut r = state.clone();
+            // Closing checked file drops fcntl locks of this process on it, so lock is checked only
+            // while generation is not running. State is kept locked during the check, so that
+            // generation doesn't start meanwhile
+            if let (false, Some(path)) = (state.locked, &self.lock_path) {
+                r.locked = crate::repodata::lock::is_locked_by_other_process(path)
+            }
+            r
+        };
+        let ready = state.last_success.is_some() && !(self.strict && state.last_error.is_some());
+        match path {
+            "/healthz" => Some(Response::json("200 OK", &state)),
+            "/readyz" if ready => Some(Response::json("200 OK", &state)),
+            "/readyz" => Some(Response::json("503 Service Unavailable", &state)),
+            _ => None,
+        }
+    }
+}
+
+/// Read request line and headers up to blank line, so that connection is not reset by unread
+/// request. Path of request line is returned
+fn read_request(stream: &std::net::TcpStream) -> Result<String> {
+    let mut reader = std::io::BufReader::new(stream.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    let _ = reader.read_line(&mut request_line)?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    // Query string is ignored
+    Ok(path.split('?').next().unwrap_or_default().to_owned())
+}
+
+fn respond(
+    mut stream: std::net::TcpStream,
+    handler: &(dyn Fn(&str) -> Option<Response> + Sync),
+) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let path = read_request(&stream)?;
+    let response = handler(&path).unwrap_or_else(|| Response {
+        status: "404 Not Found",
+        content_type: "text/plain",
+        body: "Not found\n".to_owned(),
+    });
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+pub fn bind(listen: &str) -> Result<std::net::TcpListener> {
+    let listener = std::net::TcpListener::bind(listen)
+        .map_err(|err| anyhow!("Cannot listen on {}: {}", listen, err))?;
+    // Accept is polled, so that interruption and `stop` are noticed
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Answer GET requests until `stop` is set or process is interrupted. Every connection is
+/// answered in own thread, so that slow client doesn't delay probes. Paths not handled by
+/// `handler` are answered with 404
+pub fn serve(
+    listener: &std::net::TcpListener,
+    stop: &AtomicBool,
+    handler: &(dyn Fn(&str) -> Option<Response> + Sync),
+) {
+    let connections = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        while !stop.load(Ordering::SeqCst) && !crate::platform::is_interrupted() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                        let _ = connections.fetch_sub(1, Ordering::SeqCst);
+                        warn!("Too many connections, closing new one");
+                        continue;
+                    }
+                    let connections = &connections;
+                    let _ = scope.spawn(move || {
+                        if let Err(err) = respond(stream, handler) {
+                            warn!("Cannot respond to request: {}", err)
+                        }
+                        let _ = connections.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(std::time::Duration::from_millis(100))
+                }
+                Err(err) => error!("Cannot accept connection: {}", err),
+            }
+        }
+    })
+}
+
+#[test]
+fn test_respond() {
+    let health = Health::default();
+    assert_eq!(health.respond("/healthz").unwrap().status, "200 OK");
+    assert_eq!(
+        health.respond("/readyz").unwrap().status,
+        "503 Service Unavailable"
+    );
+    health.start();
+    health.finish(&Ok(()));
+    health.set_pending(2);
+    let response = health.respond("/readyz").unwrap();
+    assert_eq!(response.status, "200 OK");
+    assert!(response.body.contains("\"pending\":2"));
+    assert!(response.body.contains("\"locked\":false"));
+    assert!(health.respond("/metrics").is_none());
+    health.finish(&Err(anyhow!("Failed")));
+    assert_eq!(health.respond("/readyz").unwrap().status, "200 OK");
+
+    let health = Health::strict();
+    health.finish(&Ok(()));
+    health.finish(&Err(anyhow!("Failed")));
+    assert_eq!(
+        health.respond("/readyz").unwrap().status,
+        "503 Service Unavailable"
+    );
+}
+
+#[test]
+fn test_respond_keeps_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("repomd.xml");
+    std::fs::write(&path, "").unwrap();
+    let health = Health::default().with_lock_path(path.clone());
+    assert!(health
+        .respond("/healthz")
+        .unwrap()
+        .body
+        .contains("\"locked\":false"));
+
+    health.start();
+    let _lock = crate::repodata::lock::LockConfig::default()
+        .lock(&path, true)
+        .unwrap();
+    assert!(health
+        .respond("/healthz")
+        .unwrap()
+        .body
+        .contains("\"locked\":true"));
+    assert!(!crate::repodata::lock::test_can_lock_in_child(&path));
+}
+
+#[test]
+fn test_serve() {
+    let listener = bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let stop = AtomicBool::new(false);
+    let health = Health::default();
+    std::thread::scope(|scope| {
+        let _ = scope.spawn(|| serve(&listener, &stop, &|path| health.respond(path)));
+
+        // Client which doesn't send anything doesn't delay others
+        let _idle = std::net::TcpStream::connect(address).unwrap();
+        let mut stream = std::net::TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        stop.store(true, Ordering::SeqCst);
+    });
+}
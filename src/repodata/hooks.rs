@@ -0,0 +1,124 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// Before "generate" and each regeneration of "watch", failure aborts generation
+    PreGenerate,
+    /// After metadata is published by "generate", {report} is path of changelog.json
+    PostGenerate,
+    /// Once per publication adding packages, {nevras} is path of file listing added NEVRAs, one
+    /// per line
+    OnPackageAdded,
+    /// After "generate" failed, {error} is set
+    OnFailure,
+}
+
+/// External command run on event. Command is not run by shell, so placeholders can't inject
+/// anything; use ["sh", "-c", "...", "hook", "{error}"] if shell is needed
+#[derive(Serialize, Deserialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    /// Program and its arguments. {repository}, {nevras}, {report} and {error} are replaced in
+    /// arguments, empty string is used if value is not defined for event
+    pub command: Vec<String>,
+    /// Run only for repositories with path matching one of patterns, for all if empty
+    #[serde(default)]
+    pub repositories: Vec<crate::glob::Glob>,
+}
+
+/// Placeholders are replaced in single pass, so values containing "{name}" are kept as is
+fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut r = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        r.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let value = tail.find('}').and_then(|end| {
+            let name = &tail[1..end];
+            vars.iter()
+                .find(|(v, _)| *v == name)
+                .map(|(_, value)| (*value, end + 1))
+        });
+        match value {
+            Some((value, len)) => {
+                r.push_str(value);
+                rest = &tail[len..];
+            }
+            None => {
+                r.push('{');
+                rest = &tail[1..];
+            }
+        }
+    }
+    r.push_str(rest);
+    r
+}
+
+impl Hook {
+    fn run(&self, vars: &[(&str, &str)]) -> Result<()> {
+        let (program, args) = match self.command.split_first() {
+            Some(v) => v,
+            None => bail!("Empty command of {:?} hook", self.event),
+        };
+        let args: Vec<_> = args.iter().map(|v| render(v, vars)).collect();
+        info!("Running {:?} hook {} {:?}", self.event, program, args);
+        let output = std::process::Command::new(program)
+            .args(&args)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .map_err(|err| anyhow!("Cannot run {:?} hook {}: {}", self.event, program, err))?;
+        if !output.status.success() {
+            bail!(
+                "{:?} hook {} failed with {}: {}",
+                self.event,
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        Ok(())
+    }
+}
+
+/// Run hooks of event configured for repository, stop on first failure
+pub fn run(
+    hooks: &[Hook],
+    event: HookEvent,
+    repository: &std::path::Path,
+    vars: &[(&str, &str)],
+) -> Result<()> {
+    let repository_str = repository.to_string_lossy();
+    let mut all_vars = vec![("repository", repository_str.as_ref())];
+    all_vars.extend_from_slice(vars);
+    for name in ["nevras", "report", "error"] {
+        if !all_vars.iter().any(|(v, _)| *v == name) {
+            all_vars.push((name, ""))
+        }
+    }
+
+    for hook in hooks.iter().filter(|v| v.event == event) {
+        if !hook.repositories.is_empty()
+            && !hook.repositories.iter().any(|v| v.is_match(repository))
+        {
+            continue;
+        }
+        hook.run(&all_vars)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_render() {
+    let vars = [("repository", "/srv/repo"), ("error", "no {repository}")];
+    assert_eq!(
+        render("{repository}/{error}.log", &vars),
+        "/srv/repo/no {repository}.log"
+    );
+    assert_eq!(
+        render("{unknown} {{repository}}", &vars),
+        "{unknown} {/srv/repo}"
+    );
+}
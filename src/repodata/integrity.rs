@@ -0,0 +1,119 @@
+//! Checks of packages listed in current metadata against files on disk, and re-indexing of
+//! packages which don't match
+
+use anyhow::{bail, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use slog_scope::{info, warn};
+
+/// Result of checking packages listed in current metadata against files on disk
+#[derive(Serialize, Debug)]
+pub struct Validation {
+    /// Number of packages listed in metadata
+    pub packages: usize,
+    /// Locations of packages listed in metadata, but missing on disk
+    pub missing: Vec<String>,
+}
+
+impl Validation {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Check that file checksum equals to one recorded in metadata
+    fn is_checksum_valid(
+        path: &std::path::Path,
+        checksum: &crate::repodata::primary::PackageChecksum,
+    ) -> bool {
+        let actual = match crate::digest::DigestType::of_name(&checksum.type_) {
+            Some(v) => crate::digest::path_digests(path, &[v]).map(|mut v| v.remove(0)),
+            None => return false,
+        };
+        match actual {
+            Ok(v) => v == checksum.value,
+            Err(err) => {
+                warn!("Cannot calculate checksum of {:?}: {}", path, err);
+                false
+            }
+        }
+    }
+
+    /// Re-index packages listed in current metadata without scanning repository tree. Records with
+    /// mismatched size, mtime or checksum are recalculated, missing fileslists are generated
+    /// from RPM headers, repomd.xml is written from scratch
+    pub fn repair(&self) -> Result<()> {
+        let state = crate::repodata::State::new(self.config, &self.options)?;
+
+        let packages: Vec<_> = state
+            .current_packages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(location, package)| (location.clone(), package.checksum.clone()))
+            .collect();
+        if packages.is_empty() {
+            bail!("No packages in current metadata, nothing to repair")
+        }
+
+        info!("Verifying checksums of {} packages", packages.len());
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.concurrency())
+            .build()
+            .unwrap();
+        let mismatched: Vec<_> = pool.install(|| {
+            packages
+                .par_iter()
+                .filter(|(location, checksum)| {
+                    let path = state.packages_root.join(location);
+                    path.exists() && !Self::is_checksum_valid(&path, checksum)
+                })
+                .map(|(location, _)| location.clone())
+                .collect()
+        });
+        crate::platform::check_interrupted()?;
+
+        {
+            let mut current_packages = state.current_packages.lock().unwrap();
+            for location in &mismatched {
+                warn!(
+                    "Checksum of {:?} doesn't match metadata, will recalculate",
+                    location
+                );
+                let _ = current_packages.remove(location);
+            }
+        }
+
+        let files: Vec<_> = packages
+            .iter()
+            .map(|(location, _)| state.packages_root.join(location))
+            .filter(|path| {
+                if path.exists() {
+                    true
+                } else {
+                    warn!("Package {:?} is missing on disk, removing from index", path);
+                    false
+                }
+            })
+            .collect();
+
+        self.register_files_list(state, &files)
+    }
+
+    pub fn validate(&self) -> Result<Validation> {
+        let state = crate::repodata::State::new(self.config, &self.options)?;
+
+        let current_packages = state.current_packages.lock().unwrap();
+        let mut missing: Vec<_> = current_packages
+            .keys()
+            .filter(|location| !state.packages_root.join(location).exists())
+            .map(|location| location.to_string_lossy().into_owned())
+            .collect();
+        missing.sort();
+        Ok(Validation {
+            packages: current_packages.len(),
+            missing,
+        })
+    }
+}
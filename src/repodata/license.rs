@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+/// Common spellings of licenses used in spec files mapped to SPDX identifiers
+const LICENSE_ALIASES: &[(&str, &str)] = &[
+    ("GPLv2", "GPL-2.0-only"),
+    ("GPLv2+", "GPL-2.0-or-later"),
+    ("GPL-2.0", "GPL-2.0-only"),
+    ("GPL-2.0+", "GPL-2.0-or-later"),
+    ("GPLv3", "GPL-3.0-only"),
+    ("GPLv3+", "GPL-3.0-or-later"),
+    ("GPL-3.0", "GPL-3.0-only"),
+    ("GPL-3.0+", "GPL-3.0-or-later"),
+    ("LGPLv2", "LGPL-2.0-only"),
+    ("LGPLv2+", "LGPL-2.0-or-later"),
+    ("LGPLv2.1", "LGPL-2.1-only"),
+    ("LGPLv2.1+", "LGPL-2.1-or-later"),
+    ("LGPLv3", "LGPL-3.0-only"),
+    ("LGPLv3+", "LGPL-3.0-or-later"),
+    ("AGPLv3", "AGPL-3.0-only"),
+    ("AGPLv3+", "AGPL-3.0-or-later"),
+    ("ASL 2.0", "Apache-2.0"),
+    ("ASL2.0", "Apache-2.0"),
+    ("Apache 2.0", "Apache-2.0"),
+    ("Apache License 2.0", "Apache-2.0"),
+    ("MPLv1.1", "MPL-1.1"),
+    ("MPLv2.0", "MPL-2.0"),
+    ("Python", "PSF-2.0"),
+    ("zlib", "Zlib"),
+    ("Boost", "BSL-1.0"),
+    ("Public Domain", "LicenseRef-Public-Domain"),
+];
+
+/// Normalize single license name, unknown names are returned with collapsed whitespace
+pub fn normalize(license: &str) -> String {
+    let license = license.split_whitespace().collect::<Vec<_>>().join(" ");
+    LICENSE_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(&license))
+        .map(|(_, spdx)| (*spdx).to_owned())
+        .unwrap_or(license)
+}
+
+/// Split license expression like "GPLv2+ and (MIT or BSD)" into normalized license names
+pub fn split_expression(expression: &str) -> Vec<String> {
+    lazy_static::lazy_static! {
+        static ref OPERATOR_RE: regex::Regex = regex::Regex::new("(?i)\\s+(?:and|or|with)\\s+|[()]").unwrap();
+    }
+
+    let mut r: Vec<_> = OPERATOR_RE
+        .split(expression)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(normalize)
+        .collect();
+    r.sort();
+    r.dedup();
+    r
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeniedLicense {
+    pub package: String,
+    pub license: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct LicenseReport {
+    pub packages: usize,
+    /// Number of packages per normalized license
+    pub licenses: BTreeMap<String, usize>,
+    /// Packages having license matched by denylist
+    pub denied: Vec<DeniedLicense>,
+}
+
+impl LicenseReport {
+    pub fn of_primary(
+        primary: &crate::repodata::primary::Primary,
+        denylist: &[regex::Regex],
+    ) -> Self {
+        let mut report = Self::default();
+        for package in &primary.package {
+            report.packages += 1;
+            let expression = package.format.rpm_license.as_deref().unwrap_or_default();
+            let licenses = if expression.trim().is_empty() {
+                vec!["(none)".to_owned()]
+            } else {
+                split_expression(expression)
+            };
+            for license in &licenses {
+                *report.licenses.entry(license.clone()).or_default() += 1;
+            }
+            if denylist.iter().any(|re| {
+                re.is_match(expression) || licenses.iter().any(|license| re.is_match(license))
+            }) {
+                report.denied.push(DeniedLicense {
+                    package: package.nevra(),
+                    license: expression.to_owned(),
+                })
+            }
+        }
+        report.denied.sort_by(|a, b| a.package.cmp(&b.package));
+        report
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    pub fn license_report(&self) -> Result<LicenseReport> {
+        let index = self.read_index()?;
+        Ok(LicenseReport::of_primary(
+            &index.primary,
+            &self.config.license_denylist,
+        ))
+    }
+}
+
+#[test]
+fn test_split_expression() {
+    assert_eq!(split_expression("GPLv2+"), vec!["GPL-2.0-or-later"]);
+    assert_eq!(
+        split_expression("GPLv2+ and (MIT or ASL  2.0)"),
+        vec!["Apache-2.0", "GPL-2.0-or-later", "MIT"]
+    );
+    assert_eq!(
+        split_expression("GPL-2.0-only WITH Linux-syscall-note"),
+        vec!["GPL-2.0-only", "Linux-syscall-note"]
+    );
+}
@@ -0,0 +1,205 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+fn default_poll_interval() -> u64 {
+    500
+}
+
+/// Acquisition of lock on repomd.xml. Updates of repository take exclusive lock, read-only commands
+/// take shared one if `shared_for_readers` is set
+#[derive(Serialize, Deserialize)]
+pub struct LockConfig {
+    /// Give up waiting for lock after this number of seconds, wait indefinitely if not set
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Delay between attempts to take lock in milliseconds, used with timeout
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    /// Fail immediately if repository is locked by another process
+    #[serde(default)]
+    pub fail_fast: bool,
+    /// Read-only commands wait for running update, and updates wait for them
+    #[serde(default)]
+    pub shared_for_readers: bool,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            poll_interval: default_poll_interval(),
+            fail_fast: false,
+            shared_for_readers: false,
+        }
+    }
+}
+
+fn is_locked_by_other(err: &std::io::Error) -> bool {
+    err.kind() == std::io::ErrorKind::WouldBlock
+        || matches!(err.raw_os_error(), Some(libc::EAGAIN) | Some(libc::EACCES))
+}
+
+impl LockConfig {
+    /// Lock is exclusive if file is opened for writing and shared otherwise
+    fn options(exclusive: bool) -> file_lock::FileOptions {
+        if exclusive {
+            file_lock::FileOptions::new().write(true)
+        } else {
+            file_lock::FileOptions::new().read(true)
+        }
+    }
+
+    pub fn lock(&self, path: &std::path::Path, exclusive: bool) -> Result<file_lock::FileLock> {
+        info!(
+            "Setting {} lock on {:?}",
+            if exclusive { "exclusive" } else { "shared" },
+            path
+        );
+        if !self.fail_fast && self.timeout.is_none() {
+            return file_lock::FileLock::lock(path, true, Self::options(exclusive))
+                .map_err(|err| anyhow!("Cannot lock {:?}: {}", path, err));
+        }
+
+        let deadline =
+            std::time::Instant::now() + std::time::Duration::from_secs(self.timeout.unwrap_or(0));
+        loop {
+            match file_lock::FileLock::lock(path, false, Self::options(exclusive)) {
+                Ok(v) => return Ok(v),
+                Err(err) if is_locked_by_other(&err) => {
+                    if self.fail_fast || std::time::Instant::now() >= deadline {
+                        return Err(anyhow!("{:?} is locked by another process", path)
+                            .context(crate::exit_code::Failure::LockTimeout));
+                    }
+                    crate::platform::check_interrupted()?;
+                    std::thread::sleep(std::time::Duration::from_millis(self.poll_interval))
+                }
+                Err(err) => bail!("Cannot lock {:?}: {}", path, err),
+            }
+        }
+    }
+}
+
+/// Lock on `path` is held by another process. Locks of this process are not reported: locks of
+/// fcntl don't conflict within one process. Must not be called while this process holds lock on
+/// `path`: closing the file drops all fcntl locks of process on it
+pub fn is_locked_by_other_process(path: &std::path::Path) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let file = match std::fs::File::open(path) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } == -1 {
+        return false;
+    }
+    lock.l_type != libc::F_UNLCK as _
+}
+
+/// Child process can take exclusive lock on `path`
+#[cfg(test)]
+pub fn test_can_lock_in_child(path: &std::path::Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+    let child = unsafe { libc::fork() };
+    assert!(child >= 0);
+    if child == 0 {
+        // Only async-signal-safe calls after fork
+        unsafe {
+            let fd = libc::open(c_path.as_ptr(), libc::O_RDWR);
+            let mut lock: libc::flock = std::mem::zeroed();
+            lock.l_type = libc::F_WRLCK as _;
+            lock.l_whence = libc::SEEK_SET as _;
+            let locked = fd >= 0 && libc::fcntl(fd, libc::F_SETLK, &lock) == 0;
+            libc::_exit(if locked { 0 } else { 1 })
+        }
+    }
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+    libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+}
+
+/// Exclusive lock is held by child process until it is killed: locks of fcntl don't conflict
+/// within one process
+#[cfg(test)]
+fn test_lock_in_child(path: &std::path::Path) -> libc::pid_t {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+    let mut pipe = [0; 2];
+    assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+    let child = unsafe { libc::fork() };
+    assert!(child >= 0);
+    if child == 0 {
+        // Only async-signal-safe calls after fork
+        unsafe {
+            let fd = libc::open(c_path.as_ptr(), libc::O_RDWR);
+            let mut lock: libc::flock = std::mem::zeroed();
+            lock.l_type = libc::F_WRLCK as _;
+            lock.l_whence = libc::SEEK_SET as _;
+            let status: &[u8] = if fd >= 0 && libc::fcntl(fd, libc::F_SETLK, &lock) == 0 {
+                b"y"
+            } else {
+                b"n"
+            };
+            let _ = libc::write(pipe[1], status.as_ptr() as *const libc::c_void, 1);
+            loop {
+                let _ = libc::pause();
+            }
+        }
+    }
+    let mut status = [0u8; 1];
+    assert_eq!(
+        unsafe { libc::read(pipe[0], status.as_mut_ptr() as *mut libc::c_void, 1) },
+        1
+    );
+    unsafe {
+        let _ = libc::close(pipe[0]);
+        let _ = libc::close(pipe[1]);
+    }
+    assert_eq!(&status, b"y");
+    child
+}
+
+#[test]
+fn test_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("repomd.xml");
+    std::fs::write(&path, "").unwrap();
+    assert!(!is_locked_by_other_process(&path));
+    let child = test_lock_in_child(&path);
+    assert!(is_locked_by_other_process(&path));
+
+    let is_lock_timeout = |err: anyhow::Error| {
+        err.downcast_ref::<crate::exit_code::Failure>()
+            == Some(&crate::exit_code::Failure::LockTimeout)
+    };
+
+    let fail_fast = LockConfig {
+        fail_fast: true,
+        ..Default::default()
+    };
+    let started = std::time::Instant::now();
+    assert!(is_lock_timeout(fail_fast.lock(&path, false).unwrap_err()));
+    assert!(started.elapsed() < std::time::Duration::from_secs(1));
+
+    let timeout = LockConfig {
+        timeout: Some(1),
+        poll_interval: 100,
+        ..Default::default()
+    };
+    let started = std::time::Instant::now();
+    assert!(is_lock_timeout(timeout.lock(&path, true).unwrap_err()));
+    assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+
+    unsafe {
+        let _ = libc::kill(child, libc::SIGKILL);
+        let _ = libc::waitpid(child, std::ptr::null_mut(), 0);
+    }
+    assert!(!is_locked_by_other_process(&path));
+    let _lock = timeout.lock(&path, true).unwrap();
+}
@@ -0,0 +1,101 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use anyhow::{anyhow, bail, Result};
+use slog_scope::{error, warn};
+
+/// Fileslists records are spilled to disk in files of this number of packages, fewer records are
+/// kept in memory
+pub const SPILL_CHUNK: usize = 1000;
+
+/// Parse size like "1048576", "512K", "512M" or "4G"
+pub fn parse_size(v: &str) -> Result<u64> {
+    let v = v.trim();
+    let (number, multiplier) = match v.char_indices().last() {
+        Some((pos, 'K' | 'k')) => (&v[..pos], 1 << 10),
+        Some((pos, 'M' | 'm')) => (&v[..pos], 1 << 20),
+        Some((pos, 'G' | 'g')) => (&v[..pos], 1 << 30),
+        _ => (v, 1),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|err| anyhow!("Invalid size {:?}: {}", v, err))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("Size {:?} is too large", v))
+}
+
+/// Backpressure by resident memory of process. Above 80% of limit accumulated records are spilled
+/// to disk and packages are processed one at a time, if limit is still reached after spilling,
+/// processing stops
+pub struct MemoryGuard {
+    limit: u64,
+    throttle: Mutex<()>,
+    throttling: AtomicBool,
+    exceeded: AtomicBool,
+}
+
+impl MemoryGuard {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            throttle: Mutex::new(()),
+            throttling: AtomicBool::new(false),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Run `f` processing one package. `spill` moves accumulated records out of memory
+    pub fn run<S, F>(&self, spill: S, f: F)
+    where
+        S: FnOnce() -> Result<()>,
+        F: FnOnce(),
+    {
+        let rss = match crate::platform::process_rss() {
+            Some(rss) if rss >= self.limit / 10 * 8 => rss,
+            _ => return f(),
+        };
+        if !self.throttling.swap(true, Ordering::SeqCst) {
+            warn!(
+                "Memory usage {} B is close to limit of {} B, spilling records to disk and processing packages one at a time",
+                rss, self.limit
+            );
+        }
+        let _guard = self.throttle.lock().unwrap();
+        if let Err(err) = spill() {
+            warn!("Cannot spill records to disk: {}", err)
+        }
+        match crate::platform::process_rss() {
+            Some(rss) if rss >= self.limit => {
+                if !self.exceeded.swap(true, Ordering::SeqCst) {
+                    error!(
+                        "Memory usage {} B reached limit of {} B, stopping",
+                        rss, self.limit
+                    );
+                }
+            }
+            _ => f(),
+        }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if self.exceeded.load(Ordering::SeqCst) {
+            bail!(
+                "Memory limit of {} B exceeded, metadata is not updated",
+                self.limit
+            )
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("1024").unwrap(), 1024);
+    assert_eq!(parse_size("512K").unwrap(), 512 << 10);
+    assert_eq!(parse_size("4G").unwrap(), 4 << 30);
+    assert!(parse_size("4X").is_err());
+    assert!(parse_size("18446744073709551615G").is_err());
+}
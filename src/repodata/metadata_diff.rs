@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, bail, Result};
+use quick_xml::events::{BytesStart, Event};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+#[derive(Serialize, Debug)]
+pub struct PackageDifference {
+    pub package: String,
+    pub differences: Vec<String>,
+}
+
+/// Semantic differences between metadata of two repositories. Ordering of packages and entries,
+/// whitespace and empty values are not considered as differences
+#[derive(Serialize, Debug, Default)]
+pub struct MetadataDiff {
+    /// Data types listed in repomd.xml of only one repository
+    pub repomd: Vec<String>,
+    /// Locations of packages listed in only one repository
+    pub only_left: Vec<String>,
+    pub only_right: Vec<String>,
+    pub primary: Vec<PackageDifference>,
+    /// Pkgids of fileslists records present in only one repository
+    pub filelists_only_left: Vec<String>,
+    pub filelists_only_right: Vec<String>,
+    pub filelists: Vec<PackageDifference>,
+}
+
+/// Metadata documents of repository. Records are kept as generic trees of elements, so that
+/// elements rpm-tool doesn't model (weak dependencies, rpm:header-range etc.) are compared too
+pub struct Documents {
+    repomd: crate::repodata::repomd::Repomd,
+    /// Records of primary by location
+    primary: BTreeMap<String, Value>,
+    /// Records of fileslists by pkgid
+    filelists: Option<BTreeMap<String, Value>>,
+}
+
+impl Documents {
+    pub fn read(path: &std::path::Path) -> Result<Self> {
+        let repomd =
+            crate::repodata::repomd::Repomd::read(&path.join("repodata").join("repomd.xml"))?;
+        let location = |data_type: crate::repodata::repomd::DataType| {
+            repomd
+                .data
+                .iter()
+                .find(|elt| elt.type_ == data_type)
+                .map(|elt| path.join(&elt.location.href))
+        };
+
+        let primary = match location(crate::repodata::repomd::DataType::Primary) {
+            Some(v) => read_records(&v, |record| {
+                record
+                    .get("location")?
+                    .get(0)?
+                    .get("@href")?
+                    .as_str()
+                    .map(|v| v.to_owned())
+            })?,
+            None => bail!("No 'primary' record in repomd.xml"),
+        };
+        let filelists = match location(crate::repodata::repomd::DataType::Filelists) {
+            Some(v) => Some(read_records(&v, |record| {
+                record.get("@pkgid")?.as_str().map(|v| v.to_owned())
+            })?),
+            None => None,
+        };
+
+        Ok(Self {
+            repomd,
+            primary,
+            filelists,
+        })
+    }
+}
+
+/// Element as JSON object: attributes are "@name" keys, text is "$value" and child elements are
+/// lists under their local names. Element having only text is string
+struct Element {
+    name: String,
+    map: Map<String, Value>,
+    text: String,
+}
+
+impl Element {
+    fn new(e: &BytesStart) -> Result<Self> {
+        let mut map = Map::new();
+        for attribute in e.attributes() {
+            let attribute = attribute.map_err(|err| anyhow!("{}", err))?;
+            let _ = map.insert(
+                format!(
+                    "@{}",
+                    String::from_utf8_lossy(attribute.key.local_name().as_ref())
+                ),
+                Value::String(attribute.unescape_value()?.to_string()),
+            );
+        }
+        Ok(Self {
+            name: String::from_utf8_lossy(e.local_name().as_ref()).to_string(),
+            map,
+            text: String::new(),
+        })
+    }
+
+    fn into_value(mut self) -> Value {
+        if self.map.is_empty() {
+            return Value::String(self.text);
+        }
+        if !self.text.trim().is_empty() {
+            let _ = self
+                .map
+                .insert("$value".to_owned(), Value::String(self.text));
+        }
+        Value::Object(self.map)
+    }
+}
+
+/// Add completed element to its parent, or to records if it's a package
+fn close_element(
+    stack: &mut Vec<Element>,
+    records: &mut Vec<Value>,
+    element: Element,
+) -> Result<()> {
+    let name = element.name.clone();
+    let value = element.into_value();
+    match stack.last_mut() {
+        Some(parent) => {
+            match parent
+                .map
+                .entry(name)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                Value::Array(list) => list.push(value),
+                _ => bail!("Element conflicts with attribute"),
+            }
+        }
+        None => records.push(value),
+    }
+    Ok(())
+}
+
+/// Package records of metadata document as element trees, keyed by `key`
+fn read_records<F>(path: &std::path::Path, key: F) -> Result<BTreeMap<String, Value>>
+where
+    F: Fn(&Value) -> Option<String>,
+{
+    let read = || -> Result<Vec<Value>> {
+        let file = std::fs::File::open(path)?;
+        let input = crate::decompress::decompress(std::io::BufReader::new(file))?;
+        let mut reader = quick_xml::Reader::from_reader(std::io::BufReader::new(input));
+        let mut buf = Vec::new();
+        let mut depth = 0;
+        let mut stack: Vec<Element> = Vec::new();
+        let mut records = Vec::new();
+        loop {
+            match reader.read_event_into(&mut buf)? {
+                Event::Start(e) => {
+                    if !stack.is_empty() || (depth == 1 && e.local_name().as_ref() == b"package") {
+                        stack.push(Element::new(&e)?)
+                    }
+                    depth += 1;
+                }
+                Event::Empty(e) => {
+                    if !stack.is_empty() || (depth == 1 && e.local_name().as_ref() == b"package") {
+                        close_element(&mut stack, &mut records, Element::new(&e)?)?
+                    }
+                }
+                Event::Text(e) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&e.unescape()?)
+                    }
+                }
+                Event::CData(e) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&String::from_utf8_lossy(&e))
+                    }
+                }
+                Event::End(_) => {
+                    depth -= 1;
+                    if let Some(element) = stack.pop() {
+                        close_element(&mut stack, &mut records, element)?
+                    }
+                }
+                Event::Eof => break,
+                _ => (),
+            }
+            buf.clear();
+        }
+        Ok(records)
+    };
+    let records = read().map_err(|err| anyhow!("Cannot read {:?}: {}", path, err))?;
+
+    let mut r = BTreeMap::new();
+    for (n, record) in records.into_iter().enumerate() {
+        match key(&record) {
+            Some(v) => {
+                let _ = r.insert(v, record);
+            }
+            None => bail!("Record {} of {:?} has no identifier", n + 1, path),
+        }
+    }
+    Ok(r)
+}
+
+/// Strings are trimmed, empty strings and lists are the same as missing values
+fn normalize(value: Value) -> Value {
+    match value {
+        Value::String(v) if v.trim().is_empty() => Value::Null,
+        Value::String(v) => Value::String(v.trim().to_owned()),
+        Value::Array(list) if list.is_empty() => Value::Null,
+        Value::Array(list) => Value::Array(list.into_iter().map(normalize).collect()),
+        Value::Object(map) => {
+            Value::Object(map.into_iter().map(|(k, v)| (k, normalize(v))).collect())
+        }
+        v => v,
+    }
+}
+
+/// Lists are compared as sets
+fn diff_values(path: &str, left: &Value, right: &Value, r: &mut Vec<String>) {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            let keys: BTreeSet<_> = left.keys().chain(right.keys()).collect();
+            for key in keys {
+                let name = key.trim_start_matches('@');
+                let path = if key == "$value" {
+                    path.to_owned()
+                } else if path.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                diff_values(
+                    &path,
+                    left.get(key).unwrap_or(&Value::Null),
+                    right.get(key).unwrap_or(&Value::Null),
+                    r,
+                )
+            }
+        }
+        // Single elements are compared field by field
+        (Value::Array(left), Value::Array(right)) if left.len() == 1 && right.len() == 1 => {
+            diff_values(path, &left[0], &right[0], r)
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            let set = |list: &[Value]| list.iter().map(|v| v.to_string()).collect::<BTreeSet<_>>();
+            let (left, right) = (set(left), set(right));
+            for v in left.difference(&right) {
+                r.push(format!("{}: only left {}", path, v))
+            }
+            for v in right.difference(&left) {
+                r.push(format!("{}: only right {}", path, v))
+            }
+        }
+        (left, right) if left != right => r.push(format!("{}: {} != {}", path, left, right)),
+        _ => (),
+    }
+}
+
+fn diff(left: &Value, right: &Value) -> Vec<String> {
+    let mut r = Vec::new();
+    diff_values(
+        "",
+        &normalize(left.clone()),
+        &normalize(right.clone()),
+        &mut r,
+    );
+    r
+}
+
+/// Differences of records present in both maps, keys of records present in only one of them
+fn diff_records(
+    left: &BTreeMap<String, Value>,
+    right: &BTreeMap<String, Value>,
+    only_left: &mut Vec<String>,
+    only_right: &mut Vec<String>,
+    differences: &mut Vec<PackageDifference>,
+) {
+    for (key, record) in left {
+        match right.get(key) {
+            None => only_left.push(key.clone()),
+            Some(other) => {
+                let v = diff(record, other);
+                if !v.is_empty() {
+                    differences.push(PackageDifference {
+                        package: key.clone(),
+                        differences: v,
+                    })
+                }
+            }
+        }
+    }
+    only_right.extend(right.keys().filter(|v| !left.contains_key(*v)).cloned());
+}
+
+impl MetadataDiff {
+    pub fn new(left: &Documents, right: &Documents) -> Self {
+        let mut r = Self::default();
+
+        let data_types = |repomd: &crate::repodata::repomd::Repomd| -> BTreeSet<String> {
+            repomd
+                .data
+                .iter()
+                .map(|v| v.type_.as_str().to_owned())
+                .collect()
+        };
+        let (left_types, right_types) = (data_types(&left.repomd), data_types(&right.repomd));
+        for v in left_types.difference(&right_types) {
+            r.repomd.push(format!("only left {}", v))
+        }
+        for v in right_types.difference(&left_types) {
+            r.repomd.push(format!("only right {}", v))
+        }
+
+        diff_records(
+            &left.primary,
+            &right.primary,
+            &mut r.only_left,
+            &mut r.only_right,
+            &mut r.primary,
+        );
+        if let (Some(left_filelists), Some(right_filelists)) = (&left.filelists, &right.filelists) {
+            diff_records(
+                left_filelists,
+                right_filelists,
+                &mut r.filelists_only_left,
+                &mut r.filelists_only_right,
+                &mut r.filelists,
+            );
+        }
+
+        r
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.repomd.is_empty()
+            && self.only_left.is_empty()
+            && self.only_right.is_empty()
+            && self.primary.is_empty()
+            && self.filelists_only_left.is_empty()
+            && self.filelists_only_right.is_empty()
+            && self.filelists.is_empty()
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Compare metadata of this repository with metadata of repository at `other`
+    pub fn metadata_diff(&self, other: &std::path::Path) -> Result<MetadataDiff> {
+        let _lock = self.read_lock()?;
+        let left = Documents::read(&self.options.path)?;
+        let right = Documents::read(other)?;
+        Ok(MetadataDiff::new(&left, &right))
+    }
+}
+
+#[test]
+fn test_diff_values() {
+    let left = normalize(serde_json::json!({
+        "name": {"$value": "foo"},
+        "vendor": "",
+        "provides": ["a", "b"],
+        "version": {"@ver": "1.0"},
+    }));
+    let right = normalize(serde_json::json!({
+        "name": {"$value": " foo\n"},
+        "provides": ["c", "a"],
+        "version": {"@ver": "1.1"},
+    }));
+    let mut r = Vec::new();
+    diff_values("", &left, &right, &mut r);
+    assert_eq!(
+        r,
+        vec![
+            "provides: only left \"b\"",
+            "provides: only right \"c\"",
+            "version.ver: \"1.0\" != \"1.1\"",
+        ]
+    );
+}
+
+#[test]
+fn test_read_records() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, recommends: &str| {
+        let path = dir.path().join(name);
+        let package = crate::repodata::primary::TestPackage::new("foo", "1.0").format(&format!(
+            r#"<rpm:header-range start="4504" end="8000"/><rpm:requires><rpm:entry name="bar"/></rpm:requires><rpm:recommends>{}</rpm:recommends>"#,
+            recommends
+        ));
+        std::fs::write(
+            &path,
+            crate::repodata::primary::test_primary_xml(&[package]),
+        )
+        .unwrap();
+        read_records(&path, |record| {
+            record
+                .get("location")?
+                .get(0)?
+                .get("@href")?
+                .as_str()
+                .map(|v| v.to_owned())
+        })
+        .unwrap()
+    };
+    let left = write("left.xml", r#"<rpm:entry name="baz"/>"#);
+    let right = write(
+        "right.xml",
+        r#"<rpm:entry name="baz"/><rpm:entry name="qux"/>"#,
+    );
+    assert_eq!(left.len(), 1);
+    assert_eq!(
+        diff(
+            &left["foo-1.0-1.x86_64.rpm"],
+            &right["foo-1.0-1.x86_64.rpm"]
+        ),
+        vec![r#"format.recommends.entry: only right {"@name":"qux"}"#]
+    );
+}
@@ -1,8 +1,47 @@
+mod adaptive;
+pub mod changelog;
+mod checkpoint;
+pub mod compose;
+mod csaf;
+pub mod error_policy;
+pub mod exporter;
 mod filelists;
+mod filelists_index;
+pub mod health;
+mod hooks;
+mod integrity;
+mod license;
+mod lock;
+pub mod memory;
+mod metadata_diff;
+mod organize;
+mod oval;
+pub mod package_check;
+mod permissions;
+mod prestodelta;
 pub mod primary;
-mod repomd;
-
-use anyhow::{anyhow, Result};
+pub mod profiles;
+mod query;
+mod records;
+pub mod repomd;
+mod retry;
+mod sanitize;
+mod schema;
+pub mod shard;
+mod sidecar;
+pub mod signature;
+mod stats;
+mod tags;
+mod timeout;
+mod trash;
+mod treeinfo;
+pub mod updateinfo;
+mod upload;
+pub mod verify_file;
+mod waste;
+pub mod watch;
+
+use anyhow::{anyhow, bail, Result};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use slog::slog_o;
@@ -10,78 +49,589 @@
 use std::{
     collections::{HashMap, HashSet},
     io::Write,
-    os::linux::fs::MetadataExt,
     rc::Rc,
     sync::{Arc, Mutex},
 };
 
+/// List of superseded metadata files with time since they are not referenced by repomd.xml
+const SUPERSEDED_FILE: &str = ".superseded.json";
+
+/// Files opened simultaneously by one worker: package read for header, the same package read for
+/// checksum, its sidecar checksum file (sidecar_checksums) and one read left by helper thread
+/// blocked on timed out package (package_timeout)
+const OPEN_FILES_PER_WORKER: u64 = 4;
+
+/// Open files kept for logger, lock, metadata files and standard streams
+const OPEN_FILES_RESERVED: u64 = 64;
+
+/// Subdirectory of repository where debug packages are published with --split-debug
+const DEBUG_REPOSITORY: &str = "debug";
+
+/// Subdirectory of repository where architecture views generated with --arch are published
+const ARCH_VIEWS_DIR: &str = "views";
+
+/// Prefix of hrefs pointing from repository in `relative_root` to the root of package tree, e.g.
+/// "../.." for "views/x86_64"
+fn parent_prefix(relative_root: &std::path::Path) -> std::path::PathBuf {
+    relative_root.components().map(|_| "..").collect()
+}
+
+fn default_cleanup_grace_period() -> u64 {
+    3600
+}
+
+/// Gain of parallel compression is small above this number of threads
+const MAX_DEFAULT_COMPRESSION_THREADS: usize = 4;
+
+fn default_compression_threads() -> usize {
+    std::thread::available_parallelism()
+        .map_or(1, |v| v.get())
+        .min(MAX_DEFAULT_COMPRESSION_THREADS)
+}
+
+fn default_checkpoint_interval() -> u64 {
+    600
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RepodataConfig {
     pub concurrency: usize,
+    /// Tune number of simultaneously processed packages by measured throughput, `concurrency` is
+    /// the upper bound then
+    #[serde(default)]
+    pub adaptive_concurrency: bool,
+    /// Records of processed packages are saved every this number of seconds and on interruption, so
+    /// that run can be resumed with --resume. 0 disables checkpoints
+    #[serde(default = "default_checkpoint_interval")]
+    pub checkpoint_interval: u64,
+    /// Reading and hashing of single package is abandoned after this number of seconds, package
+    /// is reported as failed, as well as other packages on the same mount. 0 disables timeout
+    #[serde(default)]
+    pub package_timeout: u64,
+    /// Metadata is compressed by gzp in this number of threads if greater than 1, by flate2 otherwise.
+    /// Number of CPUs up to 4 by default
+    #[serde(default = "default_compression_threads")]
+    pub compression_threads: usize,
     #[serde(with = "serde_regex")]
     pub useful_files: regex::Regex,
+    /// Reuse checksums from <package>.sha1 files written by build system instead of reading
+    /// packages, if they are not older than packages and record their size
+    #[serde(default)]
+    pub sidecar_checksums: bool,
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    #[serde(default = "default_cleanup_grace_period")]
+    pub cleanup_grace_period: u64,
+    #[serde(default)]
+    pub permissions: crate::repodata::permissions::PermissionsConfig,
+    /// Directory with RELAX NG schemas of repodata documents, built-in schemas are used if not set
+    #[serde(default)]
+    pub schema_dir: Option<std::path::PathBuf>,
+    /// Licenses reported by "repository licenses" as denied
+    #[serde(default, with = "serde_regex")]
+    pub license_denylist: Vec<regex::Regex>,
+    /// Write .treeinfo on generate so that repository can be used as installation tree
+    #[serde(default)]
+    pub treeinfo: Option<crate::repodata::treeinfo::TreeinfoConfig>,
+    /// Product certificate published as productid metadata for subscription-manager clients
+    #[serde(default)]
+    pub productid: Option<std::path::PathBuf>,
+    /// Trusted keys of repository. If configured, cached metadata is reused only if repomd.xml.asc
+    /// is absent or valid
+    #[serde(default)]
+    pub gpg: crate::repodata::signature::GpgConfig,
+    /// Retries of package reads and metadata writes failed with transient IO errors
+    #[serde(default)]
+    pub retry: crate::repodata::retry::RetryConfig,
+    /// Preferred locale of translated summary, description and group, "C" if not set
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Cleanup and size limits of strings taken from RPM headers
+    #[serde(default)]
+    pub sanitize: crate::repodata::sanitize::SanitizeConfig,
+    /// Move packages removed by "repository remove" into .trash/ instead of deleting them
+    #[serde(default)]
+    pub trash: Option<crate::repodata::trash::TrashConfig>,
+    /// Destination of packages added by "repository upload"
+    #[serde(default)]
+    pub upload: crate::repodata::upload::UploadConfig,
+    /// Debounce and batching of changes in watch mode
+    #[serde(default)]
+    pub watch: crate::repodata::watch::WatchConfig,
+    #[serde(default)]
+    pub lock: crate::repodata::lock::LockConfig,
+    /// Directory where new metadata is prepared before it is moved into repodata/, repository root
+    /// if not set. Must be on the same filesystem as repository
+    #[serde(default)]
+    pub temp_dir: Option<std::path::PathBuf>,
+    /// Directory for unpublished transient files: spilled and decompressed fileslists, fetched
+    /// metadata of remote repositories. If not set, fileslists are kept in temporary directory of
+    /// new metadata and other files in system temporary directory
+    #[serde(default)]
+    pub scratch_dir: Option<std::path::PathBuf>,
+    /// Directory where statistics of runs are recorded, for "repository trends". It must not be
+    /// in published tree. Statistics are not recorded if not set
+    #[serde(default)]
+    pub stats_dir: Option<std::path::PathBuf>,
+    /// Bearer token sent to remote repositories, e.g. by "rpm check-against-repo"
+    #[serde(default)]
+    pub http_token: Option<crate::secret::Secret>,
+    /// External commands run on generation events
+    #[serde(default)]
+    pub hooks: Vec<crate::repodata::hooks::Hook>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl RepodataConfig {
+    /// Temporary directory on scratch disk
+    pub fn scratch_tempdir(&self, prefix: &str) -> Result<tempfile::TempDir> {
+        let mut builder = tempfile::Builder::new();
+        let _ = builder.prefix(prefix);
+        match &self.scratch_dir {
+            Some(dir) => builder.tempdir_in(dir).map_err(|err| {
+                anyhow!("Cannot create temporary directory in {:?}: {}", dir, err)
+                    .context(crate::exit_code::Failure::Io)
+            }),
+            None => Ok(builder.tempdir()?),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct RepodataOptions {
     pub generate_fileslists: bool,
+    pub follow_symlinks: bool,
+    pub scan_filter: ScanFilter,
+    pub package_filter: PackageFilter,
+    /// Index only this number of newest versions of each package
+    pub latest_only: Option<usize>,
+    /// Publish debuginfo and debugsource packages in separate repository in debug/
+    pub split_debug: bool,
+    /// Remove records about packages missing on disk when cached metadata is reused, "generate"
+    /// reports them
+    pub prune_missing: bool,
+    pub timestamp: Option<u64>,
+    pub self_check: bool,
+    /// Fail on unreadable header values instead of using defaults
+    pub strict: bool,
+    /// Continue or abort when packages can't be indexed
+    pub on_error: crate::repodata::error_policy::ErrorPolicy,
+    /// Resident memory limit in bytes, processing of packages is throttled near it and stopped above it
+    pub max_memory: Option<u64>,
+    /// Reuse records saved to checkpoint by interrupted run
+    pub resume: bool,
+    /// Generate partial metadata of this shard in .shards/ instead of repository metadata
+    pub shard: Option<crate::repodata::shard::Shard>,
+    /// Identifier of sharded run, recorded in shards and checked by merge
+    pub shard_run_id: Option<String>,
+    /// Read-only commands don't take shared lock, e.g. on snapshot copy of repository
+    pub no_lock: bool,
+    /// Write metadata here instead of package tree, hrefs stay relative to package tree
+    pub output_path: Option<std::path::PathBuf>,
     pub path: std::path::PathBuf,
 }
 
+impl RepodataOptions {
+    /// Root of repository and prefix of hrefs pointing from it to package tree. Architecture views
+    /// are published in views/<arch>/, so views of the same tree don't overwrite each other
+    pub fn repository_root(&self) -> (std::path::PathBuf, std::path::PathBuf) {
+        self.repository_root_of(std::path::Path::new(""))
+    }
+
+    /// The same as repository_root() for repository of packages in `subtree` of package tree
+    pub fn repository_root_of(
+        &self,
+        subtree: &std::path::Path,
+    ) -> (std::path::PathBuf, std::path::PathBuf) {
+        match self.package_filter.view_name() {
+            Some(name) => {
+                let relative_root = std::path::Path::new(ARCH_VIEWS_DIR)
+                    .join(name)
+                    .join(subtree);
+                (
+                    self.path.join(&relative_root),
+                    parent_prefix(&relative_root).join(subtree),
+                )
+            }
+            None => (self.path.join(subtree), std::path::PathBuf::new()),
+        }
+    }
+
+    /// Directory where repodata/ of repository at `root` is written: `root` itself, or its
+    /// counterpart in output directory
+    pub fn metadata_root(&self, root: &std::path::Path) -> std::path::PathBuf {
+        match (&self.output_path, root.strip_prefix(&self.path)) {
+            (Some(output_path), Ok(relative_path)) => output_path.join(relative_path),
+            _ => root.to_path_buf(),
+        }
+    }
+
+    /// Fixed timestamp for reproducible builds, taken from options or SOURCE_DATE_EPOCH
+    pub fn reproducible_timestamp(&self) -> Result<Option<u64>> {
+        if let Some(v) = self.timestamp {
+            return Ok(Some(v));
+        }
+        match std::env::var("SOURCE_DATE_EPOCH") {
+            Ok(v) => {
+                let timestamp = v
+                    .trim()
+                    .parse()
+                    .map_err(|err| anyhow!("Invalid SOURCE_DATE_EPOCH {:?}: {}", v, err))?;
+                Ok(Some(timestamp))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+/// Rules restricting which files of repository tree are indexed, paths are relative to its root
+#[derive(Serialize, Deserialize, Default)]
+pub struct ScanFilter {
+    pub include: Vec<crate::glob::Glob>,
+    pub exclude: Vec<crate::glob::Glob>,
+    pub max_depth: Option<usize>,
+}
+
+impl ScanFilter {
+    /// Check if path or any of its parent directories matches exclude pattern
+    pub fn is_excluded(&self, relative_path: &std::path::Path) -> bool {
+        relative_path
+            .ancestors()
+            .filter(|v| !v.as_os_str().is_empty())
+            .any(|v| self.exclude.iter().any(|glob| glob.is_match(v)))
+    }
+
+    pub fn is_included(&self, relative_path: &std::path::Path) -> bool {
+        self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(relative_path))
+    }
+
+    pub fn is_too_deep(&self, relative_path: &std::path::Path) -> bool {
+        match self.max_depth {
+            Some(max_depth) => relative_path.components().count() > max_depth,
+            None => false,
+        }
+    }
+}
+
+/// Rules restricting which packages are indexed, checked against package headers
+#[derive(Serialize, Deserialize, Default)]
+pub struct PackageFilter {
+    pub arch: Vec<String>,
+    /// Patterns matched against package name or NEVRA
+    pub include: Vec<crate::glob::Glob>,
+    pub exclude: Vec<crate::glob::Glob>,
+}
+
+impl PackageFilter {
+    /// Name of architecture view directory, e.g. "i686-x86_64"
+    pub fn view_name(&self) -> Option<String> {
+        if self.arch.is_empty() {
+            return None;
+        }
+        let mut arch = self.arch.clone();
+        arch.sort();
+        arch.dedup();
+        Some(arch.join("-"))
+    }
+
+    /// noarch packages are part of every architecture view
+    fn is_arch_accepted(&self, package: &crate::repodata::primary::Package) -> bool {
+        if self.arch.is_empty() {
+            return true;
+        }
+        match &package.arch {
+            Some(arch) => arch.value == "noarch" || self.arch.contains(&arch.value),
+            None => false,
+        }
+    }
+
+    pub fn is_accepted(&self, package: &crate::repodata::primary::Package) -> bool {
+        if !self.is_arch_accepted(package) {
+            return false;
+        }
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+
+        let name = std::path::PathBuf::from(&package.name.value);
+        let nevra = std::path::PathBuf::from(package.nevra());
+        let is_match = |glob: &crate::glob::Glob| glob.is_match(&name) || glob.is_match(&nevra);
+        if self.exclude.iter().any(is_match) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(is_match)
+    }
+}
+
+/// SHA1 and size of compressed metadata file and of its uncompressed content
+struct WrittenData {
+    checksum: String,
+    size: u64,
+    open_checksum: String,
+    open_size: u64,
+}
+
+impl WrittenData {
+    fn new(compressed: &crate::digest::SharedDigest, open: &crate::digest::SharedDigest) -> Self {
+        let (checksum, size) = compressed.result();
+        let (open_checksum, open_size) = open.result();
+        Self {
+            checksum,
+            size,
+            open_checksum,
+            open_size,
+        }
+    }
+}
+
+/// Sets modification time in header of gzip stream passing through, gzp always writes its own
+struct GzipMtimeWriter<W: Write> {
+    inner: W,
+    position: usize,
+    mtime: [u8; 4],
+}
+
+impl<W: Write> GzipMtimeWriter<W> {
+    fn new(inner: W, mtime: u32) -> Self {
+        Self {
+            inner,
+            position: 0,
+            mtime: mtime.to_le_bytes(),
+        }
+    }
+}
+
+impl<W: Write> Write for GzipMtimeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // MTIME is at bytes 4..8 of header, header CRC is not written by gzp
+        let mut buf = std::borrow::Cow::Borrowed(buf);
+        for n in self.position.max(4)..(self.position + buf.len()).min(8) {
+            buf.to_mut()[n - self.position] = self.mtime[n - 4]
+        }
+        let written = self.inner.write(&buf)?;
+        self.position += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 struct State<'a> {
     config: &'a RepodataConfig,
     options: &'a RepodataOptions,
     _current_repomd_xml_lock: Option<file_lock::FileLock>,
     current_packages: Arc<Mutex<HashMap<std::path::PathBuf, crate::repodata::primary::Package>>>,
-    current_fileslist: Arc<Mutex<HashMap<String, crate::repodata::filelists::Package>>>,
+    current_fileslist: Arc<Mutex<crate::repodata::filelists_index::FilelistsIndex>>,
     tempdir: tempfile::TempDir,
+    /// Directory in repodata→scratch_dir for files which are never published: spilled fileslists
+    /// and decompressed copy of current fileslists. `tempdir` is used if scratch_dir is not set
+    scratch: Option<tempfile::TempDir>,
     primary_xml: Arc<Mutex<crate::repodata::primary::Primary>>,
     fileslist: Arc<Mutex<crate::repodata::filelists::Filelists>>,
+    /// Delta RPMs found in repository tree. None if tree was not scanned, current prestodelta
+    /// metadata is kept then
+    delta_files: Option<Vec<std::path::PathBuf>>,
+    current_repomd: Option<crate::repodata::repomd::Repomd>,
+    /// NEVRA -> checksum of packages in current metadata, for changelog
+    previous_packages: HashMap<String, String>,
+    /// Directory where repodata is published, repository root for main repository
+    root: std::path::PathBuf,
+    /// Directory which location hrefs are relative to. Differs from `root` if metadata is written
+    /// to output directory
+    packages_root: std::path::PathBuf,
+    /// Prepended to package paths relative to `tree_root` to get location hrefs
+    location_prefix: std::path::PathBuf,
+    /// Part of package tree indexed into this repository, debug/ for debug repository
+    tree_root: std::path::PathBuf,
+    /// Debug packages found with --split-debug, they are not added to this repository
+    debug_packages: Option<Arc<Mutex<Vec<std::path::PathBuf>>>>,
+    /// Records of current metadata skipped as unreadable, reported at the end of run
+    cache_warnings: Vec<crate::repodata::records::RecordWarning>,
+    /// Files in scratch directory with fileslists records moved out of memory by --max-memory
+    spilled_fileslists: Mutex<Vec<std::path::PathBuf>>,
+    /// Helper threads reading packages with repodata→package_timeout, None if timeout is not set
+    helpers: Option<Arc<crate::repodata::timeout::Helpers>>,
+    /// Package counters of crate::summary when run started
+    counters: (usize, usize, usize),
+    started: std::time::Instant,
 }
 
 impl<'a> State<'a> {
     fn empty_new(
         config: &'a RepodataConfig,
         options: &'a RepodataOptions,
+        root: std::path::PathBuf,
+        location_prefix: std::path::PathBuf,
         current_repomd_xml_lock: Option<file_lock::FileLock>,
     ) -> Result<Self> {
         let tempdir = tempfile::Builder::new()
             .prefix(".repodata_")
-            .tempdir_in(&options.path)?;
+            .tempdir_in(Self::work_area(config, &root)?)?;
 
         Ok(Self {
             tempdir,
+            scratch: Self::scratch(config)?,
             primary_xml: Arc::new(Mutex::new(crate::repodata::primary::Primary::new())),
             fileslist: Arc::new(Mutex::new(crate::repodata::filelists::Filelists::new())),
             _current_repomd_xml_lock: current_repomd_xml_lock,
             current_packages: Arc::new(Mutex::new(HashMap::new())),
-            current_fileslist: Arc::new(Mutex::new(HashMap::new())),
+            current_fileslist: Arc::new(Mutex::new(Default::default())),
+            delta_files: None,
+            current_repomd: None,
+            previous_packages: HashMap::new(),
+            packages_root: root.clone(),
+            root,
+            location_prefix,
+            tree_root: options.path.clone(),
+            debug_packages: None,
+            cache_warnings: Vec::new(),
+            spilled_fileslists: Mutex::new(Vec::new()),
+            helpers: Self::helpers(config)?,
+            counters: crate::summary::counters(),
+            started: std::time::Instant::now(),
             options,
             config,
         })
     }
 
+    /// Directory where temporary repodata is created. New metadata files are moved from there by
+    /// rename, so it must be on the same filesystem as repository
+    fn work_area(config: &RepodataConfig, root: &std::path::Path) -> Result<std::path::PathBuf> {
+        let temp_dir = match &config.temp_dir {
+            Some(v) => v,
+            None => return Ok(root.to_path_buf()),
+        };
+        // Repositories sharing temp_dir must not remove leftovers of each other
+        let root_id = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let dir = temp_dir.join(crate::digest::str_sha128(&root_id.to_string_lossy()));
+        std::fs::create_dir_all(&dir).map_err(|err| {
+            anyhow!("Cannot create {:?}: {}", dir, err).context(crate::exit_code::Failure::Io)
+        })?;
+
+        let device = |path: &std::path::Path| -> Result<u64> {
+            let metadata = std::fs::metadata(path)
+                .map_err(|err| anyhow!("Cannot read metadata of {:?}: {}", path, err))?;
+            Ok(crate::platform::file_device(&metadata))
+        };
+        if device(&dir)? != device(root)? {
+            bail!(
+                "temp_dir {:?} is not on the same filesystem as repository {:?}, metadata cannot be moved into place atomically",
+                temp_dir,
+                root
+            )
+        }
+        Ok(dir)
+    }
+
+    fn scratch(config: &RepodataConfig) -> Result<Option<tempfile::TempDir>> {
+        match &config.scratch_dir {
+            Some(_) => Ok(Some(config.scratch_tempdir("rpm-tool-scratch")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Two helper threads per worker: one reading package and one left blocked by package which
+    /// timed out before its mount was known to be hung
+    fn helpers(config: &RepodataConfig) -> Result<Option<Arc<crate::repodata::timeout::Helpers>>> {
+        match config.package_timeout {
+            0 => Ok(None),
+            _ => Ok(Some(Arc::new(crate::repodata::timeout::Helpers::new(
+                config.concurrency * 2,
+            )?))),
+        }
+    }
+
+    /// Temporary directories are removed on normal exit and on SIGINT/SIGTERM, leftovers mean that
+    /// previous run was killed or crashed. Leftovers are removed only if repository is locked
+    fn cleanup_unclean_shutdown(root: &std::path::Path, locked: bool) {
+        let entries = match std::fs::read_dir(root) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            if !entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".repodata_")
+                || !entry.path().is_dir()
+            {
+                continue;
+            }
+            warn!(
+                "Found {:?} left by unclean shutdown of previous run, published metadata may be stale",
+                entry.path()
+            );
+            if locked {
+                if let Err(err) = std::fs::remove_dir_all(entry.path()) {
+                    warn!("Cannot remove {:?}: {}", entry.path(), err);
+                }
+            }
+        }
+    }
+
     fn repodata_path(&self) -> std::path::PathBuf {
-        self.options.path.join("repodata")
+        self.root.join("repodata")
     }
 
-    fn lock_current_repomd_xml(path: &std::path::Path) -> Result<Option<file_lock::FileLock>> {
+    fn lock_current_repomd_xml(
+        config: &RepodataConfig,
+        path: &std::path::Path,
+    ) -> Result<Option<file_lock::FileLock>> {
         let xml_path = path.join("repodata").join("repomd.xml");
         if xml_path.exists() {
-            info!("Setting exclusive lock on {:?}", xml_path);
-            Ok(Some(
-                file_lock::FileLock::lock(
-                    &xml_path,
-                    true,
-                    file_lock::FileOptions::new().write(true),
-                )
-                .map_err(|err| anyhow!("Cannot lock {:?}: {}", xml_path, err))?,
-            ))
+            Ok(Some(config.lock.lock(&xml_path, true)?))
         } else {
             Ok(None)
         }
     }
 
+    /// Signed repository must not be updated from tampered metadata: signature of repomd.xml is
+    /// verified, then checksums of documents it references, which are reused or kept as is
+    fn verify_current_repomd(
+        config: &RepodataConfig,
+        path: &std::path::Path,
+        repomd: &crate::repodata::repomd::Repomd,
+    ) -> Result<()> {
+        if !config.gpg.is_configured() {
+            return Ok(());
+        }
+        let xml_path = path.join("repodata").join("repomd.xml");
+        let signature_path = path.join("repodata").join("repomd.xml.asc");
+        if !signature_path.exists() {
+            bail!(
+                "No {:?} while signing is configured, metadata cannot be verified",
+                signature_path
+            )
+        }
+        config
+            .gpg
+            .verifier()?
+            .verify_detached(&signature_path, &xml_path)?;
+
+        for data in &repomd.data {
+            let data_path = path.join(&data.location.href);
+            let digest_type =
+                crate::digest::DigestType::of_name(&data.checksum.type_).ok_or_else(|| {
+                    anyhow!(
+                        "Unsupported checksum type {:?} of {:?}",
+                        data.checksum.type_,
+                        data.location.href
+                    )
+                })?;
+            let actual = crate::digest::path_digests(&data_path, &[digest_type])
+                .map_err(|err| anyhow!("Cannot calculate checksum of {:?}: {}", data_path, err))?
+                .remove(0);
+            if actual != data.checksum.value {
+                bail!(
+                    "Checksum of {:?} doesn't match signed repomd.xml",
+                    data.location.href
+                )
+            }
+        }
+        Ok(())
+    }
+
     fn current_repomd(path: &std::path::Path) -> Result<crate::repodata::repomd::Repomd> {
         let path = path.join("repodata").join("repomd.xml");
         let xml = crate::repodata::repomd::Repomd::read(&path)?;
@@ -89,10 +639,44 @@ fn current_repomd(path: &std::path::Path) -> Result<crate::repodata::repomd::Rep
         Ok(xml)
     }
 
+    /// NEVRAs and checksums of published packages for changelog, read even if metadata is not
+    /// trusted as cache
+    fn published_packages(
+        root: &std::path::Path,
+        repomd: &crate::repodata::repomd::Repomd,
+    ) -> HashMap<String, String> {
+        let location = match repomd
+            .data
+            .iter()
+            .find(|elt| elt.type_ == crate::repodata::repomd::DataType::Primary)
+        {
+            Some(v) => &v.location.href,
+            None => return HashMap::new(),
+        };
+        match crate::repodata::primary::Primary::read_lenient(&root.join(location)) {
+            Ok((primary, _)) => primary
+                .package
+                .into_iter()
+                .map(|package| (package.nevra(), package.checksum.value))
+                .collect(),
+            Err(err) => {
+                warn!(
+                    "Cannot read published packages from {:?}, changelog will list all packages as added: {}",
+                    location, err
+                );
+                HashMap::new()
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
     fn current_packages(
         path: &std::path::Path,
-    ) -> Result<HashMap<std::path::PathBuf, crate::repodata::primary::Package>> {
-        let primary = crate::repodata::primary::Primary::read(path)?;
+    ) -> Result<(
+        HashMap<std::path::PathBuf, crate::repodata::primary::Package>,
+        Vec<crate::repodata::records::RecordWarning>,
+    )> {
+        let (primary, warnings) = crate::repodata::primary::Primary::read_lenient(path)?;
         info!(
             "Got primary metadata for {} packages",
             primary.package.len()
@@ -103,63 +687,244 @@ fn current_packages(
             .map(|p| (std::path::Path::new(&p.location.href).to_path_buf(), p))
             .collect();
 
+        Ok((r, warnings))
+    }
+
+    pub fn new(config: &'a RepodataConfig, options: &'a RepodataOptions) -> Result<Self> {
+        let (root, location_prefix) = options.repository_root();
+        Self::new_in(config, options, root, location_prefix)
+    }
+
+    /// State of repository of packages in `subtree` of package tree, e.g. debug/
+    pub fn new_of_subtree(
+        config: &'a RepodataConfig,
+        options: &'a RepodataOptions,
+        subtree: &std::path::Path,
+    ) -> Result<Self> {
+        let (root, location_prefix) = options.repository_root_of(subtree);
+        let mut r = Self::new_in(config, options, root, location_prefix)?;
+        r.tree_root = options.path.join(subtree);
         Ok(r)
     }
 
-    fn current_fileslist(
-        path: &std::path::Path,
-    ) -> Result<HashMap<String, crate::repodata::filelists::Package>> {
-        let fileslists = crate::repodata::filelists::Filelists::read(path)?;
-        info!("Got fileslists for {} packages", fileslists.package.len());
-        let r = fileslists
-            .package
-            .into_iter()
-            .map(|p| (p.pkgid.clone(), p))
+    /// State of repository published in `root`, which is not necessarily the root of scanned tree
+    pub fn new_in(
+        config: &'a RepodataConfig,
+        options: &'a RepodataOptions,
+        root: std::path::PathBuf,
+        location_prefix: std::path::PathBuf,
+    ) -> Result<Self> {
+        let mut r = Self::read_current(
+            config,
+            options,
+            options.metadata_root(&root),
+            location_prefix,
+        )?;
+        r.packages_root = root;
+        r.normalize_current_locations();
+        if options.resume {
+            r.restore_checkpoint()?;
+        }
+        Ok(r)
+    }
+
+    /// Locations with xml:base or absolute URLs, written by some tools, are converted to paths
+    /// relative to repository root, so that cached records are found by path of scanned file
+    fn normalize_current_locations(&self) {
+        let mut current_packages = self.current_packages.lock().unwrap();
+        let foreign: Vec<_> = current_packages
+            .iter()
+            .filter(|(_, package)| package.location.is_foreign())
+            .map(|(location, _)| location.clone())
             .collect();
+        if foreign.is_empty() {
+            return;
+        }
 
-        Ok(r)
+        let mut normalized = 0;
+        for location in &foreign {
+            let mut package = match current_packages.remove(location) {
+                Some(v) => v,
+                None => continue,
+            };
+            let found = package
+                .location
+                .relative_candidates()
+                .into_iter()
+                .map(|v| self.location_prefix.join(v))
+                .find(|v| self.packages_root.join(v).exists());
+            match found {
+                Some(v) => {
+                    package.location = crate::repodata::primary::PackageLocation::new(
+                        v.to_string_lossy().to_string(),
+                    );
+                    let _ = current_packages.insert(v, package);
+                    normalized += 1;
+                }
+                None => debug!("No file in repository for location {:?}", location),
+            }
+        }
+        info!(
+            "Converted {} of {} locations with xml:base or absolute URL to repository paths",
+            normalized,
+            foreign.len()
+        );
     }
 
-    pub fn new(config: &'a RepodataConfig, options: &'a RepodataOptions) -> Result<Self> {
-        let current_repomd_xml = Self::lock_current_repomd_xml(&options.path)?;
+    /// Records saved by interrupted run are used as cache, like records of current metadata
+    fn restore_checkpoint(&self) -> Result<()> {
+        let (packages, fileslists) = crate::repodata::checkpoint::read(&self.root)?;
+        let mut current_packages = self.current_packages.lock().unwrap();
+        for package in packages {
+            let _ =
+                current_packages.insert(std::path::PathBuf::from(&package.location.href), package);
+        }
+        let mut current_fileslist = self.current_fileslist.lock().unwrap();
+        for package in fileslists {
+            current_fileslist.insert(package);
+        }
+        Ok(())
+    }
+
+    /// Records are serialized under locks, compressed and written without blocking workers
+    fn write_checkpoint(&self) -> Result<()> {
+        let (primary, packages) = {
+            let primary = self.primary_xml.lock().unwrap();
+            (quick_xml::se::to_string(&*primary)?, primary.package.len())
+        };
+        let fileslists = if self.options.generate_fileslists {
+            Some(quick_xml::se::to_string(&*self.fileslist.lock().unwrap())?)
+        } else {
+            None
+        };
+        crate::repodata::checkpoint::write(&self.root, &primary, packages, fileslists.as_deref())
+    }
+
+    /// Move accumulated fileslists records to files in work area, they are read back by `finish`
+    fn spill_fileslists(&self) -> Result<()> {
+        let mut fileslist = self.fileslist.lock().unwrap();
+        // Small files are not worth it
+        if fileslist.package.len() < crate::repodata::memory::SPILL_CHUNK {
+            return Ok(());
+        }
+        let mut spilled = self.spilled_fileslists.lock().unwrap();
+        let packages = std::mem::take(&mut fileslist.package);
+        fileslist.packages = 0;
+        let count = packages.len();
+        let mut packages = packages.into_iter().peekable();
+        while packages.peek().is_some() {
+            let mut chunk = crate::repodata::filelists::Filelists::new();
+            for package in packages.by_ref().take(crate::repodata::memory::SPILL_CHUNK) {
+                chunk.add_package(package)
+            }
+            let path = self
+                .scratch
+                .as_ref()
+                .unwrap_or(&self.tempdir)
+                .path()
+                .join(format!("spill-{}.filelists.xml.gz", spilled.len()));
+            crate::repodata::checkpoint::write_gz(&path, &quick_xml::se::to_string(&chunk)?)?;
+            spilled.push(path);
+        }
+        debug!("Spilled fileslists of {} packages to disk", count);
+        Ok(())
+    }
+
+    /// Read spilled fileslists records back, skipping packages which are no longer in primary
+    fn restore_spilled_fileslists(
+        &self,
+        fileslists: &mut crate::repodata::filelists::Filelists,
+        pkgids: &HashSet<&String>,
+    ) -> Result<()> {
+        for path in self.spilled_fileslists.lock().unwrap().drain(..) {
+            let spilled = crate::repodata::filelists::Filelists::read(&path)
+                .map_err(|err| anyhow!("Cannot read spilled fileslists {:?}: {}", path, err))?;
+            for package in spilled.package {
+                if pkgids.contains(&package.pkgid) {
+                    fileslists.add_package(package)
+                }
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+        Ok(())
+    }
+
+    fn read_current(
+        config: &'a RepodataConfig,
+        options: &'a RepodataOptions,
+        root: std::path::PathBuf,
+        location_prefix: std::path::PathBuf,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(&root).map_err(|err| {
+            anyhow!("Cannot create {:?}: {}", root, err).context(crate::exit_code::Failure::Io)
+        })?;
+        let current_repomd_xml = Self::lock_current_repomd_xml(config, &root)?;
+        let work_area = Self::work_area(config, &root)?;
+        Self::cleanup_unclean_shutdown(&work_area, current_repomd_xml.is_some());
+        // Left by runs made before temp_dir was set
+        if work_area != root {
+            Self::cleanup_unclean_shutdown(&root, current_repomd_xml.is_some());
+        }
         let current_repomd = match &current_repomd_xml {
-            Some(_) => match Self::current_repomd(&options.path) {
+            Some(_) => match Self::current_repomd(&root) {
                 Ok(v) => v,
                 Err(err) => {
                     warn!(
                         "Will not use cached data due to read error of repomd.xml: {}",
                         err
                     );
-                    return Self::empty_new(config, options, None);
+                    return Self::empty_new(config, options, root, location_prefix, None);
                 }
             },
-            None => return Self::empty_new(config, options, None),
+            None => return Self::empty_new(config, options, root, location_prefix, None),
         };
 
-        let current_packages = if let Some(primary_xml_md) = current_repomd
+        if let Err(err) = Self::verify_current_repomd(config, &root, &current_repomd) {
+            warn!("Will not use cached data: {}", err);
+            let previous_packages = Self::published_packages(&root, &current_repomd);
+            let mut r =
+                Self::empty_new(config, options, root, location_prefix, current_repomd_xml)?;
+            r.previous_packages = previous_packages;
+            return Ok(r);
+        }
+
+        let (current_packages, cache_warnings) = if let Some(primary_xml_md) = current_repomd
             .data
             .iter()
             .find(|elt| elt.type_ == crate::repodata::repomd::DataType::Primary)
         {
             let location = &primary_xml_md.location.href;
-            match Self::current_packages(&options.path.join(location)) {
+            match Self::current_packages(&root.join(location)) {
                 Ok(v) => v,
                 Err(err) => {
                     warn!(
                         "Will not use primary cached data due to read error of {:?}: {}",
                         location, err
                     );
-                    HashMap::new()
+                    Default::default()
                 }
             }
+        } else if current_repomd
+            .data
+            .iter()
+            .any(|elt| elt.type_ == crate::repodata::repomd::DataType::PrimaryDb)
+        {
+            warn!("repomd.xml has only sqlite metadata, which is not supported, cache is not used");
+            Default::default()
         } else {
             warn!("No 'primary' record in repomd.xml");
-            HashMap::new()
+            Default::default()
         };
 
+        let previous_packages = current_packages
+            .values()
+            .map(|package| (package.nevra(), package.checksum.value.clone()))
+            .collect();
+
         let tempdir = tempfile::Builder::new()
             .prefix(".repodata_")
-            .tempdir_in(&options.path)?;
+            .tempdir_in(Self::work_area(config, &root)?)?;
+        let scratch = Self::scratch(config)?;
 
         let current_fileslist = if options.generate_fileslists {
             if let Some(fileslists_xml_md) = current_repomd
@@ -168,32 +933,49 @@ pub fn new(config: &'a RepodataConfig, options: &'a RepodataOptions) -> Result<S
                 .find(|elt| elt.type_ == crate::repodata::repomd::DataType::Filelists)
             {
                 let location = &fileslists_xml_md.location.href;
-                match Self::current_fileslist(&options.path.join(location)) {
+                match crate::repodata::filelists_index::FilelistsIndex::read(
+                    &root.join(location),
+                    scratch.as_ref().unwrap_or(&tempdir).path(),
+                ) {
                     Ok(v) => v,
                     Err(err) => {
                         warn!(
                             "Will not use fileslists cached data due to read error of {:?}: {}",
                             location, err
                         );
-                        HashMap::new()
+                        Default::default()
                     }
                 }
             } else {
-                HashMap::new()
+                Default::default()
             }
         } else {
-            HashMap::new()
+            Default::default()
         };
 
         info!("Will generate new repository index in {:?}", tempdir.path());
 
         let r = Self {
             tempdir,
+            scratch,
             primary_xml: Arc::new(Mutex::new(crate::repodata::primary::Primary::new())),
             fileslist: Arc::new(Mutex::new(crate::repodata::filelists::Filelists::new())),
             _current_repomd_xml_lock: current_repomd_xml,
             current_packages: Arc::new(Mutex::new(current_packages)),
             current_fileslist: Arc::new(Mutex::new(current_fileslist)),
+            delta_files: None,
+            current_repomd: Some(current_repomd),
+            previous_packages,
+            packages_root: root.clone(),
+            root,
+            location_prefix,
+            tree_root: options.path.clone(),
+            debug_packages: None,
+            cache_warnings,
+            spilled_fileslists: Mutex::new(Vec::new()),
+            helpers: Self::helpers(config)?,
+            counters: crate::summary::counters(),
+            started: std::time::Instant::now(),
             options,
             config,
         };
@@ -210,31 +992,80 @@ fn read_rpm(path: &std::path::Path) -> Result<rpm::RPMPackage> {
     pub fn add_file(&self, path: &std::path::Path, relative_path: &std::path::Path) -> Result<()> {
         debug!("Adding package");
 
-        let path_clone = path.to_path_buf();
+        // Single deadline for all reads of package below, counted from the start
+        let deadline = match self.config.package_timeout {
+            0 => None,
+            v => Some(std::time::Instant::now() + std::time::Duration::from_secs(v)),
+        };
+
+        let package_path = path.to_path_buf();
+        let helpers = self.helpers.clone();
+        let sidecar_checksums = self.config.sidecar_checksums;
         let lazy_file_sha = crate::lazy_result::LazyResult::new(move || {
-            debug!("Calculating SHA128");
-            let r = crate::digest::path_sha128(&path_clone)
-                .map_err(|err| anyhow!("Calculate file SHA1 for {:?}: {}", path_clone, err));
-            debug!("Done calculating SHA128");
-            r
+            let path_clone = package_path.clone();
+            crate::repodata::timeout::run(
+                helpers.as_deref(),
+                deadline,
+                &package_path,
+                "Calculating checksum",
+                move || {
+                    if sidecar_checksums {
+                        if let Some(v) = crate::repodata::sidecar::read(
+                            &path_clone,
+                            crate::digest::DigestType::Sha1,
+                        ) {
+                            debug!("Using checksum from sidecar file");
+                            return Ok(v);
+                        }
+                    }
+                    let _span = crate::trace::span("hash");
+                    debug!("Calculating SHA128");
+                    let r = crate::digest::path_sha128(&path_clone).map_err(|err| {
+                        anyhow!("Calculate file SHA1 for {:?}: {}", path_clone, err)
+                    });
+                    debug!("Done calculating SHA128");
+                    r
+                },
+            )
         });
-        let path_clone = path.to_path_buf();
+        let package_path = path.to_path_buf();
+        let helpers = self.helpers.clone();
         let lazy_rpm_head = crate::lazy_result::LazyResult::new(move || {
-            debug!("Reading RPM header");
-            let r = Self::read_rpm(&path_clone)
-                .map_err(|err| anyhow!("Read RPM header from {:?}: {}", path_clone, err));
-            debug!("Done reading RPM header");
-            r
+            let path_clone = package_path.clone();
+            crate::repodata::timeout::run(
+                helpers.as_deref(),
+                deadline,
+                &package_path,
+                "Reading RPM header",
+                move || {
+                    let _span = crate::trace::span("parse");
+                    debug!("Reading RPM header");
+                    let r = Self::read_rpm(&path_clone)
+                        .map_err(|err| anyhow!("Read RPM header from {:?}: {}", path_clone, err));
+                    debug!("Done reading RPM header");
+                    r
+                },
+            )
         });
-        let path_clone = path.to_path_buf();
+        let package_path = path.to_path_buf();
+        let helpers = self.helpers.clone();
         let lazy_metadata: crate::lazy_result::LazyResult<_, anyhow::Error> =
             crate::lazy_result::LazyResult::new(move || {
-                debug!("Reading RPM metadata");
-                let r = path_clone
-                    .metadata()
-                    .map_err(|err| anyhow!("Read metadata for {:?}: {}", path_clone, err))?;
-                debug!("Done reading RPM metadata");
-                Ok(r)
+                let path_clone = package_path.clone();
+                crate::repodata::timeout::run(
+                    helpers.as_deref(),
+                    deadline,
+                    &package_path,
+                    "Reading RPM metadata",
+                    move || {
+                        debug!("Reading RPM metadata");
+                        let r = path_clone.metadata().map_err(|err| {
+                            anyhow!("Read metadata for {:?}: {}", path_clone, err)
+                        })?;
+                        debug!("Done reading RPM metadata");
+                        Ok(r)
+                    },
+                )
             });
 
         let cached_package_record = {
@@ -242,8 +1073,16 @@ pub fn add_file(&self, path: &std::path::Path, relative_path: &std::path::Path)
             match current_packages.remove(relative_path) {
                 Some(v) => {
                     let metadata = lazy_metadata.get()?;
-                    if v.size.package == metadata.st_size() && v.time.file == metadata.st_mtime() {
-                        debug!("st_size and st_mtime are the same, using cached package metadata");
+                    let mtime = crate::platform::file_mtime(&metadata);
+                    // Records written with fixed timestamp have mtime clamped to it
+                    let clamped_mtime = match self.options.reproducible_timestamp()? {
+                        Some(timestamp) => mtime.min(i64::try_from(timestamp).unwrap_or(i64::MAX)),
+                        None => mtime,
+                    };
+                    if v.size.package == crate::platform::file_size(&metadata)
+                        && (v.time.file == mtime || v.time.file == clamped_mtime)
+                    {
+                        debug!("Size and mtime are the same, using cached package metadata");
                         Some(v)
                     } else {
                         None
@@ -261,30 +1100,67 @@ pub fn add_file(&self, path: &std::path::Path, relative_path: &std::path::Path)
                     Some(v) => Rc::new(v.checksum.value),
                     None => lazy_file_sha.get()?,
                 };
-                let package = crate::repodata::primary::Package::of_rpm_package(
+                let mut package = crate::repodata::primary::Package::of_rpm_package(
                     &*lazy_rpm_head.get()?,
                     path,
                     relative_path,
                     &file_sha,
                     &self.config.useful_files,
+                    self.config.locale.as_deref(),
+                    self.options.strict,
                 )?;
+                let sanitized = self.config.sanitize.apply(&mut package);
+                if !sanitized.is_empty() {
+                    warn!(
+                        "Sanitized {} of package {} at {:?}",
+                        sanitized.join(", "),
+                        package.nevra(),
+                        package.location.href
+                    );
+                    crate::summary::add_sanitized(
+                        &package.location.href,
+                        &package.nevra(),
+                        &sanitized,
+                    );
+                }
                 (package, true)
             }
         };
 
-        let sha = package.checksum.value.clone();
+        if !self.options.package_filter.is_accepted(&package) {
+            debug!("Package is not accepted by package filter, skipping");
+            return Ok(());
+        }
 
-        {
-            let mut primary_xml = self.primary_xml.lock().unwrap();
-            primary_xml.add_package(package);
+        if let Some(debug_packages) = &self.debug_packages {
+            if package.is_debug() {
+                debug!("Debug package, will be published in debug repository");
+                debug_packages.lock().unwrap().push(path.to_path_buf());
+                return Ok(());
+            }
         }
 
-        if self.options.generate_fileslists {
+        let sha = package.checksum.value.clone();
+
+        // Records are added only when everything is read, so that failed call can be retried
+        let fileslist_package = if self.options.generate_fileslists {
             let package = if is_new_record {
                 crate::repodata::filelists::Package::of_rpm_package(&*lazy_rpm_head.get()?, &sha)?
             } else {
-                let mut cache = self.current_fileslist.lock().unwrap();
-                match cache.remove(&sha) {
+                // Index is not locked while record is read, other workers use it meanwhile
+                let cached = self.current_fileslist.lock().unwrap().take(&sha);
+                let cached = match cached.map(|v| v.read()) {
+                    Some(Ok(v)) => Some(v),
+                    Some(Err(err)) => {
+                        self.current_fileslist
+                            .lock()
+                            .unwrap()
+                            .add_warning(&sha, &err);
+                        None
+                    }
+                    None => None,
+                };
+                match cached {
                     Some(v) => v,
                     None => {
                         debug!("No cached fileslist, will generate new record from RPM headers");
@@ -295,6 +1171,21 @@ pub fn add_file(&self, path: &std::path::Path, relative_path: &std::path::Path)
                     }
                 }
             };
+            Some(package)
+        } else {
+            None
+        };
+
+        if is_new_record {
+            crate::summary::add_indexed();
+        } else {
+            crate::summary::add_reused();
+        }
+        {
+            let mut primary_xml = self.primary_xml.lock().unwrap();
+            primary_xml.add_package(package);
+        }
+        if let Some(package) = fileslist_package {
             let mut fileslist = self.fileslist.lock().unwrap();
             fileslist.add_package(package)
         }
@@ -303,28 +1194,52 @@ pub fn add_file(&self, path: &std::path::Path, relative_path: &std::path::Path)
         r
     }
 
-    #[cfg(feature = "parallel-zip")]
-    fn parallel_zip(path: &std::path::Path, str: &str) -> Result<()> {
+    fn parallel_zip(
+        path: &std::path::Path,
+        str: &str,
+        threads: usize,
+        mtime: u32,
+    ) -> Result<WrittenData> {
         use gzp::{
             deflate::Gzip,
             par::compress::{ParCompress, ParCompressBuilder},
+            ZWriter,
         };
 
-        let file = std::fs::File::create(&path)?;
-        let mut gz_file: ParCompress<Gzip> = ParCompressBuilder::new().from_writer(file);
-
-        gz_file.write_all(str.as_bytes())?;
-        gz_file.flush()?;
-
-        Ok(())
+        let file = crate::digest::DigestWriter::new(
+            std::fs::File::create(&path)?,
+            crate::digest::DigestType::Sha1,
+        );
+        let digest = file.digest();
+        let mut gz_file: ParCompress<Gzip> = ParCompressBuilder::new()
+            .num_threads(threads)
+            .map_err(|err| anyhow!("Cannot use {} compression threads: {}", threads, err))?
+            .from_writer(GzipMtimeWriter::new(file, mtime));
+
+        let mut open = crate::digest::DigestWriter::new(gz_file, crate::digest::DigestType::Sha1);
+        let open_digest = open.digest();
+        open.write_all(str.as_bytes())?;
+        open.into_inner()
+            .finish()
+            .map_err(|err| anyhow!("Cannot compress {:?}: {}", path, err))?;
+
+        Ok(WrittenData::new(&digest, &open_digest))
     }
 
-    #[cfg(not(feature = "parallel-zip"))]
-    fn single_threaded_zip(path: &std::path::Path, str: &str) -> Result<()> {
-        let file = std::fs::File::create(&path)?;
-        let mut writer = flate2::write::GzEncoder::new(file, flate2::Compression::default());
-        writer.write_all(str.as_bytes())?;
-        Ok(())
+    fn single_threaded_zip(path: &std::path::Path, str: &str, mtime: u32) -> Result<WrittenData> {
+        let file = crate::digest::DigestWriter::new(
+            std::fs::File::create(&path)?,
+            crate::digest::DigestType::Sha1,
+        );
+        let digest = file.digest();
+        let mut writer = flate2::GzBuilder::new()
+            .mtime(mtime)
+            .write(file, flate2::Compression::default());
+        let mut open = crate::digest::DigestWriter::new(writer, crate::digest::DigestType::Sha1);
+        let open_digest = open.digest();
+        open.write_all(str.as_bytes())?;
+        let _ = open.into_inner().finish()?;
+        Ok(WrittenData::new(&digest, &open_digest))
     }
 
     fn finish_xml<T>(
@@ -332,90 +1247,598 @@ fn finish_xml<T>(
         filename: &str,
         data: &T,
         data_type: crate::repodata::repomd::DataType,
+        timestamp: Option<u64>,
     ) -> Result<crate::repodata::repomd::Data>
     where
         T: Serialize,
     {
-        let gz_filename = format!("{}.xml.gz", filename);
-        let path = self.tempdir.path().join(&gz_filename);
-
-        info!("Generating {gz_filename}");
-
         let xml_str = {
-            let primary_xml_str = quick_xml::se::to_string(data)?;
+            let _span = crate::trace::span("serialize");
+            quick_xml::se::to_string(data)?
+        };
+        self.finish_data(
+            &format!("{}.xml.gz", filename),
+            &xml_str,
+            data_type,
+            timestamp,
+        )
+    }
 
-            #[cfg(feature = "parallel-zip")]
-            Self::parallel_zip(&path, &primary_xml_str)?;
+    /// Compress content into repodata file and describe it for repomd.xml
+    fn finish_data(
+        &self,
+        gz_filename: &str,
+        content: &str,
+        data_type: crate::repodata::repomd::DataType,
+        timestamp: Option<u64>,
+    ) -> Result<crate::repodata::repomd::Data> {
+        let path = self.tempdir.path().join(gz_filename);
 
-            #[cfg(not(feature = "parallel-zip"))]
-            Self::single_threaded_zip(&path, &primary_xml_str)?;
+        info!("Generating {gz_filename}");
 
-            primary_xml_str
-        };
+        // Without fixed timestamp mtime is 0, like in gzip streams written by createrepo_c
+        let mtime = u32::try_from(timestamp.unwrap_or_default()).map_err(|_| {
+            anyhow!(
+                "Timestamp {} doesn't fit into gzip header of {}",
+                timestamp.unwrap_or_default(),
+                gz_filename
+            )
+        })?;
+        let compress_span = crate::trace::span("compress");
+        let (r, _) = self
+            .config
+            .retry
+            .run(&format!("Writing {}", gz_filename), || {
+                if self.config.compression_threads > 1 {
+                    Self::parallel_zip(&path, content, self.config.compression_threads, mtime)
+                } else {
+                    Self::single_threaded_zip(&path, content, mtime)
+                }
+            });
+        // Checksums are calculated while writing, file is not read again
+        let written = r?;
+        drop(compress_span);
 
-        let checksum = crate::digest::path_sha128(&path)?;
-
-        let metadata = path.metadata()?;
-
-        let open_checksum = crate::digest::str_sha128(&xml_str);
-        let open_size = xml_str.len();
+        // Unique file names let old and new metadata coexist during switchover
+        let unique_filename = format!("{}-{}", written.checksum, gz_filename);
+        let unique_path = self.tempdir.path().join(&unique_filename);
+        std::fs::rename(&path, &unique_path)?;
 
         let r = crate::repodata::repomd::Data {
             type_: data_type,
-            checksum: crate::repodata::repomd::Checksum::new(checksum),
-            open_checksum: crate::repodata::repomd::Checksum::new(open_checksum),
-            location: crate::repodata::repomd::Location::new(format!("repodata/{}", gz_filename)),
-            timestamp: metadata.st_mtime(),
-            size: metadata.st_size(),
-            open_size,
+            checksum: crate::repodata::repomd::Checksum::new(written.checksum),
+            open_checksum: Some(crate::repodata::repomd::Checksum::new(
+                written.open_checksum,
+            )),
+            header_checksum: None,
+            location: crate::repodata::repomd::Location::new(format!(
+                "repodata/{}",
+                unique_filename
+            )),
+            timestamp: match timestamp {
+                Some(v) => v as i64,
+                None => crate::platform::file_mtime(&unique_path.metadata()?),
+            },
+            size: written.size,
+            open_size: Some(written.open_size),
+            header_size: None,
+            database_version: None,
         };
 
         Ok(r)
     }
 
-    fn finish_repomd(&self, repomd: crate::repodata::repomd::Repomd) -> Result<()> {
+    fn finish_repomd(&self, repomd: &crate::repodata::repomd::Repomd) -> Result<()> {
         let filename = "repomd.xml";
         info!("Generating {filename}");
         let path = self.tempdir.path().join(filename);
         let mut file = std::fs::File::create(&path)?;
-        file.write_all(quick_xml::se::to_string(&repomd)?.as_bytes())?;
+        file.write_all(quick_xml::se::to_string(repomd)?.as_bytes())?;
+
+        Ok(())
+    }
+
+    fn data_file_name(data: &crate::repodata::repomd::Data) -> Result<&str> {
+        data.location
+            .href
+            .strip_prefix("repodata/")
+            .ok_or_else(|| anyhow!("Unexpected metadata location {:?}", data.location.href))
+    }
+
+    fn read_superseded(path: &std::path::Path) -> Result<HashMap<String, u64>> {
+        let str = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&str)?)
+    }
+
+    /// Remove files of repodata directory not referenced by repomd.xml. File is removed only after it
+    /// stays unreferenced for the grace period, so clients which got previous repomd.xml can finish
+    /// downloading
+    fn cleanup_superseded(&self, repomd: &crate::repodata::repomd::Repomd) -> Result<()> {
+        let repodata_path = self.repodata_path();
+        let superseded_path = repodata_path.join(SUPERSEDED_FILE);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let mut superseded = if superseded_path.exists() {
+            match Self::read_superseded(&superseded_path) {
+                Ok(v) => v,
+                Err(err) => {
+                    warn!("Cannot read {:?}, resetting it: {}", superseded_path, err);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        let referenced = repomd
+            .data
+            .iter()
+            .map(Self::data_file_name)
+            .collect::<Result<HashSet<_>>>()?;
+
+        for entry in std::fs::read_dir(&repodata_path)? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            if file_name == "repomd.xml"
+                || file_name == SUPERSEDED_FILE
+                || file_name == crate::repodata::changelog::CHANGELOG_FILE
+                || file_name == crate::repodata::tags::TAGS_DIR
+                || referenced.contains(file_name.as_str())
+            {
+                continue;
+            }
+            let _ = superseded.entry(file_name).or_insert(now);
+        }
+
+        superseded.retain(|file_name, since| {
+            let path = repodata_path.join(file_name.as_str());
+            if referenced.contains(file_name.as_str()) || !path.exists() {
+                return false;
+            }
+            if now.saturating_sub(*since) < self.config.cleanup_grace_period {
+                return true;
+            }
+            info!("Removing superseded {:?}", path);
+            let r = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            match r {
+                Ok(()) => false,
+                Err(err) => {
+                    warn!("Cannot remove superseded {:?}: {}", path, err);
+                    true
+                }
+            }
+        });
+
+        let tmp_path = self.tempdir.path().join(SUPERSEDED_FILE);
+        std::fs::write(&tmp_path, serde_json::to_string(&superseded)?)?;
+        self.config.permissions.apply_file(&tmp_path)?;
+        std::fs::rename(&tmp_path, &superseded_path)?;
+
+        Ok(())
+    }
+
+    /// Hooks are run once for all added packages, their NEVRAs are listed in file
+    fn run_package_added_hooks(&self, added: &[String]) -> Result<()> {
+        let event = crate::repodata::hooks::HookEvent::OnPackageAdded;
+        if added.is_empty() || !self.config.hooks.iter().any(|v| v.event == event) {
+            return Ok(());
+        }
+        let path = self.tempdir.path().join("added-packages.txt");
+        let list: String = added.iter().map(|v| format!("{}\n", v)).collect();
+        std::fs::write(&path, list)
+            .map_err(|err| anyhow!("Cannot write list of added packages {:?}: {}", path, err))?;
+        crate::repodata::hooks::run(
+            &self.config.hooks,
+            event,
+            &self.options.path,
+            &[("nevras", path.to_string_lossy().as_ref())],
+        )
+    }
+
+    /// Append changes between previous and published metadata to changelog
+    fn update_changelog(&self, changes: crate::repodata::changelog::Revision) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        info!(
+            "{} packages added, {} removed, {} changed",
+            changes.added.len(),
+            changes.removed.len(),
+            changes.changed.len()
+        );
+        if let Err(err) = self.run_package_added_hooks(&changes.added) {
+            warn!("{}", err);
+        }
+
+        let changelog_path = self
+            .repodata_path()
+            .join(crate::repodata::changelog::CHANGELOG_FILE);
+        let mut changelog = if changelog_path.exists() {
+            match crate::repodata::changelog::Changelog::read(&changelog_path) {
+                Ok(v) => v,
+                Err(err) => {
+                    warn!("Resetting changelog: {}", err);
+                    Default::default()
+                }
+            }
+        } else {
+            Default::default()
+        };
+        changelog.push(changes);
+
+        let tmp_path = self
+            .tempdir
+            .path()
+            .join(crate::repodata::changelog::CHANGELOG_FILE);
+        std::fs::write(&tmp_path, serde_json::to_string(&changelog)?)?;
+        self.config.permissions.apply_file(&tmp_path)?;
+        std::fs::rename(&tmp_path, &changelog_path)?;
 
         Ok(())
     }
 
+    /// Append statistics of this run to repodata→stats_dir
+    fn update_stats(
+        &self,
+        revision: u64,
+        packages: usize,
+        packages_size: u64,
+        repomd: &crate::repodata::repomd::Repomd,
+    ) -> Result<()> {
+        let stats_dir = match &self.config.stats_dir {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let mut run = crate::repodata::stats::Run::new(self.started, self.counters)?;
+        run.revision = revision;
+        run.packages = packages;
+        run.packages_size = packages_size;
+        run.metadata_size = repomd.data.iter().map(|data| data.size).sum();
+        crate::repodata::stats::Stats::append(
+            &crate::repodata::stats::Stats::path(stats_dir, &self.root),
+            &run,
+        )
+    }
+
+    /// Append statistics of run which failed with `err` to repodata→stats_dir
+    fn record_failed_run(&self, err: &anyhow::Error) {
+        let stats_dir = match &self.config.stats_dir {
+            Some(v) => v,
+            None => return,
+        };
+        let r =
+            crate::repodata::stats::Run::new(self.started, self.counters).and_then(|mut run| {
+                run.error = Some(format!("{:#}", err));
+                crate::repodata::stats::Stats::append(
+                    &crate::repodata::stats::Stats::path(stats_dir, &self.root),
+                    &run,
+                )
+            });
+        if let Err(err) = r {
+            warn!("Failed to update statistics: {}", err)
+        }
+    }
+
+    fn prestodelta(
+        &self,
+        delta_files: &[std::path::PathBuf],
+    ) -> crate::repodata::prestodelta::Prestodelta {
+        let mut prestodelta = crate::repodata::prestodelta::Prestodelta::new();
+        for path in delta_files {
+            let relative_path = path
+                .strip_prefix(&self.options.path)
+                .unwrap_or(path.as_path());
+            match crate::repodata::prestodelta::NewPackage::of_drpm_file(path, relative_path) {
+                Ok(v) => prestodelta.add_delta(v),
+                Err(err) => error!("Failed to process delta {:?}: {}", path, err),
+            }
+        }
+        info!("Indexed {} delta RPM files", delta_files.len());
+        prestodelta
+    }
+
+    /// Copy file of current metadata document into new metadata
+    fn keep_current_data(
+        &self,
+        data: &crate::repodata::repomd::Data,
+    ) -> Result<crate::repodata::repomd::Data> {
+        let file_name = Self::data_file_name(data)?;
+        let source = self.repodata_path().join(file_name);
+        info!(
+            "Keeping current {} metadata {:?}",
+            data.type_.as_str(),
+            source
+        );
+        std::fs::copy(&source, self.tempdir.path().join(file_name)).map_err(|err| {
+            anyhow!("Cannot copy {:?}: {}", source, err).context(crate::exit_code::Failure::Io)
+        })?;
+        Ok(data.clone())
+    }
+
+    fn keep_current_prestodelta(&self) -> Result<Option<crate::repodata::repomd::Data>> {
+        match self.current_repomd.as_ref().and_then(|v| {
+            v.data
+                .iter()
+                .find(|elt| elt.type_ == crate::repodata::repomd::DataType::Prestodelta)
+        }) {
+            Some(data) => Ok(Some(self.keep_current_data(data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Documents not produced by this run are kept as is: written by other tools (comps,
+    /// updateinfo, modules, other.xml etc.) and productid if it's not configured. Outdated
+    /// documents describing packages can't be kept, they are dropped with warning
+    fn keep_current_foreign(&self) -> Vec<crate::repodata::repomd::Data> {
+        use crate::repodata::repomd::DataType;
+
+        let current_repomd = match &self.current_repomd {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        current_repomd
+            .data
+            .iter()
+            .filter(|elt| match &elt.type_ {
+                DataType::Unknown(_) => true,
+                DataType::Productid => self.config.productid.is_none(),
+                // Describes packages of previous metadata, it would reference removed packages
+                DataType::Other => {
+                    warn!("Dropping current other metadata, it is not generated");
+                    false
+                }
+                DataType::Filelists if !self.options.generate_fileslists => {
+                    warn!("Dropping current filelists metadata, fileslists are not generated");
+                    false
+                }
+                DataType::PrimaryDb | DataType::FilelistsDb | DataType::OtherDb => {
+                    warn!(
+                        "Dropping current {} metadata, sqlite databases are not supported",
+                        elt.type_.as_str()
+                    );
+                    false
+                }
+                DataType::Primary | DataType::Filelists | DataType::Prestodelta => false,
+            })
+            .filter_map(|data| match self.keep_current_data(data) {
+                Ok(v) => Some(v),
+                Err(err) => {
+                    warn!("Dropping {} metadata: {}", data.type_.as_str(), err);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Remove packages older than `keep` newest versions from new metadata
+    fn drop_outdated(&self, keep: usize) {
+        let mut primary_xml = self.primary_xml.lock().unwrap();
+        let outdated: HashSet<_> = primary_xml
+            .outdated(keep)
+            .into_iter()
+            .map(|package| package.location.href.clone())
+            .collect();
+        if outdated.is_empty() {
+            return;
+        }
+        info!(
+            "Skipping {} packages older than {} newest versions",
+            outdated.len(),
+            keep
+        );
+
+        let _ = primary_xml.drain_filter(|package| !outdated.contains(&package.location.href));
+        let kept_ids: HashSet<_> = primary_xml
+            .package
+            .iter()
+            .map(|package| package.checksum.value.clone())
+            .collect();
+
+        let mut fileslists = self.fileslist.lock().unwrap();
+        let _ = fileslists.drain_filter(|package| kept_ids.contains(&package.pkgid));
+    }
+
     pub fn finish(self) -> Result<()> {
-        let mut repomd = crate::repodata::repomd::Repomd::new();
+        let r = self.publish();
+        if let Err(err) = &r {
+            self.record_failed_run(err)
+        }
+        r
+    }
+
+    fn publish(&self) -> Result<()> {
+        crate::progress::stage("write", None);
+        let timestamp = self.options.reproducible_timestamp()?;
+        let revision = match timestamp {
+            Some(v) => {
+                info!("Using fixed timestamp {} for reproducible metadata", v);
+                v
+            }
+            None => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+        };
+        let mut repomd = crate::repodata::repomd::Repomd::new(revision);
+
+        if let Some(keep) = self.options.latest_only {
+            self.drop_outdated(keep);
+        }
 
-        let metadata = self.primary_xml.lock().unwrap();
+        // Packages are added in order of processing by thread pool, sort them to get the same
+        // output for the same set of packages
+        let mut metadata = self.primary_xml.lock().unwrap();
+        metadata.sort();
+        // Modification times of files are clamped on writing, like tar --clamp-mtime does.
+        // Records keep mtime of files until then, so that they are compared with files as is
+        if let Some(timestamp) = timestamp {
+            let timestamp = i64::try_from(timestamp).unwrap_or(i64::MAX);
+            for package in &mut metadata.package {
+                package.time.file = package.time.file.min(timestamp)
+            }
+        }
+        let current_packages: HashMap<_, _> = metadata
+            .package
+            .iter()
+            .map(|package| (package.nevra(), package.checksum.value.clone()))
+            .collect();
+        let packages_count = metadata.package.len();
+        let packages_size = metadata
+            .package
+            .iter()
+            .map(|package| package.size.package)
+            .sum();
         repomd.add_data(self.finish_xml(
             "primary",
             &*metadata,
             crate::repodata::repomd::DataType::Primary,
+            timestamp,
         )?);
 
         if self.options.generate_fileslists {
-            let metadata = self.fileslist.lock().unwrap();
+            let pkgids: HashSet<_> = current_packages.values().collect();
+            let mut metadata = self.fileslist.lock().unwrap();
+            self.restore_spilled_fileslists(&mut metadata, &pkgids)?;
+            metadata.sort();
             repomd.add_data(self.finish_xml(
                 "fileslists",
                 &*metadata,
                 crate::repodata::repomd::DataType::Filelists,
+                timestamp,
             )?);
         }
 
-        self.finish_repomd(repomd)?;
+        match &self.delta_files {
+            Some(delta_files) => {
+                if !delta_files.is_empty() {
+                    let mut metadata = self.prestodelta(delta_files);
+                    metadata.sort();
+                    repomd.add_data(self.finish_xml(
+                        "prestodelta",
+                        &metadata,
+                        crate::repodata::repomd::DataType::Prestodelta,
+                        timestamp,
+                    )?);
+                }
+            }
+            None => {
+                if let Some(data) = self.keep_current_prestodelta()? {
+                    repomd.add_data(data)
+                }
+            }
+        }
+
+        for data in self.keep_current_foreign() {
+            repomd.add_data(data)
+        }
+
+        if let Some(productid) = &self.config.productid {
+            let certificate = std::fs::read_to_string(productid).map_err(|err| {
+                anyhow!("Cannot read productid certificate {:?}: {}", productid, err)
+            })?;
+            repomd.add_data(self.finish_data(
+                "productid.gz",
+                &certificate,
+                crate::repodata::repomd::DataType::Productid,
+                timestamp,
+            )?);
+        }
 
+        self.finish_repomd(&repomd)?;
+
+        let permissions = &self.config.permissions;
+        for data in &repomd.data {
+            permissions.apply_file(&self.tempdir.path().join(Self::data_file_name(data)?))?;
+        }
+        permissions.apply_file(&self.tempdir.path().join("repomd.xml"))?;
+
+        if self.options.self_check {
+            let validator = crate::repodata::schema::SchemaValidator::new(self.config)?;
+            validator.validate_repodata(
+                &repomd,
+                &self.tempdir.path().join("repomd.xml"),
+                self.tempdir.path(),
+            )?;
+        }
+
+        crate::platform::check_interrupted()?;
+
+        let swap_span = crate::trace::span("swap");
+        crate::progress::stage("publish", None);
         let repodata_path = self.repodata_path();
-        if repodata_path.exists() {
-            info!("Removing old {:?}", repodata_path);
-            std::fs::remove_dir_all(&repodata_path)
-                .map_err(|err| anyhow!("Cannot remove old {:?}: {}", repodata_path, err))?;
-        }
-        let temp_path = self.tempdir.into_path();
-        info!("Renaming {:?} to {:?}", temp_path, repodata_path);
-        std::fs::rename(temp_path, repodata_path)?;
+        std::fs::create_dir_all(&repodata_path).map_err(|err| {
+            anyhow!("Cannot create {:?}: {}", repodata_path, err)
+                .context(crate::exit_code::Failure::Io)
+        })?;
+        permissions.apply_dir(&repodata_path)?;
+
+        for data in &repomd.data {
+            let file_name = Self::data_file_name(data)?;
+            let target = repodata_path.join(file_name);
+            info!("Moving {} to {:?}", file_name, target);
+            std::fs::rename(self.tempdir.path().join(file_name), &target).map_err(|err| {
+                anyhow!("Cannot move {} to {:?}: {}", file_name, target, err)
+                    .context(crate::exit_code::Failure::Io)
+            })?;
+        }
+
+        // Replace repomd.xml last, at this point all files it references are in place
+        let repomd_path = repodata_path.join("repomd.xml");
+        info!("Replacing {:?}", repomd_path);
+        std::fs::rename(self.tempdir.path().join("repomd.xml"), &repomd_path).map_err(|err| {
+            anyhow!("Cannot replace {:?}: {}", repomd_path, err)
+                .context(crate::exit_code::Failure::Io)
+        })?;
+        drop(swap_span);
+
+        crate::repodata::checkpoint::remove(&self.root);
+
+        let changes = crate::repodata::changelog::Revision::new(
+            revision,
+            &self.previous_packages,
+            &current_packages,
+        );
+        if let Err(err) = crate::summary::set_published(revision, &repomd_path, &repomd, &changes) {
+            warn!("Failed to summarize published metadata: {}", err);
+        }
+        if let Err(err) = self.update_changelog(changes) {
+            warn!("Failed to update changelog: {}", err);
+        }
+
+        if let Err(err) = self.update_stats(revision, packages_count, packages_size, &repomd) {
+            warn!("Failed to update statistics: {}", err);
+        }
+
+        if let Err(err) = self.cleanup_superseded(&repomd) {
+            warn!("Failed to cleanup superseded metadata: {}", err);
+        }
+
         Ok(())
     }
 
+    /// Unreadable records of current metadata are recalculated, list them once instead of
+    /// failing whole cache
+    fn report_cache_warnings(&self) {
+        let fileslists_warnings = self.current_fileslist.lock().unwrap().take_warnings();
+        let warnings: Vec<_> = self
+            .cache_warnings
+            .iter()
+            .chain(fileslists_warnings.iter())
+            .map(|v| v.to_string())
+            .collect();
+        if !warnings.is_empty() {
+            warn!(
+                "Skipped {} unreadable records of cached metadata: {}",
+                warnings.len(),
+                warnings.join("; ")
+            );
+        }
+    }
+
     pub fn restore_current(&self) {
         let mut current_packages = self.current_packages.lock().unwrap();
         let mut primary_xml = self.primary_xml.lock().unwrap();
@@ -425,20 +1848,19 @@ pub fn restore_current(&self) {
 
         let mut current_fileslists = self.current_fileslist.lock().unwrap();
         let mut fileslists = self.fileslist.lock().unwrap();
-        for (_, package) in current_fileslists.drain() {
+        for package in current_fileslists.drain() {
             fileslists.add_package(package);
         }
     }
 
-    pub fn drain_files(
-        &self,
-        paths: &[std::path::PathBuf],
-    ) -> Vec<crate::repodata::primary::Package> {
+    /// Remove records about packages for which `is_removed` is true, together with their fileslists
+    pub fn drain_packages<F>(&self, is_removed: F) -> Vec<crate::repodata::primary::Package>
+    where
+        F: Fn(&crate::repodata::primary::Package) -> bool,
+    {
         let mut primary_xml = self.primary_xml.lock().unwrap();
 
-        let removed_packages: Vec<_> = primary_xml.drain_filter(|package| {
-            !paths.contains(&std::path::PathBuf::from(&package.location.href))
-        });
+        let removed_packages: Vec<_> = primary_xml.drain_filter(|package| !is_removed(package));
 
         let removed_ids: HashSet<_> = removed_packages
             .iter()
@@ -450,6 +1872,61 @@ pub fn drain_files(
 
         removed_packages
     }
+
+    /// Records of added packages, state is dropped without publishing metadata
+    fn take_records(
+        self,
+    ) -> Result<(
+        Vec<crate::repodata::primary::Package>,
+        Vec<crate::repodata::filelists::Package>,
+    )> {
+        crate::repodata::checkpoint::remove(&self.root);
+        let packages = std::mem::take(&mut self.primary_xml.lock().unwrap().package);
+        let pkgids: HashSet<_> = packages.iter().map(|v| &v.checksum.value).collect();
+        let mut fileslists = self.fileslist.lock().unwrap();
+        self.restore_spilled_fileslists(&mut fileslists, &pkgids)?;
+        let fileslists = std::mem::take(&mut fileslists.package);
+        Ok((packages, fileslists))
+    }
+
+    /// Use records indexed by another state of the same package tree as cache instead of current
+    /// metadata, hrefs are prefixed with `location_prefix`
+    fn reuse_records(
+        &self,
+        packages: &[crate::repodata::primary::Package],
+        fileslists: &[crate::repodata::filelists::Package],
+    ) {
+        let mut current_packages = self.current_packages.lock().unwrap();
+        current_packages.clear();
+        for package in packages {
+            let mut package = package.clone();
+            let location = self.location_prefix.join(&package.location.href);
+            package.location.href = location.to_string_lossy().to_string();
+            let _ = current_packages.insert(location, package);
+        }
+
+        let mut current_fileslist = self.current_fileslist.lock().unwrap();
+        current_fileslist.clear();
+        for package in fileslists {
+            current_fileslist.insert(package.clone())
+        }
+    }
+
+    /// Remove records about packages at `paths` relative to `tree_root`
+    pub fn drain_files(
+        &self,
+        paths: &[std::path::PathBuf],
+    ) -> Vec<crate::repodata::primary::Package> {
+        let locations: Vec<_> = paths.iter().map(|v| self.location_prefix.join(v)).collect();
+        self.drain_packages(|package| {
+            locations.contains(&std::path::PathBuf::from(&package.location.href))
+        })
+    }
+
+    /// Remove records about packages which no longer exist on disk
+    pub fn drain_missing(&self) -> Vec<crate::repodata::primary::Package> {
+        self.drain_packages(|package| !self.packages_root.join(&package.location.href).exists())
+    }
 }
 
 struct NotificationState {
@@ -475,23 +1952,7 @@ pub fn tick(&mut self, state: &State) {
 
         self.last_update = now;
 
-        let proc_info = if let Ok(proc) = psutil::process::Process::current() {
-            let cpu_times = match proc.cpu_times() {
-                Ok(v) => format!(
-                    ", CPU utime={} secs, stime={} secs",
-                    v.user().as_secs(),
-                    v.system().as_secs()
-                ),
-                Err(_) => "".to_owned(),
-            };
-            let rss = match proc.memory_info() {
-                Ok(v) => format!(", rss={} B", v.rss()),
-                Err(_) => "".to_owned(),
-            };
-            format!("{}{}", cpu_times, rss)
-        } else {
-            "".to_owned()
-        };
+        let proc_info = crate::platform::process_usage();
 
         let primary_xml = state.primary_xml.lock().unwrap();
 
@@ -502,37 +1963,115 @@ pub fn tick(&mut self, state: &State) {
     }
 }
 
+struct ScannedFiles {
+    packages: Vec<std::path::PathBuf>,
+    deltas: Vec<std::path::PathBuf>,
+}
+
 pub struct Repodata<'a> {
     pub config: &'a RepodataConfig,
     pub options: RepodataOptions,
 }
 
 impl<'a> Repodata<'a> {
+    /// Configured concurrency, reduced so that workers don't exceed limit of open files, which is
+    /// raised on start
+    fn concurrency(&self) -> usize {
+        let limit = match crate::platform::open_files_limit() {
+            Ok(v) => v,
+            Err(err) => {
+                warn!("{}", err);
+                return self.config.concurrency;
+            }
+        };
+        let max_workers =
+            (limit.saturating_sub(OPEN_FILES_RESERVED) / OPEN_FILES_PER_WORKER).max(1);
+        if (self.config.concurrency as u64) > max_workers {
+            warn!(
+                "Limit of open files is {}, concurrency is reduced from {} to {}",
+                limit, self.config.concurrency, max_workers
+            );
+            return max_workers as usize;
+        }
+        self.config.concurrency
+    }
+
     fn register_files_list(&self, state: State, files: &[std::path::PathBuf]) -> Result<()> {
+        if let Err(err) = self.index_files(&state, files) {
+            state.record_failed_run(&err);
+            return Err(err);
+        }
+        state.report_cache_warnings();
+        state.finish()
+    }
+
+    /// Add records of `files` to `state`, metadata is not published
+    fn index_files(&self, state: &State, files: &[std::path::PathBuf]) -> Result<()> {
+        let concurrency = self.concurrency();
         let pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(self.config.concurrency)
+            .num_threads(concurrency)
             .build()
             .unwrap();
+        let limiter = if self.config.adaptive_concurrency {
+            Some(crate::repodata::adaptive::AdaptiveLimiter::new(concurrency))
+        } else {
+            None
+        };
 
         let progress_notification = Arc::new(Mutex::new(NotificationState::new(
             std::time::Duration::from_secs(5),
             files.len(),
         )));
+        let memory_guard = self
+            .options
+            .max_memory
+            .map(crate::repodata::memory::MemoryGuard::new);
+        let retried = Mutex::new(Vec::new());
+        let failures = std::sync::atomic::AtomicUsize::new(0);
+        let done = std::sync::atomic::AtomicUsize::new(0);
+        let is_aborted = || {
+            self.options
+                .on_error
+                .is_exceeded(failures.load(std::sync::atomic::Ordering::SeqCst))
+        };
+        let checkpoint_interval = std::time::Duration::from_secs(self.config.checkpoint_interval);
+        let last_checkpoint = Mutex::new(std::time::Instant::now());
 
+        crate::progress::stage("index", Some(files.len()));
         pool.install(|| {
             let _: Vec<_> = files
                 .par_iter()
                 .map(|v| {
+                    if crate::platform::is_interrupted() || is_aborted() {
+                        return;
+                    }
                     {
                         let mut notification = progress_notification.lock().unwrap();
-                        notification.tick(&state)
+                        notification.tick(state)
+                    }
+                    // One worker writes checkpoint, others don't wait for it
+                    let checkpoint_due = !checkpoint_interval.is_zero()
+                        && match last_checkpoint.try_lock() {
+                            Ok(mut last_checkpoint) => {
+                                let due = last_checkpoint.elapsed() >= checkpoint_interval;
+                                if due {
+                                    *last_checkpoint = std::time::Instant::now();
+                                }
+                                due
+                            }
+                            Err(_) => false,
+                        };
+                    if checkpoint_due {
+                        if let Err(err) = state.write_checkpoint() {
+                            warn!("Failed to save checkpoint: {}", err);
+                        }
                     }
-                    let relative_path = match v.strip_prefix(&self.options.path) {
-                        Ok(v) => v,
+                    let relative_path = match v.strip_prefix(&state.tree_root) {
+                        Ok(v) => state.location_prefix.join(v),
                         Err(err) => {
                             error!(
                                 "Cannot strip base repo path from file path {:?}: {}",
-                                self.options.path, err
+                                state.tree_root, err
                             );
                             return;
                         }
@@ -541,8 +2080,35 @@ fn register_files_list(&self, state: State, files: &[std::path::PathBuf]) -> Res
                         &slog_scope::logger()
                             .new(slog_o!("package" => relative_path.to_string_lossy().to_string())),
                         || {
-                            if let Err(err) = state.add_file(v, relative_path) {
-                                error!("Failed to process: {}", err);
+                            let add_file = || {
+                                let _permit = limiter.as_ref().map(|v| v.acquire());
+                                let (r, retries) = self
+                                    .config
+                                    .retry
+                                    .run("Processing", || state.add_file(v, &relative_path));
+                                if retries > 0 {
+                                    retried.lock().unwrap().push(format!(
+                                        "{} ({} retries)",
+                                        relative_path.display(),
+                                        retries
+                                    ));
+                                }
+                                crate::progress::package(
+                                    &relative_path,
+                                    r.is_ok(),
+                                    done.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1,
+                                    files.len(),
+                                );
+                                if let Err(err) = r {
+                                    error!("Failed to process: {}", err);
+                                    let _ =
+                                        failures.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                                    crate::summary::add_failed();
+                                }
+                            };
+                            match &memory_guard {
+                                Some(guard) => guard.run(|| state.spill_fileslists(), add_file),
+                                None => add_file(),
                             }
                         },
                     )
@@ -550,14 +2116,85 @@ fn register_files_list(&self, state: State, files: &[std::path::PathBuf]) -> Res
                 .collect();
         });
 
-        state.finish()?;
+        if crate::platform::is_interrupted() && !checkpoint_interval.is_zero() {
+            if let Err(err) = state.write_checkpoint() {
+                warn!("Failed to save checkpoint: {}", err);
+            }
+        }
+        crate::platform::check_interrupted()?;
+        if is_aborted() {
+            bail!(
+                "Aborted after {} failed packages according to error policy {:?}, metadata is not updated",
+                failures.into_inner(),
+                self.options.on_error
+            )
+        }
+        if failures.load(std::sync::atomic::Ordering::SeqCst) > 0 {
+            crate::exit_code::set_partial_failure()
+        }
+        if let Some(guard) = &memory_guard {
+            guard.check()?;
+        }
+        if let Some(limiter) = &limiter {
+            info!(
+                "Effective concurrency at the end of run: {}",
+                limiter.limit()
+            );
+        }
+        let retried = retried.into_inner().unwrap();
+        if !retried.is_empty() {
+            warn!("Packages processed after retries: {}", retried.join(", "));
+        }
 
         Ok(())
     }
-    pub fn generate(&self) -> Result<()> {
+    /// Find packages and delta RPMs in repository tree
+    fn scan(&self) -> Result<ScannedFiles> {
+        let _span = crate::trace::span("scan");
+        crate::progress::stage("scan", None);
+        let follow_symlinks = self.options.follow_symlinks || self.config.follow_symlinks;
+        // Followed symlinks must not lead out of filesystem of repository
+        let root_dev = if follow_symlinks {
+            Some(crate::platform::file_device(
+                &self.options.path.metadata().map_err(|err| {
+                    anyhow!("Cannot read metadata of {:?}: {}", self.options.path, err)
+                })?,
+            ))
+        } else {
+            None
+        };
+
+        let scan_filter = &self.options.scan_filter;
+
+        let mut walkdir = walkdir::WalkDir::new(&self.options.path)
+            .same_file_system(follow_symlinks)
+            .follow_links(follow_symlinks);
+        if let Some(max_depth) = scan_filter.max_depth {
+            walkdir = walkdir.max_depth(max_depth);
+        }
+
         let mut files = Vec::new();
         files.reserve(50000);
-        for elt in walkdir::WalkDir::new(&self.options.path).same_file_system(true) {
+        let mut delta_files = Vec::new();
+        // walkdir reports symlink loops as errors, so cycles are skipped below
+        for elt in walkdir.into_iter().filter_entry(|elt| {
+            match elt.path().strip_prefix(&self.options.path) {
+                Ok(relative_path) => {
+                    if relative_path == std::path::Path::new(crate::repodata::trash::TRASH_DIR)
+                        || relative_path == std::path::Path::new(crate::repodata::shard::SHARDS_DIR)
+                    {
+                        false
+                    } else if scan_filter.is_excluded(relative_path) {
+                        debug!("Skipping excluded {:?}", elt.path());
+                        false
+                    } else {
+                        true
+                    }
+                }
+                Err(_) => true,
+            }
+        }) {
+            crate::platform::check_interrupted()?;
             let elt = match elt {
                 Ok(v) => v,
                 Err(err) => {
@@ -565,19 +2202,40 @@ pub fn generate(&self) -> Result<()> {
                     continue;
                 }
             };
-            if !elt
-                .file_name()
-                .to_str()
-                .map(|v| v.to_lowercase().ends_with(".rpm"))
-                .unwrap_or(false)
-            {
+            let file_name = elt.file_name().to_string_lossy().to_lowercase();
+            let is_delta = file_name.ends_with(".drpm");
+            if !is_delta && !file_name.ends_with(".rpm") {
+                continue;
+            }
+            if elt.path_is_symlink() && !follow_symlinks {
+                info!(
+                    "Skipping symlink {:?}, symlinks are not followed",
+                    elt.path()
+                );
                 continue;
             }
+            if let Ok(relative_path) = elt.path().strip_prefix(&self.options.path) {
+                if !scan_filter.is_included(relative_path) {
+                    debug!("Skipping {:?}, not matched by include patterns", elt.path());
+                    continue;
+                }
+            }
             match elt.metadata() {
                 Ok(v) => {
                     if !v.is_file() {
                         continue;
                     }
+                    if root_dev.map_or(false, |dev| crate::platform::file_device(&v) != dev) {
+                        if elt.path_is_symlink() {
+                            warn!(
+                                "Skipping {:?}, symlink points to another filesystem",
+                                elt.path()
+                            )
+                        } else {
+                            warn!("Skipping {:?}, file is on another filesystem", elt.path())
+                        }
+                        continue;
+                    }
                 }
                 Err(err) => {
                     warn!("Cannot read entry metadata {:?}: {}", elt.path(), err);
@@ -586,15 +2244,187 @@ pub fn generate(&self) -> Result<()> {
             }
 
             let path = elt.path().to_owned();
-            debug!("Found RPM file {:?}", path);
-            files.push(path)
+            if is_delta {
+                debug!("Found delta RPM file {:?}", path);
+                delta_files.push(path)
+            } else {
+                debug!("Found RPM file {:?}", path);
+                files.push(path)
+            }
+        }
+
+        info!(
+            "Found {} RPM files and {} delta RPM files",
+            files.len(),
+            delta_files.len()
+        );
+
+        Ok(ScannedFiles {
+            packages: files,
+            deltas: delta_files,
+        })
+    }
+
+    fn write_treeinfo(&self) -> Result<()> {
+        if let Some(treeinfo) = &self.config.treeinfo {
+            let timestamp = match self.options.reproducible_timestamp()? {
+                Some(v) => v,
+                None => std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)?
+                    .as_secs(),
+            };
+            treeinfo.write(
+                &self
+                    .options
+                    .metadata_root(&self.options.repository_root().0),
+                timestamp,
+                &self.config.permissions,
+            )?;
         }
+        Ok(())
+    }
 
-        info!("Found {} RPM files", files.len());
+    /// Generate metadata, surrounded by pre_generate, post_generate and on_failure hooks
+    pub fn generate(&self) -> Result<()> {
+        let hooks = &self.config.hooks;
+        crate::repodata::hooks::run(
+            hooks,
+            crate::repodata::hooks::HookEvent::PreGenerate,
+            &self.options.path,
+            &[],
+        )?;
+        match self.generate_metadata() {
+            Ok(()) => {
+                let report = self
+                    .options
+                    .metadata_root(&self.options.repository_root().0)
+                    .join("repodata")
+                    .join(crate::repodata::changelog::CHANGELOG_FILE);
+                if let Err(err) = crate::repodata::hooks::run(
+                    hooks,
+                    crate::repodata::hooks::HookEvent::PostGenerate,
+                    &self.options.path,
+                    &[("report", report.to_string_lossy().as_ref())],
+                ) {
+                    warn!("{}", err);
+                }
+                Ok(())
+            }
+            Err(err) => {
+                if let Err(hook_err) = crate::repodata::hooks::run(
+                    hooks,
+                    crate::repodata::hooks::HookEvent::OnFailure,
+                    &self.options.path,
+                    &[("error", format!("{:#}", err).as_str())],
+                ) {
+                    warn!("{}", hook_err);
+                }
+                Err(err)
+            }
+        }
+    }
 
-        let state = State::new(self.config, &self.options)?;
+    fn generate_metadata(&self) -> Result<()> {
+        if let Some(shard) = &self.options.shard {
+            return self.generate_shard(shard);
+        }
+        let scanned = self.scan()?;
 
-        self.register_files_list(state, &files)
+        let debug_tree = self.options.path.join(DEBUG_REPOSITORY);
+        let (mut debug_packages, packages): (Vec<_>, Vec<_>) = if self.options.split_debug {
+            scanned
+                .packages
+                .into_iter()
+                .partition(|path| path.starts_with(&debug_tree))
+        } else {
+            (Vec::new(), scanned.packages)
+        };
+
+        let mut state = State::new(self.config, &self.options)?;
+        state.delta_files = Some(scanned.deltas);
+        if self.options.prune_missing {
+            for package in state.drain_missing() {
+                warn!(
+                    "Package {:?} no longer exists, removed from index",
+                    package.location.href
+                );
+            }
+        }
+        let found_debug_packages = Arc::new(Mutex::new(Vec::new()));
+        if self.options.split_debug {
+            state.debug_packages = Some(found_debug_packages.clone());
+        }
+        self.register_files_list(state, &packages)?;
+
+        self.write_treeinfo()?;
+
+        if self.options.split_debug {
+            let found = std::mem::take(&mut *found_debug_packages.lock().unwrap());
+            debug_packages.extend(self.move_debug_packages(found)?);
+            info!(
+                "Generating debug repository of {} packages",
+                debug_packages.len()
+            );
+            let mut state = State::new_of_subtree(
+                self.config,
+                &self.options,
+                std::path::Path::new(DEBUG_REPOSITORY),
+            )?;
+            state.delta_files = Some(Vec::new());
+            self.register_files_list(state, &debug_packages)?;
+        }
+
+        Ok(())
+    }
+
+    /// Move debug packages found outside of debug/ there, keeping their paths relative to package
+    /// tree, so that hrefs of debug repository stay inside of it. Returns new paths
+    fn move_debug_packages(
+        &self,
+        packages: Vec<std::path::PathBuf>,
+    ) -> Result<Vec<std::path::PathBuf>> {
+        let mut r = Vec::new();
+        for path in packages {
+            let relative_path = path
+                .strip_prefix(&self.options.path)
+                .map_err(|err| anyhow!("Cannot get relative path of {:?}: {}", path, err))?;
+            let target = self.options.path.join(DEBUG_REPOSITORY).join(relative_path);
+            if target.exists() {
+                warn!(
+                    "Cannot move debug package {:?} to {:?}: target already exists",
+                    path, target
+                );
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).map_err(|err| {
+                    anyhow!("Cannot create {:?}: {}", parent, err)
+                        .context(crate::exit_code::Failure::Io)
+                })?;
+            }
+            info!("Moving debug package {:?} to {:?}", path, target);
+            std::fs::rename(&path, &target).map_err(|err| {
+                anyhow!("Cannot move {:?} to {:?}: {}", path, target, err)
+                    .context(crate::exit_code::Failure::Io)
+            })?;
+            self.remove_empty_parents(relative_path);
+            r.push(target)
+        }
+        Ok(r)
+    }
+
+    /// Remove directories left empty after moving package out of them
+    fn remove_empty_parents(&self, relative_path: &std::path::Path) {
+        for dir in relative_path
+            .ancestors()
+            .skip(1)
+            .filter(|v| !v.as_os_str().is_empty())
+        {
+            if std::fs::remove_dir(self.options.path.join(dir)).is_err() {
+                break;
+            }
+            debug!("Removed empty directory {:?}", dir);
+        }
     }
 
     pub fn add_files(&self, files: &[std::path::PathBuf]) -> Result<()> {
@@ -602,9 +2432,21 @@ pub fn add_files(&self, files: &[std::path::PathBuf]) -> Result<()> {
             .iter()
             .filter(|path| {
                 let full_path = self.options.path.join(path);
+                let relative_path = full_path
+                    .strip_prefix(&self.options.path)
+                    .unwrap_or(path.as_path());
                 if !full_path.exists() {
                     warn!("File {:?} not found, skipping", path);
                     false
+                } else if self.options.scan_filter.is_excluded(relative_path) {
+                    warn!("File {:?} matches exclude pattern, skipping", path);
+                    false
+                } else if !self.options.scan_filter.is_included(relative_path) {
+                    warn!("File {:?} does not match include patterns, skipping", path);
+                    false
+                } else if self.options.scan_filter.is_too_deep(relative_path) {
+                    warn!("File {:?} is deeper than max depth, skipping", path);
+                    false
                 } else {
                     match path.file_name() {
                         None => {
@@ -625,12 +2467,26 @@ pub fn add_files(&self, files: &[std::path::PathBuf]) -> Result<()> {
                     }
                 }
             })
-            .map(|v| v.to_owned())
+            .map(|path| {
+                let full_path = self.options.path.join(path);
+                match full_path.strip_prefix(&self.options.path) {
+                    Ok(v) => v.to_path_buf(),
+                    Err(_) => path.to_owned(),
+                }
+            })
             .collect();
 
         info!("Will add {} RPM files", files.len());
 
-        let state = State::new(self.config, &self.options)?;
+        let (debug_files, files): (Vec<_>, Vec<_>) = if self.options.split_debug {
+            files
+                .into_iter()
+                .partition(|path| path.starts_with(DEBUG_REPOSITORY))
+        } else {
+            (Vec::new(), files)
+        };
+
+        let mut state = State::new(self.config, &self.options)?;
         state.restore_current();
 
         let removed_packages = state.drain_files(&files);
@@ -640,17 +2496,193 @@ pub fn add_files(&self, files: &[std::path::PathBuf]) -> Result<()> {
             removed_packages.len()
         );
 
+        if self.options.prune_missing {
+            for package in state.drain_missing() {
+                warn!(
+                    "Package {:?} no longer exists, removed from index",
+                    package.location.href
+                );
+            }
+        }
+
+        let found_debug_packages = Arc::new(Mutex::new(Vec::new()));
+        if self.options.split_debug {
+            state.debug_packages = Some(found_debug_packages.clone());
+        }
         self.register_files_list(
             state,
             &files
                 .into_iter()
                 .map(|v| self.options.path.join(v))
                 .collect::<Vec<_>>(),
-        )
+        )?;
+        if !self.options.split_debug {
+            return Ok(());
+        }
+
+        let found = std::mem::take(&mut *found_debug_packages.lock().unwrap());
+        let debug_files: Vec<_> = debug_files
+            .into_iter()
+            .map(|v| self.options.path.join(v))
+            .chain(self.move_debug_packages(found)?)
+            .collect();
+        if debug_files.is_empty() {
+            return Ok(());
+        }
+        info!("Adding {} packages to debug repository", debug_files.len());
+        let state = State::new_of_subtree(
+            self.config,
+            &self.options,
+            std::path::Path::new(DEBUG_REPOSITORY),
+        )?;
+        state.restore_current();
+        let relative_paths: Vec<_> = debug_files
+            .iter()
+            .filter_map(|v| v.strip_prefix(&state.tree_root).ok())
+            .map(|v| v.to_path_buf())
+            .collect();
+        let _ = state.drain_files(&relative_paths);
+        if self.options.prune_missing {
+            for package in state.drain_missing() {
+                warn!(
+                    "Package {:?} no longer exists, removed from debug index",
+                    package.location.href
+                );
+            }
+        }
+        self.register_files_list(state, &debug_files)
     }
 
-    pub fn validate(&self) -> Result<()> {
-        let _state = State::new(self.config, &self.options)?;
-        Ok(())
+    /// Shared lock on repomd.xml of repository, held by read-only commands while they read metadata
+    fn read_lock(&self) -> Result<Option<file_lock::FileLock>> {
+        let xml_path = self.options.path.join("repodata").join("repomd.xml");
+        if self.options.no_lock || !self.config.lock.shared_for_readers || !xml_path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(self.config.lock.lock(&xml_path, false)?))
+    }
+
+    /// Read current metadata of repository for queries, restricted by package filter
+    fn read_index(&self) -> Result<crate::repodata::query::Index> {
+        let _lock = self.read_lock()?;
+        let mut index = crate::repodata::query::Index::read(&self.options.path)?;
+        index.retain(|package| self.options.package_filter.is_accepted(package));
+        Ok(index)
+    }
+}
+
+#[test]
+fn test_gzip_mtime_writer() {
+    let mut writer = GzipMtimeWriter::new(Vec::new(), 1700000000);
+    let header = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255, 1, 2];
+    // Header is split across writes
+    for chunk in header.chunks(5) {
+        writer.write_all(chunk).unwrap();
     }
+    assert_eq!(&writer.inner[..4], &header[..4]);
+    assert_eq!(&writer.inner[4..8], &1700000000u32.to_le_bytes());
+    assert_eq!(&writer.inner[8..], &header[8..]);
+}
+
+#[test]
+fn test_repository_root() {
+    let mut options = RepodataOptions {
+        path: std::path::PathBuf::from("/srv/repo"),
+        ..Default::default()
+    };
+    assert_eq!(
+        options.repository_root(),
+        (
+            std::path::PathBuf::from("/srv/repo"),
+            std::path::PathBuf::new()
+        )
+    );
+
+    options.package_filter.arch = vec!["x86_64".to_owned(), "i686".to_owned()];
+    assert_eq!(
+        options.repository_root(),
+        (
+            std::path::PathBuf::from("/srv/repo/views/i686-x86_64"),
+            std::path::PathBuf::from("../..")
+        )
+    );
+}
+
+#[test]
+fn test_move_debug_packages() {
+    let config: RepodataConfig =
+        serde_yaml::from_str("concurrency: 1\nuseful_files: ^/etc\n").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let package = dir.path().join("Packages/f/foo-debuginfo-1.0-1.x86_64.rpm");
+    std::fs::create_dir_all(package.parent().unwrap()).unwrap();
+    std::fs::write(&package, b"rpm").unwrap();
+    let repodata = Repodata {
+        config: &config,
+        options: RepodataOptions {
+            path: dir.path().to_path_buf(),
+            split_debug: true,
+            ..Default::default()
+        },
+    };
+
+    let moved = repodata.move_debug_packages(vec![package.clone()]).unwrap();
+    let target = dir
+        .path()
+        .join("debug/Packages/f/foo-debuginfo-1.0-1.x86_64.rpm");
+    assert_eq!(moved, [target.clone()]);
+    assert!(target.exists());
+    assert!(!dir.path().join("Packages").exists());
+
+    let (root, location_prefix) = repodata
+        .options
+        .repository_root_of(std::path::Path::new(DEBUG_REPOSITORY));
+    assert_eq!(root, dir.path().join("debug"));
+    assert_eq!(location_prefix, std::path::PathBuf::new());
+}
+
+#[test]
+fn test_reuse_records() {
+    let config: RepodataConfig =
+        serde_yaml::from_str("concurrency: 1\nuseful_files: ^/etc\n").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let options = RepodataOptions {
+        path: dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    let relative_root = crate::repodata::compose::ComposeVariant::relative_root("Server", "x86_64");
+    assert_eq!(
+        parent_prefix(&relative_root),
+        std::path::PathBuf::from("../../..")
+    );
+    let state = State::new_in(
+        &config,
+        &options,
+        dir.path().join(&relative_root),
+        parent_prefix(&relative_root),
+    )
+    .unwrap();
+
+    let primary =
+        crate::repodata::primary::test_primary(&[crate::repodata::primary::TestPackage::new(
+            "foo", "1.0",
+        )
+        .checksum("a")
+        .href("Packages/foo-1.0-1.x86_64.rpm")]);
+    let fileslist: crate::repodata::filelists::Package = quick_xml::de::from_str(
+        r#"<package pkgid="a" name="foo" arch="x86_64"><version epoch="0" ver="1.0" rel="1"/><file>/etc/foo</file></package>"#,
+    )
+    .unwrap();
+    state.reuse_records(&primary.package, &[fileslist.clone()]);
+
+    let location = std::path::PathBuf::from("../../../Packages/foo-1.0-1.x86_64.rpm");
+    let current_packages = state.current_packages.lock().unwrap();
+    assert_eq!(current_packages.len(), 1);
+    assert_eq!(
+        current_packages[&location].location.href,
+        location.to_string_lossy()
+    );
+    assert_eq!(
+        state.current_fileslist.lock().unwrap().remove("a"),
+        Some(fileslist)
+    );
 }
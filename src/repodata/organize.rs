@@ -0,0 +1,81 @@
+//! Moving of indexed packages into canonical layout Packages/<first letter>/<file name>
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use slog_scope::{info, warn};
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Location of package in canonical layout: Packages/<first letter>/<file name>
+    fn organized_location(package: &crate::repodata::primary::Package) -> std::path::PathBuf {
+        let first_letter: String = package
+            .name
+            .value
+            .chars()
+            .take(1)
+            .flat_map(char::to_lowercase)
+            .collect();
+        std::path::Path::new("Packages")
+            .join(first_letter)
+            .join(package.canonical_file_name())
+    }
+
+    /// Move indexed packages into canonical layout and regenerate metadata. Packages which are
+    /// not in current metadata are left in place
+    pub fn organize(&self, dry_run: bool) -> Result<()> {
+        let mut state = crate::repodata::State::new(self.config, &self.options)?;
+
+        {
+            let mut current_packages = state.current_packages.lock().unwrap();
+            if current_packages.is_empty() {
+                bail!("No packages in current metadata, run \"repository generate\" first")
+            }
+            let mut moved = 0;
+            let mut organized = HashMap::new();
+            for (location, mut package) in current_packages.drain() {
+                let target = Self::organized_location(&package);
+                if target == location {
+                    let _ = organized.insert(location, package);
+                    continue;
+                }
+                let target_path = self.options.path.join(&target);
+                if organized.contains_key(&target) || target_path.exists() {
+                    warn!(
+                        "Cannot move {:?} to {:?}: target already exists",
+                        location, target
+                    );
+                    let _ = organized.insert(location, package);
+                    continue;
+                }
+                if dry_run {
+                    println!("{} -> {}", location.display(), target.display());
+                    let _ = organized.insert(location, package);
+                    continue;
+                }
+                if let Some(parent) = target_path.parent() {
+                    std::fs::create_dir_all(parent).map_err(|err| {
+                        anyhow!("Cannot create {:?}: {}", parent, err)
+                            .context(crate::exit_code::Failure::Io)
+                    })?;
+                }
+                info!("Moving {:?} to {:?}", location, target);
+                std::fs::rename(self.options.path.join(&location), &target_path).map_err(
+                    |err| anyhow!("Cannot move {:?} to {:?}: {}", location, target, err),
+                )?;
+                self.remove_empty_parents(&location);
+                package.location.href = target.to_string_lossy().to_string();
+                let _ = organized.insert(target, package);
+                moved += 1;
+            }
+            if dry_run {
+                return Ok(());
+            }
+            info!("Moved {} packages", moved);
+            *current_packages = organized;
+        }
+
+        let scanned = self.scan()?;
+        state.delta_files = Some(scanned.deltas);
+        self.register_files_list(state, &scanned.packages)
+    }
+}
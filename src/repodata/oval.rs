@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use quick_xml::events::{BytesStart, Event};
+
+use crate::repodata::updateinfo::{Advisory, UpdatePackage};
+
+#[derive(Default)]
+struct Definition {
+    id: String,
+    advisory: Option<String>,
+    severity: Option<String>,
+    cves: Vec<String>,
+    tests: Vec<String>,
+}
+
+#[derive(Default)]
+struct Test {
+    id: String,
+    object: Option<String>,
+    state: Option<String>,
+}
+
+#[derive(Default)]
+struct Object {
+    id: String,
+    name: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    id: String,
+    /// EVR of "less than" comparison, other comparisons don't name fixed version
+    evr: Option<String>,
+    arch: Option<String>,
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Result<Option<String>> {
+    Ok(match e.try_get_attribute(name)? {
+        Some(v) => Some(v.unescape_value()?.to_string()),
+        None => None,
+    })
+}
+
+/// Architectures of "aarch64|x86_64" pattern. None if pattern is not a plain list
+fn arches(pattern: &str) -> Option<Vec<String>> {
+    let r: Vec<_> = pattern.split('|').map(|v| v.to_owned()).collect();
+    r.iter()
+        .all(|v| !v.is_empty() && v.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .then_some(r)
+}
+
+/// Advisories of OVAL definitions. Every rpminfo test of definition criteria comparing package
+/// with "less than" EVR gives fixed version, signature and other tests are ignored. Advisory is
+/// named by first non-CVE reference of definition, e.g. RHSA-2023:0001
+pub fn advisories(data: &[u8]) -> Result<Vec<Advisory>> {
+    let mut reader = quick_xml::Reader::from_reader(data);
+    let _ = reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    let mut definitions = Vec::new();
+    let mut tests = HashMap::new();
+    let mut objects = HashMap::new();
+    let mut states = HashMap::new();
+
+    let mut definition: Option<Definition> = None;
+    let mut test: Option<Test> = None;
+    let mut object: Option<Object> = None;
+    let mut state: Option<State> = None;
+    let mut less_than = false;
+    let mut path: Vec<Vec<u8>> = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|err| anyhow!("{}", err))?;
+        match &event {
+            Event::Start(e) | Event::Empty(e) => {
+                match e.local_name().as_ref() {
+                    b"definition" => {
+                        definition = Some(Definition {
+                            id: attribute(e, "id")?.unwrap_or_default(),
+                            ..Default::default()
+                        })
+                    }
+                    b"reference" => {
+                        if let (Some(definition), Some(source), Some(ref_id)) = (
+                            &mut definition,
+                            attribute(e, "source")?,
+                            attribute(e, "ref_id")?,
+                        ) {
+                            if source.eq_ignore_ascii_case("cve") {
+                                definition.cves.push(ref_id)
+                            } else if definition.advisory.is_none() {
+                                definition.advisory = Some(ref_id)
+                            }
+                        }
+                    }
+                    b"criterion" => {
+                        if let (Some(definition), Some(test_ref)) =
+                            (&mut definition, attribute(e, "test_ref")?)
+                        {
+                            definition.tests.push(test_ref)
+                        }
+                    }
+                    b"rpminfo_test" => {
+                        test = Some(Test {
+                            id: attribute(e, "id")?.unwrap_or_default(),
+                            ..Default::default()
+                        })
+                    }
+                    b"object" => {
+                        if let Some(test) = &mut test {
+                            test.object = attribute(e, "object_ref")?
+                        }
+                    }
+                    b"state" => {
+                        if let Some(test) = &mut test {
+                            test.state = attribute(e, "state_ref")?
+                        }
+                    }
+                    b"rpminfo_object" => {
+                        object = Some(Object {
+                            id: attribute(e, "id")?.unwrap_or_default(),
+                            ..Default::default()
+                        })
+                    }
+                    b"rpminfo_state" => {
+                        state = Some(State {
+                            id: attribute(e, "id")?.unwrap_or_default(),
+                            ..Default::default()
+                        })
+                    }
+                    b"evr" => {
+                        less_than = attribute(e, "operation")?.as_deref() == Some("less than")
+                    }
+                    _ => (),
+                }
+                if let Event::Start(e) = &event {
+                    path.push(e.local_name().as_ref().to_vec())
+                }
+            }
+            Event::Text(e) => {
+                let text = e.unescape().map_err(|err| anyhow!("{}", err))?.to_string();
+                match path.last().map(|v| v.as_slice()) {
+                    Some(b"severity") => {
+                        if let Some(definition) = &mut definition {
+                            definition.severity = Some(text)
+                        }
+                    }
+                    Some(b"name") => {
+                        if let Some(object) = &mut object {
+                            object.name = Some(text)
+                        }
+                    }
+                    Some(b"evr") if less_than => {
+                        if let Some(state) = &mut state {
+                            state.evr = Some(text)
+                        }
+                    }
+                    Some(b"arch") => {
+                        if let Some(state) = &mut state {
+                            state.arch = Some(text)
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Event::End(e) => {
+                let _ = path.pop();
+                match e.local_name().as_ref() {
+                    b"definition" => definitions.extend(definition.take()),
+                    b"rpminfo_test" => {
+                        if let Some(v) = test.take() {
+                            let _ = tests.insert(v.id.clone(), v);
+                        }
+                    }
+                    b"rpminfo_object" => {
+                        if let Some(v) = object.take() {
+                            let _ = objects.insert(v.id.clone(), v);
+                        }
+                    }
+                    b"rpminfo_state" => {
+                        if let Some(v) = state.take() {
+                            let _ = states.insert(v.id.clone(), v);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    let mut r = Vec::new();
+    for definition in definitions {
+        let mut fixed = Vec::new();
+        for test in definition.tests.iter().filter_map(|v| tests.get(v)) {
+            let name = test
+                .object
+                .as_ref()
+                .and_then(|v| objects.get(v))
+                .and_then(|v| v.name.as_ref());
+            let state = test.state.as_ref().and_then(|v| states.get(v));
+            let (name, state) = match (name, state) {
+                (Some(name), Some(state)) => (name, state),
+                _ => continue,
+            };
+            let evr: crate::evr::Evr = match state.evr.as_ref().map(|v| v.parse()) {
+                Some(Ok(v)) => v,
+                _ => continue,
+            };
+            let release = match evr.release {
+                Some(v) => v,
+                None => continue,
+            };
+            let arches = match state.arch.as_deref().and_then(arches) {
+                Some(arches) => arches.into_iter().map(Some).collect(),
+                None => vec![None],
+            };
+            for arch in arches {
+                let package = UpdatePackage {
+                    name: name.clone(),
+                    epoch: (evr.epoch > 0).then_some(evr.epoch),
+                    version: evr.version.clone(),
+                    release: release.clone(),
+                    arch,
+                };
+                if !fixed.contains(&package) {
+                    fixed.push(package)
+                }
+            }
+        }
+        if fixed.is_empty() {
+            continue;
+        }
+        r.push(Advisory {
+            id: definition.advisory.unwrap_or(definition.id),
+            severity: definition.severity,
+            cves: definition.cves,
+            fixed,
+        })
+    }
+    Ok(r)
+}
+
+#[test]
+fn test_advisories() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<oval_definitions xmlns="http://oval.mitre.org/XMLSchema/oval-definitions-5" xmlns:red-def="http://oval.mitre.org/XMLSchema/oval-definitions-5#linux">
+  <definitions>
+    <definition class="patch" id="oval:com.redhat.rhsa:def:20230001" version="635">
+      <metadata>
+        <title>RHSA-2023:0001: bash security update (Important)</title>
+        <reference ref_id="RHSA-2023:0001" ref_url="https://access.redhat.com/errata/RHSA-2023:0001" source="RHSA"/>
+        <reference ref_id="CVE-2023-0001" ref_url="https://access.redhat.com/security/cve/CVE-2023-0001" source="CVE"/>
+        <advisory from="secalert@redhat.com">
+          <severity>Important</severity>
+        </advisory>
+      </metadata>
+      <criteria operator="OR">
+        <criterion comment="Red Hat Enterprise Linux must be installed" test_ref="oval:com.redhat.rhsa:tst:20230001004"/>
+        <criteria operator="AND">
+          <criterion comment="bash is earlier than 0:5.1.8-6.el9_1" test_ref="oval:com.redhat.rhsa:tst:20230001001"/>
+          <criterion comment="bash is signed with Red Hat redhatrelease2 key" test_ref="oval:com.redhat.rhsa:tst:20230001002"/>
+        </criteria>
+      </criteria>
+    </definition>
+  </definitions>
+  <tests>
+    <red-def:rpminfo_test check="at least one" comment="bash is earlier than 0:5.1.8-6.el9_1" id="oval:com.redhat.rhsa:tst:20230001001" version="635">
+      <red-def:object object_ref="oval:com.redhat.rhsa:obj:20230001001"/>
+      <red-def:state state_ref="oval:com.redhat.rhsa:ste:20230001001"/>
+    </red-def:rpminfo_test>
+    <red-def:rpminfo_test check="at least one" comment="bash is signed with Red Hat redhatrelease2 key" id="oval:com.redhat.rhsa:tst:20230001002" version="635">
+      <red-def:object object_ref="oval:com.redhat.rhsa:obj:20230001001"/>
+      <red-def:state state_ref="oval:com.redhat.rhsa:ste:20230001002"/>
+    </red-def:rpminfo_test>
+  </tests>
+  <objects>
+    <red-def:rpminfo_object id="oval:com.redhat.rhsa:obj:20230001001" version="635">
+      <red-def:name>bash</red-def:name>
+    </red-def:rpminfo_object>
+  </objects>
+  <states>
+    <red-def:rpminfo_state id="oval:com.redhat.rhsa:ste:20230001001" version="635">
+      <red-def:arch datatype="string" operation="pattern match">aarch64|x86_64</red-def:arch>
+      <red-def:evr datatype="evr_string" operation="less than">0:5.1.8-6.el9_1</red-def:evr>
+    </red-def:rpminfo_state>
+    <red-def:rpminfo_state id="oval:com.redhat.rhsa:ste:20230001002" version="635">
+      <red-def:signature_keyid operation="equals">199e2f91fd431d51</red-def:signature_keyid>
+    </red-def:rpminfo_state>
+  </states>
+</oval_definitions>"#;
+    let advisories = advisories(xml.as_bytes()).unwrap();
+    assert_eq!(advisories.len(), 1);
+    let advisory = &advisories[0];
+    assert_eq!(advisory.id, "RHSA-2023:0001");
+    assert_eq!(advisory.severity.as_deref(), Some("Important"));
+    assert_eq!(advisory.cves, ["CVE-2023-0001"]);
+    let fixed: Vec<_> = advisory
+        .fixed
+        .iter()
+        .map(|v| {
+            (
+                v.name.as_str(),
+                v.epoch,
+                v.version.as_str(),
+                v.release.as_str(),
+                v.arch.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        fixed,
+        [
+            ("bash", None, "5.1.8", "6.el9_1", Some("aarch64")),
+            ("bash", None, "5.1.8", "6.el9_1", Some("x86_64")),
+        ]
+    );
+}
@@ -0,0 +1,181 @@
+use std::io::Write;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+
+/// Result of checking package against repository before publishing
+#[derive(Serialize, Debug)]
+pub struct PackageCheck {
+    pub package: String,
+    /// "newer", "older", "equal" or "absent", relative to the newest package with the same name
+    /// and architecture in repository
+    pub version: &'static str,
+    pub repository_version: Option<String>,
+    pub nevra_exists: bool,
+    pub checksum_exists: bool,
+    pub unresolved_requires: Vec<String>,
+}
+
+/// "sha" is an old name of SHA-1
+fn checksum_type(v: &str) -> &str {
+    match v {
+        "sha" => "sha1",
+        v => v,
+    }
+}
+
+impl PackageCheck {
+    /// `checksums` are checksums of package file by type, e.g. ("sha256", "...")
+    pub fn new(
+        package: &crate::repodata::primary::Package,
+        checksums: &[(&str, String)],
+        index: crate::repodata::query::Index,
+    ) -> Self {
+        let evr = crate::evr::Evr::from(&package.version);
+        let newest = index
+            .primary
+            .package
+            .iter()
+            .filter(|v| v.name == package.name && v.arch == package.arch)
+            .map(|v| crate::evr::Evr::from(&v.version))
+            .max_by(|a, b| a.compare(b));
+        let version = match &newest {
+            None => "absent",
+            Some(newest) => match evr.compare(newest) {
+                std::cmp::Ordering::Greater => "newer",
+                std::cmp::Ordering::Equal => "equal",
+                std::cmp::Ordering::Less => "older",
+            },
+        };
+
+        let nevra = package.nevra();
+        let nevra_exists = index.primary.package.iter().any(|v| v.nevra() == nevra);
+        let checksum_exists = index.primary.package.iter().any(|v| {
+            checksums.iter().any(|(type_, value)| {
+                checksum_type(&v.checksum.type_) == checksum_type(type_)
+                    && &v.checksum.value == value
+            })
+        });
+
+        let mut single = crate::repodata::primary::Primary::new();
+        single.add_package(package.clone());
+        let single = crate::repodata::query::Index {
+            primary: single,
+            filelists: None,
+        };
+        let unresolved_requires = single
+            .unresolved_requires(std::slice::from_ref(&index))
+            .into_iter()
+            .map(|(_, require)| require.to_string())
+            .collect();
+
+        Self {
+            package: nevra,
+            version,
+            repository_version: newest.map(|v| v.to_string()),
+            nevra_exists,
+            checksum_exists,
+            unresolved_requires,
+        }
+    }
+
+    /// Reasons not to publish the package
+    pub fn problems(&self) -> Vec<String> {
+        let mut r = Vec::new();
+        if self.version == "older" {
+            r.push(format!(
+                "older than {} in repository",
+                self.repository_version.as_deref().unwrap_or_default()
+            ))
+        }
+        if self.checksum_exists {
+            r.push("already published".to_owned())
+        } else if self.nevra_exists {
+            r.push("another package with the same NEVRA is published".to_owned())
+        }
+        for require in &self.unresolved_requires {
+            r.push(format!("nothing provides {}", require))
+        }
+        r
+    }
+}
+
+/// Download repomd.xml, primary and filelists of remote repository into `dir` using curl, so that
+/// `dir` can be read as local repository. Token is passed to curl through stdin, not command line
+pub fn fetch_metadata(url: &str, dir: &std::path::Path, token: Option<&str>) -> Result<()> {
+    let url = url.trim_end_matches('/');
+    let fetch = |relative: &str| -> Result<()> {
+        let relative_path = std::path::Path::new(relative);
+        if !relative_path
+            .components()
+            .all(|v| matches!(v, std::path::Component::Normal(_)))
+        {
+            bail!("Refusing to fetch {:?} outside of repository", relative)
+        }
+        let path = dir.join(relative_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|err| anyhow!("Cannot create {:?}: {}", parent, err))?;
+        }
+        let mut command = std::process::Command::new("curl");
+        let _ = command
+            .arg("--fail")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--location")
+            .arg("--output")
+            .arg(&path)
+            .arg(format!("{}/{}", url, relative))
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        if token.is_some() {
+            let _ = command.arg("--header").arg("@-");
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|err| anyhow!("Cannot run curl: {}", err))?;
+        if let (Some(token), Some(mut stdin)) = (token, child.stdin.take()) {
+            writeln!(stdin, "Authorization: Bearer {}", token)
+                .map_err(|err| anyhow!("Cannot pass token to curl: {}", err))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| anyhow!("Cannot run curl: {}", err))?;
+        if !output.status.success() {
+            bail!(
+                "Cannot fetch {}/{}: {}",
+                url,
+                relative,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        Ok(())
+    };
+
+    fetch("repodata/repomd.xml")?;
+    let repomd = crate::repodata::repomd::Repomd::read(&dir.join("repodata").join("repomd.xml"))?;
+    for data in &repomd.data {
+        if matches!(
+            data.type_,
+            crate::repodata::repomd::DataType::Primary
+                | crate::repodata::repomd::DataType::Filelists
+        ) {
+            fetch(&data.location.href)?
+        }
+    }
+    Ok(())
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Compare package with packages of repository and check its requirements. `checksums` are
+    /// checksums of package file by type
+    pub fn check_package(
+        &self,
+        package: &crate::repodata::primary::Package,
+        checksums: &[(&str, String)],
+    ) -> Result<PackageCheck> {
+        let index = self.read_index()?;
+        Ok(PackageCheck::new(package, checksums, index))
+    }
+}
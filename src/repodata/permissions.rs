@@ -0,0 +1,113 @@
+use std::os::unix::fs::PermissionsExt;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::debug;
+
+/// Modes are written as octal strings, e.g. "0755"
+mod octal_mode {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(mode: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match mode {
+            Some(v) => serializer.serialize_str(&format!("{:04o}", v)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(v) => u32::from_str_radix(&v, 8)
+                .map(Some)
+                .map_err(|err| serde::de::Error::custom(format!("Invalid mode {:?}: {}", v, err))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct PermissionsConfig {
+    #[serde(default, with = "octal_mode")]
+    pub dir_mode: Option<u32>,
+    #[serde(default, with = "octal_mode")]
+    pub file_mode: Option<u32>,
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+}
+
+impl PermissionsConfig {
+    fn chown(&self, path: &std::path::Path) -> Result<()> {
+        if self.uid.is_none() && self.gid.is_none() {
+            return Ok(());
+        }
+
+        let c_path =
+            std::ffi::CString::new(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()))?;
+        let uid = self.uid.unwrap_or(u32::MAX) as libc::uid_t;
+        let gid = self.gid.unwrap_or(u32::MAX) as libc::gid_t;
+        if unsafe { libc::chown(c_path.as_ptr(), uid, gid) } != 0 {
+            return Err(anyhow!(
+                "Cannot change owner of {:?}: {}",
+                path,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    fn set_mode(path: &std::path::Path, mode: u32) -> Result<()> {
+        debug!("Setting mode {:04o} on {:?}", mode, path);
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(|err| anyhow!("Cannot set mode of {:?}: {}", path, err))
+    }
+
+    /// Apply configured mode and owner to generated file. Without configured mode file keeps
+    /// the one it was created with
+    pub fn apply_file(&self, path: &std::path::Path) -> Result<()> {
+        if let Some(mode) = self.file_mode {
+            Self::set_mode(path, mode)?;
+        }
+        self.chown(path)
+    }
+
+    /// Apply configured mode and owner to generated directory. Without configured mode directory
+    /// keeps the one it was created with, so umask is honored
+    pub fn apply_dir(&self, path: &std::path::Path) -> Result<()> {
+        if let Some(mode) = self.dir_mode {
+            Self::set_mode(path, mode)?;
+        }
+        self.chown(path)
+    }
+}
+
+#[test]
+fn test_apply() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("repomd.xml");
+    std::fs::write(&file, "").unwrap();
+    let mode = |path: &std::path::Path| path.metadata().unwrap().permissions().mode() & 0o7777;
+
+    std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o750)).unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640)).unwrap();
+    let config = PermissionsConfig::default();
+    config.apply_dir(dir.path()).unwrap();
+    config.apply_file(&file).unwrap();
+    assert_eq!(mode(dir.path()), 0o750);
+    assert_eq!(mode(&file), 0o640);
+
+    let config: PermissionsConfig =
+        serde_yaml::from_str("dir_mode: \"0755\"\nfile_mode: \"0644\"").unwrap();
+    config.apply_dir(dir.path()).unwrap();
+    config.apply_file(&file).unwrap();
+    assert_eq!(mode(dir.path()), 0o755);
+    assert_eq!(mode(&file), 0o644);
+    assert!(serde_yaml::from_str::<PermissionsConfig>("file_mode: \"0999\"").is_err());
+}
@@ -0,0 +1,203 @@
+use std::io::{Read, Seek};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::debug;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Delta {
+    #[serde(rename = "@oldepoch")]
+    pub oldepoch: u32,
+    #[serde(rename = "@oldversion")]
+    pub oldversion: String,
+    #[serde(rename = "@oldrelease")]
+    pub oldrelease: String,
+    pub filename: String,
+    pub sequence: String,
+    pub size: u64,
+    pub checksum: crate::repodata::repomd::Checksum,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename = "newpackage")]
+pub struct NewPackage {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@epoch")]
+    pub epoch: u32,
+    #[serde(rename = "@version")]
+    pub version: String,
+    #[serde(rename = "@release")]
+    pub release: String,
+    #[serde(rename = "@arch")]
+    pub arch: String,
+    #[serde(default)]
+    pub delta: Vec<Delta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename = "prestodelta")]
+pub struct Prestodelta {
+    #[serde(default)]
+    pub newpackage: Vec<NewPackage>,
+}
+
+impl Prestodelta {
+    pub fn new() -> Self {
+        Self {
+            newpackage: Vec::new(),
+        }
+    }
+
+    /// Add delta of single .drpm file, deltas to the same new package are grouped together
+    pub fn add_delta(&mut self, package: NewPackage) {
+        match self
+            .newpackage
+            .iter_mut()
+            .find(|v| v.same_package(&package))
+        {
+            Some(v) => v.delta.extend(package.delta),
+            None => self.newpackage.push(package),
+        }
+    }
+
+    pub fn sort(&mut self) {
+        for package in &mut self.newpackage {
+            package.delta.sort_by(|a, b| a.filename.cmp(&b.filename))
+        }
+        self.newpackage.sort_by(|a, b| {
+            (&a.name, &a.arch, a.epoch, &a.version, &a.release)
+                .cmp(&(&b.name, &b.arch, b.epoch, &b.version, &b.release))
+        })
+    }
+}
+
+/// Split "name-[epoch:]version-release" into (epoch, version, release)
+fn parse_nevr(nevr: &str) -> Result<(u32, String, String)> {
+    let mut parts = nevr.rsplitn(3, '-');
+    let (release, evr, _name) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(release), Some(evr), Some(name)) => (release, evr, name),
+        _ => bail!("Invalid NEVR {:?}", nevr),
+    };
+    let (epoch, version) = match evr.split_once(':') {
+        Some((epoch, version)) => (
+            epoch
+                .parse()
+                .map_err(|err| anyhow!("Invalid epoch in NEVR {:?}: {}", nevr, err))?,
+            version,
+        ),
+        None => (0, evr),
+    };
+    Ok((epoch, version.to_owned(), release.to_owned()))
+}
+
+fn read_block<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    let mut r = vec![0; len];
+    reader.read_exact(&mut r)?;
+    Ok(r)
+}
+
+/// Old package NEVR and sequence as written in delta header
+fn read_delta_header(payload: &[u8]) -> Result<(String, String)> {
+    let mut reader = crate::decompress::decompress(payload)?;
+
+    let mut magic = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|err| anyhow!("Cannot read delta magic: {}", err))?;
+    if !matches!(&magic, b"DLT1" | b"DLT2" | b"DLT3") {
+        bail!(
+            "Unsupported delta format {:?}",
+            String::from_utf8_lossy(&magic)
+        )
+    }
+
+    let nevr = read_block(&mut reader).map_err(|err| anyhow!("Cannot read old NEVR: {}", err))?;
+    let nevr = String::from_utf8_lossy(&nevr)
+        .trim_end_matches('\0')
+        .to_owned();
+
+    let sequence =
+        read_block(&mut reader).map_err(|err| anyhow!("Cannot read delta sequence: {}", err))?;
+    if sequence.len() < 16 {
+        bail!("Delta sequence is too short")
+    }
+    let sequence: String = sequence.iter().map(|v| format!("{:02x}", v)).collect();
+
+    Ok((nevr, sequence))
+}
+
+impl NewPackage {
+    /// Read delta RPM. Header of delta is the header of new package, payload starts with delta
+    /// header containing old package NEVR. Deltas without RPM header are not supported
+    pub fn of_drpm_file(path: &std::path::Path, relative_path: &std::path::Path) -> Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if &magic == b"drpm" {
+            bail!("Deltas of installed packages (rpm-only deltas) are not supported")
+        }
+        file.seek(std::io::SeekFrom::Start(0))?;
+
+        let mut buf_reader = std::io::BufReader::new(&file);
+        let pkg = rpm::RPMPackage::parse(&mut buf_reader)
+            .map_err(|err| anyhow!("Cannot parse RPM header: {}", err))?;
+        let header = &pkg.metadata.header;
+
+        let (old_nevr, sequence) = read_delta_header(&pkg.content)?;
+        debug!("Delta from {} with sequence {}", old_nevr, sequence);
+        let (oldepoch, oldversion, oldrelease) = parse_nevr(&old_nevr)?;
+
+        let version = crate::repodata::primary::PackageVersion::of_header(header)?;
+
+        let delta = Delta {
+            oldepoch,
+            oldversion,
+            oldrelease,
+            filename: relative_path.to_string_lossy().to_string(),
+            sequence: format!("{}-{}", old_nevr, sequence),
+            size: crate::platform::file_size(&path.metadata()?),
+            checksum: crate::repodata::repomd::Checksum::new(crate::digest::path_sha128(path)?),
+        };
+
+        let r = Self {
+            name: header
+                .get_name()
+                .map_err(|err| anyhow!("Cannot extract package name: {}", err))?
+                .to_owned(),
+            epoch: version.epoch.unwrap_or_default(),
+            version: version.ver,
+            release: version.rel,
+            arch: header
+                .get_arch()
+                .map_err(|err| anyhow!("Cannot extract package arch: {}", err))?
+                .to_owned(),
+            delta: vec![delta],
+        };
+        Ok(r)
+    }
+
+    fn same_package(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.epoch == other.epoch
+            && self.version == other.version
+            && self.release == other.release
+            && self.arch == other.arch
+    }
+}
+
+#[test]
+fn test_parse_nevr() {
+    assert_eq!(
+        parse_nevr("foo-bar-1.0-1.el8").unwrap(),
+        (0, "1.0".to_owned(), "1.el8".to_owned())
+    );
+    assert_eq!(
+        parse_nevr("foo-2:1.0-1").unwrap(),
+        (2, "1.0".to_owned(), "1".to_owned())
+    );
+    assert!(parse_nevr("foo").is_err());
+}
@@ -1,8 +1,6 @@
-use std::os::linux::fs::MetadataExt;
-
 use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
-use slog_scope::info;
+use slog_scope::{info, warn};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Tagged<T> {
@@ -35,22 +33,38 @@ fn default() -> Self {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(rename = "version")]
 pub struct PackageVersion {
-    #[serde(rename = "@epoch")]
-    pub epoch: i32,
+    #[serde(rename = "@epoch", default, serialize_with = "serialize_epoch")]
+    pub epoch: Option<u32>,
     #[serde(rename = "@ver")]
     pub ver: String,
     #[serde(rename = "@rel")]
     pub rel: String,
 }
 
+/// Missing epoch is written as "0", the same way createrepo does
+fn serialize_epoch<S>(epoch: &Option<u32>, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_u32(epoch.unwrap_or_default())
+}
+
 impl PackageVersion {
-    pub fn of_header(
-        header: &rpm::Header<rpm::IndexTag>,
-    ) -> std::result::Result<Self, rpm::RPMError> {
+    pub fn of_header(header: &rpm::Header<rpm::IndexTag>) -> Result<Self> {
+        let epoch = match header.get_epoch() {
+            Ok(v) => Some(u32::try_from(v).map_err(|_| anyhow!("Invalid epoch value {}", v))?),
+            Err(_) => None,
+        };
         let r = Self {
-            epoch: header.get_epoch().unwrap_or_default(),
-            ver: header.get_version()?.to_owned(),
-            rel: header.get_release()?.to_owned(),
+            epoch,
+            ver: header
+                .get_version()
+                .map_err(|err| anyhow!("{}", err.to_string()))?
+                .to_owned(),
+            rel: header
+                .get_release()
+                .map_err(|err| anyhow!("{}", err.to_string()))?
+                .to_owned(),
         };
         Ok(r)
     }
@@ -92,7 +106,7 @@ pub struct RpmEntry {
     #[serde(rename = "@flags", skip_serializing_if = "Option::is_none")]
     pub flags: Option<String>,
     #[serde(rename = "@epoch", skip_serializing_if = "Option::is_none")]
-    pub epoch: Option<String>,
+    pub epoch: Option<u32>,
     #[serde(rename = "@ver", skip_serializing_if = "Option::is_none")]
     pub ver: Option<String>,
     #[serde(rename = "@rel", skip_serializing_if = "Option::is_none")]
@@ -139,7 +153,7 @@ fn nonempty_or_none(v: Option<&str>) -> Option<String> {
 
     pub fn of_rpmentry(v: &rpm::RpmEntry) -> Result<Self> {
         lazy_static::lazy_static! {
-            static ref VERSION_RE: regex::Regex = regex::Regex::new("^(:?(\\d+):)?(.+?)(:?-(.+))?$").unwrap();
+            static ref VERSION_RE: regex::Regex = regex::Regex::new("^(?:(\\d+):)?(.+?)(?:-(.+))?$").unwrap();
         }
 
         let (epoch, ver, rel) = if v.version.is_empty() {
@@ -149,10 +163,18 @@ pub fn of_rpmentry(v: &rpm::RpmEntry) -> Result<Self> {
                 Some(v) => v,
                 None => bail!("Cannot parse version {:?}", v.version),
             };
+            // Versioned dependency without explicit epoch has epoch 0
+            let epoch = match version_caps.get(1) {
+                Some(epoch) => epoch
+                    .as_str()
+                    .parse()
+                    .map_err(|err| anyhow!("Invalid epoch in version {:?}: {}", v.version, err))?,
+                None => 0,
+            };
             (
+                Some(epoch),
                 Self::nonempty_or_none(version_caps.get(2).map(|v| v.as_str())),
                 Self::nonempty_or_none(version_caps.get(3).map(|v| v.as_str())),
-                Self::nonempty_or_none(version_caps.get(5).map(|v| v.as_str())),
             )
         };
 
@@ -224,6 +246,69 @@ pub struct PackageFormat {
 pub struct PackageLocation {
     #[serde(rename = "@href")]
     pub href: String,
+    /// xml:base, set by pulp, Nexus and similar tools. Never written, hrefs of rpm-tool are
+    /// relative to repository root
+    #[serde(default, rename = "@base", skip_serializing)]
+    pub base: Option<String>,
+}
+
+/// Decode %XX sequences of URL path
+pub fn percent_decode(str: &str) -> String {
+    let bytes = str.as_bytes();
+    let mut r = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 3 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(v) = u8::from_str_radix(hex, 16) {
+                r.push(v);
+                i += 3;
+                continue;
+            }
+        }
+        r.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&r).to_string()
+}
+
+impl PackageLocation {
+    pub fn new(href: String) -> Self {
+        Self { href, base: None }
+    }
+
+    /// Location is not a path relative to repository root: it has xml:base or absolute href
+    pub fn is_foreign(&self) -> bool {
+        self.base.is_some() || self.href.contains("://") || self.href.starts_with('/')
+    }
+
+    /// Possible paths relative to repository root for foreign location, longest first. Position
+    /// of repository root in URL is unknown, caller checks which of them exists
+    pub fn relative_candidates(&self) -> Vec<String> {
+        if !self.is_foreign() {
+            return vec![self.href.clone()];
+        }
+        let full = match &self.base {
+            Some(base) if !self.href.contains("://") && !self.href.starts_with('/') => {
+                format!("{}/{}", base.trim_end_matches('/'), self.href)
+            }
+            _ => self.href.clone(),
+        };
+        let path = match full.split_once("://") {
+            // Host is not a part of path
+            Some((_, rest)) => rest.split_once('/').map(|v| v.1).unwrap_or_default(),
+            None => full.as_str(),
+        };
+        let path = path.split(&['?', '#'][..]).next().unwrap_or_default();
+        let components: Vec<_> = path
+            .split('/')
+            .filter(|v| !v.is_empty() && *v != ".")
+            .map(percent_decode)
+            .collect();
+        (0..components.len())
+            .map(|n| components[n..].join("/"))
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -247,7 +332,106 @@ pub struct Package {
     pub format: PackageFormat,
 }
 
+/// In lenient mode unreadable header value is replaced by default, error is added to `warnings`
+fn lenient<T: Default>(
+    strict: bool,
+    what: &str,
+    value: Result<T>,
+    warnings: &mut Vec<String>,
+) -> Result<T> {
+    match value {
+        Ok(v) => Ok(v),
+        Err(err) if strict => Err(anyhow!("{}: {}", what, err)),
+        Err(err) => {
+            warnings.push(format!("{}: {}", what, err));
+            Ok(T::default())
+        }
+    }
+}
+
+/// In lenient mode unreadable entries are skipped, errors are added to `warnings`
+fn lenient_list<T, I>(strict: bool, entries: I, warnings: &mut Vec<String>) -> Result<Vec<T>>
+where
+    I: Iterator<Item = Result<T>>,
+{
+    if strict {
+        return entries.collect();
+    }
+    Ok(entries
+        .filter_map(|v| match v {
+            Ok(v) => Some(v),
+            Err(err) => {
+                warnings.push(err.to_string());
+                None
+            }
+        })
+        .collect())
+}
+
 impl Package {
+    /// name-[epoch:]version-release.arch
+    pub fn nevra(&self) -> String {
+        let evr = crate::evr::Evr::from(&self.version);
+        match &self.arch {
+            Some(arch) => format!("{}-{}.{}", self.name.value, evr, arch.value),
+            None => format!("{}-{}", self.name.value, evr),
+        }
+    }
+
+    /// debuginfo and debugsource packages, named so by rpmbuild
+    pub fn is_debug(&self) -> bool {
+        self.name.value.ends_with("-debuginfo") || self.name.value.ends_with("-debugsource")
+    }
+
+    /// Source packages have no source RPM of their own, like in rpm itself. Some tools write
+    /// "src" arch for them instead of the build architecture
+    pub fn is_source(&self) -> bool {
+        self.arch.as_ref().map(|v| v.value.as_str()) == Some("src")
+            || self
+                .format
+                .rpm_sourcerpm
+                .as_ref()
+                .map_or(true, |v| v.is_empty())
+    }
+
+    /// File name as produced by rpmbuild, e.g. bash-5.1-1.x86_64.rpm or bash-5.1-1.src.rpm. Epoch
+    /// is not included
+    pub fn canonical_file_name(&self) -> String {
+        let arch = match &self.arch {
+            _ if self.is_source() => Some("src"),
+            Some(arch) => Some(arch.value.as_str()),
+            None => None,
+        };
+        match arch {
+            Some(arch) => format!(
+                "{}-{}-{}.{}.rpm",
+                self.name.value, self.version.ver, self.version.rel, arch
+            ),
+            None => format!(
+                "{}-{}-{}.rpm",
+                self.name.value, self.version.ver, self.version.rel
+            ),
+        }
+    }
+
+    /// Pick translation of i18n string: requested locale, its language without territory and
+    /// encoding, then "C". The first translation is used if nothing matches
+    fn localized(strings: &[String], i18n_table: &[String], locale: Option<&str>) -> String {
+        let position = |wanted: &str| {
+            i18n_table
+                .iter()
+                .position(|v| v == wanted)
+                .filter(|n| *n < strings.len())
+        };
+        let language = locale.map(|v| v.split(|c| c == '_' || c == '.' || c == '@').next());
+        let n = locale
+            .and_then(position)
+            .or_else(|| language.flatten().and_then(position))
+            .or_else(|| position("C"))
+            .unwrap_or(0);
+        strings.get(n).cloned().unwrap_or_default()
+    }
+
     fn useful_file(entry: &rpm::FileEntry, regex: &regex::Regex) -> bool {
         regex.is_match(entry.path.to_string_lossy().as_ref())
     }
@@ -258,24 +442,41 @@ pub fn of_rpm_package(
         relative_path: &std::path::Path,
         file_sha: &str,
         useful_files: &regex::Regex,
+        locale: Option<&str>,
+        strict: bool,
     ) -> Result<Self> {
         let header = &pkg.metadata.header;
+        let i18n_table = header.get_i18n_table().unwrap_or_default();
+        let mut warnings = Vec::new();
 
         let metadata = path.metadata()?;
 
         let time = PackageTime {
-            file: metadata.st_mtime(),
-            build: header
-                .get_build_time()
-                .map_err(|err| anyhow!("{}", err.to_string()))?,
+            file: crate::platform::file_mtime(&metadata),
+            build: lenient(
+                strict,
+                "build time",
+                header
+                    .get_build_time()
+                    .map_err(|err| anyhow!("{}", err.to_string())),
+                &mut warnings,
+            )?,
         };
 
         let size = PackageSize {
-            archive: header.get_archive_size().ok(),
-            installed: header
-                .get_installed_size()
-                .map_err(|err| anyhow!("{}", err.to_string()))?,
-            package: metadata.st_size(),
+            archive: header
+                .get_archive_size()
+                .or_else(|_| pkg.metadata.signature.get_payload_size())
+                .ok(),
+            installed: lenient(
+                strict,
+                "installed size",
+                header
+                    .get_installed_size()
+                    .map_err(|err| anyhow!("{}", err.to_string())),
+                &mut warnings,
+            )?,
+            package: crate::platform::file_size(&metadata),
         };
 
         let rpm_provides = header
@@ -324,18 +525,25 @@ pub fn of_rpm_package(
             .collect::<Result<Vec<_>>>()?
             .into();
 
-        let files: Vec<_> = header
-            .get_file_entries()
-            .unwrap_or_default()
-            .into_iter()
-            .filter(|f| Self::useful_file(f, useful_files))
-            .map(FileEntry::of_rpm_file_entry)
-            .collect::<Result<_>>()?;
+        let files = lenient_list(
+            strict,
+            header
+                .get_file_entries()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|f| Self::useful_file(f, useful_files))
+                .map(FileEntry::of_rpm_file_entry),
+            &mut warnings,
+        )?;
 
         let format = PackageFormat {
             rpm_license: header.get_license().ok().map(|v| v.to_owned()),
             rpm_vendor: header.get_vendor().ok().map(|v| v.to_owned()),
-            rpm_group: header.get_group().unwrap_or_default().join("").into(),
+            rpm_group: Some(Self::localized(
+                header.get_group().unwrap_or_default(),
+                i18n_table,
+                locale,
+            )),
             rpm_buildhost: header.get_buildhost().ok().map(|v| v.to_owned()),
             rpm_sourcerpm: header.get_source_rpm().ok().map(|v| v.to_owned()),
             rpm_provides,
@@ -348,30 +556,39 @@ pub fn of_rpm_package(
         let r = Self {
             type_: "rpm".to_owned(),
             name: header.get_name().ok().into(),
-            location: PackageLocation {
-                href: relative_path.to_string_lossy().to_string(),
-            },
+            location: PackageLocation::new(relative_path.to_string_lossy().to_string()),
             arch: header.get_arch().map(|v| v.to_owned().into()).ok(),
-            description: Some(
-                header
-                    .get_description()
-                    .map_err(|err| anyhow!("{}", err.to_string()))?
-                    .join(""),
-            )
+            description: Some(Self::localized(
+                &lenient(
+                    strict,
+                    "description",
+                    header
+                        .get_description()
+                        .map_err(|err| anyhow!("{}", err.to_string())),
+                    &mut warnings,
+                )?,
+                i18n_table,
+                locale,
+            ))
             .into(),
-            version: PackageVersion::of_header(header)
-                .map_err(|err| anyhow!("{}", err.to_string()))?,
+            version: PackageVersion::of_header(header)?,
             checksum: PackageChecksum {
                 type_: "sha".to_owned(),
                 pkgid: "YES".to_owned(),
                 value: file_sha.to_owned(),
             },
-            summary: Some(
-                header
-                    .get_summary()
-                    .map_err(|err| anyhow!("{}", err.to_string()))?
-                    .join(""),
-            )
+            summary: Some(Self::localized(
+                &lenient(
+                    strict,
+                    "summary",
+                    header
+                        .get_summary()
+                        .map_err(|err| anyhow!("{}", err.to_string())),
+                    &mut warnings,
+                )?,
+                i18n_table,
+                locale,
+            ))
             .into(),
             packager: header.get_packager().unwrap_or_default().join("").into(),
             url: header.get_url().ok().map(|v| v.to_owned()),
@@ -379,6 +596,15 @@ pub fn of_rpm_package(
             size,
             format,
         };
+        if !warnings.is_empty() {
+            warn!(
+                "Package {} at {:?} is indexed with default values: {}",
+                r.nevra(),
+                r.location.href,
+                warnings.join("; ")
+            );
+            crate::summary::add_defaulted(&r.location.href, &r.nevra(), warnings);
+        }
         Ok(r)
     }
 }
@@ -411,6 +637,11 @@ pub fn add_package(&mut self, package: Package) {
         self.package.push(package)
     }
 
+    pub fn sort(&mut self) {
+        self.package
+            .sort_by(|a, b| a.location.href.cmp(&b.location.href))
+    }
+
     pub fn drain_filter<F>(&mut self, pred: F) -> Vec<Package>
     where
         F: Fn(&Package) -> bool,
@@ -431,16 +662,182 @@ pub fn drain_filter<F>(&mut self, pred: F) -> Vec<Package>
         drained
     }
 
+    /// Packages older than `keep` newest versions of package with the same name and architecture
+    pub fn outdated(&self, keep: usize) -> Vec<&Package> {
+        let mut by_name: std::collections::BTreeMap<_, Vec<&Package>> =
+            std::collections::BTreeMap::new();
+        for package in &self.package {
+            by_name
+                .entry((
+                    package.name.value.as_str(),
+                    package.arch.as_ref().map(|v| v.value.as_str()),
+                ))
+                .or_default()
+                .push(package);
+        }
+
+        let mut r = Vec::new();
+        for (_, mut packages) in by_name {
+            packages.sort_by(|a, b| {
+                crate::evr::Evr::from(&b.version).compare(&crate::evr::Evr::from(&a.version))
+            });
+            let mut versions: Vec<crate::evr::Evr> = Vec::new();
+            for package in packages {
+                let evr = crate::evr::Evr::from(&package.version);
+                if !versions
+                    .iter()
+                    .any(|v| v.compare(&evr) == std::cmp::Ordering::Equal)
+                {
+                    versions.push(evr);
+                }
+                if versions.len() > keep {
+                    r.push(package)
+                }
+            }
+        }
+        r
+    }
+
+    /// Packages which can't be parsed are skipped and reported, instead of failing whole document
+    pub fn read_lenient(
+        path: &std::path::Path,
+    ) -> Result<(Self, Vec<crate::repodata::records::RecordWarning>)> {
+        info!("Reading primary metadata from {:?}", path);
+        let (package, warnings) = crate::repodata::records::read_records(path, "package")?;
+        Ok((
+            Self {
+                packages: package.len(),
+                package,
+                ..Self::new()
+            },
+            warnings,
+        ))
+    }
+
+    /// Documents compressed by gzip, xz, zstd, bzip2 or uncompressed are accepted, format is
+    /// detected by magic bytes
     pub fn read(path: &std::path::Path) -> Result<Self> {
         info!("Reading primary metadata from {:?}", path);
         let file = std::fs::File::open(path)?;
-        let reader = flate2::read::GzDecoder::new(file);
+        let reader = crate::decompress::decompress(std::io::BufReader::new(file))?;
         let buf_reader = std::io::BufReader::new(reader);
         let r = quick_xml::de::from_reader(buf_reader)?;
         Ok(r)
     }
 }
 
+/// Package record of primary metadata for tests. By default x86_64 package with release "1",
+/// checksum "<name>-<ver>", location of canonical file name and MIT license
+#[cfg(test)]
+pub struct TestPackage {
+    name: String,
+    ver: String,
+    arch: String,
+    rel: String,
+    checksum: Option<String>,
+    size: u64,
+    href: Option<String>,
+    format: String,
+}
+
+#[cfg(test)]
+impl TestPackage {
+    pub fn new(name: &str, ver: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ver: ver.to_owned(),
+            arch: "x86_64".to_owned(),
+            rel: "1".to_owned(),
+            checksum: None,
+            size: 1,
+            href: None,
+            format: "<rpm:license>MIT</rpm:license>".to_owned(),
+        }
+    }
+
+    pub fn arch(self, arch: &str) -> Self {
+        Self {
+            arch: arch.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn rel(self, rel: &str) -> Self {
+        Self {
+            rel: rel.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn checksum(self, checksum: &str) -> Self {
+        Self {
+            checksum: Some(checksum.to_owned()),
+            ..self
+        }
+    }
+
+    pub fn size(self, size: u64) -> Self {
+        Self { size, ..self }
+    }
+
+    pub fn href(self, href: &str) -> Self {
+        Self {
+            href: Some(href.to_owned()),
+            ..self
+        }
+    }
+
+    /// Content of <format> element, replaces the license
+    pub fn format(self, format: &str) -> Self {
+        Self {
+            format: format.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn xml(&self) -> String {
+        let Self {
+            name,
+            ver,
+            arch,
+            rel,
+            size,
+            format,
+            ..
+        } = self;
+        let checksum = self
+            .checksum
+            .clone()
+            .unwrap_or_else(|| format!("{name}-{ver}"));
+        let href = self
+            .href
+            .clone()
+            .unwrap_or_else(|| format!("{name}-{ver}-{rel}.{arch}.rpm"));
+        format!(
+            r#"<package type="rpm"><name>{name}</name><arch>{arch}</arch><version epoch="0" ver="{ver}" rel="{rel}"/><checksum type="sha256" pkgid="YES">{checksum}</checksum><summary>{name}</summary><description>{name}</description><time file="1" build="1"/><size package="{size}" installed="1" archive="1"/><location href="{href}"/><format>{format}</format></package>"#
+        )
+    }
+
+    pub fn package(&self) -> Package {
+        quick_xml::de::from_str(&self.xml()).unwrap()
+    }
+}
+
+/// Primary metadata document of `packages` for tests
+#[cfg(test)]
+pub fn test_primary_xml(packages: &[TestPackage]) -> String {
+    format!(
+        r#"<metadata xmlns="http://linux.duke.edu/metadata/common" xmlns:rpm="http://linux.duke.edu/metadata/rpm" packages="{}">{}</metadata>"#,
+        packages.len(),
+        packages.iter().map(|v| v.xml()).collect::<String>()
+    )
+}
+
+#[cfg(test)]
+pub fn test_primary(packages: &[TestPackage]) -> Primary {
+    quick_xml::de::from_str(&test_primary_xml(packages)).unwrap()
+}
+
 #[test]
 fn test_de_rpm_entry() {
     let r: RpmEntry = quick_xml::de::from_str(
@@ -457,7 +854,7 @@ fn test_de_rpm_entry() {
         RpmEntry {
             name: "attr-debuginfo".to_owned(),
             flags: Some("EQ".to_owned()),
-            epoch: Some("0".to_owned()),
+            epoch: Some(0),
             ver: Some("2.4.46".to_owned()),
             rel: Some("13.vk1.el7".to_owned()),
             pre: None
@@ -482,6 +879,21 @@ fn test_de_empty_metadata() {
     )
 }
 
+#[test]
+fn test_read_xz() {
+    use std::io::Write;
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut writer = xz2::write::XzEncoder::new(file.reopen().unwrap(), 6);
+    writer
+        .write_all(br#"<metadata xmlns="http://linux.duke.edu/metadata/common" xmlns:rpm="http://linux.duke.edu/metadata/rpm" packages="0"></metadata>"#)
+        .unwrap();
+    let _ = writer.finish().unwrap();
+
+    let r = Primary::read(file.path()).unwrap();
+    assert_eq!(r.packages, 0);
+}
+
 #[test]
 fn test_de_metadata_one_package() {
     let r: Primary = quick_xml::de::from_str(
@@ -524,7 +936,7 @@ fn test_de_metadata_one_package() {
         RpmEntry {
             name: "v8_monolith".to_owned(),
             flags: Some("EQ".to_owned()),
-            epoch: Some("0".to_owned()),
+            epoch: Some(0),
             ver: Some("10.3.174.14".to_owned()),
             rel: Some("1".to_owned()),
             pre: None,
@@ -532,7 +944,7 @@ fn test_de_metadata_one_package() {
         RpmEntry {
             name: "v8_monolith(x86-64)".to_owned(),
             flags: Some("EQ".to_owned()),
-            epoch: Some("0".to_owned()),
+            epoch: Some(0),
             ver: Some("10.3.174.14".to_owned()),
             rel: Some("1".to_owned()),
             pre: None,
@@ -543,12 +955,12 @@ fn test_de_metadata_one_package() {
         Package {
             type_: "rpm".to_owned(),
             name: Tagged { value: "v8_monolith".to_owned() },
-            location: PackageLocation { href: "v8_monolith-10.3.174.14-1.x86_64.rpm".to_owned() },
+            location: PackageLocation { href: "v8_monolith-10.3.174.14-1.x86_64.rpm".to_owned(), base: None },
             arch: Some(Tagged { value: "x86_64".to_owned() }),
             description: Tagged { value: Some(r#"V8 is Google's open source high-performance JavaScript engine, written in C++ and used in Google Chrome, the open source browser from
 Google. It implements ECMAScript as specified in ECMA-262, 3rd edition, and runs on Windows XP or later, Mac OS X 10.5+, and Linux systems
 that use IA-32, ARM or MIPS processors. V8 can run standalone, or can be embedded into any C++ application."#.to_owned()) },
-            version: PackageVersion { epoch: 0, ver: "10.3.174.14".to_owned(), rel: "1".to_owned() },
+            version: PackageVersion { epoch: Some(0), ver: "10.3.174.14".to_owned(), rel: "1".to_owned() },
             checksum: PackageChecksum { type_: "sha".to_owned(), pkgid: "YES".to_owned(), value: "bff3977e704f06e9f8ff51ee365c4ab419e91225".to_owned() },
             summary: Tagged { value: Some("JavaScript Engine".to_owned()) },
             packager: Some("".to_owned()),
@@ -579,3 +991,187 @@ fn test_de_metadata_one_package() {
         }
     )
 }
+
+#[test]
+fn test_rpm_entry_epoch() {
+    let entry = |version: &str| {
+        RpmEntry::of_rpmentry(&rpm::RpmEntry {
+            name: "foo".to_owned(),
+            flags: 8,
+            version: version.to_owned(),
+        })
+        .unwrap()
+    };
+
+    let r = entry("2:1.0-3.el7");
+    assert_eq!(r.epoch, Some(2));
+    assert_eq!(r.ver, Some("1.0".to_owned()));
+    assert_eq!(r.rel, Some("3.el7".to_owned()));
+
+    let r = entry("1.0");
+    assert_eq!(r.epoch, Some(0));
+    assert_eq!(r.ver, Some("1.0".to_owned()));
+    assert_eq!(r.rel, None);
+
+    let r = entry("");
+    assert_eq!(r.epoch, None);
+    assert_eq!(r.ver, None);
+}
+
+#[test]
+fn test_se_missing_epoch() {
+    let version = PackageVersion {
+        epoch: None,
+        ver: "1.0".to_owned(),
+        rel: "1".to_owned(),
+    };
+    assert_eq!(
+        quick_xml::se::to_string(&version).unwrap(),
+        r#"<version epoch="0" ver="1.0" rel="1"/>"#
+    );
+}
+
+#[test]
+fn test_de_large_sizes() {
+    let r: PackageSize = quick_xml::de::from_str(
+        r#"<size package="5368709120" installed="10737418240" archive="10737418240"/>"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        r,
+        PackageSize {
+            package: 5 << 30,
+            installed: 10 << 30,
+            archive: Some(10 << 30),
+        }
+    )
+}
+
+#[test]
+fn test_localized() {
+    let strings = vec![
+        "Shell".to_owned(),
+        "Оболочка".to_owned(),
+        "Schale".to_owned(),
+    ];
+    let table = vec!["C".to_owned(), "ru_RU".to_owned(), "de".to_owned()];
+    assert_eq!(Package::localized(&strings, &table, None), "Shell");
+    assert_eq!(
+        Package::localized(&strings, &table, Some("ru_RU")),
+        "Оболочка"
+    );
+    assert_eq!(
+        Package::localized(&strings, &table, Some("de_DE.UTF-8")),
+        "Schale"
+    );
+    assert_eq!(Package::localized(&strings, &table, Some("fr")), "Shell");
+    assert_eq!(Package::localized(&strings[..1], &[], Some("de")), "Shell");
+}
+
+#[test]
+fn test_relative_candidates() {
+    let location: PackageLocation = quick_xml::de::from_str(
+        r#"<location xml:base="https://mirror.example.com/repo/el8/" href="Packages/g/gcc-c%2B%2B-8.5-1.x86_64.rpm"/>"#,
+    )
+    .unwrap();
+    assert!(location.is_foreign());
+    assert_eq!(
+        location.relative_candidates(),
+        vec![
+            "repo/el8/Packages/g/gcc-c++-8.5-1.x86_64.rpm",
+            "el8/Packages/g/gcc-c++-8.5-1.x86_64.rpm",
+            "Packages/g/gcc-c++-8.5-1.x86_64.rpm",
+            "g/gcc-c++-8.5-1.x86_64.rpm",
+            "gcc-c++-8.5-1.x86_64.rpm",
+        ]
+    );
+
+    let location = PackageLocation::new("Packages/foo-1.0-1.noarch.rpm".to_owned());
+    assert!(!location.is_foreign());
+    assert_eq!(
+        location.relative_candidates(),
+        vec!["Packages/foo-1.0-1.noarch.rpm"]
+    );
+}
+
+#[test]
+fn test_percent_decode() {
+    assert_eq!(percent_decode("gcc-c%2B%2B"), "gcc-c++");
+    assert_eq!(percent_decode("a%20"), "a ");
+    assert_eq!(percent_decode("100%"), "100%");
+    assert_eq!(percent_decode("%zz%4"), "%zz%4");
+}
+
+#[test]
+fn test_outdated() {
+    let packages: Vec<_> = [
+        ("foo", "x86_64", "1.0", "1"),
+        ("foo", "x86_64", "1.10", "1"),
+        ("foo", "x86_64", "1.9", "1"),
+        // The same version at another location is outdated together with it
+        ("foo", "x86_64", "1.9", "1"),
+        ("foo", "i686", "1.0", "1"),
+        ("bar", "noarch", "2.0", "1~rc1"),
+        ("bar", "noarch", "2.0", "1"),
+    ]
+    .iter()
+    .enumerate()
+    .map(|(n, (name, arch, ver, rel))| {
+        TestPackage::new(name, ver)
+            .arch(arch)
+            .rel(rel)
+            .checksum(&n.to_string())
+            .href(&format!("{n}.rpm"))
+    })
+    .collect();
+    let primary = test_primary(&packages);
+
+    let outdated = |keep: usize| -> Vec<&str> {
+        let mut r: Vec<_> = primary
+            .outdated(keep)
+            .iter()
+            .map(|v| v.location.href.as_str())
+            .collect();
+        r.sort();
+        r
+    };
+    assert_eq!(outdated(1), ["0.rpm", "2.rpm", "3.rpm", "5.rpm"]);
+    assert_eq!(outdated(2), ["0.rpm"]);
+    assert!(outdated(3).is_empty());
+}
+
+#[test]
+fn test_is_debug() {
+    let package = |name: &str| TestPackage::new(name, "1.0").package();
+    assert!(package("foo-debuginfo").is_debug());
+    assert!(package("foo-debugsource").is_debug());
+    assert!(!package("foo").is_debug());
+    assert!(!package("foo-debuginfo-tools").is_debug());
+}
+
+#[test]
+fn test_canonical_file_name() {
+    let package = |arch: &str, sourcerpm: &str| {
+        TestPackage::new("bash", "5.1")
+            .arch(arch)
+            .href("bash.rpm")
+            .format(&format!("<rpm:license>MIT</rpm:license>{sourcerpm}"))
+            .package()
+    };
+    let binary = package(
+        "x86_64",
+        "<rpm:sourcerpm>bash-5.1-1.src.rpm</rpm:sourcerpm>",
+    );
+    assert!(!binary.is_source());
+    assert_eq!(binary.canonical_file_name(), "bash-5.1-1.x86_64.rpm");
+    // Source package built on x86_64 must not take place of binary package
+    assert_eq!(
+        package("x86_64", "").canonical_file_name(),
+        "bash-5.1-1.src.rpm"
+    );
+    assert_eq!(
+        package("src", "<rpm:sourcerpm/>").canonical_file_name(),
+        "bash-5.1-1.src.rpm"
+    );
+}
@@ -0,0 +1,93 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use slog::slog_o;
+use slog_scope::{error, info};
+
+/// Repository maintained by "repository generate --all"
+#[derive(Serialize, Deserialize)]
+pub struct RepositoryProfile {
+    pub name: String,
+    pub path: std::path::PathBuf,
+    #[serde(default)]
+    pub fileslists: bool,
+    #[serde(default)]
+    pub split_debug: bool,
+    #[serde(default)]
+    pub latest_only: Option<usize>,
+    #[serde(default)]
+    pub arch: Vec<String>,
+}
+
+impl RepositoryProfile {
+    /// Settings of profile are added to options given on command line
+    pub fn apply(&self, options: &mut crate::repodata::RepodataOptions) {
+        options.path = self.path.clone();
+        options.generate_fileslists |= self.fileslists;
+        options.split_debug |= self.split_debug;
+        if self.latest_only.is_some() {
+            options.latest_only = self.latest_only;
+        }
+        if !self.arch.is_empty() {
+            options.package_filter.arch = self.arch.clone();
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RunSummary {
+    pub name: String,
+    pub path: std::path::PathBuf,
+    pub seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Generate metadata of every repository, `jobs` repositories at a time. Failure of one repository
+/// doesn't stop others
+pub fn generate_all<F>(
+    config: &crate::repodata::RepodataConfig,
+    profiles: &[RepositoryProfile],
+    jobs: usize,
+    options: F,
+) -> anyhow::Result<Vec<RunSummary>>
+where
+    F: Fn(&RepositoryProfile) -> crate::repodata::RepodataOptions + Sync,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.max(1))
+        .build()?;
+    Ok(pool.install(|| {
+        profiles
+            .par_iter()
+            .map(|profile| {
+                slog_scope::scope(
+                    &slog_scope::logger().new(slog_o!("repository" => profile.name.clone())),
+                    || {
+                        info!(
+                            "Generating repository {:?} in {:?}",
+                            profile.name, profile.path
+                        );
+                        let started = std::time::Instant::now();
+                        let repodata = crate::repodata::Repodata {
+                            config,
+                            options: options(profile),
+                        };
+                        let error = match repodata.generate() {
+                            Ok(()) => None,
+                            Err(err) => {
+                                error!("Repository {:?} failed: {:#}", profile.name, err);
+                                Some(format!("{:#}", err))
+                            }
+                        };
+                        RunSummary {
+                            name: profile.name.clone(),
+                            path: profile.path.clone(),
+                            seconds: started.elapsed().as_secs(),
+                            error,
+                        }
+                    },
+                )
+            })
+            .collect()
+    }))
+}
@@ -0,0 +1,758 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+
+/// Relation of dependency version, the same bits as rpm uses
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Op {
+    const LESS: u8 = 2;
+    const GREATER: u8 = 4;
+    const EQUAL: u8 = 8;
+
+    fn bits(&self) -> u8 {
+        match self {
+            Self::Lt => Self::LESS,
+            Self::Le => Self::LESS | Self::EQUAL,
+            Self::Eq => Self::EQUAL,
+            Self::Ge => Self::GREATER | Self::EQUAL,
+            Self::Gt => Self::GREATER,
+        }
+    }
+
+    /// Parse flags attribute of repodata entry
+    pub fn of_flags(flags: &str) -> Option<Self> {
+        let r = match flags {
+            "LT" => Self::Lt,
+            "LE" => Self::Le,
+            "EQ" => Self::Eq,
+            "GE" => Self::Ge,
+            "GT" => Self::Gt,
+            _ => return None,
+        };
+        Some(r)
+    }
+}
+
+impl std::str::FromStr for Op {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let r = match s {
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            "=" | "==" => Self::Eq,
+            ">=" => Self::Ge,
+            ">" => Self::Gt,
+            _ => bail!("Unknown version relation {:?}", s),
+        };
+        Ok(r)
+    }
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Eq => "=",
+            Self::Ge => ">=",
+            Self::Gt => ">",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Name with optional version constraint, e.g. "libfoo.so.1()(64bit)" or "foo >= 1.2"
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Capability {
+    pub name: String,
+    pub constraint: Option<(Op, crate::evr::Evr)>,
+}
+
+impl Capability {
+    pub fn of_rpm_entry(entry: &crate::repodata::primary::RpmEntry) -> Self {
+        let constraint = match (entry.flags.as_deref().and_then(Op::of_flags), &entry.ver) {
+            (Some(op), Some(ver)) => Some((
+                op,
+                crate::evr::Evr {
+                    epoch: entry.epoch.unwrap_or_default(),
+                    version: ver.clone(),
+                    release: entry.rel.clone(),
+                },
+            )),
+            _ => None,
+        };
+        Self {
+            name: entry.name.clone(),
+            constraint,
+        }
+    }
+
+    /// Check if version ranges of two capabilities with the same name overlap. Capability without
+    /// version matches any version
+    pub fn overlaps(&self, other: &Self) -> bool {
+        if self.name != other.name {
+            return false;
+        }
+        let ((op_a, evr_a), (op_b, evr_b)) = match (&self.constraint, &other.constraint) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return true,
+        };
+        let (a, b) = (op_a.bits(), op_b.bits());
+        match evr_a.compare(evr_b) {
+            std::cmp::Ordering::Less => a & Op::GREATER != 0 || b & Op::LESS != 0,
+            std::cmp::Ordering::Greater => a & Op::LESS != 0 || b & Op::GREATER != 0,
+            std::cmp::Ordering::Equal => a & b != 0,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.name.starts_with('/')
+    }
+}
+
+impl std::str::FromStr for Capability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<_> = s.split_whitespace().collect();
+        match parts.as_slice() {
+            [name] => Ok(Self {
+                name: (*name).to_owned(),
+                constraint: None,
+            }),
+            [name, op, evr] => Ok(Self {
+                name: (*name).to_owned(),
+                constraint: Some((op.parse()?, evr.parse()?)),
+            }),
+            _ => bail!("Invalid capability {:?}, expected \"name [op version]\"", s),
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.constraint {
+            Some((op, evr)) => write!(f, "{} {} {}", self.name, op, evr),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelationIssueKind {
+    /// Two packages conflict with each other
+    MutualConflict,
+    /// Obsoletes matches no package of repository
+    DanglingObsolete,
+    /// Package obsoletes its own version
+    SelfObsolete,
+}
+
+impl RelationIssueKind {
+    /// Obsoletes of packages which are already gone from repository are common, they are
+    /// reported, but don't fail the check
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Self::DanglingObsolete)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RelationIssue {
+    pub kind: RelationIssueKind,
+    pub package: String,
+    pub entry: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub related: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct RelationsReport {
+    pub issues: Vec<RelationIssue>,
+}
+
+impl RelationsReport {
+    pub fn errors(&self) -> usize {
+        self.issues.iter().filter(|v| v.kind.is_error()).count()
+    }
+}
+
+/// Package found by query with description of what matched
+#[derive(Serialize, Debug)]
+pub struct QueryMatch {
+    pub package: String,
+    pub matched: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileMatch {
+    pub package: String,
+    pub files: Vec<std::path::PathBuf>,
+}
+
+/// Everything provided by set of repositories, for dependency resolution
+struct Providers<'a> {
+    provides: HashMap<&'a str, Vec<Capability>>,
+    files: HashSet<&'a std::path::Path>,
+}
+
+impl<'a> Providers<'a> {
+    fn new() -> Self {
+        Self {
+            provides: HashMap::new(),
+            files: HashSet::new(),
+        }
+    }
+
+    fn add_index(&mut self, index: &'a Index) {
+        for package in &index.primary.package {
+            for entry in &package.format.rpm_provides.list {
+                self.provides
+                    .entry(entry.name.as_str())
+                    .or_default()
+                    .push(Capability::of_rpm_entry(entry))
+            }
+            for file in &package.format.files {
+                self.files.insert(file.path.as_path());
+            }
+        }
+        if let Some(filelists) = &index.filelists {
+            for package in &filelists.package {
+                for file in &package.files {
+                    self.files.insert(file.path.as_path());
+                }
+            }
+        }
+    }
+
+    fn is_satisfied(&self, require: &Capability) -> bool {
+        if require.is_file() && self.files.contains(std::path::Path::new(&require.name)) {
+            return true;
+        }
+        match self.provides.get(require.name.as_str()) {
+            Some(list) => list.iter().any(|v| v.overlaps(require)),
+            None => false,
+        }
+    }
+}
+
+/// Metadata of existing repository loaded for queries
+pub struct Index {
+    pub primary: crate::repodata::primary::Primary,
+    pub filelists: Option<crate::repodata::filelists::Filelists>,
+}
+
+impl Index {
+    pub fn read(path: &std::path::Path) -> Result<Self> {
+        let repomd =
+            crate::repodata::repomd::Repomd::read(&path.join("repodata").join("repomd.xml"))?;
+        let location = |data_type: crate::repodata::repomd::DataType| {
+            repomd
+                .data
+                .iter()
+                .find(|elt| elt.type_ == data_type)
+                .map(|elt| path.join(&elt.location.href))
+        };
+
+        let primary = match location(crate::repodata::repomd::DataType::Primary) {
+            Some(v) => crate::repodata::primary::Primary::read(&v)
+                .map_err(|err| anyhow!("Cannot read {:?}: {}", v, err))?,
+            None => bail!("No 'primary' record in repomd.xml"),
+        };
+        let filelists = match location(crate::repodata::repomd::DataType::Filelists) {
+            Some(v) => Some(
+                crate::repodata::filelists::Filelists::read(&v)
+                    .map_err(|err| anyhow!("Cannot read {:?}: {}", v, err))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self { primary, filelists })
+    }
+
+    /// Keep only packages matching predicate
+    pub fn retain<F>(&mut self, pred: F)
+    where
+        F: Fn(&crate::repodata::primary::Package) -> bool,
+    {
+        let removed: HashSet<_> = self
+            .primary
+            .drain_filter(pred)
+            .into_iter()
+            .map(|package| package.checksum.value)
+            .collect();
+        if let Some(filelists) = &mut self.filelists {
+            let _ = filelists.drain_filter(|package| !removed.contains(&package.pkgid));
+        }
+    }
+
+    fn packages_by_pkgid(&self) -> HashMap<&str, &crate::repodata::primary::Package> {
+        self.primary
+            .package
+            .iter()
+            .map(|package| (package.checksum.value.as_str(), package))
+            .collect()
+    }
+
+    fn matched_files<'b>(
+        glob: &crate::glob::Glob,
+        files: &'b [crate::repodata::primary::FileEntry],
+    ) -> Vec<&'b std::path::Path> {
+        files
+            .iter()
+            .filter(|v| glob.is_match(&v.path))
+            .map(|v| v.path.as_path())
+            .collect()
+    }
+
+    /// Packages providing capability, with description of matched provide or file
+    pub fn whatprovides(
+        &self,
+        capability: &Capability,
+    ) -> Vec<(&crate::repodata::primary::Package, String)> {
+        let mut r = Vec::new();
+
+        for package in &self.primary.package {
+            for entry in &package.format.rpm_provides.list {
+                let provide = Capability::of_rpm_entry(entry);
+                if provide.overlaps(capability) {
+                    r.push((package, format!("provides {}", provide)))
+                }
+            }
+        }
+
+        if !capability.is_file() {
+            return r;
+        }
+
+        let path = std::path::Path::new(&capability.name);
+        match &self.filelists {
+            Some(filelists) => {
+                let packages = self.packages_by_pkgid();
+                for filelist in &filelists.package {
+                    if !filelist.files.iter().any(|v| v.path == path) {
+                        continue;
+                    }
+                    if let Some(package) = packages.get(filelist.pkgid.as_str()) {
+                        r.push((*package, format!("file {}", capability.name)))
+                    }
+                }
+            }
+            // Without filelists only files listed in primary are known
+            None => {
+                for package in &self.primary.package {
+                    if package.format.files.iter().any(|v| v.path == path) {
+                        r.push((package, format!("file {}", capability.name)))
+                    }
+                }
+            }
+        }
+
+        r
+    }
+
+    /// Packages owning files matched by glob, with matched paths
+    pub fn find_files(
+        &self,
+        glob: &crate::glob::Glob,
+    ) -> Vec<(&crate::repodata::primary::Package, Vec<&std::path::Path>)> {
+        let mut r = Vec::new();
+        match &self.filelists {
+            Some(filelists) => {
+                let packages = self.packages_by_pkgid();
+                for filelist in &filelists.package {
+                    let files = Self::matched_files(glob, &filelist.files);
+                    if files.is_empty() {
+                        continue;
+                    }
+                    if let Some(package) = packages.get(filelist.pkgid.as_str()) {
+                        r.push((*package, files))
+                    }
+                }
+            }
+            None => {
+                for package in &self.primary.package {
+                    let files = Self::matched_files(glob, &package.format.files);
+                    if !files.is_empty() {
+                        r.push((package, files))
+                    }
+                }
+            }
+        }
+
+        r
+    }
+
+    /// Requirements of packages which are not provided by this repository or any of `base`
+    /// repositories. rpmlib() requirements are provided by rpm itself and are not checked
+    pub fn unresolved_requires<'b>(
+        &'b self,
+        base: &'b [Index],
+    ) -> Vec<(&'b crate::repodata::primary::Package, Capability)> {
+        let mut providers = Providers::new();
+        providers.add_index(self);
+        for index in base {
+            providers.add_index(index);
+        }
+
+        let mut r = Vec::new();
+        for package in &self.primary.package {
+            for entry in &package.format.rpm_requires.list {
+                if entry.name.starts_with("rpmlib(") {
+                    continue;
+                }
+                let require = Capability::of_rpm_entry(entry);
+                if !providers.is_satisfied(&require) {
+                    r.push((package, require))
+                }
+            }
+        }
+        r
+    }
+
+    /// Find problems in conflicts and obsoletes relations between packages of repository
+    pub fn relations_report(&self) -> RelationsReport {
+        let packages = &self.primary.package;
+        let package_capability = |package: &crate::repodata::primary::Package| Capability {
+            name: package.name.value.clone(),
+            constraint: Some((Op::Eq, crate::evr::Evr::from(&package.version))),
+        };
+
+        let mut provides: HashMap<&str, Vec<(usize, Capability)>> = HashMap::new();
+        let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (idx, package) in packages.iter().enumerate() {
+            for entry in &package.format.rpm_provides.list {
+                provides
+                    .entry(entry.name.as_str())
+                    .or_default()
+                    .push((idx, Capability::of_rpm_entry(entry)))
+            }
+            by_name
+                .entry(package.name.value.as_str())
+                .or_default()
+                .push(idx)
+        }
+
+        let mut report = RelationsReport::default();
+
+        let mut conflicts = HashMap::new();
+        for (idx, package) in packages.iter().enumerate() {
+            for entry in &package.format.rpm_conflicts.list {
+                let conflict = Capability::of_rpm_entry(entry);
+                for (other, provide) in provides.get(entry.name.as_str()).into_iter().flatten() {
+                    if *other != idx && provide.overlaps(&conflict) {
+                        let _ = conflicts
+                            .entry((idx, *other))
+                            .or_insert_with(|| conflict.clone());
+                    }
+                }
+            }
+        }
+        for ((a, b), conflict) in &conflicts {
+            if a < b && conflicts.contains_key(&(*b, *a)) {
+                report.issues.push(RelationIssue {
+                    kind: RelationIssueKind::MutualConflict,
+                    package: packages[*a].nevra(),
+                    entry: format!("conflicts {}", conflict),
+                    related: Some(packages[*b].nevra()),
+                })
+            }
+        }
+
+        for (idx, package) in packages.iter().enumerate() {
+            for entry in &package.format.rpm_obsoletes.list {
+                let obsolete = Capability::of_rpm_entry(entry);
+                let matched: Vec<_> = by_name
+                    .get(entry.name.as_str())
+                    .into_iter()
+                    .flatten()
+                    .filter(|other| obsolete.overlaps(&package_capability(&packages[**other])))
+                    .collect();
+                let kind = if matched.contains(&&idx) {
+                    RelationIssueKind::SelfObsolete
+                } else if matched.is_empty() {
+                    RelationIssueKind::DanglingObsolete
+                } else {
+                    continue;
+                };
+                report.issues.push(RelationIssue {
+                    kind,
+                    package: package.nevra(),
+                    entry: format!("obsoletes {}", obsolete),
+                    related: None,
+                })
+            }
+        }
+
+        report
+            .issues
+            .sort_by(|a, b| (&a.package, &a.entry).cmp(&(&b.package, &b.entry)));
+        report
+    }
+
+    /// Capabilities provided by package, including files listed in primary metadata
+    fn package_provides(package: &crate::repodata::primary::Package) -> Vec<Capability> {
+        package
+            .format
+            .rpm_provides
+            .list
+            .iter()
+            .map(Capability::of_rpm_entry)
+            .chain(package.format.files.iter().map(|v| Capability {
+                name: v.path.to_string_lossy().to_string(),
+                constraint: None,
+            }))
+            .collect()
+    }
+
+    fn add_provides(provides: &mut HashMap<String, Vec<Capability>>, list: Vec<Capability>) {
+        for provide in list {
+            provides
+                .entry(provide.name.clone())
+                .or_default()
+                .push(provide)
+        }
+    }
+
+    /// Packages requiring capability, with matched requirement. If capability names packages
+    /// of repository, requirements of anything these packages provide are searched. In recursive
+    /// mode packages requiring found packages are searched too, until nothing new is found
+    pub fn whatrequires(
+        &self,
+        capability: &Capability,
+        recursive: bool,
+    ) -> Vec<(&crate::repodata::primary::Package, String)> {
+        let mut visited = HashSet::new();
+        let mut provides = HashMap::new();
+
+        for package in &self.primary.package {
+            if package.name.value == capability.name {
+                let version = crate::evr::Evr::from(&package.version);
+                if capability.overlaps(&Capability {
+                    name: package.name.value.clone(),
+                    constraint: Some((Op::Eq, version)),
+                }) {
+                    visited.insert(package.checksum.value.as_str());
+                    Self::add_provides(&mut provides, Self::package_provides(package));
+                }
+            }
+        }
+        if provides.is_empty() {
+            Self::add_provides(&mut provides, vec![capability.clone()]);
+        }
+
+        let mut r = Vec::new();
+        while !provides.is_empty() {
+            let mut next_provides = HashMap::new();
+            for package in &self.primary.package {
+                if visited.contains(package.checksum.value.as_str()) {
+                    continue;
+                }
+                let matched = package.format.rpm_requires.list.iter().find_map(|entry| {
+                    let require = Capability::of_rpm_entry(entry);
+                    provides
+                        .get(&require.name)
+                        .filter(|list| list.iter().any(|v| v.overlaps(&require)))
+                        .map(|_| require)
+                });
+                if let Some(require) = matched {
+                    visited.insert(package.checksum.value.as_str());
+                    r.push((package, format!("requires {}", require)));
+                    if recursive {
+                        Self::add_provides(&mut next_provides, Self::package_provides(package));
+                    }
+                }
+            }
+            provides = next_provides;
+        }
+
+        r
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    pub fn whatprovides(&self, capability: &str) -> Result<Vec<QueryMatch>> {
+        let capability: Capability = capability.parse()?;
+        let index = self.read_index()?;
+        let found = index.whatprovides(&capability);
+        if found.is_empty() {
+            bail!("No package provides {}", capability)
+        }
+        Ok(found
+            .into_iter()
+            .map(|(package, matched)| QueryMatch {
+                package: package.nevra(),
+                matched,
+            })
+            .collect())
+    }
+
+    pub fn whatrequires(&self, capability: &str, recursive: bool) -> Result<Vec<QueryMatch>> {
+        let capability: Capability = capability.parse()?;
+        let index = self.read_index()?;
+        Ok(index
+            .whatrequires(&capability, recursive)
+            .into_iter()
+            .map(|(package, matched)| QueryMatch {
+                package: package.nevra(),
+                matched,
+            })
+            .collect())
+    }
+
+    pub fn find_files(&self, glob: &crate::glob::Glob) -> Result<Vec<FileMatch>> {
+        let index = self.read_index()?;
+        Ok(index
+            .find_files(glob)
+            .into_iter()
+            .map(|(package, files)| FileMatch {
+                package: package.nevra(),
+                files: files.into_iter().map(|v| v.to_path_buf()).collect(),
+            })
+            .collect())
+    }
+
+    /// Requirements not provided by repository itself or any of base repositories
+    pub fn depcheck(&self, base_repositories: &[std::path::PathBuf]) -> Result<Vec<QueryMatch>> {
+        let index = self.read_index()?;
+        let base = base_repositories
+            .iter()
+            .map(|path| Index::read(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(index
+            .unresolved_requires(&base)
+            .into_iter()
+            .map(|(package, require)| QueryMatch {
+                package: package.nevra(),
+                matched: format!("nothing provides {}", require),
+            })
+            .collect())
+    }
+
+    pub fn relations_report(&self) -> Result<RelationsReport> {
+        let index = self.read_index()?;
+        Ok(index.relations_report())
+    }
+}
+
+#[test]
+fn test_capability_overlaps() {
+    let provide: Capability = "foo = 1.2-3".parse().unwrap();
+    assert!(provide.overlaps(&"foo".parse().unwrap()));
+    assert!(provide.overlaps(&"foo >= 1.0".parse().unwrap()));
+    assert!(provide.overlaps(&"foo = 1.2".parse().unwrap()));
+    assert!(!provide.overlaps(&"foo > 1.2".parse().unwrap()));
+    assert!(!provide.overlaps(&"foo < 1.0".parse().unwrap()));
+    assert!(provide.overlaps(&"foo < 1:1.0".parse().unwrap()));
+    assert!(!provide.overlaps(&"bar".parse().unwrap()));
+}
+
+#[cfg(test)]
+fn test_index(packages: &[(&str, &str, &str)]) -> Index {
+    // (name, version, format content)
+    let packages: Vec<_> = packages
+        .iter()
+        .map(|(name, ver, format)| {
+            crate::repodata::primary::TestPackage::new(name, ver).format(&format!(
+                r#"<rpm:provides><rpm:entry name="{name}" flags="EQ" epoch="0" ver="{ver}" rel="1"/></rpm:provides>{format}"#
+            ))
+        })
+        .collect();
+    Index {
+        primary: crate::repodata::primary::test_primary(&packages),
+        filelists: None,
+    }
+}
+
+#[test]
+fn test_queries() {
+    let index = test_index(&[
+        (
+            "bash",
+            "5.1",
+            "<file>/bin/bash</file><file>/usr/bin/bash</file>",
+        ),
+        (
+            "vim",
+            "9.0",
+            r#"<rpm:requires><rpm:entry name="/bin/bash"/><rpm:entry name="libgpm.so.2()(64bit)"/><rpm:entry name="rpmlib(CompressedFileNames)"/></rpm:requires>"#,
+        ),
+        (
+            "vim-plugin",
+            "1.0",
+            r#"<rpm:requires><rpm:entry name="vim" flags="GE" epoch="0" ver="8.0"/></rpm:requires>"#,
+        ),
+    ]);
+    let nevras = |list: Vec<(&crate::repodata::primary::Package, String)>| -> Vec<String> {
+        list.into_iter().map(|(v, _)| v.nevra()).collect()
+    };
+
+    assert_eq!(
+        nevras(index.whatprovides(&"/bin/bash".parse().unwrap())),
+        vec!["bash-5.1-1.x86_64"]
+    );
+    assert!(index
+        .whatprovides(&"bash > 5.1".parse().unwrap())
+        .is_empty());
+
+    let found = index.find_files(&"/usr/bin/*".parse().unwrap());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].1, vec![std::path::Path::new("/usr/bin/bash")]);
+
+    let unresolved = index.unresolved_requires(&[]);
+    assert_eq!(unresolved.len(), 1);
+    assert_eq!(unresolved[0].1.name, "libgpm.so.2()(64bit)");
+
+    assert_eq!(
+        nevras(index.whatrequires(&"bash".parse().unwrap(), false)),
+        vec!["vim-9.0-1.x86_64"]
+    );
+    assert_eq!(
+        nevras(index.whatrequires(&"bash".parse().unwrap(), true)),
+        vec!["vim-9.0-1.x86_64", "vim-plugin-1.0-1.x86_64"]
+    );
+}
+
+#[test]
+fn test_relations_report() {
+    let index = test_index(&[
+        (
+            "foo",
+            "1.0",
+            r#"<rpm:conflicts><rpm:entry name="bar"/></rpm:conflicts><rpm:obsoletes><rpm:entry name="foo" flags="LE" epoch="0" ver="1.0"/></rpm:obsoletes>"#,
+        ),
+        (
+            "bar",
+            "2.0",
+            r#"<rpm:conflicts><rpm:entry name="foo"/></rpm:conflicts><rpm:obsoletes><rpm:entry name="gone"/></rpm:obsoletes>"#,
+        ),
+    ]);
+    let report = index.relations_report();
+    let kinds: Vec<_> = report
+        .issues
+        .iter()
+        .map(|v| (v.package.as_str(), v.kind))
+        .collect();
+    assert!(kinds.contains(&("bar-2.0-1.x86_64", RelationIssueKind::DanglingObsolete)));
+    assert!(kinds.contains(&("foo-1.0-1.x86_64", RelationIssueKind::SelfObsolete)));
+    assert_eq!(
+        report
+            .issues
+            .iter()
+            .filter(|v| v.kind == RelationIssueKind::MutualConflict)
+            .count(),
+        1
+    );
+    assert_eq!(report.errors(), 2);
+}
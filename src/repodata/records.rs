@@ -0,0 +1,162 @@
+//! Record-level parsing of cached metadata. Record which can't be parsed is skipped and reported,
+//! instead of discarding whole document on first error
+
+use anyhow::Result;
+use quick_xml::events::Event;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Record of cached metadata which is not used
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordWarning {
+    pub document: String,
+    /// Location or pkgid of record if it could be found, otherwise number of record
+    pub record: String,
+    pub error: String,
+}
+
+impl std::fmt::Display for RecordWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.document, self.record, self.error)
+    }
+}
+
+/// Offset of '<' of start tag, reader position is right after its '>'
+pub fn start_offset(position: usize, tag: &quick_xml::events::BytesStart) -> usize {
+    position - tag.len() - 2
+}
+
+fn describe(raw: &str, n: usize) -> String {
+    for attribute in ["href=\"", "pkgid=\""] {
+        if let Some(pos) = raw.find(attribute) {
+            let value = &raw[pos + attribute.len()..];
+            if let Some(end) = value.find('"') {
+                return value[..end].to_owned();
+            }
+        }
+    }
+    format!("#{}", n)
+}
+
+/// Deserialize every `element` of document separately. Document is read as stream, only one
+/// record is kept in memory as text
+pub fn parse_records<T: DeserializeOwned, R: std::io::BufRead>(
+    input: R,
+    element: &str,
+    document: &str,
+) -> (Vec<T>, Vec<RecordWarning>) {
+    let mut records = Vec::new();
+    let mut warnings = Vec::new();
+    let mut warn = |record: String, error: String| {
+        warnings.push(RecordWarning {
+            document: document.to_owned(),
+            record,
+            error,
+        })
+    };
+
+    let mut reader = quick_xml::Reader::from_reader(input);
+    let mut buf = Vec::new();
+    let mut record_buf = Vec::new();
+    let mut n = 0;
+    loop {
+        buf.clear();
+        let start = match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) if e.local_name().as_ref() == element.as_bytes() => e,
+            Ok(Event::Eof) => break,
+            Ok(_) => continue,
+            Err(err) => {
+                warn(
+                    format!("after #{}", n),
+                    format!("document is damaged, rest of it is skipped: {}", err),
+                );
+                break;
+            }
+        };
+        n += 1;
+
+        // Events of record are written back as they were read, so that record is deserialized
+        // separately
+        let mut writer = quick_xml::Writer::new(Vec::new());
+        let mut depth = 1;
+        let mut r = writer.write_event(Event::Start(start));
+        while r.is_ok() && depth > 0 {
+            record_buf.clear();
+            r = match reader.read_event_into(&mut record_buf) {
+                Ok(Event::Eof) => Err(quick_xml::Error::UnexpectedEof(format!(
+                    "end of {}",
+                    element
+                ))),
+                Ok(event) => {
+                    match &event {
+                        Event::Start(_) => depth += 1,
+                        Event::End(_) => depth -= 1,
+                        _ => (),
+                    }
+                    writer.write_event(event)
+                }
+                Err(err) => Err(err),
+            };
+        }
+        if let Err(err) = r {
+            warn(
+                format!("#{}", n),
+                format!("document is damaged, rest of it is skipped: {}", err),
+            );
+            break;
+        }
+
+        let raw = String::from_utf8_lossy(writer.get_ref());
+        match quick_xml::de::from_str(&raw) {
+            Ok(v) => records.push(v),
+            Err(err) => warn(describe(&raw, n), err.to_string()),
+        }
+    }
+    (records, warnings)
+}
+
+/// Decompress document and parse its records, see `parse_records`
+pub fn read_records<T: DeserializeOwned>(
+    path: &std::path::Path,
+    element: &str,
+) -> Result<(Vec<T>, Vec<RecordWarning>)> {
+    let file = std::fs::File::open(path)?;
+    let input = crate::decompress::decompress(std::io::BufReader::new(file))?;
+    let document = path
+        .file_name()
+        .map(|v| v.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(parse_records(
+        std::io::BufReader::new(input),
+        element,
+        &document,
+    ))
+}
+
+#[test]
+fn test_parse_records() {
+    #[derive(serde::Deserialize, Debug)]
+    struct Record {
+        #[serde(rename = "@pkgid")]
+        pkgid: String,
+        size: u64,
+    }
+
+    let (records, warnings) = parse_records::<Record, _>(
+        &br#"<filelists packages="3">
+<package pkgid="aaa"><size>1</size><unknown/></package>
+<package pkgid="bbb"><size>large</size></package>
+<package pkgid="ccc"><size>3</size></package>
+</filelists>"#[..],
+        "package",
+        "filelists.xml",
+    );
+    assert_eq!(
+        records
+            .iter()
+            .map(|v| (v.pkgid.as_str(), v.size))
+            .collect::<Vec<_>>(),
+        vec![("aaa", 1), ("ccc", 3)]
+    );
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].record, "bbb");
+}
@@ -31,20 +31,58 @@ pub fn new(href: String) -> Self {
     }
 }
 
+/// Types of documents referenced by repomd.xml. Types written by other tools (group, updateinfo,
+/// modules etc.) are kept as is
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(from = "String", into = "String")]
 pub enum DataType {
-    #[serde(rename = "primary")]
     Primary,
-    #[serde(rename = "filelists")]
     Filelists,
-    #[serde(rename = "other")]
     Other,
-    #[serde(rename = "primary_db")]
     PrimaryDb,
-    #[serde(rename = "filelists_db")]
     FilelistsDb,
-    #[serde(rename = "other_db")]
     OtherDb,
+    Prestodelta,
+    Productid,
+    Unknown(String),
+}
+
+impl DataType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Primary => "primary",
+            Self::Filelists => "filelists",
+            Self::Other => "other",
+            Self::PrimaryDb => "primary_db",
+            Self::FilelistsDb => "filelists_db",
+            Self::OtherDb => "other_db",
+            Self::Prestodelta => "prestodelta",
+            Self::Productid => "productid",
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+impl From<String> for DataType {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "primary" => Self::Primary,
+            "filelists" => Self::Filelists,
+            "other" => Self::Other,
+            "primary_db" => Self::PrimaryDb,
+            "filelists_db" => Self::FilelistsDb,
+            "other_db" => Self::OtherDb,
+            "prestodelta" => Self::Prestodelta,
+            "productid" => Self::Productid,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<DataType> for String {
+    fn from(v: DataType) -> Self {
+        v.as_str().to_owned()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -54,24 +92,49 @@ pub struct Data {
     pub type_: DataType,
     #[serde(rename = "checksum")]
     pub checksum: Checksum,
-    #[serde(rename = "open-checksum")]
-    pub open_checksum: Checksum,
+    /// Not set for uncompressed documents
+    #[serde(
+        rename = "open-checksum",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub open_checksum: Option<Checksum>,
+    /// Set for zchunk documents
+    #[serde(
+        rename = "header-checksum",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub header_checksum: Option<Checksum>,
     #[serde(rename = "location")]
     pub location: Location,
     #[serde(rename = "timestamp")]
     pub timestamp: i64,
     #[serde(rename = "size")]
     pub size: u64,
-    #[serde(rename = "open-size")]
-    pub open_size: usize,
+    #[serde(rename = "open-size", default, skip_serializing_if = "Option::is_none")]
+    pub open_size: Option<u64>,
+    #[serde(
+        rename = "header-size",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub header_size: Option<u64>,
+    /// Set for sqlite documents
+    #[serde(
+        rename = "database_version",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub database_version: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 #[serde(rename = "repomd")]
 pub struct Repomd {
-    #[serde(rename = "@xmlns")]
+    #[serde(rename = "@xmlns", default)]
     pub xmlns: String,
-    #[serde(rename(deserialize = "@rpm", serialize = "@xmlns:rpm"))]
+    #[serde(rename(deserialize = "@rpm", serialize = "@xmlns:rpm"), default)]
     pub xmlns_url: String,
     #[serde(default)]
     pub revision: u64,
@@ -80,14 +143,11 @@ pub struct Repomd {
 }
 
 impl Repomd {
-    pub fn new() -> Self {
+    pub fn new(revision: u64) -> Self {
         Self {
             xmlns: "http://linux.duke.edu/metadata/common".to_owned(),
             xmlns_url: "http://linux.duke.edu/metadata/rpm".to_owned(),
-            revision: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            revision,
             data: Vec::new(),
         }
     }
@@ -104,3 +164,32 @@ pub fn read(path: &std::path::Path) -> Result<Self> {
         Ok(r)
     }
 }
+
+#[test]
+fn test_de_foreign_repomd() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<repomd xmlns="http://linux.duke.edu/metadata/repo" xmlns:rpm="http://linux.duke.edu/metadata/rpm">
+  <revision>1700000000</revision>
+  <data type="primary_db">
+    <checksum type="sha256">aaaa</checksum>
+    <open-checksum type="sha256">bbbb</open-checksum>
+    <location href="repodata/aaaa-primary.sqlite.bz2"/>
+    <timestamp>1700000000</timestamp>
+    <database_version>10</database_version>
+    <size>100</size>
+    <open-size>200</open-size>
+  </data>
+  <data type="group">
+    <checksum type="sha256">cccc</checksum>
+    <location href="repodata/cccc-comps.xml"/>
+    <timestamp>1700000000</timestamp>
+    <size>300</size>
+  </data>
+</repomd>"#;
+    let repomd: Repomd = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(repomd.data.len(), 2);
+    assert_eq!(repomd.data[0].type_, DataType::PrimaryDb);
+    assert_eq!(repomd.data[0].database_version, Some(10));
+    assert_eq!(repomd.data[1].type_, DataType::Unknown("group".to_owned()));
+    assert_eq!(repomd.data[1].open_checksum, None);
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use slog_scope::warn;
+
+fn default_backoff() -> u64 {
+    500
+}
+
+/// Retries of operations failed with transient IO errors, e.g. hiccups of network storage
+#[derive(Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Number of retries, 0 disables them
+    #[serde(default)]
+    pub attempts: u32,
+    /// Delay before first retry in milliseconds, doubled for each next one
+    #[serde(default = "default_backoff")]
+    pub backoff: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 0,
+            backoff: default_backoff(),
+        }
+    }
+}
+
+/// Errors which may disappear on retry. Messages of errors are often built from io::Error with
+/// format!, so "os error N" is looked up in the text as well
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    let transient = [
+        libc::EIO,
+        libc::EAGAIN,
+        libc::EINTR,
+        libc::ENFILE,
+        libc::EMFILE,
+        libc::ETIMEDOUT,
+        libc::ESTALE,
+    ];
+    if err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .and_then(std::io::Error::raw_os_error)
+            .map_or(false, |code| transient.contains(&code))
+    }) {
+        return true;
+    }
+    let text = format!("{:#}", err);
+    text.match_indices("os error ").any(|(pos, matched)| {
+        let code: String = text[pos + matched.len()..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        code.parse().map_or(false, |code| transient.contains(&code))
+    })
+}
+
+impl RetryConfig {
+    /// Run `f` until it succeeds, fails with permanent error or attempts are exhausted. Returns
+    /// result and number of retries made
+    pub fn run<T, F>(&self, what: &str, mut f: F) -> (Result<T>, u32)
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let mut retries = 0;
+        let mut delay = std::time::Duration::from_millis(self.backoff);
+        loop {
+            match f() {
+                Err(err)
+                    if retries < self.attempts
+                        && is_transient(&err)
+                        && !crate::platform::is_interrupted() =>
+                {
+                    retries += 1;
+                    warn!(
+                        "{} failed, retry {} of {} in {} ms: {:#}",
+                        what,
+                        retries,
+                        self.attempts,
+                        delay.as_millis(),
+                        err
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                r => return (r, retries),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_is_transient() {
+    let err = anyhow::Error::from(std::io::Error::from_raw_os_error(libc::EIO));
+    assert!(is_transient(&err));
+    let err = anyhow::anyhow!(
+        "Read RPM header from \"foo.rpm\": {}",
+        std::io::Error::from_raw_os_error(libc::ESTALE)
+    );
+    assert!(is_transient(&err));
+    let err = anyhow::Error::from(std::io::Error::from_raw_os_error(libc::ENOENT));
+    assert!(!is_transient(&err));
+    assert!(!is_transient(&anyhow::anyhow!("Invalid RPM header")));
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+fn default_strip_invalid() -> bool {
+    true
+}
+
+/// Cleanup of strings taken from RPM headers before they are written to primary.xml
+#[derive(Serialize, Deserialize)]
+pub struct SanitizeConfig {
+    /// Remove control characters (except tab and newline) and characters replacing invalid UTF-8
+    #[serde(default = "default_strip_invalid")]
+    pub strip_invalid: bool,
+    /// Maximum length of summary in characters
+    #[serde(default)]
+    pub max_summary: Option<usize>,
+    /// Maximum length of description in characters
+    #[serde(default)]
+    pub max_description: Option<usize>,
+}
+
+impl Default for SanitizeConfig {
+    fn default() -> Self {
+        Self {
+            strip_invalid: default_strip_invalid(),
+            max_summary: None,
+            max_description: None,
+        }
+    }
+}
+
+fn is_invalid(c: char) -> bool {
+    c == char::REPLACEMENT_CHARACTER || (c.is_control() && c != '\t' && c != '\n')
+}
+
+impl SanitizeConfig {
+    /// Returns true if value was changed
+    fn sanitize(&self, value: &mut String, max_length: Option<usize>) -> bool {
+        let mut changed = false;
+        if self.strip_invalid && value.chars().any(is_invalid) {
+            value.retain(|c| !is_invalid(c));
+            changed = true;
+        }
+        if let Some(max_length) = max_length {
+            if let Some((pos, _)) = value.char_indices().nth(max_length) {
+                value.truncate(pos);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Sanitize text fields of package, returns names of changed fields
+    pub fn apply(&self, package: &mut crate::repodata::primary::Package) -> Vec<&'static str> {
+        let mut r = Vec::new();
+        let mut check = |name, value: Option<&mut String>, max_length| {
+            if let Some(value) = value {
+                if self.sanitize(value, max_length) {
+                    r.push(name)
+                }
+            }
+        };
+        check("summary", package.summary.value.as_mut(), self.max_summary);
+        check(
+            "description",
+            package.description.value.as_mut(),
+            self.max_description,
+        );
+        check("packager", package.packager.as_mut(), None);
+        check("url", package.url.as_mut(), None);
+        check("license", package.format.rpm_license.as_mut(), None);
+        check("vendor", package.format.rpm_vendor.as_mut(), None);
+        check("group", package.format.rpm_group.as_mut(), None);
+        check("buildhost", package.format.rpm_buildhost.as_mut(), None);
+        check("sourcerpm", package.format.rpm_sourcerpm.as_mut(), None);
+        r
+    }
+}
+
+#[test]
+fn test_sanitize() {
+    let config = SanitizeConfig {
+        max_description: Some(5),
+        ..Default::default()
+    };
+    let mut value = "ab\u{1}c\u{fffd}d\tef".to_owned();
+    assert!(config.sanitize(&mut value, config.max_description));
+    assert_eq!(value, "abcd\t");
+
+    let mut value = "fine\ntext".to_owned();
+    assert!(!config.sanitize(&mut value, None));
+    assert_eq!(value, "fine\ntext");
+}
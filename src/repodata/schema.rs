@@ -0,0 +1,172 @@
+use anyhow::{anyhow, bail, Result};
+use slog_scope::{info, warn};
+
+/// Schemas shipped with rpm-tool, used when repodata.schema_dir is not configured
+const BUILTIN_SCHEMAS: [(&str, &str); 5] = [
+    ("primary.rng", include_str!("../../schemas/primary.rng")),
+    ("filelists.rng", include_str!("../../schemas/filelists.rng")),
+    ("other.rng", include_str!("../../schemas/other.rng")),
+    ("repomd.rng", include_str!("../../schemas/repomd.rng")),
+    (
+        "repomd-content.rng",
+        include_str!("../../schemas/repomd-content.rng"),
+    ),
+];
+
+/// Validator of repodata documents against RELAX NG schemas. Validation is done by xmllint, which
+/// also reads gzipped documents directly
+pub struct SchemaValidator {
+    schema_dir: std::path::PathBuf,
+    /// Holds built-in schemas written to disk for xmllint
+    _builtin: Option<tempfile::TempDir>,
+}
+
+impl SchemaValidator {
+    pub fn new(config: &crate::repodata::RepodataConfig) -> Result<Self> {
+        if let Err(err) = std::process::Command::new("xmllint")
+            .arg("--version")
+            .output()
+        {
+            bail!(
+                "Schema validation requires xmllint, install libxml2 (libxml2-utils on Debian): {}",
+                err
+            )
+        }
+        if let Some(schema_dir) = &config.schema_dir {
+            return Ok(Self {
+                schema_dir: schema_dir.clone(),
+                _builtin: None,
+            });
+        }
+        let dir = config.scratch_tempdir(".schemas_")?;
+        for (file_name, content) in BUILTIN_SCHEMAS {
+            let path = dir.path().join(file_name);
+            std::fs::write(&path, content)
+                .map_err(|err| anyhow!("Cannot write {:?}: {}", path, err))?;
+        }
+        Ok(Self {
+            schema_dir: dir.path().to_path_buf(),
+            _builtin: Some(dir),
+        })
+    }
+
+    fn schema_file(data_type: &crate::repodata::repomd::DataType) -> Option<&'static str> {
+        match data_type {
+            crate::repodata::repomd::DataType::Primary => Some("primary.rng"),
+            crate::repodata::repomd::DataType::Filelists => Some("filelists.rng"),
+            crate::repodata::repomd::DataType::Other => Some("other.rng"),
+            _ => None,
+        }
+    }
+
+    pub fn validate_file(&self, schema: &str, path: &std::path::Path) -> Result<()> {
+        let schema_path = self.schema_dir.join(schema);
+        if !schema_path.exists() {
+            bail!(
+                "Schema {:?} not found, unset repodata.schema_dir to use built-in schemas",
+                schema_path
+            )
+        }
+
+        info!("Validating {:?} against {:?}", path, schema_path);
+        let output = std::process::Command::new("xmllint")
+            .arg("--noout")
+            .arg("--relaxng")
+            .arg(&schema_path)
+            .arg(path)
+            .output()
+            .map_err(|err| anyhow!("Cannot run xmllint: {}", err))?;
+
+        if !output.status.success() {
+            bail!(
+                "{:?} does not conform to {:?}: {}",
+                path,
+                schema_path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        Ok(())
+    }
+
+    /// Validate repomd.xml and all documents it references. `repodata_dir` is where the files
+    /// named in location hrefs are stored
+    pub fn validate_repodata(
+        &self,
+        repomd: &crate::repodata::repomd::Repomd,
+        repomd_path: &std::path::Path,
+        repodata_dir: &std::path::Path,
+    ) -> Result<()> {
+        let mut errors = Vec::new();
+
+        if let Err(err) = self.validate_file("repomd.rng", repomd_path) {
+            errors.push(err);
+        }
+
+        for data in &repomd.data {
+            let schema = match Self::schema_file(&data.type_) {
+                Some(v) => v,
+                None => {
+                    info!("No schema for {:?}, skipping", data.type_);
+                    continue;
+                }
+            };
+            let file_name = match std::path::Path::new(&data.location.href).file_name() {
+                Some(v) => v,
+                None => {
+                    errors.push(anyhow!("Invalid location {:?}", data.location.href));
+                    continue;
+                }
+            };
+            if let Err(err) = self.validate_file(schema, &repodata_dir.join(file_name)) {
+                errors.push(err);
+            }
+        }
+
+        for err in &errors {
+            warn!("{}", err);
+        }
+        if !errors.is_empty() {
+            return Err(
+                anyhow!("{} documents failed schema validation", errors.len())
+                    .context(crate::exit_code::Failure::Mismatch),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    pub fn validate_schema(&self) -> Result<()> {
+        let validator = SchemaValidator::new(self.config)?;
+        let repodata_path = self.options.path.join("repodata");
+        let repomd_path = repodata_path.join("repomd.xml");
+        let _lock = self.read_lock()?;
+        let repomd = crate::repodata::repomd::Repomd::read(&repomd_path)?;
+        validator.validate_repodata(&repomd, &repomd_path, &repodata_path)
+    }
+}
+
+#[test]
+fn test_builtin_schemas() {
+    let config: crate::repodata::RepodataConfig =
+        serde_yaml::from_str("concurrency: 1\nuseful_files: ^/etc\n").unwrap();
+    let validator = match SchemaValidator::new(&config) {
+        Ok(v) => v,
+        // xmllint is not installed
+        Err(_) => return,
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("filelists.xml");
+    std::fs::write(
+        &path,
+        r#"<filelists xmlns="http://linux.duke.edu/metadata/filelists" packages="1"><package pkgid="ab12" name="bash" arch="x86_64"><version epoch="0" ver="5.1" rel="1"/><file>/bin/bash</file></package></filelists>"#,
+    )
+    .unwrap();
+    validator.validate_file("filelists.rng", &path).unwrap();
+    std::fs::write(
+        &path,
+        r#"<filelists xmlns="http://linux.duke.edu/metadata/filelists" packages="1"><package name="bash"/></filelists>"#,
+    )
+    .unwrap();
+    assert!(validator.validate_file("filelists.rng", &path).is_err());
+}
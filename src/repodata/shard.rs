@@ -0,0 +1,297 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::{info, warn};
+
+/// Directory in repository root with partial metadata of shards, skipped by scans
+pub const SHARDS_DIR: &str = ".shards";
+
+/// Location hrefs of shard metadata are relative to .shards/<i>-of-<N>
+pub const SHARD_LOCATION_PREFIX: &str = "../..";
+
+/// File in .shards/<i>-of-<N> naming run which generated the shard
+const SHARD_RUN_FILE: &str = "run.json";
+
+/// Deterministic subset of packages: `index` of `count`, starting from 1
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Package belongs to shard by hash of its path relative to repository root, so all machines
+    /// split the tree in the same way
+    pub fn contains(&self, relative_path: &std::path::Path) -> bool {
+        let digest = crate::digest::str_sha128(&relative_path.to_string_lossy());
+        let hash = u64::from_str_radix(&digest[..16], 16).unwrap_or_default();
+        (hash % self.count as u64) as usize == self.index - 1
+    }
+
+    pub fn dir_name(&self) -> String {
+        format!("{}-of-{}", self.index, self.count)
+    }
+
+    pub fn of_dir_name(name: &str) -> Option<Self> {
+        let (index, count) = name.split_once("-of-")?;
+        let r = Self {
+            index: index.parse().ok()?,
+            count: count.parse().ok()?,
+        };
+        r.validate().ok()?;
+        Some(r)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.count == 0 || self.index == 0 || self.index > self.count {
+            bail!(
+                "Invalid shard {}/{}, expected i/N with 1 <= i <= N",
+                self.index,
+                self.count
+            )
+        }
+        Ok(())
+    }
+}
+
+/// Shards are merged only if all of them are generated by the same run, so that shards left by
+/// earlier or failed runs don't get into metadata
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ShardRun {
+    pub run_id: String,
+    pub shard: Shard,
+}
+
+impl ShardRun {
+    pub fn read(shard_path: &std::path::Path) -> Result<Self> {
+        let path = shard_path.join(SHARD_RUN_FILE);
+        let file =
+            std::fs::File::open(&path).map_err(|err| anyhow!("Cannot open {:?}: {}", path, err))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|err| anyhow!("Cannot parse {:?}: {}", path, err))
+    }
+
+    /// Written after metadata of shard is published
+    pub fn write(&self, shard_path: &std::path::Path) -> Result<()> {
+        let path = shard_path.join(SHARD_RUN_FILE);
+        let tmp_path = shard_path.join(format!(".{}", SHARD_RUN_FILE));
+        std::fs::write(&tmp_path, serde_json::to_string(self)?)
+            .map_err(|err| anyhow!("Cannot write {:?}: {}", tmp_path, err))?;
+        std::fs::rename(&tmp_path, &path)
+            .map_err(|err| anyhow!("Cannot rename {:?} to {:?}: {}", tmp_path, path, err))
+    }
+}
+
+/// Run ID shared by all shards, or `expected` one
+pub fn common_run_id<'a>(runs: &'a [ShardRun], expected: Option<&str>) -> Result<&'a str> {
+    let run_id = match runs.first() {
+        Some(v) => v.run_id.as_str(),
+        None => bail!("No shards to merge"),
+    };
+    if let Some(v) = runs.iter().find(|v| v.run_id != run_id) {
+        bail!(
+            "Shards {}/{} and {}/{} are generated by different runs {:?} and {:?}, regenerate stale ones",
+            runs[0].shard.index,
+            runs[0].shard.count,
+            v.shard.index,
+            v.shard.count,
+            run_id,
+            v.run_id
+        )
+    }
+    if let Some(expected) = expected {
+        if expected != run_id {
+            bail!(
+                "Shards are generated by run {:?}, expected {:?}",
+                run_id,
+                expected
+            )
+        }
+    }
+    Ok(run_id)
+}
+
+impl std::str::FromStr for Shard {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Invalid shard {:?}, expected i/N", s))?;
+        let r = Self {
+            index: index
+                .parse()
+                .map_err(|err| anyhow!("Invalid shard {:?}: {}", s, err))?,
+            count: count
+                .parse()
+                .map_err(|err| anyhow!("Invalid shard {:?}: {}", s, err))?,
+        };
+        r.validate()?;
+        Ok(r)
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Index subset of packages into .shards/<i>-of-<N>, delta RPMs are indexed by "merge-shards"
+    pub(super) fn generate_shard(&self, shard: &Shard) -> Result<()> {
+        if self.options.split_debug {
+            bail!("--split-debug cannot be used with --shard, split debug packages after merge")
+        }
+        let run_id = match &self.options.shard_run_id {
+            Some(v) => v.clone(),
+            None => bail!("--run-id is required with --shard, use the same one for all shards"),
+        };
+        let packages: Vec<_> = self
+            .scan()?
+            .packages
+            .into_iter()
+            .filter(|path| match path.strip_prefix(&self.options.path) {
+                Ok(v) => shard.contains(v),
+                Err(_) => false,
+            })
+            .collect();
+        info!(
+            "Generating shard {}/{} of {} packages",
+            shard.index,
+            shard.count,
+            packages.len()
+        );
+
+        let shard_path = self.options.path.join(SHARDS_DIR).join(shard.dir_name());
+        let mut state = crate::repodata::State::new_in(
+            self.config,
+            &self.options,
+            shard_path.clone(),
+            std::path::PathBuf::from(SHARD_LOCATION_PREFIX),
+        )?;
+        state.delta_files = Some(Vec::new());
+        self.register_files_list(state, &packages)?;
+        ShardRun {
+            run_id,
+            shard: *shard,
+        }
+        .write(&shard_path)
+    }
+
+    /// Combine partial metadata of all shards of .shards/ into repository metadata
+    pub fn merge_shards(&self) -> Result<()> {
+        let shards_path = self.options.path.join(SHARDS_DIR);
+        let mut shards = Vec::new();
+        for entry in std::fs::read_dir(&shards_path)
+            .map_err(|err| anyhow!("Cannot read {:?}: {}", shards_path, err))?
+        {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            match Shard::of_dir_name(&file_name) {
+                Some(v) => shards.push(v),
+                None => warn!(
+                    "Unexpected entry {:?} in {:?}, skipping",
+                    file_name, shards_path
+                ),
+            }
+        }
+        shards.sort_by_key(|v| (v.count, v.index));
+        let count = match shards.first() {
+            Some(v) => v.count,
+            None => bail!("No shards found in {:?}", shards_path),
+        };
+        if shards.iter().any(|v| v.count != count) {
+            bail!(
+                "Shards of different splits found in {:?}, remove stale ones",
+                shards_path
+            )
+        }
+        if shards.len() != count {
+            bail!("Only {} of {} shards are generated", shards.len(), count)
+        }
+        let runs = shards
+            .iter()
+            .map(|shard| {
+                ShardRun::read(&shards_path.join(shard.dir_name())).map_err(|err| {
+                    anyhow!(
+                        "Shard {}/{} has no run record, regenerate it: {}",
+                        shard.index,
+                        shard.count,
+                        err
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let run_id =
+            crate::repodata::shard::common_run_id(&runs, self.options.shard_run_id.as_deref())?;
+        info!("Merging {} shards of run {:?}", count, run_id);
+
+        let mut state = crate::repodata::State::new(self.config, &self.options)?;
+        state.current_packages.lock().unwrap().clear();
+        state.current_fileslist.lock().unwrap().clear();
+
+        for shard in &shards {
+            let shard_path = shards_path.join(shard.dir_name());
+            let index = crate::repodata::query::Index::read(&shard_path)
+                .map_err(|err| anyhow!("Cannot read shard {:?}: {}", shard_path, err))?;
+            info!(
+                "Merging shard {}/{} of {} packages",
+                shard.index,
+                shard.count,
+                index.primary.package.len()
+            );
+            let mut primary_xml = state.primary_xml.lock().unwrap();
+            for mut package in index.primary.package {
+                if let Ok(v) =
+                    std::path::Path::new(&package.location.href).strip_prefix(SHARD_LOCATION_PREFIX)
+                {
+                    package.location.href =
+                        state.location_prefix.join(v).to_string_lossy().to_string();
+                }
+                primary_xml.add_package(package);
+            }
+            if self.options.generate_fileslists {
+                let filelists = match index.filelists {
+                    Some(v) => v,
+                    None => bail!(
+                        "Shard {:?} has no fileslists, generate shards with --fileslists",
+                        shard_path
+                    ),
+                };
+                let mut fileslist = state.fileslist.lock().unwrap();
+                for package in filelists.package {
+                    fileslist.add_package(package)
+                }
+            }
+        }
+
+        state.delta_files = Some(self.scan()?.deltas);
+        state.finish()?;
+        self.write_treeinfo()?;
+
+        std::fs::remove_dir_all(&shards_path)
+            .map_err(|err| anyhow!("Cannot remove merged shards {:?}: {}", shards_path, err))
+    }
+}
+
+#[test]
+fn test_shard() {
+    let shards: Vec<Shard> = (1..=3)
+        .map(|n| format!("{}/3", n).parse().unwrap())
+        .collect();
+    for path in ["Packages/b/bash-5.1-1.x86_64.rpm", "foo.rpm", "a/b/c.rpm"] {
+        let path = std::path::Path::new(path);
+        assert_eq!(shards.iter().filter(|v| v.contains(path)).count(), 1);
+    }
+    assert_eq!(Shard::of_dir_name(&shards[1].dir_name()), Some(shards[1]));
+    assert!("0/3".parse::<Shard>().is_err());
+    assert!("4/3".parse::<Shard>().is_err());
+    assert!("1-3".parse::<Shard>().is_err());
+}
+
+#[test]
+fn test_common_run_id() {
+    let run = |index, run_id: &str| ShardRun {
+        run_id: run_id.to_owned(),
+        shard: Shard { index, count: 2 },
+    };
+    let runs = [run(1, "a"), run(2, "a")];
+    assert_eq!(common_run_id(&runs, None).unwrap(), "a");
+    assert_eq!(common_run_id(&runs, Some("a")).unwrap(), "a");
+    assert!(common_run_id(&runs, Some("b")).is_err());
+    assert!(common_run_id(&[run(1, "a"), run(2, "b")], None).is_err());
+    assert!(common_run_id(&[], None).is_err());
+}
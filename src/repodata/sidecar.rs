@@ -0,0 +1,73 @@
+use slog_scope::debug;
+
+/// Digest and size of file from content of checksum file: output of sha1sum and similar tools for
+/// file with given name, followed by size of file
+fn parse(
+    content: &str,
+    digest_type: crate::digest::DigestType,
+    file_name: &std::ffi::OsStr,
+) -> Option<(String, u64)> {
+    let mut fields = content.split_whitespace();
+    let digest = fields.next()?.to_ascii_lowercase();
+    // "*" marks binary mode of sha1sum
+    let name = fields.next()?;
+    if std::path::Path::new(name.trim_start_matches('*')).file_name() != Some(file_name) {
+        return None;
+    }
+    let size = fields.next()?.parse().ok()?;
+    if digest.len() != digest_type.hex_len() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((digest, size))
+}
+
+/// Checksum of package taken from <package>.<type> file (e.g. foo.rpm.sha256) written by build
+/// system. File is trusted only if it is not older than package, records size of package and its
+/// content is valid
+pub fn read(path: &std::path::Path, digest_type: crate::digest::DigestType) -> Option<String> {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(digest_type.name());
+    let sidecar = std::path::PathBuf::from(sidecar);
+
+    let sidecar_metadata = std::fs::metadata(&sidecar).ok()?;
+    let metadata = std::fs::metadata(path).ok()?;
+    if crate::platform::file_mtime(&sidecar_metadata) < crate::platform::file_mtime(&metadata) {
+        debug!("{:?} is older than package, ignoring it", sidecar);
+        return None;
+    }
+    let content = std::fs::read_to_string(&sidecar).ok()?;
+    let (digest, size) = match parse(&content, digest_type, path.file_name()?) {
+        Some(v) => v,
+        None => {
+            debug!("{:?} has invalid content, ignoring it", sidecar);
+            return None;
+        }
+    };
+    if size != crate::platform::file_size(&metadata) {
+        debug!("{:?} records other size of package, ignoring it", sidecar);
+        return None;
+    }
+    Some(digest)
+}
+
+#[test]
+fn test_parse() {
+    let digest = "a9993e364706816aba3e25717850c26c9cd0d89d";
+    let name = std::ffi::OsStr::new("foo.rpm");
+    let sha1 = crate::digest::DigestType::Sha1;
+    assert_eq!(
+        parse(&format!("{} *dist/foo.rpm 1024\n", digest), sha1, name),
+        Some((digest.to_owned(), 1024))
+    );
+    assert_eq!(parse(digest, sha1, name), None);
+    assert_eq!(parse(&format!("{}  foo.rpm", digest), sha1, name), None);
+    assert_eq!(
+        parse(&format!("{}  bar.rpm  1024", digest), sha1, name),
+        None
+    );
+    assert_eq!(
+        parse(&format!("{}  foo.rpm  1024", &digest[..32]), sha1, name),
+        None
+    );
+}
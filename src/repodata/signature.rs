@@ -0,0 +1,224 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+/// Trusted keys for verification of signatures. Verification is done by gpgv, host rpm database
+/// is not used
+#[derive(Serialize, Deserialize, Default)]
+pub struct GpgConfig {
+    /// Keyrings in format accepted by gpgv
+    #[serde(default)]
+    pub keyrings: Vec<std::path::PathBuf>,
+    /// Public keys, ASCII armored or binary
+    #[serde(default)]
+    pub key_files: Vec<std::path::PathBuf>,
+    /// Key servers to fetch `key_ids` from
+    #[serde(default)]
+    pub key_servers: Vec<String>,
+    #[serde(default)]
+    pub key_ids: Vec<String>,
+}
+
+/// Keyrings collected from all key sources of `GpgConfig`
+pub struct Verifier {
+    keyrings: Vec<std::path::PathBuf>,
+    tempdir: tempfile::TempDir,
+}
+
+/// Temporary rpm database with trusted keys imported, used to check signatures of packages
+pub struct RpmVerifier {
+    dbpath: std::path::PathBuf,
+    _verifier: Verifier,
+}
+
+fn run_gpg(command: &mut std::process::Command) -> Result<()> {
+    let output = command
+        .output()
+        .map_err(|err| anyhow!("Cannot run gpg: {}", err))?;
+    if !output.status.success() {
+        bail!(
+            "gpg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+    Ok(())
+}
+
+impl GpgConfig {
+    pub fn is_configured(&self) -> bool {
+        !self.keyrings.is_empty() || !self.key_files.is_empty() || !self.key_ids.is_empty()
+    }
+
+    /// Convert key files and keys from key servers into keyrings usable by gpgv
+    pub fn verifier(&self) -> Result<Verifier> {
+        if !self.is_configured() {
+            bail!("Signature verification requested, but no trusted keys are configured")
+        }
+
+        let tempdir = tempfile::Builder::new().prefix("rpm-tool-gpg").tempdir()?;
+        let mut keyrings = self.keyrings.clone();
+
+        for (n, key_file) in self.key_files.iter().enumerate() {
+            let content = std::fs::read(key_file)
+                .map_err(|err| anyhow!("Cannot read key file {:?}: {}", key_file, err))?;
+            if !content.starts_with(b"-----BEGIN PGP") {
+                keyrings.push(key_file.clone());
+                continue;
+            }
+            let keyring = tempdir.path().join(format!("key-{}.gpg", n));
+            run_gpg(
+                std::process::Command::new("gpg")
+                    .arg("--batch")
+                    .arg("--yes")
+                    .arg("--dearmor")
+                    .arg("--output")
+                    .arg(&keyring)
+                    .arg(key_file),
+            )
+            .map_err(|err| anyhow!("Cannot read key file {:?}: {}", key_file, err))?;
+            keyrings.push(keyring);
+        }
+
+        if !self.key_ids.is_empty() {
+            if self.key_servers.is_empty() {
+                bail!("key_ids are set, but no key_servers are configured")
+            }
+            let homedir = tempdir.path().join("home");
+            std::fs::create_dir(&homedir)?;
+            let mut errors = Vec::new();
+            for key_server in &self.key_servers {
+                info!("Fetching keys {:?} from {}", self.key_ids, key_server);
+                match run_gpg(
+                    std::process::Command::new("gpg")
+                        .arg("--batch")
+                        .arg("--homedir")
+                        .arg(&homedir)
+                        .arg("--keyserver")
+                        .arg(key_server)
+                        .arg("--recv-keys")
+                        .args(&self.key_ids),
+                ) {
+                    Ok(()) => {
+                        errors.clear();
+                        break;
+                    }
+                    Err(err) => errors.push(format!("{}: {}", key_server, err)),
+                }
+            }
+            if !errors.is_empty() {
+                bail!("Cannot fetch keys from key servers: {}", errors.join("; "))
+            }
+            let keyring = tempdir.path().join("key-servers.gpg");
+            run_gpg(
+                std::process::Command::new("gpg")
+                    .arg("--batch")
+                    .arg("--homedir")
+                    .arg(&homedir)
+                    .arg("--output")
+                    .arg(&keyring)
+                    .arg("--export")
+                    .args(&self.key_ids),
+            )?;
+            keyrings.push(keyring);
+        }
+
+        Ok(Verifier { keyrings, tempdir })
+    }
+}
+
+impl Verifier {
+    /// Import keyrings into temporary rpm database. Host rpm database is not used
+    pub fn rpm_verifier(self) -> Result<RpmVerifier> {
+        let dbpath = self.tempdir.path().join("rpmdb");
+        std::fs::create_dir(&dbpath)?;
+        for (n, keyring) in self.keyrings.iter().enumerate() {
+            let armored = self.tempdir.path().join(format!("rpm-key-{}.asc", n));
+            run_gpg(
+                std::process::Command::new("gpg")
+                    .arg("--batch")
+                    .arg("--homedir")
+                    .arg(self.tempdir.path())
+                    .arg("--no-default-keyring")
+                    .arg("--keyring")
+                    .arg(keyring)
+                    .arg("--armor")
+                    .arg("--output")
+                    .arg(&armored)
+                    .arg("--export"),
+            )
+            .map_err(|err| anyhow!("Cannot export keys of {:?}: {}", keyring, err))?;
+            let output = std::process::Command::new("rpmkeys")
+                .arg("--dbpath")
+                .arg(&dbpath)
+                .arg("--import")
+                .arg(&armored)
+                .output()
+                .map_err(|err| anyhow!("Cannot run rpmkeys: {}", err))?;
+            if !output.status.success() {
+                bail!(
+                    "Cannot import keys of {:?} into rpm database: {}",
+                    keyring,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )
+            }
+        }
+        Ok(RpmVerifier {
+            dbpath,
+            _verifier: self,
+        })
+    }
+
+    /// Verify detached signature of file
+    pub fn verify_detached(
+        &self,
+        signature: &std::path::Path,
+        data: &std::path::Path,
+    ) -> Result<()> {
+        info!("Verifying signature {:?} of {:?}", signature, data);
+        let mut command = std::process::Command::new("gpgv");
+        for keyring in &self.keyrings {
+            let _ = command.arg("--keyring").arg(keyring);
+        }
+        let output = command
+            .arg(signature)
+            .arg(data)
+            .output()
+            .map_err(|err| anyhow!("Cannot run gpgv: {}", err))?;
+
+        if !output.status.success() {
+            bail!(
+                "Bad signature {:?} of {:?}: {}",
+                signature,
+                data,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+        Ok(())
+    }
+}
+
+impl RpmVerifier {
+    /// Check that package is signed by one of trusted keys and its digests are valid
+    pub fn verify(&self, path: &std::path::Path) -> Result<()> {
+        info!("Verifying signature of {:?}", path);
+        let output = std::process::Command::new("rpmkeys")
+            .arg("--dbpath")
+            .arg(&self.dbpath)
+            .arg("--checksig")
+            .arg(path)
+            .output()
+            .map_err(|err| anyhow!("Cannot run rpmkeys: {}", err))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // Unsigned packages pass --checksig with "digests OK"
+        if !output.status.success() || !stdout.contains("signatures OK") {
+            return Err(anyhow!(
+                "Package {:?} is not signed by trusted key: {}{}",
+                path,
+                stdout.trim(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .context(crate::exit_code::Failure::Mismatch));
+        }
+        Ok(())
+    }
+}
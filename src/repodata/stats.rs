@@ -0,0 +1,226 @@
+use std::io::{BufRead, Write};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Older runs are dropped from statistics
+const MAX_RUNS: usize = 10000;
+
+/// Statistics file is appended by one line per run and compacted to MAX_RUNS runs when it grows
+/// over this size
+const COMPACT_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Run {
+    /// UNIX timestamp of publication or failure
+    pub finished: u64,
+    /// Revision of repomd.xml, 0 for failed run
+    pub revision: u64,
+    pub packages: usize,
+    /// Total size of packages in bytes
+    pub packages_size: u64,
+    /// Total size of compressed metadata documents in bytes
+    pub metadata_size: u64,
+    /// Packages read from RPM files
+    pub indexed: usize,
+    /// Packages taken from cached metadata
+    pub reused: usize,
+    pub failed: usize,
+    /// Seconds from start of indexing to publication or failure
+    pub duration: f64,
+    /// Error of run which didn't publish metadata
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Run {
+    /// Run started at `started` with package counters of crate::summary::counters() taken then
+    pub fn new(started: std::time::Instant, counters: (usize, usize, usize)) -> Result<Self> {
+        let (indexed, reused, failed) = crate::summary::counters();
+        Ok(Self {
+            finished: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+            indexed: indexed - counters.0,
+            reused: reused - counters.1,
+            failed: failed - counters.2,
+            duration: started.elapsed().as_secs_f64(),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub runs: Vec<Run>,
+}
+
+/// Run with growth since previous successful one, for "repository trends"
+#[derive(Serialize, Debug)]
+pub struct Trend {
+    pub finished: u64,
+    /// "ok" or "failed"
+    pub status: &'static str,
+    pub revision: u64,
+    pub packages: usize,
+    pub packages_delta: i64,
+    pub packages_size: u64,
+    pub packages_size_delta: i64,
+    pub metadata_size: u64,
+    pub indexed: usize,
+    pub reused: usize,
+    pub failed: usize,
+    pub duration: f64,
+    /// Packages read from RPM files per second
+    pub indexing_rate: f64,
+}
+
+impl Stats {
+    /// File with statistics of repository in `root`, in repodata→stats_dir. Repositories sharing
+    /// the directory have files named by their path
+    pub fn path(stats_dir: &std::path::Path, root: &std::path::Path) -> std::path::PathBuf {
+        let root_id = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        stats_dir.join(format!(
+            "{}.jsonl",
+            crate::digest::str_sha128(&root_id.to_string_lossy())
+        ))
+    }
+
+    /// Read runs, one JSON record per line. Broken lines, e.g. left by crash in the middle of
+    /// append, are skipped
+    pub fn read(path: &std::path::Path) -> Result<Self> {
+        let file =
+            std::fs::File::open(path).map_err(|err| anyhow!("Cannot open {:?}: {}", path, err))?;
+        let mut runs = Vec::new();
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line.map_err(|err| anyhow!("Cannot read {:?}: {}", path, err))?;
+            if let Ok(run) = serde_json::from_str(&line) {
+                runs.push(run)
+            }
+        }
+        Ok(Self { runs })
+    }
+
+    /// Append `run` to file in `path`. File which grew over COMPACT_SIZE is rewritten with last
+    /// MAX_RUNS runs
+    pub fn append(path: &std::path::Path, run: &Run) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| anyhow!("Cannot create {:?}: {}", dir, err))?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| anyhow!("Cannot open {:?}: {}", path, err))?;
+        file.write_all(format!("{}\n", serde_json::to_string(run)?).as_bytes())
+            .map_err(|err| anyhow!("Cannot write {:?}: {}", path, err))?;
+
+        if file.metadata()?.len() > COMPACT_SIZE {
+            let stats = Self::read(path)?;
+            let skip = stats.runs.len().saturating_sub(MAX_RUNS);
+            let mut content = String::new();
+            for run in &stats.runs[skip..] {
+                content.push_str(&serde_json::to_string(run)?);
+                content.push('\n')
+            }
+            let tmp_path = path.with_extension("tmp");
+            std::fs::write(&tmp_path, content)
+                .map_err(|err| anyhow!("Cannot write {:?}: {}", tmp_path, err))?;
+            std::fs::rename(&tmp_path, path)
+                .map_err(|err| anyhow!("Cannot rename {:?} to {:?}: {}", tmp_path, path, err))?;
+        }
+        Ok(())
+    }
+
+    /// Last `last` runs, with differences of published ones to preceding published runs
+    pub fn trends(&self, last: usize) -> Result<Vec<Trend>> {
+        if self.runs.is_empty() {
+            bail!("No runs recorded")
+        }
+        let skip = self.runs.len().saturating_sub(last);
+        let r = self
+            .runs
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(n, run)| {
+                let previous = match run.error {
+                    Some(_) => None,
+                    None => self.runs[..n].iter().rev().find(|v| v.error.is_none()),
+                };
+                Trend {
+                    finished: run.finished,
+                    status: match run.error {
+                        Some(_) => "failed",
+                        None => "ok",
+                    },
+                    revision: run.revision,
+                    packages: run.packages,
+                    packages_delta: previous.map_or(0, |v| run.packages as i64 - v.packages as i64),
+                    packages_size: run.packages_size,
+                    packages_size_delta: previous
+                        .map_or(0, |v| run.packages_size as i64 - v.packages_size as i64),
+                    metadata_size: run.metadata_size,
+                    indexed: run.indexed,
+                    reused: run.reused,
+                    failed: run.failed,
+                    duration: (run.duration * 1000.0).round() / 1000.0,
+                    indexing_rate: if run.duration > 0.0 {
+                        (run.indexed as f64 / run.duration * 10.0).round() / 10.0
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+        Ok(r)
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Statistics of last `last` runs with growth since preceding ones
+    pub fn trends(&self, last: usize) -> Result<Vec<Trend>> {
+        let stats_dir = match &self.config.stats_dir {
+            Some(v) => v,
+            None => bail!("Statistics are not recorded, repodata→stats_dir is not set"),
+        };
+        let path = Stats::path(stats_dir, &self.options.metadata_root(&self.options.path));
+        if !path.exists() {
+            bail!("No statistics of repository, they are recorded since first run with repodata→stats_dir set")
+        }
+        Stats::read(&path)?.trends(last)
+    }
+}
+
+#[test]
+fn test_trends() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stats.jsonl");
+    for (packages, packages_size, error) in [
+        (10, 1000, None),
+        (12, 1500, None),
+        (0, 0, Some("Interrupted".to_owned())),
+        (11, 1400, None),
+    ] {
+        let run = Run {
+            packages,
+            packages_size,
+            indexed: 2,
+            duration: 4.0,
+            error,
+            ..Default::default()
+        };
+        Stats::append(&path, &run).unwrap()
+    }
+    let stats = Stats::read(&path).unwrap();
+    assert_eq!(stats.runs.len(), 4);
+    let trends = stats.trends(3).unwrap();
+    assert_eq!(trends.len(), 3);
+    assert_eq!(trends[0].packages_delta, 2);
+    assert_eq!(trends[0].packages_size_delta, 500);
+    assert_eq!(trends[1].status, "failed");
+    assert_eq!(trends[1].packages_delta, 0);
+    assert_eq!(trends[2].packages_delta, -1);
+    assert_eq!(trends[2].indexing_rate, 0.5);
+}
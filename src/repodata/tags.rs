@@ -0,0 +1,175 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+/// Subdirectory of repodata/ where tags are stored
+pub const TAGS_DIR: &str = "tags";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagPackage {
+    pub nevra: String,
+    pub location: String,
+    pub checksum: String,
+}
+
+/// Named state of repository: identity of repomd.xml and list of packages it described
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Tag {
+    pub name: String,
+    /// UNIX timestamp of tag creation
+    pub created: u64,
+    pub revision: u64,
+    /// SHA256 of repomd.xml
+    pub repomd_checksum: String,
+    pub packages: Vec<TagPackage>,
+}
+
+/// Short description of tag for listing
+#[derive(Serialize, Debug)]
+pub struct TagSummary {
+    pub name: String,
+    pub created: u64,
+    pub revision: u64,
+    pub repomd_checksum: String,
+    pub packages: usize,
+}
+
+impl From<Tag> for TagSummary {
+    fn from(v: Tag) -> Self {
+        Self {
+            name: v.name,
+            created: v.created,
+            revision: v.revision,
+            repomd_checksum: v.repomd_checksum,
+            packages: v.packages.len(),
+        }
+    }
+}
+
+impl Tag {
+    fn validate_name(name: &str) -> Result<()> {
+        if name.is_empty()
+            || name.starts_with('.')
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+        {
+            bail!(
+                "Invalid tag name {:?}, only letters, digits, '.', '-' and '_' are allowed",
+                name
+            )
+        }
+        Ok(())
+    }
+
+    fn path(repodata_path: &std::path::Path, name: &str) -> Result<std::path::PathBuf> {
+        Self::validate_name(name)?;
+        Ok(repodata_path.join(TAGS_DIR).join(format!("{}.json", name)))
+    }
+
+    /// Describe current state of repository
+    pub fn new(
+        name: &str,
+        repodata_path: &std::path::Path,
+        primary: &crate::repodata::primary::Primary,
+    ) -> Result<Self> {
+        Self::validate_name(name)?;
+        let repomd_path = repodata_path.join("repomd.xml");
+        let repomd = crate::repodata::repomd::Repomd::read(&repomd_path)?;
+        let repomd_checksum = crate::digest::path_sha256(&repomd_path)
+            .map_err(|err| anyhow!("Cannot calculate checksum of {:?}: {}", repomd_path, err))?;
+
+        let packages = primary
+            .package
+            .iter()
+            .map(|package| TagPackage {
+                nevra: package.nevra(),
+                location: package.location.href.clone(),
+                checksum: package.checksum.value.clone(),
+            })
+            .collect();
+
+        Ok(Self {
+            name: name.to_owned(),
+            created: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+            revision: repomd.revision,
+            repomd_checksum,
+            packages,
+        })
+    }
+
+    pub fn write(
+        &self,
+        repodata_path: &std::path::Path,
+        force: bool,
+        permissions: &crate::repodata::permissions::PermissionsConfig,
+    ) -> Result<()> {
+        let path = Self::path(repodata_path, &self.name)?;
+        if path.exists() && !force {
+            bail!("Tag {:?} already exists", self.name)
+        }
+        let dir = repodata_path.join(TAGS_DIR);
+        std::fs::create_dir_all(&dir).map_err(|err| anyhow!("Cannot create {:?}: {}", dir, err))?;
+        permissions.apply_dir(&dir)?;
+
+        info!("Writing tag {:?}", path);
+        let tmp_path = dir.join(format!(".{}.json.tmp", self.name));
+        std::fs::write(&tmp_path, serde_json::to_string(self)?)
+            .map_err(|err| anyhow!("Cannot write {:?}: {}", tmp_path, err))?;
+        permissions.apply_file(&tmp_path)?;
+        std::fs::rename(&tmp_path, &path)
+            .map_err(|err| anyhow!("Cannot write {:?}: {}", path, err))?;
+        Ok(())
+    }
+
+    pub fn read(repodata_path: &std::path::Path, name: &str) -> Result<Self> {
+        let path = Self::path(repodata_path, name)?;
+        if !path.exists() {
+            bail!("Tag {:?} not found", name)
+        }
+        let file =
+            std::fs::File::open(&path).map_err(|err| anyhow!("Cannot open {:?}: {}", path, err))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|err| anyhow!("Cannot parse {:?}: {}", path, err))
+    }
+
+    pub fn list(repodata_path: &std::path::Path) -> Result<Vec<TagSummary>> {
+        let dir = repodata_path.join(TAGS_DIR);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut r = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            if let Some(name) = file_name.strip_suffix(".json") {
+                if Self::validate_name(name).is_ok() {
+                    r.push(TagSummary::from(Self::read(repodata_path, name)?))
+                }
+            }
+        }
+        r.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+        Ok(r)
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Record current state of repository under given name
+    pub fn tag(&self, name: &str, force: bool) -> Result<()> {
+        let index = crate::repodata::query::Index::read(&self.options.path)?;
+        let repodata_path = self.options.path.join("repodata");
+        let tag = Tag::new(name, &repodata_path, &index.primary)?;
+        tag.write(&repodata_path, force, &self.config.permissions)
+    }
+
+    pub fn tags(&self) -> Result<Vec<TagSummary>> {
+        let _lock = self.read_lock()?;
+        Tag::list(&self.options.path.join("repodata"))
+    }
+
+    pub fn read_tag(&self, name: &str) -> Result<Tag> {
+        let _lock = self.read_lock()?;
+        Tag::read(&self.options.path.join("repodata"), name)
+    }
+}
@@ -0,0 +1,147 @@
+//! Deadline of processing single package. System calls blocked on hung storage (e.g. NFS mount)
+//! can't be cancelled, so work is done in helper threads which are abandoned on timeout. Number
+//! of helper threads is fixed, and no more work is scheduled onto mount where work timed out, so
+//! hung storage doesn't leak a thread per package
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use anyhow::{anyhow, bail, Result};
+use slog_scope::warn;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Pool of helper threads
+pub struct Helpers {
+    jobs: Mutex<mpsc::Sender<Job>>,
+    /// Mount points where work timed out
+    stuck: Mutex<Vec<std::path::PathBuf>>,
+}
+
+impl Helpers {
+    pub fn new(threads: usize) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for n in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            let _ = std::thread::Builder::new()
+                .name(format!("rpm-tool-helper-{}", n))
+                .spawn(move || loop {
+                    // Idle helpers exit when pool is dropped, blocked ones are abandoned
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(v) => v,
+                        Err(_) => return,
+                    };
+                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+                })
+                .map_err(|err| anyhow!("Cannot start helper thread: {}", err))?;
+        }
+        Ok(Self {
+            jobs: Mutex::new(sender),
+            stuck: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Run `f` reading `path` in helper thread and wait for it until `deadline`. Fails at once if
+    /// work on the same mount already timed out
+    pub fn run<T, F>(
+        &self,
+        deadline: std::time::Instant,
+        path: &std::path::Path,
+        what: &str,
+        f: F,
+    ) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        {
+            let stuck = self.stuck.lock().unwrap();
+            if !stuck.is_empty() {
+                let mount = crate::platform::mount_point(path);
+                if stuck.contains(&mount) {
+                    bail!("{} skipped, earlier work on {:?} timed out", what, mount)
+                }
+            }
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let started = Arc::new(AtomicBool::new(false));
+        let job_started = started.clone();
+        let logger = slog_scope::logger();
+        let job: Job = Box::new(move || {
+            // Waiting is over if job was queued behind blocked helpers until deadline
+            if std::time::Instant::now() >= deadline {
+                return;
+            }
+            job_started.store(true, Ordering::SeqCst);
+            let r = slog_scope::scope(&logger, f);
+            // Receiver is gone if waiting timed out
+            let _ = sender.send(r);
+        });
+        self.jobs
+            .lock()
+            .unwrap()
+            .send(job)
+            .map_err(|_| anyhow!("Cannot run {}: helper threads are stopped", what))?;
+
+        let timeout = deadline.saturating_duration_since(std::time::Instant::now());
+        match receiver.recv_timeout(timeout) {
+            Ok(r) => r,
+            Err(mpsc::RecvTimeoutError::Timeout) if !started.load(Ordering::SeqCst) => {
+                bail!("{} timed out waiting for free helper thread", what)
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let mount = crate::platform::mount_point(path);
+                let mut stuck = self.stuck.lock().unwrap();
+                if !stuck.contains(&mount) {
+                    warn!(
+                        "{} timed out, other packages on {:?} are not read in this run",
+                        what, mount
+                    );
+                    stuck.push(mount)
+                }
+                bail!("{} timed out, package processing timeout is exceeded", what)
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => bail!("{} panicked", what),
+        }
+    }
+}
+
+/// Run `f` in helper thread of `helpers` if package processing has deadline, in current thread
+/// otherwise
+pub fn run<T, F>(
+    helpers: Option<&Helpers>,
+    deadline: Option<std::time::Instant>,
+    path: &std::path::Path,
+    what: &str,
+    f: F,
+) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    match (helpers, deadline) {
+        (Some(helpers), Some(deadline)) => helpers.run(deadline, path, what, f),
+        _ => f(),
+    }
+}
+
+#[test]
+fn test_run() {
+    let helpers = Helpers::new(1).unwrap();
+    let path = std::path::Path::new("/proc/self/status");
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(100);
+    assert_eq!(helpers.run(deadline, path, "Quick", || Ok(1)).unwrap(), 1);
+
+    let r = helpers.run(deadline, path, "Hung", || {
+        std::thread::sleep(std::time::Duration::from_secs(5));
+        Ok(())
+    });
+    assert!(r.unwrap_err().to_string().contains("timed out"));
+
+    // Mount of timed out work is not used anymore, no thread is taken by the attempt
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let r = helpers.run(deadline, path, "Quick", || Ok(1));
+    assert!(r.unwrap_err().to_string().contains("skipped"));
+}
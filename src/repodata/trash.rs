@@ -0,0 +1,136 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::{info, warn};
+
+/// Directory in repository root where removed packages are kept, skipped by scans
+pub const TRASH_DIR: &str = ".trash";
+
+fn default_retention() -> u64 {
+    30 * 86400
+}
+
+/// Soft deletion of packages removed by "repository remove"
+#[derive(Serialize, Deserialize)]
+pub struct TrashConfig {
+    /// Seconds removed packages are kept before "repository purge-trash" deletes them
+    #[serde(default = "default_retention")]
+    pub retention: u64,
+}
+
+/// Move file into .trash/<UNIX timestamp>/<relative path>
+pub fn move_to_trash(
+    root: &std::path::Path,
+    relative_path: &std::path::Path,
+    now: u64,
+) -> Result<()> {
+    let target = root
+        .join(TRASH_DIR)
+        .join(now.to_string())
+        .join(relative_path);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| anyhow!("Cannot create {:?}: {}", parent, err))?;
+    }
+    info!("Moving {:?} to {:?}", relative_path, target);
+    std::fs::rename(root.join(relative_path), &target)
+        .map_err(|err| anyhow!("Cannot move {:?} to trash: {}", relative_path, err))
+}
+
+/// Delete batches of trash older than `retention` seconds, all of them if `retention` is None.
+/// Returns number of deleted batches
+pub fn purge(root: &std::path::Path, retention: Option<u64>, now: u64) -> Result<usize> {
+    let trash_path = root.join(TRASH_DIR);
+    if !trash_path.exists() {
+        return Ok(0);
+    }
+    let mut purged = 0;
+    for entry in std::fs::read_dir(&trash_path)
+        .map_err(|err| anyhow!("Cannot read {:?}: {}", trash_path, err))?
+    {
+        let path = entry?.path();
+        let since: u64 = match path.file_name().and_then(|v| v.to_str()).map(str::parse) {
+            Some(Ok(v)) => v,
+            _ => {
+                warn!("Unexpected entry {:?} in trash, skipping", path);
+                continue;
+            }
+        };
+        if let Some(retention) = retention {
+            if now.saturating_sub(since) < retention {
+                continue;
+            }
+        }
+        info!("Deleting {:?}", path);
+        std::fs::remove_dir_all(&path)
+            .map_err(|err| anyhow!("Cannot delete {:?}: {}", path, err))?;
+        purged += 1;
+    }
+    Ok(purged)
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Remove packages with name or NEVRA matching one of patterns from metadata, then move their files
+    /// to trash if repodata→trash is configured and `use_trash` is set, or delete them
+    pub fn remove(&self, patterns: &[crate::glob::Glob], use_trash: bool) -> Result<()> {
+        let state = crate::repodata::State::new(self.config, &self.options)?;
+        state.restore_current();
+
+        let removed_packages = state.drain_packages(|package| {
+            let name = std::path::PathBuf::from(&package.name.value);
+            let nevra = std::path::PathBuf::from(package.nevra());
+            patterns
+                .iter()
+                .any(|glob| glob.is_match(&name) || glob.is_match(&nevra))
+        });
+        if removed_packages.is_empty() {
+            bail!("No packages matched")
+        }
+        for package in &removed_packages {
+            info!("Removing {} ({})", package.nevra(), package.location.href);
+        }
+        state.finish()?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let use_trash = use_trash && self.config.trash.is_some();
+        for package in &removed_packages {
+            let relative_path = std::path::PathBuf::from(&package.location.href);
+            if relative_path
+                .components()
+                .any(|v| !matches!(v, std::path::Component::Normal(_)))
+            {
+                warn!(
+                    "Package {:?} is outside of repository, file is left in place",
+                    relative_path
+                );
+                continue;
+            }
+            if use_trash {
+                crate::repodata::trash::move_to_trash(&self.options.path, &relative_path, now)?;
+            } else {
+                info!("Deleting {:?}", relative_path);
+                std::fs::remove_file(self.options.path.join(&relative_path))
+                    .map_err(|err| anyhow!("Cannot delete {:?}: {}", relative_path, err))?;
+            }
+            self.remove_empty_parents(&relative_path);
+        }
+        info!("Removed {} packages", removed_packages.len());
+        Ok(())
+    }
+
+    /// Delete packages kept in trash longer than repodata→trash→retention, or all of them
+    pub fn purge_trash(&self, all: bool) -> Result<()> {
+        let retention = match (&self.config.trash, all) {
+            (_, true) => None,
+            (Some(v), false) => Some(v.retention),
+            (None, false) => bail!("Trash is not configured, use --all to empty it"),
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let purged = crate::repodata::trash::purge(&self.options.path, retention, now)?;
+        info!("Purged {} batches of removed packages", purged);
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+fn default_packages_dir() -> String {
+    "Packages".to_owned()
+}
+
+/// Description of installable tree written to .treeinfo (productmd format 1.2)
+#[derive(Serialize, Deserialize)]
+pub struct TreeinfoConfig {
+    /// Release name, e.g. "CentOS Stream"
+    pub name: String,
+    /// Short release name, e.g. "CentOS"
+    pub short: String,
+    pub version: String,
+    pub arch: String,
+    pub variant: String,
+    #[serde(default = "default_packages_dir")]
+    pub packages: String,
+    /// Boot images by platform, e.g. x86_64: {kernel: images/pxeboot/vmlinuz}. Paths are relative to
+    /// repository root
+    #[serde(default)]
+    pub images: BTreeMap<String, BTreeMap<String, String>>,
+    /// Installer runtime image, e.g. images/install.img
+    #[serde(default)]
+    pub stage2: Option<String>,
+}
+
+impl TreeinfoConfig {
+    fn render(&self, root: &std::path::Path, timestamp: u64) -> Result<String> {
+        let mut checksums = BTreeMap::new();
+        let files = self
+            .images
+            .values()
+            .flat_map(|v| v.values())
+            .chain(self.stage2.iter())
+            .map(|v| v.as_str())
+            .chain(std::iter::once("repodata/repomd.xml"));
+        for file in files {
+            let path = root.join(file);
+            let checksum = crate::digest::path_sha256(&path)
+                .map_err(|err| anyhow!("Cannot calculate checksum of {:?}: {}", path, err))?;
+            let _ = checksums.insert(file, checksum);
+        }
+
+        let platforms: Vec<_> = self.images.keys().map(|v| v.as_str()).collect();
+
+        let mut r = String::new();
+        writeln!(r, "[header]\ntype = productmd.treeinfo\nversion = 1.2\n")?;
+        writeln!(
+            r,
+            "[release]\nname = {}\nshort = {}\nversion = {}\n",
+            self.name, self.short, self.version
+        )?;
+        writeln!(
+            r,
+            "[tree]\narch = {}\nbuild_timestamp = {}\nplatforms = {}\nvariants = {}\n",
+            self.arch,
+            timestamp,
+            platforms.join(","),
+            self.variant
+        )?;
+        writeln!(
+            r,
+            "[variant-{0}]\nid = {0}\nname = {0}\npackages = {1}\nrepository = .\ntype = variant\nuid = {0}\n",
+            self.variant, self.packages
+        )?;
+        if let Some(stage2) = &self.stage2 {
+            writeln!(r, "[stage2]\nmainimage = {}\n", stage2)?;
+        }
+        for (platform, images) in &self.images {
+            writeln!(r, "[images-{}]", platform)?;
+            for (image_type, path) in images {
+                writeln!(r, "{} = {}", image_type, path)?;
+            }
+            writeln!(r)?;
+        }
+        writeln!(r, "[checksums]")?;
+        for (file, checksum) in checksums {
+            writeln!(r, "{} = sha256:{}", file, checksum)?;
+        }
+
+        Ok(r)
+    }
+
+    /// Write .treeinfo to repository root, must be called after repodata is published
+    pub fn write(
+        &self,
+        root: &std::path::Path,
+        timestamp: u64,
+        permissions: &crate::repodata::permissions::PermissionsConfig,
+    ) -> Result<()> {
+        let content = self.render(root, timestamp)?;
+        let path = root.join(".treeinfo");
+        let tmp_path = root.join(".treeinfo.tmp");
+        info!("Writing {:?}", path);
+        std::fs::write(&tmp_path, content)
+            .map_err(|err| anyhow!("Cannot write {:?}: {}", tmp_path, err))?;
+        permissions.apply_file(&tmp_path)?;
+        std::fs::rename(&tmp_path, &path)
+            .map_err(|err| anyhow!("Cannot replace {:?}: {}", path, err))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct UpdatePackage {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(default, rename = "@epoch")]
+    pub epoch: Option<u32>,
+    #[serde(rename = "@version")]
+    pub version: String,
+    #[serde(rename = "@release")]
+    pub release: String,
+    #[serde(default, rename = "@arch")]
+    pub arch: Option<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Collection {
+    #[serde(default)]
+    pub package: Vec<UpdatePackage>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct PackageList {
+    #[serde(default)]
+    pub collection: Vec<Collection>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Reference {
+    #[serde(default, rename = "@id")]
+    pub id: Option<String>,
+    #[serde(default, rename = "@type")]
+    pub type_: Option<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Default)]
+pub struct References {
+    #[serde(default)]
+    pub reference: Vec<Reference>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Update {
+    #[serde(default, rename = "@type")]
+    pub type_: Option<String>,
+    pub id: String,
+    #[serde(default)]
+    pub severity: Option<String>,
+    #[serde(default)]
+    pub references: References,
+    #[serde(default)]
+    pub pkglist: PackageList,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename = "updates")]
+pub struct Updateinfo {
+    #[serde(default)]
+    pub update: Vec<Update>,
+}
+
+impl Updateinfo {
+    /// Security updates of updateinfo.xml
+    pub fn advisories(&self) -> Vec<Advisory> {
+        self.update
+            .iter()
+            .filter(|update| update.type_.as_deref() == Some("security"))
+            .map(|update| Advisory {
+                id: update.id.clone(),
+                severity: update.severity.clone(),
+                cves: update
+                    .references
+                    .reference
+                    .iter()
+                    .filter(|v| v.type_.as_deref() == Some("cve"))
+                    .filter_map(|v| v.id.clone())
+                    .collect(),
+                fixed: update
+                    .pkglist
+                    .collection
+                    .iter()
+                    .flat_map(|v| v.package.clone())
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Security advisory with versions fixing it, common for all supported feed formats
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Advisory {
+    pub id: String,
+    pub severity: Option<String>,
+    pub cves: Vec<String>,
+    /// Package without arch applies to all architectures
+    pub fixed: Vec<UpdatePackage>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum FeedFormat {
+    /// Detect by document content
+    Auto,
+    Updateinfo,
+    Oval,
+    Csaf,
+}
+
+impl FeedFormat {
+    fn detect(data: &[u8]) -> Result<Self> {
+        if data.iter().find(|v| !v.is_ascii_whitespace()) == Some(&b'{') {
+            return Ok(Self::Csaf);
+        }
+        let mut reader = quick_xml::Reader::from_reader(data);
+        let mut buf = Vec::new();
+        loop {
+            match reader
+                .read_event_into(&mut buf)
+                .map_err(|err| anyhow!("Cannot detect feed format: {}", err))?
+            {
+                quick_xml::events::Event::Start(e) | quick_xml::events::Event::Empty(e) => {
+                    return match e.local_name().as_ref() {
+                        b"updates" => Ok(Self::Updateinfo),
+                        b"oval_definitions" => Ok(Self::Oval),
+                        name => Err(anyhow!(
+                            "Unknown feed root element {:?}, expected updateinfo.xml, OVAL or CSAF",
+                            String::from_utf8_lossy(name)
+                        )),
+                    }
+                }
+                quick_xml::events::Event::Eof => bail!("Empty feed document"),
+                _ => (),
+            }
+            buf.clear();
+        }
+    }
+}
+
+/// Read advisories of updateinfo.xml, OVAL definitions or CSAF document. Compressed documents are
+/// decompressed transparently
+pub fn read_feed(path: &std::path::Path, format: FeedFormat) -> Result<Vec<Advisory>> {
+    info!("Reading security feed from {:?}", path);
+    let file = std::fs::File::open(path)?;
+    let mut reader = crate::decompress::decompress(std::io::BufReader::new(file))?;
+    let mut data = Vec::new();
+    let _ = std::io::Read::read_to_end(&mut reader, &mut data)
+        .map_err(|err| anyhow!("Cannot read {:?}: {}", path, err))?;
+
+    let format = match format {
+        FeedFormat::Auto => FeedFormat::detect(&data)?,
+        v => v,
+    };
+    let r = match format {
+        FeedFormat::Updateinfo => quick_xml::de::from_reader::<_, Updateinfo>(data.as_slice())
+            .map_err(|err| anyhow!("{}", err))
+            .map(|v| v.advisories()),
+        FeedFormat::Oval => crate::repodata::oval::advisories(&data),
+        FeedFormat::Csaf => crate::repodata::csaf::advisories(&data),
+        FeedFormat::Auto => unreachable!(),
+    }
+    .map_err(|err| anyhow!("Cannot parse {:?} as {:?} feed: {}", path, format, err))?;
+    info!("Got {} security advisories", r.len());
+    Ok(r)
+}
+
+#[derive(Serialize, Debug)]
+pub struct Vulnerability {
+    pub package: String,
+    pub advisory: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    pub cves: Vec<String>,
+    pub fixed_version: String,
+    /// Fixed version is present in repository
+    pub fixed_available: bool,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct VulnerabilityReport {
+    pub affected_packages: usize,
+    pub vulnerabilities: Vec<Vulnerability>,
+}
+
+impl VulnerabilityReport {
+    /// Match packages against security advisories. Package is affected if advisory lists newer
+    /// version of package with the same name and architecture
+    pub fn of_primary(
+        primary: &crate::repodata::primary::Primary,
+        advisories: &[Advisory],
+    ) -> Self {
+        let mut report = Self::default();
+        let mut affected = std::collections::HashSet::new();
+
+        for advisory in advisories {
+            for fixed in &advisory.fixed {
+                let fixed_evr = crate::evr::Evr {
+                    epoch: fixed.epoch.unwrap_or_default(),
+                    version: fixed.version.clone(),
+                    release: Some(fixed.release.clone()),
+                };
+                let same_package = |package: &&crate::repodata::primary::Package| {
+                    package.name.value == fixed.name
+                        && (fixed.arch.is_none()
+                            || package.arch.as_ref().map(|v| v.value.as_str())
+                                == fixed.arch.as_deref())
+                };
+                let fixed_available = primary.package.iter().filter(same_package).any(|v| {
+                    crate::evr::Evr::from(&v.version).compare(&fixed_evr)
+                        != std::cmp::Ordering::Less
+                });
+
+                for package in primary.package.iter().filter(same_package) {
+                    if crate::evr::Evr::from(&package.version).compare(&fixed_evr)
+                        != std::cmp::Ordering::Less
+                    {
+                        continue;
+                    }
+                    let _ = affected.insert(package.nevra());
+                    report.vulnerabilities.push(Vulnerability {
+                        package: package.nevra(),
+                        advisory: advisory.id.clone(),
+                        severity: advisory.severity.clone(),
+                        cves: advisory.cves.clone(),
+                        fixed_version: fixed_evr.to_string(),
+                        fixed_available,
+                    })
+                }
+            }
+        }
+
+        report.affected_packages = affected.len();
+        report
+            .vulnerabilities
+            .sort_by(|a, b| (&a.package, &a.advisory).cmp(&(&b.package, &b.advisory)));
+        report
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    pub fn vulnerability_report(
+        &self,
+        feed_path: &std::path::Path,
+        feed_format: FeedFormat,
+    ) -> Result<VulnerabilityReport> {
+        let index = self.read_index()?;
+        let advisories = crate::repodata::updateinfo::read_feed(feed_path, feed_format)?;
+        Ok(VulnerabilityReport::of_primary(&index.primary, &advisories))
+    }
+}
+
+#[test]
+fn test_de_updateinfo() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<updates>
+  <update from="security@example.com" status="stable" type="security" version="2.0">
+    <id>RHSA-2023:0001</id>
+    <title>foo security update</title>
+    <severity>Important</severity>
+    <references>
+      <reference href="https://example.com/CVE-2023-0001" id="CVE-2023-0001" type="cve" title="CVE-2023-0001"/>
+    </references>
+    <pkglist>
+      <collection short="el8">
+        <name>EL8</name>
+        <package name="foo" version="1.2" release="3.el8" epoch="0" arch="x86_64" src="foo-1.2-3.el8.src.rpm">
+          <filename>foo-1.2-3.el8.x86_64.rpm</filename>
+        </package>
+      </collection>
+    </pkglist>
+  </update>
+</updates>"#;
+    let updateinfo: Updateinfo = quick_xml::de::from_str(xml).unwrap();
+    assert_eq!(updateinfo.update.len(), 1);
+    let update = &updateinfo.update[0];
+    assert_eq!(update.id, "RHSA-2023:0001");
+    assert_eq!(
+        update.references.reference[0].id.as_deref(),
+        Some("CVE-2023-0001")
+    );
+    assert_eq!(update.pkglist.collection[0].package[0].release, "3.el8");
+}
+
+#[test]
+fn test_vulnerability_report() {
+    let primary = crate::repodata::primary::test_primary(&[
+        crate::repodata::primary::TestPackage::new("foo", "1.2").rel("1.el8"),
+        crate::repodata::primary::TestPackage::new("foo", "1.2").rel("3.el8"),
+        crate::repodata::primary::TestPackage::new("bar", "2.0").arch("noarch"),
+    ]);
+    let fixed = |name: &str, version: &str, release: &str, arch: Option<&str>| UpdatePackage {
+        name: name.to_owned(),
+        epoch: None,
+        version: version.to_owned(),
+        release: release.to_owned(),
+        arch: arch.map(|v| v.to_owned()),
+    };
+    let advisories = [
+        Advisory {
+            id: "RHSA-2023:0001".to_owned(),
+            severity: Some("Important".to_owned()),
+            cves: vec!["CVE-2023-0001".to_owned()],
+            fixed: vec![fixed("foo", "1.2", "3.el8", Some("x86_64"))],
+        },
+        Advisory {
+            id: "RHSA-2023:0002".to_owned(),
+            severity: None,
+            cves: vec![],
+            fixed: vec![fixed("bar", "2.1", "1", None)],
+        },
+    ];
+
+    let report = VulnerabilityReport::of_primary(&primary, &advisories);
+    assert_eq!(report.affected_packages, 2);
+    let found: Vec<_> = report
+        .vulnerabilities
+        .iter()
+        .map(|v| (v.package.as_str(), v.advisory.as_str(), v.fixed_available))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("bar-2.0-1.noarch", "RHSA-2023:0002", false),
+            ("foo-1.2-1.el8.x86_64", "RHSA-2023:0001", true),
+        ]
+    );
+}
+
+#[test]
+fn test_feed_format_detect() {
+    assert_eq!(
+        FeedFormat::detect(b"<?xml version=\"1.0\"?>\n<updates/>").unwrap(),
+        FeedFormat::Updateinfo
+    );
+    assert_eq!(
+        FeedFormat::detect(b"<oval_definitions xmlns=\"http://oval.mitre.org/XMLSchema/oval-definitions-5\"></oval_definitions>").unwrap(),
+        FeedFormat::Oval
+    );
+    assert_eq!(
+        FeedFormat::detect(b"  {\"document\": {}}").unwrap(),
+        FeedFormat::Csaf
+    );
+    assert!(FeedFormat::detect(b"<html/>").is_err());
+}
@@ -0,0 +1,183 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::info;
+
+fn default_layout() -> String {
+    "Packages/{first_letter}/{name}-{version}-{release}.{arch}.rpm".to_owned()
+}
+
+/// Placement of packages added by "repository upload"
+#[derive(Serialize, Deserialize)]
+pub struct UploadConfig {
+    /// Path of package relative to repository root. Placeholders: {name}, {version}, {release},
+    /// {arch} ("src" for source packages), {first_letter} (lowercase first letter of name) and {file_name} (name of uploaded file)
+    #[serde(default = "default_layout")]
+    pub layout: String,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            layout: default_layout(),
+        }
+    }
+}
+
+impl UploadConfig {
+    /// Destination of package relative to repository root
+    pub fn location(
+        &self,
+        header: &rpm::Header<rpm::IndexTag>,
+        file_name: &str,
+    ) -> Result<std::path::PathBuf> {
+        let name = header
+            .get_name()
+            .map_err(|err| anyhow!("Cannot get package name: {}", err))?;
+        let version = crate::repodata::primary::PackageVersion::of_header(header)?;
+        // Source package has no source RPM, its {arch} is "src" like in its file name
+        let arch = match header.get_source_rpm() {
+            Ok(_) => header.get_arch().unwrap_or("noarch"),
+            Err(_) => "src",
+        };
+        let first_letter: String = name.chars().take(1).flat_map(char::to_lowercase).collect();
+        Self::render(
+            &self.layout,
+            &[
+                ("name", name),
+                ("version", &version.ver),
+                ("release", &version.rel),
+                ("arch", arch),
+                ("first_letter", &first_letter),
+                ("file_name", file_name),
+            ],
+        )
+    }
+
+    fn render(layout: &str, values: &[(&str, &str)]) -> Result<std::path::PathBuf> {
+        let mut r = layout.to_owned();
+        for (key, value) in values {
+            if value.contains('/') || *value == ".." {
+                bail!("Value {:?} of {{{}}} cannot be used in path", value, key)
+            }
+            r = r.replace(&format!("{{{}}}", key), value);
+        }
+        if let Some(pos) = r.find('{') {
+            bail!("Unknown placeholder in upload layout at {:?}", &r[pos..])
+        }
+        let path = std::path::PathBuf::from(r);
+        if path.is_absolute()
+            || path
+                .components()
+                .any(|v| matches!(v, std::path::Component::ParentDir))
+        {
+            bail!(
+                "Upload layout must give path inside repository, got {:?}",
+                path
+            )
+        }
+        Ok(path)
+    }
+}
+
+/// Copy file into repository under temporary name and rename it, so that partially written file is
+/// never seen as package. With `move_file` the source is removed
+pub fn place_file(
+    source: &std::path::Path,
+    target: &std::path::Path,
+    move_file: bool,
+) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| anyhow!("Cannot create {:?}: {}", parent, err))?;
+    }
+    if move_file && std::fs::rename(source, target).is_ok() {
+        return Ok(());
+    }
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| anyhow!("Path {:?} does not contain file name", target))?;
+    let tmp_path = target.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    if let Err(err) = std::fs::copy(source, &tmp_path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        bail!("Cannot copy {:?} to {:?}: {}", source, tmp_path, err)
+    }
+    std::fs::rename(&tmp_path, target)
+        .map_err(|err| anyhow!("Cannot rename {:?} to {:?}: {}", tmp_path, target, err))?;
+    if move_file {
+        std::fs::remove_file(source)
+            .map_err(|err| anyhow!("Cannot remove {:?}: {}", source, err))?;
+    }
+    Ok(())
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Validate external packages, place them into repository according to repodata→upload→layout and
+    /// index them. Nothing is copied if any of packages is invalid
+    pub fn upload(
+        &self,
+        files: &[std::path::PathBuf],
+        move_files: bool,
+        require_signature: bool,
+        force: bool,
+    ) -> Result<()> {
+        let verifier = if require_signature {
+            Some(self.config.gpg.verifier()?.rpm_verifier()?)
+        } else {
+            None
+        };
+
+        let mut targets = Vec::new();
+        for path in files {
+            let file_name = match path.file_name() {
+                Some(v) => v.to_string_lossy().to_string(),
+                None => bail!("Path {:?} does not contain file name", path),
+            };
+            if !file_name.to_lowercase().ends_with(".rpm") {
+                bail!("File {:?} does not have .rpm extension", path)
+            }
+            let rpm = crate::repodata::State::read_rpm(path)
+                .map_err(|err| anyhow!("Cannot read RPM file {:?}: {}", path, err))?;
+            if let Some(verifier) = &verifier {
+                verifier.verify(path)?;
+            }
+            let target = self
+                .config
+                .upload
+                .location(&rpm.metadata.header, &file_name)
+                .map_err(|err| anyhow!("Cannot get location of {:?}: {}", path, err))?;
+            if targets.contains(&target) {
+                bail!("Several packages are uploaded to {:?}", target)
+            }
+            if self.options.path.join(&target).exists() && !force {
+                bail!("{:?} already exists in repository", target)
+            }
+            targets.push(target);
+        }
+
+        for (path, target) in files.iter().zip(&targets) {
+            crate::platform::check_interrupted()?;
+            info!("Placing {:?} to {:?}", path, target);
+            crate::repodata::upload::place_file(path, &self.options.path.join(target), move_files)?;
+        }
+
+        self.add_files(&targets)
+    }
+}
+
+#[test]
+fn test_render() {
+    let values = [
+        ("name", "bash"),
+        ("version", "5.1"),
+        ("release", "1.el9"),
+        ("arch", "x86_64"),
+        ("first_letter", "b"),
+        ("file_name", "bash.rpm"),
+    ];
+    assert_eq!(
+        UploadConfig::render(&default_layout(), &values).unwrap(),
+        std::path::PathBuf::from("Packages/b/bash-5.1-1.el9.x86_64.rpm")
+    );
+    assert!(UploadConfig::render("{arch}/{unknown}.rpm", &values).is_err());
+    assert!(UploadConfig::render("../{file_name}", &values).is_err());
+}
@@ -0,0 +1,188 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+
+/// Attribute of file which disagrees with metadata record
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub attribute: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileVerification {
+    /// Location href of file in metadata
+    pub location: String,
+    /// "package" or data type of repomd.xml record
+    pub record: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl FileVerification {
+    fn new(location: &str, record: &str) -> Self {
+        Self {
+            location: location.to_owned(),
+            record: record.to_owned(),
+            mismatches: Vec::new(),
+        }
+    }
+
+    fn check<T: ToString + PartialEq>(&mut self, attribute: &str, expected: T, actual: T) {
+        if expected != actual {
+            self.mismatches.push(Mismatch {
+                attribute: attribute.to_owned(),
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            })
+        }
+    }
+
+    fn check_digest(
+        &mut self,
+        attribute: &str,
+        checksum_type: &str,
+        expected: &str,
+        actual: impl FnOnce(crate::digest::DigestType) -> Result<String>,
+    ) -> Result<()> {
+        match crate::digest::DigestType::of_name(checksum_type) {
+            Some(v) => self.check(attribute, expected.to_owned(), actual(v)?),
+            None => self.mismatches.push(Mismatch {
+                attribute: format!("{} type", attribute),
+                expected: "sha, sha1, sha256 or sha512".to_owned(),
+                actual: checksum_type.to_owned(),
+            }),
+        }
+        Ok(())
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    /// Package file against its record in primary.xml. Modification time is recorded as time of
+    /// file
+    pub fn package(
+        path: &std::path::Path,
+        package: &crate::repodata::primary::Package,
+    ) -> Result<Self> {
+        let metadata = path
+            .metadata()
+            .map_err(|err| anyhow!("Cannot read metadata of {:?}: {}", path, err))?;
+        let mut r = Self::new(&package.location.href, "package");
+        r.check(
+            "size",
+            package.size.package,
+            crate::platform::file_size(&metadata),
+        );
+        r.check(
+            "mtime",
+            package.time.file,
+            crate::platform::file_mtime(&metadata),
+        );
+        r.check_digest(
+            "checksum",
+            &package.checksum.type_,
+            &package.checksum.value,
+            |digest_type| Ok(crate::digest::path_digests(path, &[digest_type])?.remove(0)),
+        )?;
+        Ok(r)
+    }
+
+    /// Metadata file against its record in repomd.xml. Timestamp of record is not compared with
+    /// mtime, it may be set from packages or command line
+    pub fn data(path: &std::path::Path, data: &crate::repodata::repomd::Data) -> Result<Self> {
+        let metadata = path
+            .metadata()
+            .map_err(|err| anyhow!("Cannot read metadata of {:?}: {}", path, err))?;
+        let mut r = Self::new(&data.location.href, data.type_.as_str());
+        r.check("size", data.size, crate::platform::file_size(&metadata));
+        r.check_digest(
+            "checksum",
+            &data.checksum.type_,
+            &data.checksum.value,
+            |digest_type| Ok(crate::digest::path_digests(path, &[digest_type])?.remove(0)),
+        )?;
+
+        if data.open_checksum.is_none() && data.open_size.is_none() {
+            return Ok(r);
+        }
+        let open_checksum_type = data
+            .open_checksum
+            .as_ref()
+            .and_then(|v| crate::digest::DigestType::of_name(&v.type_))
+            .unwrap_or(crate::digest::DigestType::Sha1);
+        let file = std::fs::File::open(path)?;
+        let mut reader = crate::decompress::decompress(std::io::BufReader::new(file))?;
+        let mut writer = crate::digest::DigestWriter::new(std::io::sink(), open_checksum_type);
+        let _ = std::io::copy(&mut reader, &mut writer)
+            .map_err(|err| anyhow!("Cannot decompress {:?}: {}", path, err))?;
+        let (open_checksum, open_size) = writer.digest().result();
+
+        if let Some(expected) = data.open_size {
+            r.check("open-size", expected, open_size)
+        }
+        if let Some(expected) = &data.open_checksum {
+            r.check_digest("open-checksum", &expected.type_, &expected.value, |_| {
+                Ok(open_checksum)
+            })?
+        }
+        Ok(r)
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Find metadata record of single file and compare checksum, size and mtime of file with it.
+    /// Path is relative to repository root or starts with repository path
+    pub fn verify_file(&self, path: &std::path::Path) -> Result<FileVerification> {
+        let _lock = self.read_lock()?;
+        let href = match path.strip_prefix(&self.options.path) {
+            Ok(v) => v,
+            Err(_) if path.is_relative() => path,
+            Err(_) => bail!(
+                "{:?} is not inside repository {:?}",
+                path,
+                self.options.path
+            ),
+        };
+        let href = href.to_string_lossy();
+        let full_path = self.options.path.join(href.as_ref());
+
+        let repomd = crate::repodata::repomd::Repomd::read(
+            &self.options.path.join("repodata").join("repomd.xml"),
+        )?;
+        if let Some(data) = repomd.data.iter().find(|v| v.location.href == href) {
+            return FileVerification::data(&full_path, data);
+        }
+
+        let primary_path = match repomd
+            .data
+            .iter()
+            .find(|v| v.type_ == crate::repodata::repomd::DataType::Primary)
+        {
+            Some(v) => self.options.path.join(&v.location.href),
+            None => bail!("No 'primary' record in repomd.xml"),
+        };
+        let primary = crate::repodata::primary::Primary::read(&primary_path)
+            .map_err(|err| anyhow!("Cannot read {:?}: {}", primary_path, err))?;
+        match primary.package.iter().find(|v| v.location.href == href) {
+            Some(package) => FileVerification::package(&full_path, package),
+            None => bail!("{} is not listed in metadata", href),
+        }
+    }
+}
+
+#[test]
+fn test_check() {
+    let mut r = FileVerification::new("foo.rpm", "package");
+    r.check("size", 10, 10);
+    assert!(r.is_ok());
+    r.check("mtime", 1, 2);
+    assert_eq!(
+        r.mismatches,
+        vec![Mismatch {
+            attribute: "mtime".to_owned(),
+            expected: "1".to_owned(),
+            actual: "2".to_owned(),
+        }]
+    );
+}
@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::Serialize;
+use slog_scope::warn;
+
+#[derive(Serialize, Debug)]
+pub struct RetainedVersions {
+    pub name: String,
+    pub arch: Option<String>,
+    /// Number of distinct versions in repository
+    pub versions: usize,
+    /// Packages older than kept versions
+    pub prunable: Vec<String>,
+    pub size: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DuplicatePackage {
+    pub checksum: String,
+    pub locations: Vec<String>,
+    /// Size of all copies except one
+    pub size: u64,
+}
+
+/// Regular file of package payload
+#[derive(Debug, Clone)]
+pub struct PackageFile {
+    pub path: String,
+    pub digest: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SharedFile {
+    pub digest: String,
+    pub file_size: u64,
+    /// Copies in form of "location:path"
+    pub copies: Vec<String>,
+    /// Size of all copies except one
+    pub size: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DelistedPackage {
+    pub location: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct WasteReport {
+    pub retained_versions: Vec<RetainedVersions>,
+    pub duplicates: Vec<DuplicatePackage>,
+    /// Byte-identical files shipped by several kept packages
+    pub shared_files: Vec<SharedFile>,
+    /// RPM files present in repository tree but not listed in metadata
+    pub delisted: Vec<DelistedPackage>,
+    /// Size of prunable, duplicate and delisted RPM files, every file is counted once
+    pub wasted_bytes: u64,
+    /// Uncompressed size of shared file copies. Not included in wasted_bytes: copies are parts of
+    /// packages and can't be removed separately
+    pub shared_bytes: u64,
+}
+
+/// Packages which stay after pruning: not outdated and one copy of every duplicate
+pub fn kept_packages(
+    primary: &crate::repodata::primary::Primary,
+    keep_versions: usize,
+) -> Vec<&crate::repodata::primary::Package> {
+    let outdated: HashSet<_> = primary
+        .outdated(keep_versions)
+        .into_iter()
+        .map(|v| v.location.href.as_str())
+        .collect();
+    let mut by_checksum: BTreeMap<_, &crate::repodata::primary::Package> = BTreeMap::new();
+    for package in &primary.package {
+        if outdated.contains(package.location.href.as_str()) {
+            continue;
+        }
+        let kept = by_checksum
+            .entry(package.checksum.value.as_str())
+            .or_insert(package);
+        if package.location.href < kept.location.href {
+            *kept = package
+        }
+    }
+    by_checksum.into_values().collect()
+}
+
+impl WasteReport {
+    /// `files` are locations of RPM files found in repository tree with their sizes, `contents`
+    /// are locations of kept packages with their payload files. Only `keep_versions` newest
+    /// versions of each package are considered useful
+    pub fn new(
+        primary: &crate::repodata::primary::Primary,
+        files: &[(String, u64)],
+        contents: &[(String, Vec<PackageFile>)],
+        keep_versions: usize,
+    ) -> Self {
+        let mut report = Self::default();
+
+        let mut by_checksum: BTreeMap<_, Vec<&crate::repodata::primary::Package>> = BTreeMap::new();
+        let mut versions: BTreeMap<_, Vec<crate::evr::Evr>> = BTreeMap::new();
+        for package in &primary.package {
+            let evr = crate::evr::Evr::from(&package.version);
+            let known = versions
+                .entry((
+                    package.name.value.as_str(),
+                    package.arch.as_ref().map(|v| v.value.as_str()),
+                ))
+                .or_default();
+            if !known
+                .iter()
+                .any(|v| v.compare(&evr) == std::cmp::Ordering::Equal)
+            {
+                known.push(evr)
+            }
+            by_checksum
+                .entry(package.checksum.value.as_str())
+                .or_default()
+                .push(package);
+        }
+
+        let mut outdated: BTreeMap<_, Vec<&crate::repodata::primary::Package>> = BTreeMap::new();
+        for package in primary.outdated(keep_versions) {
+            outdated
+                .entry((
+                    package.name.value.as_str(),
+                    package.arch.as_ref().map(|v| v.value.as_str()),
+                ))
+                .or_default()
+                .push(package);
+        }
+        for ((name, arch), prunable) in outdated {
+            report.retained_versions.push(RetainedVersions {
+                name: name.to_owned(),
+                arch: arch.map(|v| v.to_owned()),
+                versions: versions.get(&(name, arch)).map_or(0, |v| v.len()),
+                prunable: prunable.iter().map(|v| v.location.href.clone()).collect(),
+                size: prunable.iter().map(|v| v.size.package).sum(),
+            })
+        }
+
+        for (checksum, mut packages) in by_checksum {
+            if packages.len() < 2 {
+                continue;
+            }
+            // The first location is kept, the same way kept_packages() does
+            packages.sort_by(|a, b| a.location.href.cmp(&b.location.href));
+            report.duplicates.push(DuplicatePackage {
+                checksum: checksum.to_owned(),
+                locations: packages.iter().map(|v| v.location.href.clone()).collect(),
+                size: packages.iter().skip(1).map(|v| v.size.package).sum(),
+            })
+        }
+
+        let mut by_digest: BTreeMap<_, Vec<(&str, &PackageFile)>> = BTreeMap::new();
+        for (location, package_files) in contents {
+            for file in package_files {
+                by_digest
+                    .entry(file.digest.as_str())
+                    .or_default()
+                    .push((location, file));
+            }
+        }
+        for (digest, copies) in by_digest {
+            let packages: BTreeSet<_> = copies.iter().map(|(location, _)| location).collect();
+            if packages.len() < 2 {
+                continue;
+            }
+            let file_size = copies[0].1.size;
+            let mut copies: Vec<_> = copies
+                .iter()
+                .map(|(location, file)| format!("{}:{}", location, file.path))
+                .collect();
+            copies.sort();
+            report.shared_files.push(SharedFile {
+                digest: digest.to_owned(),
+                file_size,
+                size: file_size * (copies.len() as u64 - 1),
+                copies,
+            })
+        }
+        report
+            .shared_files
+            .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.digest.cmp(&b.digest)));
+
+        let listed: HashSet<_> = primary
+            .package
+            .iter()
+            .map(|v| v.location.href.as_str())
+            .collect();
+        for (location, size) in files {
+            if !listed.contains(location.as_str()) {
+                report.delisted.push(DelistedPackage {
+                    location: location.clone(),
+                    size: *size,
+                })
+            }
+        }
+        report.delisted.sort_by(|a, b| a.location.cmp(&b.location));
+
+        // Copies of outdated package are both prunable and duplicate
+        let sizes: HashMap<_, _> = primary
+            .package
+            .iter()
+            .map(|v| (v.location.href.as_str(), v.size.package))
+            .collect();
+        let wasted: HashSet<_> = report
+            .retained_versions
+            .iter()
+            .flat_map(|v| &v.prunable)
+            .chain(
+                report
+                    .duplicates
+                    .iter()
+                    .flat_map(|v| v.locations.iter().skip(1)),
+            )
+            .map(|v| v.as_str())
+            .collect();
+        report.wasted_bytes = wasted
+            .iter()
+            .map(|v| sizes.get(v).copied().unwrap_or_default())
+            .chain(report.delisted.iter().map(|v| v.size))
+            .sum();
+        report.shared_bytes = report.shared_files.iter().map(|v| v.size).sum();
+
+        report
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    pub fn waste_report(&self, keep_versions: usize, min_shared_size: u64) -> Result<WasteReport> {
+        let index = self.read_index()?;
+        let files = self
+            .scan()?
+            .packages
+            .iter()
+            .filter_map(|path| {
+                let relative_path = path.strip_prefix(&self.options.path).ok()?;
+                match path.metadata() {
+                    Ok(v) => Some((
+                        relative_path.to_string_lossy().to_string(),
+                        crate::platform::file_size(&v),
+                    )),
+                    Err(err) => {
+                        warn!("Cannot read metadata of {:?}: {}", path, err);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let kept = match min_shared_size {
+            0 => Vec::new(),
+            _ => crate::repodata::waste::kept_packages(&index.primary, keep_versions),
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.concurrency())
+            .build()
+            .unwrap();
+        let contents = pool.install(|| {
+            kept.par_iter()
+                .filter_map(|package| {
+                    let path = self.options.path.join(&package.location.href);
+                    let rpm = match crate::repodata::State::read_rpm(&path) {
+                        Ok(v) => v,
+                        Err(err) => {
+                            warn!("Cannot read {:?}: {}", path, err);
+                            return None;
+                        }
+                    };
+                    let files = match crate::rpm_dump::FileDetails::of_package(&rpm) {
+                        Ok(v) => v,
+                        Err(err) => {
+                            warn!("Cannot read files of {:?}: {}", path, err);
+                            return None;
+                        }
+                    };
+                    let files = files
+                        .into_iter()
+                        .filter(|v| v.is_payload_file() && v.size >= min_shared_size)
+                        .filter_map(|v| {
+                            Some(PackageFile {
+                                digest: v.digest?,
+                                path: v.path.to_string_lossy().to_string(),
+                                size: v.size,
+                            })
+                        })
+                        .collect();
+                    Some((package.location.href.clone(), files))
+                })
+                .collect::<Vec<_>>()
+        });
+
+        Ok(WasteReport::new(
+            &index.primary,
+            &files,
+            &contents,
+            keep_versions,
+        ))
+    }
+}
+
+#[cfg(test)]
+fn test_primary(packages: &[(&str, &str, &str, u64)]) -> crate::repodata::primary::Primary {
+    // (name, version, checksum, size)
+    let packages: Vec<_> = packages
+        .iter()
+        .enumerate()
+        .map(|(n, (name, ver, checksum, size))| {
+            crate::repodata::primary::TestPackage::new(name, ver)
+                .checksum(checksum)
+                .size(*size)
+                .href(&format!("{n}/{name}-{ver}-1.x86_64.rpm"))
+        })
+        .collect();
+    crate::repodata::primary::test_primary(&packages)
+}
+
+#[test]
+fn test_waste_report() {
+    let primary = test_primary(&[
+        ("foo", "1.0", "a", 100),
+        ("foo", "2.0", "b", 200),
+        // Outdated and duplicate at the same time
+        ("foo", "1.0", "a", 100),
+        ("bar", "1.0", "c", 1000),
+        ("baz", "1.0", "d", 10),
+    ]);
+    let files = [
+        ("0/foo-1.0-1.x86_64.rpm".to_owned(), 100),
+        ("orphan-1.0-1.x86_64.rpm".to_owned(), 5),
+    ];
+    let kept: Vec<_> = kept_packages(&primary, 1)
+        .iter()
+        .map(|v| v.location.href.clone())
+        .collect();
+    assert_eq!(
+        kept,
+        [
+            "1/foo-2.0-1.x86_64.rpm",
+            "3/bar-1.0-1.x86_64.rpm",
+            "4/baz-1.0-1.x86_64.rpm"
+        ]
+    );
+
+    let file = |path: &str, digest: &str, size: u64| PackageFile {
+        path: path.to_owned(),
+        digest: digest.to_owned(),
+        size,
+    };
+    let contents = [
+        (
+            "3/bar-1.0-1.x86_64.rpm".to_owned(),
+            vec![
+                file("/usr/share/bar/data", "x", 50),
+                file("/usr/bin/bar", "y", 7),
+            ],
+        ),
+        (
+            "4/baz-1.0-1.x86_64.rpm".to_owned(),
+            vec![file("/usr/share/baz/data", "x", 50)],
+        ),
+    ];
+
+    let report = WasteReport::new(&primary, &files, &contents, 1);
+    assert_eq!(report.retained_versions.len(), 1);
+    assert_eq!(report.retained_versions[0].versions, 2);
+    assert_eq!(
+        report.retained_versions[0].prunable,
+        ["0/foo-1.0-1.x86_64.rpm", "2/foo-1.0-1.x86_64.rpm"]
+    );
+    assert_eq!(report.duplicates.len(), 1);
+    assert_eq!(report.duplicates[0].size, 100);
+    assert_eq!(report.delisted.len(), 1);
+    assert_eq!(report.delisted[0].location, "orphan-1.0-1.x86_64.rpm");
+    // Both copies of foo-1.0 are counted once, plus the delisted file
+    assert_eq!(report.wasted_bytes, 205);
+
+    assert_eq!(report.shared_files.len(), 1);
+    assert_eq!(
+        report.shared_files[0].copies,
+        [
+            "3/bar-1.0-1.x86_64.rpm:/usr/share/bar/data",
+            "4/baz-1.0-1.x86_64.rpm:/usr/share/baz/data"
+        ]
+    );
+    assert_eq!(report.shared_bytes, 50);
+}
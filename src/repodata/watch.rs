@@ -0,0 +1,463 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::{debug, error, info};
+
+fn default_debounce() -> u64 {
+    5
+}
+
+fn default_quiet_period() -> u64 {
+    30
+}
+
+fn default_max_batch() -> usize {
+    500
+}
+
+/// Coalescing of changes of repository tree in watch mode, so that burst of uploads triggers single
+/// regeneration. Periods are in seconds
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WatchConfig {
+    /// Regeneration starts not earlier than this period after first detected change
+    #[serde(default = "default_debounce")]
+    pub debounce: u64,
+    /// Regeneration is postponed until tree stays unchanged for this period
+    #[serde(default = "default_quiet_period")]
+    pub quiet_period: u64,
+    /// Regenerate without waiting for quiet period when this number of files is changed
+    #[serde(default = "default_max_batch")]
+    pub max_batch: usize,
+    /// Period of scans of repository tree, used if --interval is not set
+    #[serde(default)]
+    pub interval: Option<u64>,
+    /// Cron expressions in UTC, metadata is regenerated at these times even if no changes are
+    /// detected
+    #[serde(default)]
+    pub schedule: Vec<Schedule>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            debounce: default_debounce(),
+            quiet_period: default_quiet_period(),
+            max_batch: default_max_batch(),
+            interval: None,
+            schedule: Vec::new(),
+        }
+    }
+}
+
+/// Parse interval like "90", "30s", "15m", "2h" or "1d". Number without suffix is in seconds
+pub fn parse_interval(v: &str) -> Result<std::time::Duration> {
+    let v = v.trim();
+    let (number, multiplier) = match v.char_indices().last() {
+        Some((pos, 's')) => (&v[..pos], 1),
+        Some((pos, 'm')) => (&v[..pos], 60),
+        Some((pos, 'h')) => (&v[..pos], 3600),
+        Some((pos, 'd')) => (&v[..pos], 86400),
+        _ => (v, 1),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|err| anyhow!("Invalid interval {:?}: {}", v, err))?;
+    if number == 0 {
+        bail!("Interval must be greater than zero")
+    }
+    let secs = number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow!("Interval {:?} is too large", v))?;
+    Ok(std::time::Duration::from_secs(secs))
+}
+
+/// Allowed values of one field of cron expression
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CronField {
+    values: Vec<bool>,
+    /// Field is "*" or "*/n"
+    any: bool,
+}
+
+impl CronField {
+    fn parse(v: &str, min: u32, max: u32) -> Result<Self> {
+        let mut values = vec![false; max as usize + 1];
+        let mut any = false;
+        for item in v.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (
+                    range,
+                    step.parse::<u32>()
+                        .ok()
+                        .filter(|v| *v > 0)
+                        .ok_or_else(|| anyhow!("Invalid step {:?}", step))?,
+                ),
+                None => (item, 1),
+            };
+            let parse = |v: &str| -> Result<u32> {
+                v.parse::<u32>()
+                    .ok()
+                    .filter(|v| (min..=max).contains(v))
+                    .ok_or_else(|| anyhow!("Value {:?} is not in range {}-{}", v, min, max))
+            };
+            let (first, last) = if range == "*" {
+                any = true;
+                (min, max)
+            } else {
+                match range.split_once('-') {
+                    Some((first, last)) => (parse(first)?, parse(last)?),
+                    // "a/n" is "a-max/n"
+                    None if step > 1 => (parse(range)?, max),
+                    None => (parse(range)?, parse(range)?),
+                }
+            };
+            if first > last {
+                bail!("Invalid range {:?}", range)
+            }
+            for v in (first..=last).step_by(step as usize) {
+                values[v as usize] = true
+            }
+        }
+        Ok(Self { values, any })
+    }
+
+    fn contains(&self, v: u32) -> bool {
+        self.values.get(v as usize).copied().unwrap_or(false)
+    }
+}
+
+/// Cron expression "minute hour day-of-month month day-of-week" evaluated in UTC. Fields accept
+/// "*", numbers, ranges "a-b", steps "*/n" or "a-b/n" and comma separated lists of them. Sunday is
+/// 0 or 7
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    expression: String,
+    minute: CronField,
+    hour: CronField,
+    day: CronField,
+    month: CronField,
+    weekday: CronField,
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = anyhow::Error;
+
+    fn try_from(expression: String) -> Result<Self> {
+        let fields: Vec<_> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            bail!(
+                "Cron expression {:?} must have 5 fields: minute hour day-of-month month day-of-week",
+                expression
+            )
+        };
+        let field = |v: &str, min, max| {
+            CronField::parse(v, min, max)
+                .map_err(|err| anyhow!("Invalid cron expression {:?}: {}", expression, err))
+        };
+        let mut weekday = field(weekday, 0, 7)?;
+        if weekday.values[7] {
+            weekday.values[0] = true
+        }
+        Ok(Self {
+            minute: field(minute, 0, 59)?,
+            hour: field(hour, 0, 23)?,
+            day: field(day, 1, 31)?,
+            month: field(month, 1, 12)?,
+            weekday,
+            expression,
+        })
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(v: Schedule) -> Self {
+        v.expression
+    }
+}
+
+/// Year, month and day of days since UNIX epoch
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl Schedule {
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 is Thursday
+        let weekday = (days + 4).rem_euclid(7) as u32;
+        if !self.month.contains(month) {
+            return false;
+        }
+        // Like in cron, restricted day-of-month and day-of-week match if either of them matches
+        match (self.day.any, self.weekday.any) {
+            (true, true) => true,
+            (false, true) => self.day.contains(day),
+            (true, false) => self.weekday.contains(weekday),
+            (false, false) => self.day.contains(day) || self.weekday.contains(weekday),
+        }
+    }
+
+    /// First matching minute after UNIX timestamp `after`, as UNIX timestamp. Searched within 5
+    /// years, so that impossible dates like 31 February give None
+    pub fn next(&self, after: u64) -> Option<u64> {
+        let mut minute = after / 60 + 1;
+        let end = minute + 5 * 366 * 24 * 60;
+        while minute < end {
+            let days = (minute / (24 * 60)) as i64;
+            if !self.matches_day(days) {
+                minute = (minute / (24 * 60) + 1) * 24 * 60;
+                continue;
+            }
+            if !self.hour.contains((minute / 60 % 24) as u32) {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+            if self.minute.contains((minute % 60) as u32) {
+                return Some(minute * 60);
+            }
+            minute += 1
+        }
+        None
+    }
+}
+
+/// Earliest time of scheduled regeneration after UNIX timestamp `after`
+pub fn next_scheduled(schedule: &[Schedule], after: u64) -> Option<u64> {
+    schedule.iter().filter_map(|v| v.next(after)).min()
+}
+
+/// Size and mtime of files found in repository tree
+pub struct Snapshot(HashMap<std::path::PathBuf, (u64, i64)>);
+
+impl Snapshot {
+    pub fn new<'a, I>(files: I) -> Self
+    where
+        I: Iterator<Item = &'a std::path::PathBuf>,
+    {
+        Self(
+            files
+                .filter_map(|path| {
+                    let metadata = std::fs::metadata(path).ok()?;
+                    Some((
+                        path.clone(),
+                        (
+                            crate::platform::file_size(&metadata),
+                            crate::platform::file_mtime(&metadata),
+                        ),
+                    ))
+                })
+                .collect(),
+        )
+    }
+
+    /// Files added, removed or modified since previous snapshot
+    pub fn changes(&self, previous: &Self) -> Vec<std::path::PathBuf> {
+        let mut r: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(path, v)| previous.0.get(*path) != Some(v))
+            .map(|(path, _)| path.clone())
+            .collect();
+        r.extend(
+            previous
+                .0
+                .keys()
+                .filter(|path| !self.0.contains_key(*path))
+                .cloned(),
+        );
+        r
+    }
+}
+
+/// Changes waiting for regeneration
+pub struct Pending {
+    first_change: std::time::Instant,
+    last_change: std::time::Instant,
+    pub files: HashSet<std::path::PathBuf>,
+}
+
+impl Pending {
+    pub fn new(now: std::time::Instant) -> Self {
+        Self {
+            first_change: now,
+            last_change: now,
+            files: HashSet::new(),
+        }
+    }
+
+    pub fn push(&mut self, now: std::time::Instant, files: Vec<std::path::PathBuf>) {
+        self.last_change = now;
+        self.files.extend(files);
+    }
+
+    pub fn is_ready(&self, config: &WatchConfig, now: std::time::Instant) -> bool {
+        if self.files.len() >= config.max_batch {
+            return true;
+        }
+        now.duration_since(self.first_change) >= std::time::Duration::from_secs(config.debounce)
+            && now.duration_since(self.last_change)
+                >= std::time::Duration::from_secs(config.quiet_period)
+    }
+}
+
+/// Sleep which is cut short by SIGINT or SIGTERM
+pub fn sleep(duration: std::time::Duration) -> Result<()> {
+    let deadline = std::time::Instant::now() + duration;
+    loop {
+        crate::platform::check_interrupted()?;
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return Ok(());
+        }
+        std::thread::sleep((deadline - now).min(std::time::Duration::from_secs(1)));
+    }
+}
+
+impl<'a> crate::repodata::Repodata<'a> {
+    /// Generate metadata, then rescan repository tree every `interval` and regenerate metadata when
+    /// changes are coalesced according to repodata→watch. `reload` is called before each scan and
+    /// returns new repodata→watch if configuration is reloaded. `health` tracks generations and
+    /// pending changes
+    pub fn watch(
+        &self,
+        interval: std::time::Duration,
+        reload: &mut dyn FnMut() -> Option<WatchConfig>,
+        health: &crate::repodata::health::Health,
+    ) -> Result<()> {
+        let mut config = self.config.watch.clone();
+        info!(
+            "Watching {:?}, scanning every {} secs",
+            self.options.path,
+            interval.as_secs()
+        );
+
+        let scan_snapshot = || -> Result<Snapshot> {
+            let scanned = self.scan()?;
+            Ok(Snapshot::new(
+                scanned.packages.iter().chain(scanned.deltas.iter()),
+            ))
+        };
+
+        let generate = || {
+            health.start();
+            #[cfg(feature = "otlp")]
+            let started = std::time::SystemTime::now();
+            let r = self.generate();
+            health.finish(&r);
+            #[cfg(feature = "otlp")]
+            if let Err(err) = crate::otlp::export(started, crate::exit_code::of_result(&r)) {
+                error!("Cannot export to OTLP collector: {}", err)
+            }
+            r
+        };
+
+        let unix_now = || {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|v| v.as_secs())
+                .unwrap_or_default()
+        };
+
+        let mut snapshot = scan_snapshot()?;
+        generate()?;
+        let mut pending: Option<Pending> = None;
+        let mut scheduled = crate::repodata::watch::next_scheduled(&config.schedule, unix_now());
+        loop {
+            let mut delay = match pending {
+                Some(_) => std::time::Duration::from_secs(config.debounce.max(1)).min(interval),
+                None => interval,
+            };
+            if let Some(v) = scheduled {
+                delay = delay.min(std::time::Duration::from_secs(v.saturating_sub(unix_now())))
+            }
+            crate::repodata::watch::sleep(delay)?;
+            if let Some(v) = reload() {
+                if v.schedule != config.schedule {
+                    scheduled = crate::repodata::watch::next_scheduled(&v.schedule, unix_now())
+                }
+                config = v;
+            }
+
+            // Tree may be temporarily unavailable, e.g. NFS mount is stale
+            let current = match scan_snapshot() {
+                Ok(v) => v,
+                Err(err) => {
+                    crate::platform::check_interrupted()?;
+                    error!("Cannot scan repository, retrying on next tick: {}", err);
+                    continue;
+                }
+            };
+            let changes = current.changes(&snapshot);
+            snapshot = current;
+            let now = std::time::Instant::now();
+            if !changes.is_empty() {
+                debug!("Detected changes of {} files", changes.len());
+                pending
+                    .get_or_insert_with(|| Pending::new(now))
+                    .push(now, changes);
+            }
+            health.set_pending(pending.as_ref().map_or(0, |v| v.files.len()));
+
+            let is_scheduled = scheduled.map_or(false, |v| unix_now() >= v);
+            let is_ready = pending.as_ref().map_or(false, |v| v.is_ready(&config, now));
+            if is_scheduled || is_ready {
+                match &pending {
+                    Some(v) if is_ready => info!(
+                        "Regenerating metadata after changes of {} files",
+                        v.files.len()
+                    ),
+                    _ => info!("Regenerating metadata by schedule"),
+                }
+                pending = None;
+                health.set_pending(0);
+                scheduled = crate::repodata::watch::next_scheduled(&config.schedule, unix_now());
+                if let Err(err) = generate() {
+                    crate::platform::check_interrupted()?;
+                    error!("Failed to regenerate metadata: {}", err);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_interval() {
+    assert_eq!(parse_interval("90").unwrap().as_secs(), 90);
+    assert_eq!(parse_interval("30s").unwrap().as_secs(), 30);
+    assert_eq!(parse_interval("15m").unwrap().as_secs(), 900);
+    assert_eq!(parse_interval("2h").unwrap().as_secs(), 7200);
+    assert_eq!(parse_interval("1d").unwrap().as_secs(), 86400);
+    assert!(parse_interval("0m").is_err());
+    assert!(parse_interval("15x").is_err());
+    assert!(parse_interval("18446744073709551615d").is_err());
+}
+
+#[test]
+fn test_schedule() {
+    let schedule = |v: &str| Schedule::try_from(v.to_owned()).unwrap();
+    // 2024-03-01 00:00:00 UTC, Friday
+    let t = 1709251200;
+    assert_eq!(schedule("*/15 * * * *").next(t), Some(t + 15 * 60));
+    assert_eq!(schedule("30 2 * * *").next(t), Some(t + 2 * 3600 + 30 * 60));
+    // Next Monday
+    assert_eq!(schedule("0 0 * * 1").next(t), Some(t + 3 * 86400));
+    // Sunday as 7
+    assert_eq!(schedule("0 0 * * 7").next(t), Some(t + 2 * 86400));
+    // Leap day of 2028
+    assert_eq!(schedule("0 0 29 2 *").next(t), Some(1835395200));
+    assert_eq!(schedule("0 0 31 2 *").next(t), None);
+    assert!(Schedule::try_from("* * *".to_owned()).is_err());
+    assert!(Schedule::try_from("60 * * * *".to_owned()).is_err());
+    assert!(Schedule::try_from("*/0 * * * *".to_owned()).is_err());
+}
@@ -0,0 +1,607 @@
+//! Details of RPM file shown by "rpm dump" in addition to primary metadata
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use slog_scope::warn;
+
+/// Names of RPMFILE_* flags
+const FILE_FLAGS: &[(i32, &str)] = &[
+    (1, "config"),
+    (1 << 1, "doc"),
+    (1 << 3, "missingok"),
+    (1 << 4, "noreplace"),
+    (1 << 5, "specfile"),
+    (1 << 6, "ghost"),
+    (1 << 7, "license"),
+    (1 << 8, "readme"),
+    (1 << 11, "pubkey"),
+    (1 << 12, "artifact"),
+];
+
+fn file_flags(flags: i32) -> Vec<&'static str> {
+    FILE_FLAGS
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// IMA signatures are stored in main header by modern rpm and in signature header by older one
+fn ima_signatures(pkg: &rpm::RPMPackage) -> &[String] {
+    pkg.metadata
+        .header
+        .get_file_signatures()
+        .or_else(|_| pkg.metadata.signature.get_file_ima_signatures())
+        .unwrap_or(&[])
+}
+
+fn verity_signatures(pkg: &rpm::RPMPackage) -> &[String] {
+    pkg.metadata
+        .signature
+        .get_file_verity_signatures()
+        .unwrap_or(&[])
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum FileSignatureKind {
+    Ima,
+    Verity,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileDetails {
+    pub path: std::path::PathBuf,
+    /// Hex encoded, algorithm is set per package. Missing for directories and special files
+    pub digest: Option<String>,
+    pub size: u64,
+    /// Octal mode including file type bits, e.g. "100755"
+    pub mode: String,
+    pub user: String,
+    pub group: String,
+    pub flags: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ima_signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verity_signature: Option<String>,
+    #[serde(skip)]
+    raw_mode: u16,
+}
+
+impl FileDetails {
+    pub fn of_package(pkg: &rpm::RPMPackage) -> Result<Vec<Self>> {
+        let header = &pkg.metadata.header;
+        // Packages without files have no file tags at all
+        let paths = match header.get_file_paths() {
+            Ok(v) => v,
+            Err(_) => return Ok(Vec::new()),
+        };
+        let err = |what: &str, err: rpm::RPMError| anyhow!("Cannot read file {}: {}", what, err);
+        let digests = header.get_file_checksums().map_err(|e| err("digests", e))?;
+        let sizes = header.get_file_sizes().map_err(|e| err("sizes", e))?;
+        let modes = header.get_file_modes().map_err(|e| err("modes", e))?;
+        let users = header.get_file_user_names().map_err(|e| err("users", e))?;
+        let groups = header
+            .get_file_group_names()
+            .map_err(|e| err("groups", e))?;
+        let flags = header.get_file_flags().map_err(|e| err("flags", e))?;
+        let ima_signatures = ima_signatures(pkg);
+        let verity_signatures = verity_signatures(pkg);
+        let signature = |list: &[String], n: usize| list.get(n).filter(|v| !v.is_empty()).cloned();
+
+        Ok(paths
+            .into_iter()
+            .enumerate()
+            .map(|(n, path)| Self {
+                path,
+                raw_mode: modes.get(n).copied().unwrap_or_default() as u16,
+                digest: digests.get(n).filter(|v| !v.is_empty()).cloned(),
+                size: sizes.get(n).copied().unwrap_or_default(),
+                mode: format!("{:o}", modes.get(n).copied().unwrap_or_default() as u16),
+                user: users.get(n).cloned().unwrap_or_default(),
+                group: groups.get(n).cloned().unwrap_or_default(),
+                flags: file_flags(flags.get(n).copied().unwrap_or_default()),
+                ima_signature: signature(ima_signatures, n),
+                verity_signature: signature(verity_signatures, n),
+            })
+            .collect())
+    }
+
+    /// Regular file present in payload, only such files can be signed
+    pub fn is_payload_file(&self) -> bool {
+        self.raw_mode & 0o170000 == 0o100000 && !self.flags.contains(&"ghost")
+    }
+}
+
+/// Regular files without signature of given kind
+pub fn unsigned_files(
+    details: &[FileDetails],
+    kind: &FileSignatureKind,
+) -> Vec<std::path::PathBuf> {
+    details
+        .iter()
+        .filter(|v| v.is_payload_file())
+        .filter(|v| match kind {
+            FileSignatureKind::Ima => v.ima_signature.is_none(),
+            FileSignatureKind::Verity => v.verity_signature.is_none(),
+        })
+        .map(|v| v.path.clone())
+        .collect()
+}
+
+/// Numbers of files with IMA and fsverity signatures
+#[derive(Serialize, Debug)]
+pub struct FileSignatureSummary {
+    pub ima: usize,
+    pub verity: usize,
+}
+
+impl FileSignatureSummary {
+    pub fn of_package(pkg: &rpm::RPMPackage) -> Self {
+        let count = |list: &[String]| list.iter().filter(|v| !v.is_empty()).count();
+        Self {
+            ima: count(ima_signatures(pkg)),
+            verity: count(verity_signatures(pkg)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PayloadInfo {
+    pub format: String,
+    pub compressor: String,
+    pub flags: Option<String>,
+    pub uncompressed_size: Option<u64>,
+}
+
+impl PayloadInfo {
+    pub fn of_package(pkg: &rpm::RPMPackage) -> Self {
+        let header = &pkg.metadata.header;
+        Self {
+            // Tags are missing in packages built by ancient rpm, which supported only cpio and gzip
+            format: header.get_payload_format().unwrap_or("cpio").to_owned(),
+            compressor: header.get_payload_compressor().unwrap_or("gzip").to_owned(),
+            flags: header.get_payload_flags().ok().map(|v| v.to_owned()),
+            uncompressed_size: header
+                .get_archive_size()
+                .or_else(|_| pkg.metadata.signature.get_payload_size())
+                .ok(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct SignatureInfo {
+    /// "header" or "header+payload"
+    pub scope: &'static str,
+    /// Hex encoded 64-bit key ID
+    pub key_id: Option<String>,
+    pub fingerprint: Option<String>,
+    pub public_key_algorithm: String,
+    pub hash_algorithm: String,
+    /// UNIX timestamp of signature creation
+    pub created: Option<u32>,
+}
+
+fn public_key_algorithm(v: u8) -> String {
+    match v {
+        1..=3 => "RSA".to_owned(),
+        17 => "DSA".to_owned(),
+        19 => "ECDSA".to_owned(),
+        22 => "EdDSA".to_owned(),
+        v => format!("unknown ({})", v),
+    }
+}
+
+fn hash_algorithm(v: u8) -> String {
+    match v {
+        1 => "MD5".to_owned(),
+        2 => "SHA1".to_owned(),
+        3 => "RIPEMD160".to_owned(),
+        8 => "SHA256".to_owned(),
+        9 => "SHA384".to_owned(),
+        10 => "SHA512".to_owned(),
+        11 => "SHA224".to_owned(),
+        v => format!("unknown ({})", v),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|v| format!("{:02X}", v)).collect()
+}
+
+/// Minimal reader of OpenPGP packets, only fields describing signature are read
+struct PacketReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.data.len() < n {
+            bail!("Truncated OpenPGP packet")
+        }
+        let (r, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(r)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let v = self.take(2)?;
+        Ok(u16::from_be_bytes([v[0], v[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let v = self.take(4)?;
+        Ok(u32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+    }
+
+    /// Body of signature packet
+    fn signature_packet(&mut self) -> Result<&'a [u8]> {
+        let tag = self.u8()?;
+        if tag & 0x80 == 0 {
+            bail!("Invalid OpenPGP packet tag {:#x}", tag)
+        }
+        let (packet_type, len) = if tag & 0x40 == 0 {
+            let len = match tag & 3 {
+                0 => self.u8()? as usize,
+                1 => self.u16()? as usize,
+                2 => self.u32()? as usize,
+                _ => self.data.len(),
+            };
+            ((tag >> 2) & 0x0f, len)
+        } else {
+            let len = match self.u8()? as usize {
+                v @ 0..=191 => v,
+                v @ 192..=223 => ((v - 192) << 8) + self.u8()? as usize + 192,
+                255 => self.u32()? as usize,
+                _ => bail!("OpenPGP packets with partial body length are not supported"),
+            };
+            (tag & 0x3f, len)
+        };
+        if packet_type != 2 {
+            bail!("OpenPGP packet of type {} is not a signature", packet_type)
+        }
+        self.take(len)
+    }
+
+    fn subpackets(&mut self, info: &mut SignatureInfo) -> Result<()> {
+        while !self.data.is_empty() {
+            let len = match self.u8()? as usize {
+                v @ 0..=191 => v,
+                v @ 192..=254 => ((v - 192) << 8) + self.u8()? as usize + 192,
+                _ => self.u32()? as usize,
+            };
+            let (type_, value) = match self.take(len)?.split_first() {
+                Some((type_, value)) => (type_ & 0x7f, value),
+                None => continue,
+            };
+            match type_ {
+                2 if value.len() == 4 => {
+                    info.created =
+                        Some(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+                }
+                16 if value.len() == 8 => info.key_id = Some(hex(value)),
+                // The first byte is key version
+                33 if value.len() > 1 => info.fingerprint = Some(hex(&value[1..])),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SignatureInfo {
+    /// Parse version 3 or 4 OpenPGP signature packet
+    pub fn parse(scope: &'static str, data: &[u8]) -> Result<Self> {
+        let mut reader = PacketReader {
+            data: PacketReader { data }.signature_packet()?,
+        };
+        let mut info = Self {
+            scope,
+            key_id: None,
+            fingerprint: None,
+            public_key_algorithm: String::new(),
+            hash_algorithm: String::new(),
+            created: None,
+        };
+        match reader.u8()? {
+            3 => {
+                let _hashed_len = reader.u8()?;
+                let _signature_type = reader.u8()?;
+                info.created = Some(reader.u32()?);
+                info.key_id = Some(hex(reader.take(8)?));
+                info.public_key_algorithm = public_key_algorithm(reader.u8()?);
+                info.hash_algorithm = hash_algorithm(reader.u8()?);
+            }
+            4 => {
+                let _signature_type = reader.u8()?;
+                info.public_key_algorithm = public_key_algorithm(reader.u8()?);
+                info.hash_algorithm = hash_algorithm(reader.u8()?);
+                for _ in 0..2 {
+                    let len = reader.u16()? as usize;
+                    PacketReader {
+                        data: reader.take(len)?,
+                    }
+                    .subpackets(&mut info)?;
+                }
+            }
+            v => bail!("Unsupported signature version {}", v),
+        }
+        // Key ID is the low 64 bits of v4 fingerprint
+        if info.key_id.is_none() {
+            info.key_id = info
+                .fingerprint
+                .as_ref()
+                .filter(|v| v.len() >= 16)
+                .map(|v| v[v.len() - 16..].to_owned());
+        }
+        Ok(info)
+    }
+
+    pub fn of_package(pkg: &rpm::RPMPackage) -> Vec<Self> {
+        pkg.metadata
+            .signature
+            .get_pgp_signatures()
+            .into_iter()
+            .filter_map(|(tag, data)| {
+                let scope = match tag {
+                    rpm::IndexSignatureTag::RPMSIGTAG_RSA
+                    | rpm::IndexSignatureTag::RPMSIGTAG_DSA => "header",
+                    _ => "header+payload",
+                };
+                match Self::parse(scope, data) {
+                    Ok(v) => Some(v),
+                    Err(err) => {
+                        warn!("Cannot parse signature {}: {}", tag, err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// UNIX timestamp as "YYYY-MM-DD HH:MM:SS UTC"
+fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let secs = timestamp.rem_euclid(86400);
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Aligned text in the style of "rpm -qi"
+pub fn info(package: &crate::repodata::primary::Package, signatures: &[SignatureInfo]) -> String {
+    let or_none = |v: Option<&String>| match v {
+        Some(v) if !v.is_empty() => v.clone(),
+        _ => "(none)".to_owned(),
+    };
+    let signature = match signatures.first() {
+        Some(v) => format!(
+            "{}/{}, {}, Key ID {}",
+            v.public_key_algorithm,
+            v.hash_algorithm,
+            v.created
+                .map(|v| format_timestamp(v.into()))
+                .unwrap_or_default(),
+            or_none(v.key_id.as_ref()).to_lowercase()
+        ),
+        None => "(none)".to_owned(),
+    };
+    let format = &package.format;
+    let mut fields = vec![("Name", package.name.value.clone())];
+    if let Some(epoch) = package.version.epoch {
+        fields.push(("Epoch", epoch.to_string()))
+    }
+    fields.extend([
+        ("Version", package.version.ver.clone()),
+        ("Release", package.version.rel.clone()),
+        (
+            "Architecture",
+            or_none(package.arch.as_ref().map(|v| &v.value)),
+        ),
+        ("Group", or_none(format.rpm_group.as_ref())),
+        ("Size", package.size.installed.to_string()),
+        ("License", or_none(format.rpm_license.as_ref())),
+        ("Signature", signature),
+        ("Source RPM", or_none(format.rpm_sourcerpm.as_ref())),
+        ("Build Date", format_timestamp(package.time.build.into())),
+        ("Build Host", or_none(format.rpm_buildhost.as_ref())),
+        ("Packager", or_none(package.packager.as_ref())),
+        ("Vendor", or_none(format.rpm_vendor.as_ref())),
+        ("URL", or_none(package.url.as_ref())),
+        ("Summary", or_none(package.summary.value.as_ref())),
+    ]);
+
+    let width = fields
+        .iter()
+        .map(|(k, _)| k.len())
+        .max()
+        .unwrap_or_default();
+    let mut r = String::new();
+    for (k, v) in fields {
+        r.push_str(&format!("{:width$}: {}\n", k, v, width = width));
+    }
+    r.push_str(&format!("{:width$}:\n", "Description", width = width));
+    r.push_str(&or_none(package.description.value.as_ref()));
+    r
+}
+
+/// SHA256 of every regular file of payload by path. Payload is decompressed and read as stream,
+/// nothing is extracted
+pub fn payload_digests(
+    pkg: &rpm::RPMPackage,
+) -> Result<std::collections::BTreeMap<String, String>> {
+    let payload = crate::decompress::decompress(&pkg.content[..])?;
+    archive_digests(payload).map_err(|err| anyhow!("Cannot read payload: {}", err))
+}
+
+/// Every link of hardlinked file gets digest of the link carrying data
+fn archive_digests<R: std::io::Read>(
+    archive: R,
+) -> Result<std::collections::BTreeMap<String, String>> {
+    let mut r = std::collections::BTreeMap::new();
+    // Links without data by hardlinked file
+    let mut pending: HashMap<_, Vec<String>> = HashMap::new();
+    let mut linked = HashMap::new();
+    crate::cpio::for_each_entry(archive, |entry, data| {
+        if !entry.is_file() {
+            return Ok(());
+        }
+        let path = entry
+            .path
+            .strip_prefix('.')
+            .unwrap_or(&entry.path)
+            .to_owned();
+        let key = match entry.link_key() {
+            Some(v) => v,
+            None => {
+                let digest = crate::digest::reader_digest(data, crate::digest::DigestType::Sha256)?;
+                let _ = r.insert(path, digest);
+                return Ok(());
+            }
+        };
+        let digest = match linked.get(&key) {
+            Some(v) => String::clone(v),
+            None if entry.size == 0 => {
+                pending.entry(key).or_default().push(path);
+                return Ok(());
+            }
+            None => crate::digest::reader_digest(data, crate::digest::DigestType::Sha256)?,
+        };
+        for path in pending.remove(&key).unwrap_or_default() {
+            let _ = r.insert(path, digest.clone());
+        }
+        let _ = linked.insert(key, digest.clone());
+        let _ = r.insert(path, digest);
+        Ok(())
+    })?;
+    // Links of empty file, none of them carries data
+    if !pending.is_empty() {
+        let empty = crate::digest::reader_digest(&[][..], crate::digest::DigestType::Sha256)?;
+        for path in pending.into_values().flatten() {
+            let _ = r.insert(path, empty.clone());
+        }
+    }
+    Ok(r)
+}
+
+#[derive(Serialize, Debug)]
+pub struct Dump {
+    #[serde(flatten)]
+    pub package: crate::repodata::primary::Package,
+    pub payload: PayloadInfo,
+    pub signatures: Vec<SignatureInfo>,
+    pub file_signatures: FileSignatureSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_details: Option<Vec<FileDetails>>,
+}
+
+#[test]
+fn test_parse_signature() {
+    #[rustfmt::skip]
+    let packet = [
+        // Old format signature packet, 2-byte length
+        0x89, 0x00, 0x1b,
+        // Version 4, binary document, RSA, SHA256
+        0x04, 0x00, 0x01, 0x08,
+        // Hashed subpackets: creation time
+        0x00, 0x06, 0x05, 0x02, 0x62, 0x5e, 0x2b, 0x40,
+        // Unhashed subpackets: issuer
+        0x00, 0x0a, 0x09, 0x10, 0x19, 0x9e, 0x27, 0x11, 0x64, 0x37, 0x3a, 0x8b,
+        // Hash prefix, signature MPI is not read
+        0xab, 0xcd, 0x00,
+    ];
+    let info = SignatureInfo::parse("header", &packet).unwrap();
+    assert_eq!(info.key_id.as_deref(), Some("199E271164373A8B"));
+    assert_eq!(info.public_key_algorithm, "RSA");
+    assert_eq!(info.hash_algorithm, "SHA256");
+    assert_eq!(info.created, Some(0x625e2b40));
+}
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_timestamp(1650338624), "2022-04-19 03:23:44 UTC");
+    assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+}
+
+#[test]
+fn test_file_flags() {
+    assert_eq!(file_flags(0), Vec::<&str>::new());
+    assert_eq!(file_flags(1 | 1 << 4), vec!["config", "noreplace"]);
+    assert_eq!(file_flags(1 << 6), vec!["ghost"]);
+}
+
+#[test]
+fn test_unsigned_files() {
+    let file =
+        |path: &str, raw_mode: u16, flags: Vec<&'static str>, ima: Option<&str>| FileDetails {
+            path: std::path::PathBuf::from(path),
+            digest: None,
+            size: 0,
+            mode: format!("{:o}", raw_mode),
+            user: "root".to_owned(),
+            group: "root".to_owned(),
+            flags,
+            ima_signature: ima.map(|v| v.to_owned()),
+            verity_signature: None,
+            raw_mode,
+        };
+    let details = [
+        file("/usr/bin/foo", 0o100755, vec![], Some("0302...")),
+        file("/usr/bin/bar", 0o100755, vec![], None),
+        file("/usr/share/foo", 0o040755, vec![], None),
+        file("/var/log/foo.log", 0o100644, vec!["ghost"], None),
+    ];
+    assert_eq!(
+        unsigned_files(&details, &FileSignatureKind::Ima),
+        vec![std::path::PathBuf::from("/usr/bin/bar")]
+    );
+    assert_eq!(
+        unsigned_files(&details, &FileSignatureKind::Verity).len(),
+        2
+    );
+}
+
+#[test]
+fn test_archive_digests() {
+    let mut archive = Vec::new();
+    crate::cpio::test_link(&mut archive, "./usr/bin/foo", 0o100755, 2, 2, b"");
+    crate::cpio::test_link(&mut archive, "./usr/bin/bar", 0o100755, 2, 2, b"hello");
+    crate::cpio::test_link(&mut archive, "./usr/bin/baz", 0o100755, 3, 2, b"");
+    crate::cpio::test_link(&mut archive, "./usr/bin/qux", 0o100755, 3, 2, b"");
+    crate::cpio::test_entry(&mut archive, "./usr/bin/empty", 0o100644, b"");
+    crate::cpio::test_entry(&mut archive, "TRAILER!!!", 0, b"");
+
+    let digests = archive_digests(&archive[..]).unwrap();
+    let hello =
+        crate::digest::reader_digest(&b"hello"[..], crate::digest::DigestType::Sha256).unwrap();
+    let empty = crate::digest::reader_digest(&[][..], crate::digest::DigestType::Sha256).unwrap();
+    assert_eq!(digests["/usr/bin/foo"], hello);
+    assert_eq!(digests["/usr/bin/bar"], hello);
+    assert_eq!(digests["/usr/bin/baz"], empty);
+    assert_eq!(digests["/usr/bin/qux"], empty);
+    assert_eq!(digests["/usr/bin/empty"], empty);
+}
@@ -0,0 +1,36 @@
+//! Secrets referenced from config file instead of being written into it
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use slog_scope::warn;
+
+/// Source of secret. Only reference is kept in config, so dump-config never shows the value
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Secret {
+    /// Name of environment variable
+    Env(String),
+    /// File with secret, trailing newlines are removed
+    File(std::path::PathBuf),
+}
+
+fn read_file(path: &std::path::Path) -> Result<String> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|err| anyhow!("Cannot read secret file {:?}: {}", path, err))?;
+    if crate::platform::file_mode(&metadata) & 0o077 != 0 {
+        warn!("Secret file {:?} is accessible by group or others", path);
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Cannot read secret file {:?}: {}", path, err))?;
+    Ok(content.trim_end_matches(&['\r', '\n'][..]).to_owned())
+}
+
+impl Secret {
+    pub fn resolve(&self) -> Result<String> {
+        match self {
+            Self::Env(name) => std::env::var(name)
+                .map_err(|err| anyhow!("Cannot read secret from ${}: {}", name, err)),
+            Self::File(path) => read_file(path),
+        }
+    }
+}
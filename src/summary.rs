@@ -0,0 +1,210 @@
+//! Machine readable summary of run printed to stdout after command, separately from logs
+
+use std::collections::BTreeMap;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Mutex,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum SummaryFormat {
+    Json,
+}
+
+#[derive(Serialize, Default)]
+struct Published {
+    /// SHA256 of new repomd.xml
+    repomd_checksum: String,
+    revision: u64,
+    /// Locations of metadata documents relative to repository root
+    files: Vec<String>,
+}
+
+/// Package with header strings changed by repodata→sanitize
+#[derive(Serialize)]
+struct Sanitized {
+    location: String,
+    nevra: String,
+    fields: Vec<&'static str>,
+}
+
+/// Package indexed with default values in place of unreadable header values, see --strict
+#[derive(Serialize)]
+struct Defaulted {
+    location: String,
+    nevra: String,
+    errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    status: &'static str,
+    exit_code: i32,
+    /// Packages read from RPM files
+    indexed: usize,
+    /// Packages taken from cached metadata
+    reused: usize,
+    failed: usize,
+    /// Changes of published package set, by NEVRA
+    added: usize,
+    removed: usize,
+    changed: usize,
+    /// Seconds spent in pipeline stages, summed over worker threads
+    stages: BTreeMap<&'static str, f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    published: Option<&'a Published>,
+    #[serde(skip_serializing_if = "<[Sanitized]>::is_empty")]
+    sanitized: &'a [Sanitized],
+    #[serde(skip_serializing_if = "<[Defaulted]>::is_empty")]
+    defaulted: &'a [Defaulted],
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static INDEXED: AtomicUsize = AtomicUsize::new(0);
+static REUSED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+struct Changes {
+    added: usize,
+    removed: usize,
+    changed: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref STAGES: Mutex<BTreeMap<&'static str, std::time::Duration>> =
+        Mutex::new(BTreeMap::new());
+    static ref CHANGES: Mutex<Changes> = Mutex::new(Changes::default());
+    static ref PUBLISHED: Mutex<Option<Published>> = Mutex::new(None);
+    static ref SANITIZED: Mutex<Vec<Sanitized>> = Mutex::new(Vec::new());
+    static ref DEFAULTED: Mutex<Vec<Defaulted>> = Mutex::new(Vec::new());
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst)
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn add_indexed() {
+    let _ = INDEXED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn add_reused() {
+    let _ = REUSED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn add_failed() {
+    let _ = FAILED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Indexed, reused and failed packages since start of process
+pub fn counters() -> (usize, usize, usize) {
+    (
+        INDEXED.load(Ordering::SeqCst),
+        REUSED.load(Ordering::SeqCst),
+        FAILED.load(Ordering::SeqCst),
+    )
+}
+
+pub fn add_sanitized(location: &str, nevra: &str, fields: &[&'static str]) {
+    if is_enabled() {
+        SANITIZED.lock().unwrap().push(Sanitized {
+            location: location.to_owned(),
+            nevra: nevra.to_owned(),
+            fields: fields.to_vec(),
+        })
+    }
+}
+
+pub fn add_defaulted(location: &str, nevra: &str, errors: Vec<String>) {
+    if is_enabled() {
+        DEFAULTED.lock().unwrap().push(Defaulted {
+            location: location.to_owned(),
+            nevra: nevra.to_owned(),
+            errors,
+        })
+    }
+}
+
+pub fn add_stage(name: &'static str, duration: std::time::Duration) {
+    if is_enabled() {
+        *STAGES.lock().unwrap().entry(name).or_default() += duration
+    }
+}
+
+/// Record published metadata, called after repomd.xml is replaced
+pub fn set_published(
+    revision: u64,
+    repomd_path: &std::path::Path,
+    repomd: &crate::repodata::repomd::Repomd,
+    changes: &crate::repodata::changelog::Revision,
+) -> Result<()> {
+    if !is_enabled() {
+        return Ok(());
+    }
+    *CHANGES.lock().unwrap() = Changes {
+        added: changes.added.len(),
+        removed: changes.removed.len(),
+        changed: changes.changed.len(),
+    };
+    let mut files: Vec<_> = repomd
+        .data
+        .iter()
+        .map(|data| data.location.href.clone())
+        .collect();
+    files.push("repodata/repomd.xml".to_owned());
+    *PUBLISHED.lock().unwrap() = Some(Published {
+        repomd_checksum: crate::digest::path_sha256(repomd_path)?,
+        revision,
+        files,
+    });
+    Ok(())
+}
+
+/// Print summary as single line of JSON
+pub fn print(format: SummaryFormat, exit_code: i32) -> Result<()> {
+    use std::io::Write;
+
+    let published = PUBLISHED.lock().unwrap();
+    let changes = CHANGES.lock().unwrap();
+    let mut sanitized = SANITIZED.lock().unwrap();
+    sanitized.sort_by(|a, b| a.location.cmp(&b.location));
+    let mut defaulted = DEFAULTED.lock().unwrap();
+    defaulted.sort_by(|a, b| a.location.cmp(&b.location));
+    let summary = Summary {
+        status: match exit_code {
+            0 => "ok",
+            crate::exit_code::PARTIAL_FAILURE => "partial",
+            _ => "failed",
+        },
+        exit_code,
+        indexed: INDEXED.load(Ordering::SeqCst),
+        reused: REUSED.load(Ordering::SeqCst),
+        failed: FAILED.load(Ordering::SeqCst),
+        added: changes.added,
+        removed: changes.removed,
+        changed: changes.changed,
+        stages: STAGES
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (*k, v.as_secs_f64()))
+            .collect(),
+        published: published.as_ref(),
+        sanitized: &sanitized,
+        defaulted: &defaulted,
+    };
+    let mut stdout = std::io::stdout().lock();
+    match format {
+        SummaryFormat::Json => serde_json::to_writer(&mut stdout, &summary)?,
+    }
+    stdout.write_all(b"\n")?;
+    stdout.flush()?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Rendering of serializable values as plain text tables for humans
+
+use anyhow::Result;
+use serde_json::Value;
+
+fn is_rows(list: &[Value]) -> bool {
+    !list.is_empty() && list.iter().all(Value::is_object)
+}
+
+/// Value is printed in separate section rather than in single cell
+fn is_section(value: &Value) -> bool {
+    match value {
+        Value::Array(list) => is_rows(list),
+        Value::Object(_) => true,
+        _ => false,
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_owned(),
+        Value::String(v) => v.clone(),
+        Value::Array(list) if !list.iter().any(is_section) => {
+            list.iter().map(cell).collect::<Vec<_>>().join(", ")
+        }
+        v => v.to_string(),
+    }
+}
+
+fn write_rows(r: &mut String, rows: &[Value]) {
+    let mut columns: Vec<&str> = Vec::new();
+    for row in rows.iter().filter_map(Value::as_object) {
+        for key in row.keys() {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key)
+            }
+        }
+    }
+
+    let mut lines = vec![columns.iter().map(|v| v.to_uppercase()).collect::<Vec<_>>()];
+    for row in rows.iter().filter_map(Value::as_object) {
+        lines.push(
+            columns
+                .iter()
+                .map(|column| row.get(*column).map(cell).unwrap_or_default())
+                .collect(),
+        )
+    }
+
+    let widths: Vec<_> = (0..columns.len())
+        .map(|n| {
+            lines
+                .iter()
+                .map(|line| line[n].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    for line in lines {
+        let line: Vec<_> = line
+            .iter()
+            .zip(&widths)
+            .map(|(v, width)| format!("{:width$}", v, width = width))
+            .collect();
+        r.push_str(line.join("  ").trim_end());
+        r.push('\n');
+    }
+}
+
+fn write_value(r: &mut String, value: &Value) {
+    match value {
+        Value::Array(list) if is_rows(list) => write_rows(r, list),
+        Value::Array(list) => {
+            for v in list {
+                r.push_str(&cell(v));
+                r.push('\n');
+            }
+        }
+        Value::Object(map) => {
+            let width = map
+                .iter()
+                .filter(|(_, v)| !is_section(v))
+                .map(|(k, _)| k.chars().count())
+                .max()
+                .unwrap_or_default();
+            for (key, v) in map.iter().filter(|(_, v)| !is_section(v)) {
+                r.push_str(&format!("{:width$}  {}", key, cell(v), width = width));
+                r.push('\n');
+            }
+            for (key, v) in map.iter().filter(|(_, v)| is_section(v)) {
+                if !r.is_empty() {
+                    r.push('\n');
+                }
+                r.push_str(&format!("{}:\n", key));
+                write_value(r, v);
+            }
+        }
+        v => {
+            r.push_str(&cell(v));
+            r.push('\n');
+        }
+    }
+}
+
+pub fn render<T>(v: &T) -> Result<String>
+where
+    T: serde::Serialize,
+{
+    let mut r = String::new();
+    write_value(&mut r, &serde_json::to_value(v)?);
+    Ok(r.trim_end().to_owned())
+}
+
+#[test]
+fn test_render() {
+    let rows = serde_json::json!([
+        {"package": "foo-1.0-1.x86_64", "matched": "provides foo"},
+        {"package": "bar-10-1.noarch", "matched": "file /usr/bin/foo"},
+    ]);
+    assert_eq!(
+        render(&rows).unwrap(),
+        "PACKAGE           MATCHED\n\
+         foo-1.0-1.x86_64  provides foo\n\
+         bar-10-1.noarch   file /usr/bin/foo"
+    );
+
+    let report =
+        serde_json::json!({"packages": 2, "denied": [{"package": "foo", "license": "AGPLv3"}]});
+    assert_eq!(
+        render(&report).unwrap(),
+        "packages  2\n\ndenied:\nPACKAGE  LICENSE\nfoo      AGPLv3"
+    );
+}
@@ -0,0 +1,159 @@
+//! Spans of pipeline stages written in Chrome trace event format, viewable in chrome://tracing or
+//! Perfetto
+
+use std::collections::BTreeMap;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Event {
+    name: &'static str,
+    ph: &'static str,
+    /// Microseconds since start of process
+    ts: u64,
+    dur: u64,
+    pid: u32,
+    tid: u64,
+}
+
+/// Spans of one stage merged into one, for export to OpenTelemetry collector
+#[derive(Clone, Copy)]
+pub struct StageSpan {
+    /// Nanoseconds since UNIX epoch of first start and last end
+    pub start: u128,
+    pub end: u128,
+    pub count: u64,
+    /// Sum of durations of spans over worker threads
+    pub duration: std::time::Duration,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STAGES_ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+lazy_static::lazy_static! {
+    static ref START: std::time::Instant = std::time::Instant::now();
+    static ref START_TIME: std::time::SystemTime = std::time::SystemTime::now();
+    static ref EVENTS: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+    static ref STAGES: Mutex<BTreeMap<&'static str, StageSpan>> = Mutex::new(BTreeMap::new());
+}
+
+thread_local! {
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn enable() {
+    lazy_static::initialize(&START);
+    lazy_static::initialize(&START_TIME);
+    ENABLED.store(true, Ordering::SeqCst)
+}
+
+/// Record spans merged by stage, instead of every span
+#[cfg(feature = "otlp")]
+pub fn enable_stages() {
+    lazy_static::initialize(&START);
+    lazy_static::initialize(&START_TIME);
+    STAGES_ENABLED.store(true, Ordering::SeqCst)
+}
+
+/// Span is recorded when guard is dropped
+pub struct Span {
+    name: &'static str,
+    start: Option<std::time::Instant>,
+}
+
+pub fn span(name: &'static str) -> Span {
+    Span {
+        name,
+        start: if ENABLED.load(Ordering::Relaxed)
+            || STAGES_ENABLED.load(Ordering::Relaxed)
+            || crate::summary::is_enabled()
+        {
+            Some(std::time::Instant::now())
+        } else {
+            None
+        },
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            crate::summary::add_stage(self.name, start.elapsed());
+            if STAGES_ENABLED.load(Ordering::Relaxed) {
+                let start_time = unix_nanos(*START_TIME) + start.duration_since(*START).as_nanos();
+                let end = start_time + start.elapsed().as_nanos();
+                let mut stages = STAGES.lock().unwrap();
+                let stage = stages.entry(self.name).or_insert(StageSpan {
+                    start: start_time,
+                    end,
+                    count: 0,
+                    duration: std::time::Duration::ZERO,
+                });
+                stage.start = stage.start.min(start_time);
+                stage.end = stage.end.max(end);
+                stage.count += 1;
+                stage.duration += start.elapsed();
+            }
+            if !ENABLED.load(Ordering::Relaxed) {
+                return;
+            }
+            let event = Event {
+                name: self.name,
+                ph: "X",
+                ts: start.duration_since(*START).as_micros() as u64,
+                dur: start.elapsed().as_micros() as u64,
+                pid: std::process::id(),
+                tid: THREAD_ID.with(|v| *v),
+            };
+            EVENTS.lock().unwrap().push(event)
+        }
+    }
+}
+
+/// Write recorded spans as JSON array of trace events
+pub fn write(path: &std::path::Path) -> Result<()> {
+    let events = EVENTS.lock().unwrap();
+    let file =
+        std::fs::File::create(path).map_err(|err| anyhow!("Cannot create {:?}: {}", path, err))?;
+    serde_json::to_writer(std::io::BufWriter::new(file), &*events)
+        .map_err(|err| anyhow!("Cannot write {:?}: {}", path, err))
+}
+
+fn unix_nanos(time: std::time::SystemTime) -> u128 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map(|v| v.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Wall clock time of `enable`
+#[cfg(feature = "otlp")]
+pub fn start_time() -> std::time::SystemTime {
+    *START_TIME
+}
+
+/// Stages recorded since previous call, spans of each stage merged
+#[cfg(feature = "otlp")]
+pub fn take_stages() -> Vec<(&'static str, StageSpan)> {
+    std::mem::take(&mut *STAGES.lock().unwrap())
+        .into_iter()
+        .collect()
+}
+
+#[cfg(feature = "otlp")]
+#[test]
+fn test_stages() {
+    enable_stages();
+    for _ in 0..3 {
+        let _span = span("test_stage");
+    }
+    let stages = STAGES.lock().unwrap();
+    let stage = stages.get("test_stage").unwrap();
+    assert_eq!(stage.count, 3);
+    assert!(stage.start <= stage.end);
+}